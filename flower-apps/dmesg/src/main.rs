@@ -0,0 +1,100 @@
+#![no_std]
+#![no_main]
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use flower_libc::file::File;
+use flower_libc::{env, print, println, process};
+use flower_mono::ioctl::KMSG_SET_CONSOLE_LEVEL;
+
+extern crate alloc;
+
+const LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _start() -> ! {
+    flower_libc::_init();
+
+    let args: Vec<&str> = env::args().collect();
+
+    let mut filter = LEVELS.len() - 1;
+    let mut console_level = None;
+
+    let mut i = 1;
+    while i < args.len() {
+        let level = args.get(i + 1).and_then(|arg| parse_level(arg));
+        match (args[i], level) {
+            ("-l", Some(level)) => filter = level,
+            ("-n", Some(level)) => console_level = Some(level),
+            _ => usage(),
+        }
+        i += 2;
+    }
+
+    process::exit(dmesg(filter, console_level) as u64);
+}
+
+fn usage() -> ! {
+    println!("usage: dmesg [-l level] [-n level]");
+    println!("  -l  only show messages at or above this level");
+    println!("  -n  set the level of messages printed to the console");
+    println!("levels: {}, or 0-5", LEVELS.join(", "));
+    process::exit(1);
+}
+
+fn parse_level(arg: &str) -> Option<usize> {
+    if let Ok(level) = arg.parse::<usize>() {
+        return (level < LEVELS.len()).then_some(level);
+    }
+    LEVELS.iter().position(|name| *name == arg)
+}
+
+pub fn dmesg(filter: usize, console_level: Option<usize>) -> i32 {
+    let Ok(file) = File::open("/dev/kmsg".to_string()) else {
+        println!("failed to open /dev/kmsg");
+        return 1;
+    };
+
+    if let Some(level) = console_level {
+        if file.ioctl(KMSG_SET_CONSOLE_LEVEL, level as u64).is_err() {
+            println!("failed to set console level");
+            return 1;
+        }
+        return 0;
+    }
+
+    // records never get split across reads, so every chunk is whole lines
+    let mut buffer = [0u8; 4096];
+    loop {
+        let read_bytes = file.read(&mut buffer).unwrap_or(0);
+        if read_bytes == 0 {
+            break;
+        }
+
+        let chunk = String::from_utf8_lossy(&buffer[..read_bytes]);
+        for line in chunk.lines() {
+            print_record(line, filter);
+        }
+    }
+
+    0
+}
+
+/// records look like `<level>[seconds.millis] file:line message`
+fn print_record(line: &str, filter: usize) {
+    let Some((level, rest)) =
+        line.strip_prefix('<').and_then(|line| line.split_once('>'))
+    else {
+        println!("{}", line);
+        return;
+    };
+
+    let level = level.parse::<usize>().unwrap_or(0);
+    if level > filter {
+        return;
+    }
+
+    print!("{:<5} ", LEVELS.get(level).unwrap_or(&"?"));
+    println!("{}", rest);
+}
@@ -4,7 +4,7 @@
 extern crate alloc;
 use alloc::collections::btree_map::BTreeMap;
 use alloc::string::{String, ToString};
-use alloc::vec;
+use alloc::{format, vec};
 
 use flower_libc::file::File;
 use flower_libc::{println, process};
@@ -78,16 +78,59 @@ pub extern "C" fn _start() -> ! {
         mem_total_kb.saturating_sub(mem_free_kb)
     };
 
+    let cpu = read_proc("/proc/cpuinfo")
+        .and_then(|info| {
+            info.lines()
+                .find(|line| line.starts_with("model name"))
+                .and_then(|line| line.split_once(':'))
+                .map(|(_, name)| name.trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+
+    // uptime is "seconds.hundredths idle"
+    let uptime_secs = read_proc("/proc/uptime")
+        .and_then(|uptime| {
+            uptime
+                .split('.')
+                .next()
+                .and_then(|secs| secs.trim().parse::<u64>().ok())
+        })
+        .unwrap_or(0);
+
     println!(
         "flower@vocachuds
 ------------
 Kernel: {} [v{}]
+Uptime: {}
+CPU: {}
 Memory: {}/{}MB",
         kernel_name,
         kernel_version,
+        format_uptime(uptime_secs),
+        cpu,
         mem_used_kb / 1024,
         mem_total_kb / 1024
     );
 
     process::exit(0);
 }
+
+/// reads a whole /proc file, they're always small
+fn read_proc(path: &str) -> Option<String> {
+    let file = File::open(path.to_string()).ok()?;
+    let mut buf = vec![0u8; 4096];
+    let read_bytes = file.read(&mut buf).ok()?;
+    buf.truncate(read_bytes);
+    String::from_utf8(buf).ok()
+}
+
+fn format_uptime(secs: u64) -> String {
+    let (hours, mins) = (secs / 3600, secs / 60 % 60);
+    if hours > 0 {
+        format!("{} hours, {} mins", hours, mins)
+    } else if mins > 0 {
+        format!("{} mins", mins)
+    } else {
+        format!("{} secs", secs)
+    }
+}
@@ -0,0 +1,161 @@
+#![no_std]
+#![no_main]
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use flower_libc::file::File;
+use flower_libc::{env, print, println, process};
+
+extern crate alloc;
+
+/// one line of /proc/pci
+struct Device<'a> {
+    addr: &'a str,
+    ids: &'a str,
+    class: &'a str,
+    revision: &'a str,
+    parent: Option<&'a str>,
+    secondary: Option<&'a str>,
+    driver: Option<&'a str>,
+}
+
+impl<'a> Device<'a> {
+    /// `address vendor:device class rev xx parent secondary driver`
+    fn parse(line: &'a str) -> Option<Self> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [addr, ids, class, "rev", revision, parent, secondary, driver] =
+            fields.as_slice()
+        else {
+            return None;
+        };
+
+        Some(Self {
+            addr,
+            ids,
+            class,
+            revision,
+            parent: (*parent != "-").then_some(*parent),
+            secondary: (*secondary != "-").then_some(*secondary),
+            driver: (*driver != "-").then_some(*driver),
+        })
+    }
+
+    /// `0000:00:1f.2` prints as `00:1f.2` like lspci does
+    fn short_addr(&self) -> &str { self.addr.get(5..).unwrap_or(self.addr) }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _start() -> ! {
+    flower_libc::_init();
+
+    let args: Vec<&str> = env::args().collect();
+    let mut tree = false;
+    let mut drivers = false;
+    for arg in &args[1..] {
+        match *arg {
+            "-t" => tree = true,
+            "-k" => drivers = true,
+            _ => usage(),
+        }
+    }
+
+    let Some(pci) = read_file("/proc/pci") else {
+        println!("failed to read /proc/pci");
+        process::exit(1);
+    };
+    let devices: Vec<Device> = pci.lines().filter_map(Device::parse).collect();
+
+    if tree {
+        print_tree(&devices, None, "");
+    } else {
+        for device in &devices {
+            print_device(device);
+            if drivers && let Some(driver) = device.driver {
+                println!("\tKernel driver in use: {}", driver);
+            }
+        }
+    }
+
+    process::exit(0);
+}
+
+fn usage() -> ! {
+    println!("usage: lspci [-t] [-k]");
+    println!("  -t  show devices as a tree of bridges");
+    println!("  -k  show the kernel driver bound to each device");
+    process::exit(1);
+}
+
+fn read_file(path: &str) -> Option<String> {
+    let file = File::open(path.to_string()).ok()?;
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let read_bytes = file.read(&mut buf).ok()?;
+        if read_bytes == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..read_bytes]);
+    }
+    String::from_utf8(data).ok()
+}
+
+fn print_device(device: &Device) {
+    print!(
+        "{} {}: {}",
+        device.short_addr(),
+        class_name(device.class),
+        device.ids
+    );
+    if device.revision != "00" {
+        print!(" (rev {})", device.revision);
+    }
+    println!();
+}
+
+fn print_tree(devices: &[Device], parent: Option<&str>, indent: &str) {
+    let children: Vec<&Device> =
+        devices.iter().filter(|device| device.parent == parent).collect();
+
+    for (i, device) in children.iter().enumerate() {
+        let last = i == children.len() - 1;
+        print!("{}{}", indent, if last { "`-" } else { "+-" });
+        print_device(device);
+
+        if let Some(bus) = device.secondary {
+            let indent =
+                format!("{}{}", indent, if last { "  " } else { "| " });
+            println!("{}\\-[{}]", indent, bus);
+            print_tree(devices, Some(device.addr), &format!("{}  ", indent));
+        }
+    }
+}
+
+/// the class and subclass part of `class.subclass.interface`
+fn class_name(class: &str) -> &'static str {
+    match class.get(..5).unwrap_or("") {
+        "01.01" => "IDE interface",
+        "01.06" => "SATA controller",
+        "01.08" => "Non-Volatile memory controller",
+        "02.00" => "Ethernet controller",
+        "03.00" => "VGA compatible controller",
+        "04.01" => "Multimedia audio controller",
+        "04.03" => "Audio device",
+        "06.00" => "Host bridge",
+        "06.01" => "ISA bridge",
+        "06.04" => "PCI bridge",
+        "0c.03" => "USB controller",
+        "0c.05" => "SMBus",
+        _ => match class.get(..2).unwrap_or("") {
+            "01" => "Mass storage controller",
+            "02" => "Network controller",
+            "03" => "Display controller",
+            "04" => "Multimedia controller",
+            "06" => "Bridge",
+            "0c" => "Serial bus controller",
+            _ => "Unclassified device",
+        },
+    }
+}
@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use flower_libc::file::File;
+use flower_libc::{env, println, process};
+
+extern crate alloc;
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _start() -> ! {
+    flower_libc::_init();
+
+    let args: Vec<&str> = env::args().collect();
+    let code = match args.as_slice() {
+        [_] => list(),
+        [_, target, volume] => set(target, volume),
+        _ => usage(),
+    };
+
+    process::exit(code as u64);
+}
+
+fn usage() -> ! {
+    println!("usage: mixer [<stream id>|master <volume>]");
+    println!("  with no arguments lists the master volume and every stream");
+    println!("  volumes go from 0 to 100");
+    process::exit(1);
+}
+
+fn list() -> i32 {
+    let Some(text) = read_file("/dev/mixer") else {
+        println!("failed to read /dev/mixer");
+        return 1;
+    };
+
+    let mut header = false;
+    for line in text.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["master", volume] => println!("master volume: {}", volume),
+            [id, pid, rate, channels, format, volume, queued] => {
+                if !header {
+                    println!(
+                        "{:>4} {:>5} {:>6} {:>3} {:>6} {:>4} {:>6}",
+                        "id", "pid", "rate", "ch", "format", "vol", "queued"
+                    );
+                    header = true;
+                }
+                println!(
+                    "{:>4} {:>5} {:>6} {:>3} {:>6} {:>4} {:>6}",
+                    id, pid, rate, channels, format, volume, queued
+                );
+            },
+            _ => {},
+        }
+    }
+
+    0
+}
+
+fn set(target: &str, volume: &str) -> i32 {
+    if volume.parse::<usize>().is_err()
+        || (target != "master" && target.parse::<usize>().is_err())
+    {
+        usage();
+    }
+
+    let Ok(file) = File::open("/dev/mixer".to_string()) else {
+        println!("failed to open /dev/mixer");
+        return 1;
+    };
+
+    let line = format!("{} {}\n", target, volume);
+    if file.write(line.as_bytes()).is_err() {
+        println!("failed to set the volume");
+        return 1;
+    }
+
+    0
+}
+
+fn read_file(path: &str) -> Option<String> {
+    let file = File::open(path.to_string()).ok()?;
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let read_bytes = file.read(&mut buf).ok()?;
+        if read_bytes == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..read_bytes]);
+    }
+    String::from_utf8(data).ok()
+}
@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use flower_libc::file::File;
+use flower_libc::{env, print, println, process, sys};
+use flower_mono::syscalls::{MS_BIND, MS_RDONLY};
+
+const USAGE: &str = "usage: mount [-t type] [-r] [--bind] <source> <target>";
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _start() -> ! {
+    flower_libc::_init();
+
+    let args: Vec<&str> = env::args().skip(1).collect();
+    // no arguments just shows what's mounted
+    if args.is_empty() {
+        process::exit(list() as u64);
+    }
+
+    let mut fstype = "";
+    let mut flags = 0;
+    let mut paths = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg {
+            "-t" => match args.next() {
+                Some(typ) => fstype = typ,
+                None => {
+                    println!("{}", USAGE);
+                    process::exit(1);
+                },
+            },
+            "-r" => flags |= MS_RDONLY,
+            "--bind" => flags |= MS_BIND,
+            path => paths.push(path),
+        }
+    }
+
+    let [source, target] = paths[..] else {
+        println!("{}", USAGE);
+        process::exit(1);
+    };
+
+    let result = sys::fs::mount(
+        source.as_ptr(),
+        source.len(),
+        target.as_ptr(),
+        target.len(),
+        fstype.as_ptr(),
+        fstype.len(),
+        flags,
+    );
+    if result < 0 {
+        println!("mount: failed to mount {} at {}", source, target);
+        process::exit(1);
+    }
+
+    process::exit(0);
+}
+
+fn list() -> i32 {
+    let Ok(file) = File::open("/proc/mounts".to_string()) else {
+        println!("mount: failed to open /proc/mounts");
+        return 1;
+    };
+
+    let mut buffer = [0u8; 1024];
+    loop {
+        let read = file.read(&mut buffer).unwrap_or(0);
+        if read == 0 {
+            break;
+        }
+        print!("{}", core::str::from_utf8(&buffer[..read]).unwrap_or(""));
+    }
+    0
+}
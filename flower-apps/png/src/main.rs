@@ -10,10 +10,8 @@ use core::cmp::min;
 use flower_libc::file::File;
 use flower_libc::sys::kernel;
 use flower_libc::{println, process};
-
-const FB_WIDTH: usize = 1280;
-const FB_HEIGHT: usize = 720;
-const FB_PITCH: usize = FB_WIDTH * 4;
+use flower_mono::ioctl::FBIOGET_INFO;
+use flower_mono::structs::FramebufferInfo;
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _start() -> ! {
@@ -54,7 +52,21 @@ pub fn cat(filename: &str) -> i32 {
 
         // get the framebuffer
         if let Ok(framebuffer) = File::open("/dev/fb0".to_string()) {
-            let fb_addr = framebuffer.mmap(FB_PITCH * FB_HEIGHT);
+            let mut info = FramebufferInfo::default();
+            if framebuffer
+                .ioctl(FBIOGET_INFO, &mut info as *mut _ as u64)
+                .is_err()
+            {
+                println!("failed to query /dev/fb0");
+                return -1;
+            }
+
+            if info.bpp != 32 {
+                println!("unsupported framebuffer depth: {} bpp", info.bpp);
+                return -1;
+            }
+
+            let fb_addr = framebuffer.mmap(info.size());
             if fb_addr.is_err() {
                 println!("failed to mmap /dev/fb0");
                 return -1;
@@ -63,15 +75,13 @@ pub fn cat(filename: &str) -> i32 {
 
             draw_rgba_to_framebuffer(
                 fb_addr,
-                FB_WIDTH,
-                FB_HEIGHT,
-                FB_PITCH,
+                &info,
                 header.width as usize,
                 header.height as usize,
                 &image_data,
             );
 
-            kernel::munmap(fb_addr, FB_PITCH * FB_HEIGHT);
+            kernel::munmap(fb_addr, info.size());
         } else {
             println!("failed to open /dev/fb0");
             return -1;
@@ -92,15 +102,14 @@ fn blend_channel(src: u8, dst: u8, alpha: u8) -> u8 {
 
 fn draw_rgba_to_framebuffer(
     fb_addr: *mut u8,
-    fb_width: usize,
-    fb_height: usize,
-    fb_pitch: usize,
+    info: &FramebufferInfo,
     img_width: usize,
     img_height: usize,
     image_data: &[[u8; 4]],
 ) {
-    let draw_width = min(img_width, fb_width);
-    let draw_height = min(img_height, fb_height);
+    let draw_width = min(img_width, info.width as usize);
+    let draw_height = min(img_height, info.height as usize);
+    let fb_pitch = info.pitch as usize;
 
     for y in 0..draw_height {
         let row_start = y * img_width;
@@ -112,9 +121,9 @@ fn draw_rgba_to_framebuffer(
                 let pixel = fb_addr.add(pixel_offset) as *mut u32;
                 let dst = *pixel;
 
-                let dst_r = ((dst >> 16) & 0xFF) as u8;
-                let dst_g = ((dst >> 8) & 0xFF) as u8;
-                let dst_b = (dst & 0xFF) as u8;
+                let dst_r = (dst >> info.red_mask_shift) as u8;
+                let dst_g = (dst >> info.green_mask_shift) as u8;
+                let dst_b = (dst >> info.blue_mask_shift) as u8;
 
                 let out_r = if src_a == 255 {
                     src_r
@@ -132,8 +141,9 @@ fn draw_rgba_to_framebuffer(
                     blend_channel(src_b, dst_b, src_a)
                 };
 
-                *pixel =
-                    (out_r as u32) << 16 | (out_g as u32) << 8 | out_b as u32;
+                *pixel = (out_r as u32) << info.red_mask_shift
+                    | (out_g as u32) << info.green_mask_shift
+                    | (out_b as u32) << info.blue_mask_shift;
             }
         }
     }
@@ -42,6 +42,8 @@ fn help(_: &str) {
     println!("  exec <filename> [args...] - fork and exec in child");
     println!("  exit - exit the shell");
     println!("  help - show this message");
+    println!("  reboot - restart the machine");
+    println!("  poweroff - turn the machine off");
 }
 
 fn exec(input: String) {
@@ -65,6 +67,8 @@ fn exec(input: String) {
         "help" => help(&args),
         "exec" => tools::exec::run(&args),
         "exit" => process::exit(0),
+        "reboot" => process::reboot(),
+        "poweroff" => process::poweroff(),
         _ => {
             let mut path = format!("/init/bin/{}", cmd);
             let file = File::open(path.clone());
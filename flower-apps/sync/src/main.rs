@@ -0,0 +1,14 @@
+#![no_std]
+#![no_main]
+
+use flower_libc::{process, sys};
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _start() -> ! {
+    flower_libc::_init();
+
+    // writes every dirty cached block out to its disk
+    sys::fs::sync();
+
+    process::exit(0);
+}
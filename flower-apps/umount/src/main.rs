@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use flower_libc::{env, println, process, sys};
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _start() -> ! {
+    flower_libc::_init();
+
+    let args: Vec<&str> = env::args().collect();
+    if args.len() != 2 {
+        println!("usage: umount <target>");
+        process::exit(1);
+    }
+
+    let target = args[1];
+    if sys::fs::umount(target.as_ptr(), target.len()) < 0 {
+        println!("umount: failed to unmount {}", target);
+        process::exit(1);
+    }
+
+    process::exit(0);
+}
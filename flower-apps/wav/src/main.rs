@@ -11,6 +11,10 @@ use alloc::vec;
 
 use flower_libc::file::File;
 use flower_libc::{env, println, process};
+use flower_mono::ioctl::{
+    AUDIO_FORMAT_S16_LE, AUDIO_FORMAT_U8, AUDIO_SET_CHANNELS, AUDIO_SET_FORMAT,
+    AUDIO_SET_RATE,
+};
 
 use crate::resample::resample_linear_bits;
 
@@ -64,6 +68,27 @@ pub fn play(args: &str) -> i32 {
         wav.sample_rate, wav.channels, wav.bits_per_sample
     );
 
+    // the driver takes 8 and 16 bit audio as is, anything else gets
+    // converted here
+    if configure(
+        &driver_file,
+        wav.sample_rate,
+        wav.channels as u64,
+        wav.bits_per_sample,
+    ) {
+        return write_all(&driver_file, wav.data);
+    }
+
+    if !configure(
+        &driver_file,
+        TARGET_SAMPLE_RATE as u32,
+        TARGET_CHANNELS as u64,
+        TARGET_BITS_PER_SAMPLE as u16,
+    ) {
+        println!("failed to configure audio driver");
+        return 1;
+    }
+
     let target_buffer_size =
         DRIVER_BUFFER * (TARGET_SAMPLE_RATE / wav.sample_rate as usize);
 
@@ -100,15 +125,8 @@ pub fn play(args: &str) -> i32 {
             )
         };
 
-        let mut written_total = 0;
-        while written_total < out_bytes.len() {
-            let written =
-                driver_file.write(&out_bytes[written_total..]).unwrap();
-            if written == 0 {
-                println!("failed to write to audio driver");
-                return 1;
-            }
-            written_total += written;
+        if write_all(&driver_file, out_bytes) != 0 {
+            return 1;
         }
 
         total_bytes += chunk.len();
@@ -116,3 +134,33 @@ pub fn play(args: &str) -> i32 {
 
     0
 }
+
+/// asks the driver for this exact format, false if it can't do it
+fn configure(driver: &File, rate: u32, channels: u64, bits: u16) -> bool {
+    let format = match bits {
+        8 => AUDIO_FORMAT_U8,
+        16 => AUDIO_FORMAT_S16_LE,
+        _ => return false,
+    };
+
+    driver.ioctl(AUDIO_SET_FORMAT, format).is_ok()
+        && driver.ioctl(AUDIO_SET_CHANNELS, channels).is_ok()
+        && driver
+            .ioctl(AUDIO_SET_RATE, u64::from(rate))
+            .is_ok_and(|actual| actual == u64::from(rate))
+}
+
+/// the driver blocks until there's room, so this just loops on short writes
+fn write_all(driver: &File, data: &[u8]) -> i32 {
+    let mut written_total = 0;
+    while written_total < data.len() {
+        let written = driver.write(&data[written_total..]).unwrap();
+        if written == 0 {
+            println!("failed to write to audio driver");
+            return 1;
+        }
+        written_total += written;
+    }
+
+    0
+}
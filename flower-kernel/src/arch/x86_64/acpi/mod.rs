@@ -1,4 +1,5 @@
 use acpi::AcpiTables;
+use acpi::platform::AcpiPlatform;
 use spin::once::Once;
 
 use crate::arch::acpi::parser::AcpiReader;
@@ -6,10 +7,15 @@ use crate::arch::acpi::tables::KernelAcpiTables;
 use crate::boot::limine::RSDP_REQUEST;
 
 mod parser;
+pub mod pci;
+pub mod power;
 mod tables;
 
 pub static ACPI_TABLES: Once<KernelAcpiTables> = Once::new();
 
+/// the crate's view of the tables, used for the fixed registers and aml
+static PLATFORM: Once<AcpiPlatform<AcpiReader>> = Once::new();
+
 pub fn install() {
     let mut tables = KernelAcpiTables::default();
 
@@ -21,6 +27,14 @@ pub fn install() {
             if let Ok(acpi) = AcpiTables::from_rsdp(AcpiReader, rsdp.address())
             {
                 tables.parse_madt(&acpi);
+                tables.parse_mcfg(&acpi);
+
+                match AcpiPlatform::new(acpi, AcpiReader) {
+                    Ok(platform) => {
+                        PLATFORM.call_once(|| platform);
+                    },
+                    Err(e) => log::error!("ACPI: no platform info: {:?}", e),
+                }
             } else {
                 panic!("failed to parse acpi tables");
             }
@@ -35,3 +49,8 @@ pub fn install() {
 pub fn get() -> &'static KernelAcpiTables {
     ACPI_TABLES.get().expect("acpi tables not initialized")
 }
+
+/// same as get, for code that can run before acpi is up
+pub fn try_get() -> Option<&'static KernelAcpiTables> { ACPI_TABLES.get() }
+
+fn platform() -> Option<&'static AcpiPlatform<AcpiReader>> { PLATFORM.get() }
@@ -1,11 +1,61 @@
+use alloc::vec::Vec;
 use core::ptr::NonNull;
 
 use acpi::PhysicalMapping;
+use pci_types::ConfigRegionAccess;
+use spin::Mutex;
 use x86_64::PhysAddr;
+use x86_64::instructions::port::Port;
 use x86_64::structures::paging::PageTableFlags;
 
+use crate::drivers::pci::io::PciIO;
 use crate::system;
 
+/// aml mutexes, we're single core so a flag per mutex is plenty
+static AML_MUTEXES: Mutex<Vec<bool>> = Mutex::new(Vec::new());
+
+/// maps every page of a physical range that isn't mapped yet, the hhdm
+/// doesn't always cover acpi and mmio regions
+fn map_range(physical_address: usize, size: usize) {
+    let start = physical_address as u64 & !0xFFF;
+    let end = physical_address as u64 + size.max(1) as u64;
+
+    for phys in (start..end).step_by(4096) {
+        let virt = system::mem::vmm::phys_to_virt(PhysAddr::new(phys));
+        if !system::mem::vmm::page_is_mapped(virt)
+            && let Err(e) = system::mem::vmm::page_map(
+                virt,
+                PhysAddr::new(phys),
+                PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+            )
+        {
+            panic!("failed to map physical region: {e}");
+        }
+    }
+}
+
+fn mmio<T>(address: usize) -> *mut T {
+    map_range(address, core::mem::size_of::<T>());
+    system::mem::vmm::phys_to_virt(PhysAddr::new(address as u64)).as_mut_ptr()
+}
+
+fn pci_read(address: acpi::PciAddress, offset: u16) -> u32 {
+    unsafe { PciIO.read(address, offset & !3) }
+}
+
+/// config space only does dwords, so smaller writes have to merge
+fn pci_write_masked(
+    address: acpi::PciAddress,
+    offset: u16,
+    mask: u32,
+    value: u32,
+) {
+    let shift = (offset & 3) * 8;
+    let old = pci_read(address, offset);
+    let new = (old & !(mask << shift)) | ((value & mask) << shift);
+    unsafe { PciIO.write(address, offset & !3, new) }
+}
+
 #[derive(Clone, Debug)]
 pub struct AcpiReader;
 
@@ -15,20 +65,11 @@ impl acpi::Handler for AcpiReader {
         physical_address: usize,
         size: usize,
     ) -> acpi::PhysicalMapping<Self, T> {
+        map_range(physical_address, size);
         let virt_addr = system::mem::vmm::phys_to_virt(PhysAddr::new(
             physical_address as u64,
         ));
 
-        if !system::mem::vmm::page_is_mapped(virt_addr)
-            && let Err(e) = system::mem::vmm::page_map(
-                virt_addr,
-                PhysAddr::new(physical_address as u64),
-                PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
-            )
-        {
-            panic!("failed to map physical region: {e}");
-        }
-
         let virtual_start = NonNull::new(virt_addr.as_mut_ptr::<T>())
             .expect("acpi physical mapping translated to null virtual pointer");
 
@@ -45,88 +86,145 @@ impl acpi::Handler for AcpiReader {
         // noop.
     }
 
-    fn read_u8(&self, _address: usize) -> u8 { todo!() }
+    fn read_u8(&self, address: usize) -> u8 {
+        unsafe { mmio::<u8>(address).read_volatile() }
+    }
 
-    fn read_u16(&self, _address: usize) -> u16 { todo!() }
+    fn read_u16(&self, address: usize) -> u16 {
+        unsafe { mmio::<u16>(address).read_volatile() }
+    }
 
-    fn read_u32(&self, _address: usize) -> u32 { todo!() }
+    fn read_u32(&self, address: usize) -> u32 {
+        unsafe { mmio::<u32>(address).read_volatile() }
+    }
 
-    fn read_u64(&self, _address: usize) -> u64 { todo!() }
+    fn read_u64(&self, address: usize) -> u64 {
+        unsafe { mmio::<u64>(address).read_volatile() }
+    }
 
-    fn write_u8(&self, _address: usize, _value: u8) { todo!() }
+    fn write_u8(&self, address: usize, value: u8) {
+        unsafe { mmio::<u8>(address).write_volatile(value) }
+    }
 
-    fn write_u16(&self, _address: usize, _value: u16) { todo!() }
+    fn write_u16(&self, address: usize, value: u16) {
+        unsafe { mmio::<u16>(address).write_volatile(value) }
+    }
 
-    fn write_u32(&self, _address: usize, _value: u32) { todo!() }
+    fn write_u32(&self, address: usize, value: u32) {
+        unsafe { mmio::<u32>(address).write_volatile(value) }
+    }
 
-    fn write_u64(&self, _address: usize, _svalue: u64) { todo!() }
+    fn write_u64(&self, address: usize, value: u64) {
+        unsafe { mmio::<u64>(address).write_volatile(value) }
+    }
 
-    fn read_io_u8(&self, _port: u16) -> u8 { todo!() }
+    fn read_io_u8(&self, port: u16) -> u8 { unsafe { Port::new(port).read() } }
 
-    fn read_io_u16(&self, _port: u16) -> u16 { todo!() }
+    fn read_io_u16(&self, port: u16) -> u16 {
+        unsafe { Port::new(port).read() }
+    }
 
-    fn read_io_u32(&self, _port: u16) -> u32 { todo!() }
+    fn read_io_u32(&self, port: u16) -> u32 {
+        unsafe { Port::new(port).read() }
+    }
 
-    fn write_io_u8(&self, _port: u16, _value: u8) { todo!() }
+    fn write_io_u8(&self, port: u16, value: u8) {
+        unsafe { Port::new(port).write(value) }
+    }
 
-    fn write_io_u16(&self, _port: u16, _value: u16) { todo!() }
+    fn write_io_u16(&self, port: u16, value: u16) {
+        unsafe { Port::new(port).write(value) }
+    }
 
-    fn write_io_u32(&self, _port: u16, _value: u32) { todo!() }
+    fn write_io_u32(&self, port: u16, value: u32) {
+        unsafe { Port::new(port).write(value) }
+    }
 
-    fn read_pci_u8(&self, _address: acpi::PciAddress, _offset: u16) -> u8 {
-        todo!()
+    fn read_pci_u8(&self, address: acpi::PciAddress, offset: u16) -> u8 {
+        (pci_read(address, offset) >> ((offset & 3) * 8)) as u8
     }
 
-    fn read_pci_u16(&self, _address: acpi::PciAddress, _offset: u16) -> u16 {
-        todo!()
+    fn read_pci_u16(&self, address: acpi::PciAddress, offset: u16) -> u16 {
+        (pci_read(address, offset) >> ((offset & 2) * 8)) as u16
     }
 
-    fn read_pci_u32(&self, _address: acpi::PciAddress, _offset: u16) -> u32 {
-        todo!()
+    fn read_pci_u32(&self, address: acpi::PciAddress, offset: u16) -> u32 {
+        pci_read(address, offset)
     }
 
-    fn write_pci_u8(
-        &self,
-        _address: acpi::PciAddress,
-        _offset: u16,
-        _value: u8,
-    ) {
-        todo!()
+    fn write_pci_u8(&self, address: acpi::PciAddress, offset: u16, value: u8) {
+        pci_write_masked(address, offset, 0xFF, value as u32);
     }
 
     fn write_pci_u16(
         &self,
-        _address: acpi::PciAddress,
-        _offset: u16,
-        _value: u16,
+        address: acpi::PciAddress,
+        offset: u16,
+        value: u16,
     ) {
-        todo!()
+        pci_write_masked(address, offset, 0xFFFF, value as u32);
     }
 
     fn write_pci_u32(
         &self,
-        _address: acpi::PciAddress,
-        _offset: u16,
-        _value: u32,
+        address: acpi::PciAddress,
+        offset: u16,
+        value: u32,
     ) {
-        todo!()
+        unsafe { PciIO.write(address, offset, value) }
     }
 
-    fn nanos_since_boot(&self) -> u64 { todo!() }
+    fn nanos_since_boot(&self) -> u64 {
+        system::time::monotonic().as_nanos() as u64
+    }
 
-    fn stall(&self, _microseconds: u64) { todo!() }
+    fn stall(&self, microseconds: u64) {
+        // aml can stall with interrupts off, so no ticks. a write to the
+        // post port takes about a microsecond on everything.
+        let mut post: Port<u8> = Port::new(0x80);
+        for _ in 0..microseconds {
+            unsafe { post.write(0) };
+        }
+    }
 
-    fn sleep(&self, _milliseconds: u64) { todo!() }
+    fn sleep(&self, milliseconds: u64) { self.stall(milliseconds * 1000) }
 
-    fn create_mutex(&self) -> acpi::Handle { todo!() }
+    fn create_mutex(&self) -> acpi::Handle {
+        let mut mutexes = AML_MUTEXES.lock();
+        mutexes.push(false);
+        acpi::Handle(mutexes.len() as u32 - 1)
+    }
 
     fn acquire(
         &self,
-        _mutex: acpi::Handle,
-        _timeout: u16,
+        mutex: acpi::Handle,
+        timeout: u16,
     ) -> Result<(), acpi::aml::AmlError> {
-        todo!()
+        // 0xFFFF means wait forever, anything else is in milliseconds
+        let mut waited = 0;
+        loop {
+            {
+                let mut mutexes = AML_MUTEXES.lock();
+                let Some(locked) = mutexes.get_mut(mutex.0 as usize) else {
+                    return Err(acpi::aml::AmlError::MutexAcquireTimeout);
+                };
+                if !*locked {
+                    *locked = true;
+                    return Ok(());
+                }
+            }
+
+            if timeout != 0xFFFF && waited >= timeout {
+                return Err(acpi::aml::AmlError::MutexAcquireTimeout);
+            }
+            self.stall(1000);
+            waited += 1;
+        }
     }
 
-    fn release(&self, _mutex: acpi::Handle) { todo!() }
+    fn release(&self, mutex: acpi::Handle) {
+        if let Some(locked) = AML_MUTEXES.lock().get_mut(mutex.0 as usize) {
+            *locked = false;
+        }
+    }
 }
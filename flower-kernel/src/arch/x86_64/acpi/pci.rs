@@ -0,0 +1,128 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::str::FromStr;
+
+use acpi::aml::Interpreter;
+use acpi::aml::namespace::{AmlName, NamespaceLevelKind};
+use acpi::aml::pci_routing::{PciRoutingTable, Pin};
+use acpi::aml::resource::{InterruptPolarity, InterruptTrigger};
+use spin::Once;
+
+use crate::arch::acpi::parser::AcpiReader;
+use crate::arch::irq::{Polarity, Trigger};
+
+/// PNP0A03 and PNP0A08 as eisa ids, pci and pcie root bridges
+const ROOT_BRIDGE_IDS: [u64; 2] = [0x030A_D041, 0x080A_D041];
+
+/// a root bridge and how its bus routes the interrupt pins
+struct RootBridge {
+    segment: u16,
+    bus: u8,
+    table: PciRoutingTable,
+}
+
+static ROOTS: Once<Vec<RootBridge>> = Once::new();
+
+/// an integer object below `device`, None if it isn't there
+fn integer(
+    aml: &Interpreter<AcpiReader>,
+    device: &AmlName,
+    name: &str,
+) -> Option<u64> {
+    let path = AmlName::from_str(name).ok()?.resolve(device).ok()?;
+    aml.evaluate_if_present(path, vec![]).ok()??.as_integer().ok()
+}
+
+/// every root bridge with a `_PRT`, bridges further down are reached by
+/// swizzling so their own tables aren't needed
+fn find_roots(aml: &Interpreter<AcpiReader>) -> Vec<RootBridge> {
+    // the namespace stays locked while it's walked, nothing can be
+    // evaluated until that's over
+    let mut devices = Vec::new();
+    let walked = aml.namespace.lock().traverse(|name, level| {
+        if level.kind == NamespaceLevelKind::Device
+            && level.values.keys().any(|seg| seg.as_str() == "_PRT")
+        {
+            devices.push(name.clone());
+        }
+        Ok(true)
+    });
+    if let Err(e) = walked {
+        log::warn!("ACPI: can't walk the namespace: {:?}", e);
+    }
+
+    let mut roots = Vec::new();
+    for device in devices {
+        let is_root = ["_HID", "_CID"].iter().any(|id| {
+            integer(aml, &device, id)
+                .is_some_and(|id| ROOT_BRIDGE_IDS.contains(&id))
+        });
+        if !is_root {
+            continue;
+        }
+
+        let Ok(prt) =
+            AmlName::from_str("_PRT").and_then(|p| p.resolve(&device))
+        else {
+            continue;
+        };
+        match PciRoutingTable::from_prt_path(prt, aml) {
+            Ok(table) => roots.push(RootBridge {
+                segment: integer(aml, &device, "_SEG").unwrap_or(0) as u16,
+                bus: integer(aml, &device, "_BBN").unwrap_or(0) as u8,
+                table,
+            }),
+            Err(e) => {
+                log::warn!("ACPI: bad _PRT on {}: {:?}", device.as_string(), e)
+            },
+        }
+    }
+    roots
+}
+
+/// the gsi that interrupt `pin` (1 is INTA) of a device on a root bus is
+/// wired to, None without acpi or if the firmware doesn't say
+pub fn route(
+    segment: u16,
+    bus: u8,
+    device: u8,
+    function: u8,
+    pin: u8,
+) -> Option<(u32, Trigger, Polarity)> {
+    let aml = super::power::aml()?;
+    let roots = ROOTS.call_once(|| find_roots(aml));
+    let root =
+        roots.iter().find(|root| root.segment == segment && root.bus == bus)?;
+
+    let pin = match pin {
+        1 => Pin::IntA,
+        2 => Pin::IntB,
+        3 => Pin::IntC,
+        4 => Pin::IntD,
+        _ => return None,
+    };
+    let irq = root
+        .table
+        .route(device.into(), function.into(), pin, aml)
+        .inspect_err(|e| {
+            log::warn!(
+                "ACPI: no route for {:02x}:{:02x}.{} {:?}: {:?}",
+                bus,
+                device,
+                function,
+                pin,
+                e
+            )
+        })
+        .ok()?;
+
+    let trigger = match irq.trigger {
+        InterruptTrigger::Edge => Trigger::Edge,
+        InterruptTrigger::Level => Trigger::Level,
+    };
+    let polarity = match irq.polarity {
+        InterruptPolarity::ActiveHigh => Polarity::High,
+        InterruptPolarity::ActiveLow => Polarity::Low,
+    };
+    Some((irq.irq, trigger, polarity))
+}
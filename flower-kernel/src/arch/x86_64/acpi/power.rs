@@ -0,0 +1,229 @@
+use alloc::vec;
+use core::str::FromStr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use acpi::address::MappedGas;
+use acpi::aml::Interpreter;
+use acpi::aml::namespace::AmlName;
+use acpi::aml::object::Object;
+use acpi::registers::{Pm1ControlBit, Pm1Event};
+use acpi::sdt::fadt::Fadt;
+use spin::Once;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+use crate::arch;
+use crate::arch::acpi::parser::AcpiReader;
+use crate::arch::irq::{self, Polarity, Trigger};
+
+/// PWRBTN_STS in the pm1 status register
+const PM1_POWER_BUTTON: u64 = 1 << 8;
+
+static AML: Once<Interpreter<AcpiReader>> = Once::new();
+
+/// SLP_TYPa and SLP_TYPb for S5, from the \_S5 package
+static S5: Once<(u8, u8)> = Once::new();
+
+static POWER_BUTTON_PRESSED: AtomicBool = AtomicBool::new(false);
+
+/// the interpreter, once install has loaded the tables
+pub(super) fn aml() -> Option<&'static Interpreter<AcpiReader>> { AML.get() }
+
+fn aml_path(path: &str) -> AmlName {
+    AmlName::from_str(path).expect("bad aml path")
+}
+
+/// reads the sleep type values for S5 out of the dsdt
+fn read_s5(aml: &Interpreter<AcpiReader>) -> Option<(u8, u8)> {
+    let s5 = aml.evaluate_if_present(aml_path("\\_S5"), vec![]).ok()??;
+    let Object::Package(ref values) = *s5 else {
+        return None;
+    };
+
+    let typ = |idx: usize| values.get(idx).and_then(|v| v.as_integer().ok());
+    Some((typ(0)? as u8, typ(1).unwrap_or(0) as u8))
+}
+
+/// loads the dsdt/ssdts, finds out how to power off and takes over the
+/// power button. needs the heap and the ioapic.
+pub fn install() {
+    let Some(platform) = super::platform() else {
+        log::warn!("ACPI: no platform info, power management disabled");
+        return;
+    };
+
+    match Interpreter::new_from_platform(platform) {
+        Ok(aml) => {
+            aml.initialize_namespace();
+            // _PRT answers for the 8259 unless told the ioapic is in use
+            if let Err(e) = aml.evaluate_if_present(
+                aml_path("\\_PIC"),
+                vec![Object::Integer(1).wrap()],
+            ) {
+                log::warn!("ACPI: \\_PIC failed: {:?}", e);
+            }
+            if let Some(s5) = read_s5(&aml) {
+                log::debug!("ACPI: S5 sleep type {:?}", s5);
+                S5.call_once(|| s5);
+            } else {
+                log::warn!("ACPI: no \\_S5, poweroff won't work");
+            }
+            AML.call_once(|| aml);
+        },
+        Err(e) => log::error!("ACPI: failed to load aml: {:?}", e),
+    }
+
+    if let Err(e) = platform.enter_acpi_mode() {
+        log::error!("ACPI: failed to enter acpi mode: {:?}", e);
+        return;
+    }
+
+    let events = &platform.registers.pm1_event_registers;
+    if let Err(e) = platform
+        .initialize_events()
+        .and_then(|_| events.set_event_enabled(Pm1Event::PowerButton, true))
+    {
+        log::error!("ACPI: failed to enable the power button: {:?}", e);
+        return;
+    }
+
+    // the sci is always shareable, level triggered and active low
+    if let Err(e) = irq::request_irq_flags(
+        platform.sci_interrupt as u8,
+        Trigger::Level,
+        Polarity::Low,
+        "acpi",
+        sci_interrupt_handler,
+    ) {
+        log::error!("ACPI: failed to get the sci: {}", e);
+        return;
+    }
+    log::info!("ACPI: sci on irq {}", platform.sci_interrupt);
+}
+
+fn sci_interrupt_handler() -> bool {
+    let mut handled = false;
+    if let Some(platform) = super::platform() {
+        let events = &platform.registers.pm1_event_registers;
+        if let Ok(status) = events.read()
+            && status & PM1_POWER_BUTTON != 0
+        {
+            // status bits are cleared by writing a one back. the enable
+            // bits share the register, keep them as they are.
+            let enable_shift = events.pm1_event_length * 8 / 2;
+            let clear = |gas: &MappedGas<AcpiReader>| {
+                if let Ok(raw) = gas.read() {
+                    let enables = raw & !((1u64 << enable_shift) - 1);
+                    let _ = gas.write(enables | PM1_POWER_BUTTON);
+                }
+            };
+            clear(&events.pm1a);
+            if let Some(pm1b) = &events.pm1b {
+                clear(pm1b);
+            }
+            POWER_BUTTON_PRESSED.store(true, Ordering::Release);
+            handled = true;
+        }
+    }
+
+    handled
+}
+
+/// kernel process that turns acpi events into actions. the sci handler
+/// can't shut down by itself, it could have interrupted anything.
+pub fn event_loop() {
+    loop {
+        if POWER_BUTTON_PRESSED.swap(false, Ordering::AcqRel) {
+            log::info!("ACPI: power button pressed");
+            poweroff();
+        }
+        crate::system::proc::sleep(100);
+    }
+}
+
+/// puts the machine into S5. only returns if that didn't work.
+fn enter_s5() -> Result<(), &'static str> {
+    let platform = super::platform().ok_or("no acpi platform")?;
+    let (slp_typa, _slp_typb) = *S5.get().ok_or("no S5 sleep type")?;
+
+    if let Some(aml) = AML.get()
+        && let Err(e) = aml.evaluate_if_present(
+            aml_path("\\_PTS"),
+            vec![Object::Integer(5).wrap()],
+        )
+    {
+        log::warn!("ACPI: \\_PTS failed: {:?}", e);
+    }
+
+    // the crate writes the same type to pm1a and pm1b, every board we care
+    // about has the same value in both anyway
+    let control = &platform.registers.pm1_control_registers;
+    control.set_sleep_typ(slp_typa).map_err(|_| "failed to set SLP_TYP")?;
+    control
+        .set_bit(Pm1ControlBit::SleepEnable, true)
+        .map_err(|_| "failed to set SLP_EN")?;
+
+    // the write takes a moment to land
+    for _ in 0..1000 {
+        core::hint::spin_loop();
+    }
+    Err("machine is still running after S5")
+}
+
+/// writes the fadt reset register, if the firmware says it works
+fn acpi_reset() -> Result<(), &'static str> {
+    let platform = super::platform().ok_or("no acpi platform")?;
+    let fadt = platform.tables.find_table::<Fadt>().ok_or("no fadt")?;
+
+    let flags = fadt.flags;
+    if !flags.supports_system_reset_via_fadt() {
+        return Err("reset register not supported");
+    }
+
+    let reset = fadt.reset_register().map_err(|_| "bad reset register")?;
+    let gas = unsafe { MappedGas::map_gas(reset, &AcpiReader) }
+        .map_err(|_| "can't map reset register")?;
+    gas.write(fadt.reset_value as u64).map_err(|_| "reset write failed")?;
+    Ok(())
+}
+
+pub fn poweroff() -> ! {
+    log::info!("system is powering off");
+    // disks poll, so this works with interrupts either way
+    crate::system::cache::sync();
+    crate::drivers::pci::driver::unbind_all();
+    interrupts::disable();
+
+    if let Err(e) = enter_s5() {
+        log::error!("ACPI: poweroff failed: {}", e);
+    }
+    log::error!("it is now safe to turn off your computer");
+    arch::halt()
+}
+
+pub fn reboot() -> ! {
+    log::info!("system is rebooting");
+    // disks poll, so this works with interrupts either way
+    crate::system::cache::sync();
+    crate::drivers::pci::driver::unbind_all();
+    interrupts::disable();
+
+    if let Err(e) = acpi_reset() {
+        log::warn!("ACPI: reset failed: {}, trying the keyboard controller", e);
+    }
+
+    // pulse the cpu reset line through the 8042
+    let mut command: Port<u8> = Port::new(0x64);
+    unsafe { command.write(0xFE) };
+
+    // last resort, triple fault with an empty idt
+    unsafe {
+        let idt = x86_64::structures::DescriptorTablePointer {
+            limit: 0,
+            base: x86_64::VirtAddr::new(0),
+        };
+        x86_64::instructions::tables::lidt(&idt);
+        core::arch::asm!("int3");
+    }
+    arch::halt()
+}
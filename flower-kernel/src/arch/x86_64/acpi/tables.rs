@@ -2,6 +2,7 @@ use alloc::vec::Vec;
 
 use acpi::AcpiTables;
 use acpi::sdt::madt::{Madt, MadtEntry};
+use acpi::sdt::mcfg::Mcfg;
 
 use crate::arch::acpi::parser::AcpiReader;
 
@@ -16,15 +17,41 @@ pub struct LapicInfo {
 pub struct IoApicInfo {
     pub id: u8,
     pub address: u32,
+    pub gsi_base: u32,
+}
+
+/// an isa irq that isn't wired to the ioapic pin with the same number
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    /// mps inti flags, bits 0-1 are polarity and 2-3 are trigger mode
+    pub flags: u16,
+}
+
+/// a window of pcie config space from the mcfg, one 4k page per function
+#[derive(Debug, Clone, Copy)]
+pub struct EcamRegion {
+    pub base: u64,
+    pub segment: u16,
+    pub bus_start: u8,
+    pub bus_end: u8,
 }
 
 #[derive(Debug, Default)]
 pub struct KernelAcpiTables {
     pub lapics: Vec<LapicInfo>,
     pub ioapics: Vec<IoApicInfo>,
+    pub overrides: Vec<InterruptOverride>,
+    pub ecam: Vec<EcamRegion>,
 }
 
 impl KernelAcpiTables {
+    /// the override for an isa irq, if the firmware gave one
+    pub fn interrupt_override(&self, irq: u8) -> Option<InterruptOverride> {
+        self.overrides.iter().find(|iso| iso.irq == irq).copied()
+    }
+
     pub fn parse_madt(&mut self, acpi: &AcpiTables<AcpiReader>) {
         for madt in acpi.find_tables::<Madt>() {
             for entry in madt.get().entries() {
@@ -40,6 +67,14 @@ impl KernelAcpiTables {
                         self.ioapics.push(IoApicInfo {
                             id: ioapic.io_apic_id,
                             address: ioapic.io_apic_address,
+                            gsi_base: ioapic.global_system_interrupt_base,
+                        })
+                    },
+                    MadtEntry::InterruptSourceOverride(iso) => {
+                        self.overrides.push(InterruptOverride {
+                            irq: iso.irq,
+                            gsi: iso.global_system_interrupt,
+                            flags: iso.flags,
                         })
                     },
                     _ => {},
@@ -47,4 +82,30 @@ impl KernelAcpiTables {
             }
         }
     }
+
+    pub fn parse_mcfg(&mut self, acpi: &AcpiTables<AcpiReader>) {
+        let Some(mcfg) = acpi.find_table::<Mcfg>() else {
+            return;
+        };
+
+        for entry in mcfg.get().entries() {
+            self.ecam.push(EcamRegion {
+                base: entry.base_address,
+                segment: entry.pci_segment_group,
+                bus_start: entry.bus_number_start,
+                bus_end: entry.bus_number_end,
+            });
+        }
+    }
+
+    /// the ecam region that covers a bus, if there is one
+    pub fn ecam_region(&self, segment: u16, bus: u8) -> Option<EcamRegion> {
+        self.ecam
+            .iter()
+            .find(|region| {
+                region.segment == segment
+                    && (region.bus_start..=region.bus_end).contains(&bus)
+            })
+            .copied()
+    }
 }
@@ -44,8 +44,13 @@ pub const LAPIC_TIMER_DIV: u64 = 0x3E0;
 const IOAPIC_VIRT: u64 = 0xFFFF_FFFF_FEC0_0000;
 const IOAPIC_REG_SELECT: u64 = 0x00;
 const IOAPIC_REG_DATA: u64 = 0x10;
+const IOAPIC_REG_VERSION: u32 = 0x01;
 const IOAPIC_REDIR_TABLE: u32 = 0x10;
 
+const IOAPIC_ACTIVE_LOW: u32 = 1 << 13;
+const IOAPIC_LEVEL_TRIGGERED: u32 = 1 << 15;
+const IOAPIC_MASKED: u32 = 1 << 16;
+
 const LAPIC_ID: u64 = 0x020;
 
 pub unsafe fn lapic_read(offset: u64) -> u32 {
@@ -58,28 +63,82 @@ pub unsafe fn lapic_write(offset: u64, value: u32) {
     unsafe { core::ptr::write_volatile(ptr, value) }
 }
 
-pub unsafe fn ioapic_read(reg: u32) -> u32 {
-    let select = IOAPIC_VIRT as *mut u32;
-    let data = (IOAPIC_VIRT + IOAPIC_REG_DATA) as *mut u32;
+/// virtual base of the nth ioapic, they sit one page apart
+fn ioapic_base(idx: usize) -> u64 { IOAPIC_VIRT + idx as u64 * 0x1000 }
+
+pub unsafe fn ioapic_read(base: u64, reg: u32) -> u32 {
+    let select = base as *mut u32;
+    let data = (base + IOAPIC_REG_DATA) as *mut u32;
     unsafe { core::ptr::write_volatile(select, reg) };
     unsafe { core::ptr::read_volatile(data) }
 }
 
-pub unsafe fn ioapic_write(reg: u32, value: u32) {
-    let select = IOAPIC_VIRT as *mut u32;
-    let data = (IOAPIC_VIRT + IOAPIC_REG_DATA) as *mut u32;
+pub unsafe fn ioapic_write(base: u64, reg: u32, value: u32) {
+    let select = base as *mut u32;
+    let data = (base + IOAPIC_REG_DATA) as *mut u32;
     unsafe { core::ptr::write_volatile(select, reg) };
     unsafe { core::ptr::write_volatile(data, value) };
 }
 
-unsafe fn ioapic_set_redirection(irq: u8, vector: u8, dest_apic_id: u8) {
-    let redir = IOAPIC_REDIR_TABLE + (u32::from(irq) * 2);
-    let low = u32::from(vector);
+/// number of pins on the ioapic
+unsafe fn ioapic_pins(base: u64) -> u32 {
+    (unsafe { ioapic_read(base, IOAPIC_REG_VERSION) } >> 16 & 0xFF) + 1
+}
+
+/// finds the ioapic that owns a gsi, returns its base and the pin
+fn ioapic_for_gsi(gsi: u32) -> Option<(u64, u32)> {
+    acpi::get().ioapics.iter().enumerate().find_map(|(idx, ioapic)| {
+        let base = ioapic_base(idx);
+        let pins = unsafe { ioapic_pins(base) };
+        (gsi >= ioapic.gsi_base && gsi < ioapic.gsi_base + pins)
+            .then(|| (base, gsi - ioapic.gsi_base))
+    })
+}
+
+unsafe fn ioapic_set_redirection(
+    base: u64,
+    pin: u32,
+    low: u32,
+    dest_apic_id: u8,
+) {
+    let redir = IOAPIC_REDIR_TABLE + pin * 2;
     let high = u32::from(dest_apic_id) << 24;
 
     unsafe {
-        ioapic_write(redir + 1, high);
-        ioapic_write(redir, low);
+        ioapic_write(base, redir + 1, high);
+        ioapic_write(base, redir, low);
+    }
+}
+
+/// points a gsi at a vector on this cpu and unmasks it
+pub fn ioapic_route(
+    gsi: u32,
+    vector: u8,
+    active_low: bool,
+    level_triggered: bool,
+) -> Result<(), &'static str> {
+    let (base, pin) =
+        ioapic_for_gsi(gsi).ok_or("no ioapic handles this gsi")?;
+
+    let mut low = u32::from(vector);
+    if active_low {
+        low |= IOAPIC_ACTIVE_LOW;
+    }
+    if level_triggered {
+        low |= IOAPIC_LEVEL_TRIGGERED;
+    }
+
+    unsafe { ioapic_set_redirection(base, pin, low, lapic_id()) };
+    Ok(())
+}
+
+/// id of the lapic we're running on
+pub fn lapic_id() -> u8 { (unsafe { lapic_read(LAPIC_ID) } >> 24) as u8 }
+
+/// masks a gsi so it can't fire anymore
+pub fn ioapic_mask(gsi: u32) {
+    if let Some((base, pin)) = ioapic_for_gsi(gsi) {
+        unsafe { ioapic_set_redirection(base, pin, IOAPIC_MASKED, 0) }
     }
 }
 
@@ -171,19 +230,31 @@ pub fn install() {
         )
         .expect("failed to map lapic.");
 
-        // also map ioapic
+        // also map the ioapics, everything starts masked until a driver
+        // asks for it
         let acpi_tables = acpi::get();
         if acpi_tables.ioapics.is_empty() {
             panic!("no ioapic found in acpi tables");
         }
-        let ioapic_addr = acpi_tables.ioapics[0].address;
-        log::debug!("ioapic addr: {:#x}", ioapic_addr);
-        vmm::page_map(
-            VirtAddr::new(IOAPIC_VIRT),
-            PhysAddr::new(ioapic_addr as u64),
-            flags,
-        )
-        .expect("failed to map ioapic");
+        for (idx, ioapic) in acpi_tables.ioapics.iter().enumerate() {
+            log::debug!(
+                "ioapic {} addr: {:#x}, gsi base {}",
+                ioapic.id,
+                ioapic.address,
+                ioapic.gsi_base
+            );
+            vmm::page_map(
+                VirtAddr::new(ioapic_base(idx)),
+                PhysAddr::new(ioapic.address as u64),
+                flags,
+            )
+            .expect("failed to map ioapic");
+
+            let base = ioapic_base(idx);
+            for pin in 0..unsafe { ioapic_pins(base) } {
+                unsafe { ioapic_set_redirection(base, pin, IOAPIC_MASKED, 0) };
+            }
+        }
 
         // enable spurious
         unsafe {
@@ -205,9 +276,6 @@ pub fn install() {
                 (1 << 17) | InterruptIndex::Timer as u32,
             );
             lapic_write(LAPIC_TIMER_INIT, *ticks_1ms);
-
-            let lapic_id = (lapic_read(LAPIC_ID) >> 24) as u8;
-            ioapic_set_redirection(1, InterruptIndex::Keyboard as u8, lapic_id);
         }
     }
 }
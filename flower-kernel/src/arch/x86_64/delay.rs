@@ -0,0 +1,25 @@
+use x86_64::instructions::port::Port;
+
+/// roughly a microsecond, a write to the post port takes about that long.
+/// for drivers that have to wait on hardware before the timer runs or with
+/// interrupts off, where ticks don't move
+pub fn udelay(us: u64) {
+    let mut port = Port::<u8>::new(0x80);
+    for _ in 0..us {
+        unsafe { port.write(0) };
+    }
+}
+
+/// polls `done` until it's true or `timeout` ms have gone by
+pub fn wait_for(
+    timeout: u64,
+    mut done: impl FnMut() -> bool,
+) -> Result<(), &'static str> {
+    for _ in 0..timeout * 1000 {
+        if done() {
+            return Ok(());
+        }
+        udelay(1);
+    }
+    Err("timed out")
+}
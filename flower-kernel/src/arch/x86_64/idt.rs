@@ -8,7 +8,7 @@ use crate::arch::gdt::DOUBLE_FAULT_IST_INDEX;
 use crate::arch::interrupts::{
     InterruptIndex, spurious_interrupt_handler, timer_interrupt_handler,
 };
-use crate::drivers::ps2::keyboard;
+use crate::arch::irq;
 use crate::{println, system};
 
 static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
@@ -26,9 +26,10 @@ static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
             .set_stack_index(DOUBLE_FAULT_IST_INDEX);
     }
 
-    // ps2/keyboard
-    idt[InterruptIndex::Keyboard.as_u8()]
-        .set_handler_fn(keyboard::keyboard_interrupt_handler);
+    // device irqs, which one goes where is decided by irq::request_*
+    for (idx, stub) in irq::STUBS.iter().enumerate() {
+        idt[irq::IRQ_VECTOR_BASE + idx as u8].set_handler_fn(*stub);
+    }
 
     // spurious
     idt[InterruptIndex::Timer.as_u8()].set_handler_fn(timer_interrupt_handler);
@@ -4,22 +4,34 @@ use x86_64::instructions::interrupts;
 use x86_64::structures::idt::InterruptStackFrame;
 
 use crate::arch::apic;
-use crate::system::proc;
+use crate::system::{proc, random};
 
 static TICKS: AtomicU64 = AtomicU64::new(0);
 
+/// how many times each vector has fired
+static COUNTS: [AtomicU64; 256] = [const { AtomicU64::new(0) }; 256];
+
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum InterruptIndex {
     Timer = 32,
-    Keyboard = 33,
     Spurious = 255,
 }
 
 impl InterruptIndex {
+    pub const ALL: [InterruptIndex; 2] =
+        [InterruptIndex::Timer, InterruptIndex::Spurious];
+
     pub fn as_u8(self) -> u8 { self as u8 }
 
     pub fn as_usize(self) -> usize { usize::from(self.as_u8()) }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            InterruptIndex::Timer => "timer",
+            InterruptIndex::Spurious => "spurious",
+        }
+    }
 }
 
 pub fn enable() { interrupts::enable(); }
@@ -28,15 +40,29 @@ pub fn disable() { interrupts::disable(); }
 
 pub fn get_ticks() -> u64 { TICKS.load(Ordering::Relaxed) }
 
+/// bumps the counter for the vector and feeds the timing to the random
+/// pool, every handler should call this
+pub fn record(vector: u8) {
+    COUNTS[vector as usize].fetch_add(1, Ordering::Relaxed);
+    random::add_interrupt(vector);
+}
+
+pub fn count(vector: u8) -> u64 {
+    COUNTS[vector as usize].load(Ordering::Relaxed)
+}
+
 pub extern "x86-interrupt" fn timer_interrupt_handler(
     _stack_frame: InterruptStackFrame,
 ) {
     TICKS.fetch_add(1, Ordering::Relaxed);
+    record(InterruptIndex::Timer.as_u8());
     apic::eoi();
+    proc::tick();
     proc::schedule();
 }
 
 pub extern "x86-interrupt" fn spurious_interrupt_handler(
     _stack_frame: InterruptStackFrame,
 ) {
+    record(InterruptIndex::Spurious.as_u8());
 }
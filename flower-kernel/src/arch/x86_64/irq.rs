@@ -0,0 +1,280 @@
+use alloc::vec::Vec;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::structures::idt::{HandlerFunc, InterruptStackFrame};
+
+use crate::arch::{acpi, apic, interrupts};
+
+/// returns true if the device actually raised the interrupt, shared lines
+/// ask every handler until one of them claims it.
+pub type IrqHandler = fn() -> bool;
+
+/// first vector handed out to devices, everything below is exceptions and
+/// the fixed lapic vectors.
+pub const IRQ_VECTOR_BASE: u8 = 48;
+pub const IRQ_VECTORS: usize = 64;
+
+/// how many handlers can share one line
+const MAX_SHARED: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Edge,
+    Level,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    High,
+    Low,
+}
+
+/// where the interrupt comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqSource {
+    /// a pin on one of the ioapics
+    Gsi(u32),
+    /// a message signalled interrupt, the device writes the vector itself
+    Msi,
+}
+
+pub struct IrqLine {
+    pub source: IrqSource,
+    pub vector: u8,
+    pub trigger: Trigger,
+    pub polarity: Polarity,
+    /// times nobody claimed the interrupt
+    pub unhandled: u64,
+    handlers: Vec<(&'static str, IrqHandler)>,
+}
+
+impl IrqLine {
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.handlers.iter().map(|(name, _)| *name)
+    }
+
+    pub fn count(&self) -> u64 { interrupts::count(self.vector) }
+}
+
+static LINES: Mutex<Vec<IrqLine>> = Mutex::new(Vec::new());
+
+extern "x86-interrupt" fn stub<const V: u8>(_stack_frame: InterruptStackFrame) {
+    dispatch(V);
+}
+
+macro_rules! stubs {
+    ($($n:literal)*) => { [$(stub::<{ IRQ_VECTOR_BASE + $n }>),*] };
+}
+
+/// one entry point per device vector, idt.rs installs all of them
+#[rustfmt::skip]
+pub static STUBS: [HandlerFunc; IRQ_VECTORS] = stubs!(
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15
+    16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+    32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47
+    48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63
+);
+
+fn dispatch(vector: u8) {
+    interrupts::record(vector);
+
+    // copy the handlers out so they run without the lock held
+    let mut handlers: [Option<IrqHandler>; MAX_SHARED] = [None; MAX_SHARED];
+    if let Some(line) = LINES.lock().iter().find(|line| line.vector == vector) {
+        for (slot, (_, handler)) in handlers.iter_mut().zip(&line.handlers) {
+            *slot = Some(*handler);
+        }
+    }
+
+    // every handler gets a go, more than one device can be pending
+    let mut handled = false;
+    for handler in handlers.iter().flatten() {
+        handled |= handler();
+    }
+
+    if !handled
+        && let Some(line) =
+            LINES.lock().iter_mut().find(|line| line.vector == vector)
+    {
+        line.unhandled += 1;
+    }
+
+    apic::eoi();
+}
+
+/// the first vector nothing is using yet
+fn free_vector(lines: &[IrqLine]) -> Option<u8> {
+    (IRQ_VECTOR_BASE..IRQ_VECTOR_BASE + IRQ_VECTORS as u8)
+        .find(|vector| lines.iter().all(|line| line.vector != *vector))
+}
+
+/// decodes the mps inti flags of an override, 0 means conforms to the bus
+fn override_flags(
+    flags: u16,
+    trigger: Trigger,
+    polarity: Polarity,
+) -> (Trigger, Polarity) {
+    let polarity = match flags & 0b11 {
+        0b01 => Polarity::High,
+        0b11 => Polarity::Low,
+        _ => polarity,
+    };
+    let trigger = match (flags >> 2) & 0b11 {
+        0b01 => Trigger::Edge,
+        0b11 => Trigger::Level,
+        _ => trigger,
+    };
+    (trigger, polarity)
+}
+
+/// registers a handler for a global system interrupt and returns the
+/// vector it landed on. level triggered lines can be shared.
+pub fn request_gsi(
+    gsi: u32,
+    trigger: Trigger,
+    polarity: Polarity,
+    name: &'static str,
+    handler: IrqHandler,
+) -> Result<u8, &'static str> {
+    without_interrupts(|| {
+        let mut lines = LINES.lock();
+
+        if let Some(line) =
+            lines.iter_mut().find(|line| line.source == IrqSource::Gsi(gsi))
+        {
+            if line.trigger != Trigger::Level || trigger != Trigger::Level {
+                return Err("irq is already taken and can't be shared");
+            }
+            if line.polarity != polarity {
+                return Err("irq is shared with a different polarity");
+            }
+            if line.handlers.len() >= MAX_SHARED {
+                return Err("too many handlers on this irq");
+            }
+            line.handlers.push((name, handler));
+            return Ok(line.vector);
+        }
+
+        let vector = free_vector(&lines).ok_or("out of irq vectors")?;
+        apic::ioapic_route(
+            gsi,
+            vector,
+            polarity == Polarity::Low,
+            trigger == Trigger::Level,
+        )?;
+
+        lines.push(IrqLine {
+            source: IrqSource::Gsi(gsi),
+            vector,
+            trigger,
+            polarity,
+            unhandled: 0,
+            handlers: alloc::vec![(name, handler)],
+        });
+        log::debug!(
+            "irq: gsi {} -> vector {} ({:?}, {:?}) for {}",
+            gsi,
+            vector,
+            trigger,
+            polarity,
+            name
+        );
+        Ok(vector)
+    })
+}
+
+/// registers a handler for a bus irq, the madt overrides win over the
+/// trigger and polarity given here.
+pub fn request_irq_flags(
+    irq: u8,
+    trigger: Trigger,
+    polarity: Polarity,
+    name: &'static str,
+    handler: IrqHandler,
+) -> Result<u8, &'static str> {
+    let (gsi, trigger, polarity) = match acpi::get().interrupt_override(irq) {
+        Some(iso) => {
+            let (trigger, polarity) =
+                override_flags(iso.flags, trigger, polarity);
+            (iso.gsi, trigger, polarity)
+        },
+        None => (u32::from(irq), trigger, polarity),
+    };
+    request_gsi(gsi, trigger, polarity, name, handler)
+}
+
+/// registers a handler for a legacy isa irq, edge triggered and active high
+pub fn request_irq(
+    irq: u8,
+    name: &'static str,
+    handler: IrqHandler,
+) -> Result<u8, &'static str> {
+    request_irq_flags(irq, Trigger::Edge, Polarity::High, name, handler)
+}
+
+/// registers a handler for the interrupt line of a pci device, those are
+/// level triggered, active low and usually shared. the line is only what
+/// the firmware wrote there for the 8259, so this is the fallback for when
+/// acpi can't route the pin.
+pub fn request_pci_irq(
+    line: u8,
+    name: &'static str,
+    handler: IrqHandler,
+) -> Result<u8, &'static str> {
+    request_irq_flags(line, Trigger::Level, Polarity::Low, name, handler)
+}
+
+/// removes a handler, the line gets masked once nobody is left on it
+pub fn free_irq(gsi: u32, handler: IrqHandler) {
+    without_interrupts(|| {
+        let mut lines = LINES.lock();
+        let Some(idx) =
+            lines.iter().position(|line| line.source == IrqSource::Gsi(gsi))
+        else {
+            return;
+        };
+
+        lines[idx].handlers.retain(|(_, h)| *h as usize != handler as usize);
+        if lines[idx].handlers.is_empty() {
+            apic::ioapic_mask(gsi);
+            lines.remove(idx);
+        }
+    });
+}
+
+/// gives a message signalled interrupt its own vector, nothing to route
+/// since the device writes straight to the lapic.
+pub fn request_msi(
+    name: &'static str,
+    handler: IrqHandler,
+) -> Result<u8, &'static str> {
+    without_interrupts(|| {
+        let mut lines = LINES.lock();
+        let vector = free_vector(&lines).ok_or("out of irq vectors")?;
+        lines.push(IrqLine {
+            source: IrqSource::Msi,
+            vector,
+            trigger: Trigger::Edge,
+            polarity: Polarity::High,
+            unhandled: 0,
+            handlers: alloc::vec![(name, handler)],
+        });
+        log::debug!("irq: msi -> vector {} for {}", vector, name);
+        Ok(vector)
+    })
+}
+
+/// releases a vector from request_msi, the device should be quiet by now
+pub fn free_msi(vector: u8) {
+    without_interrupts(|| {
+        LINES.lock().retain(|line| {
+            line.source != IrqSource::Msi || line.vector != vector
+        })
+    });
+}
+
+/// runs `f` over every registered line, for /proc/interrupts
+pub fn lines<R>(f: impl FnOnce(&[IrqLine]) -> R) -> R {
+    without_interrupts(|| f(&LINES.lock()))
+}
@@ -1,8 +1,10 @@
 pub mod acpi;
 pub mod apic;
+pub mod delay;
 pub mod gdt;
 pub mod idt;
 pub mod interrupts;
+pub mod irq;
 pub mod layout;
 
 use core::arch::asm;
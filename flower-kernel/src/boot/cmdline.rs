@@ -0,0 +1,23 @@
+use alloc::string::String;
+
+use spin::Lazy;
+
+use crate::boot::limine::CMDLINE_REQUEST;
+
+/// the kernel command line from limine.conf, copied out so it doesn't
+/// matter what happens to bootloader memory later
+static CMDLINE: Lazy<String> = Lazy::new(|| {
+    CMDLINE_REQUEST
+        .get_response()
+        .map(|response| String::from(response.cmdline().to_str().unwrap_or("")))
+        .unwrap_or_default()
+});
+
+pub fn get() -> &'static str { &CMDLINE }
+
+/// value of a `key=value` option, `root=/dev/vda1` and the like
+pub fn option(key: &str) -> Option<&'static str> {
+    CMDLINE.split_whitespace().find_map(|option| {
+        option.strip_prefix(key).and_then(|rest| rest.strip_prefix('='))
+    })
+}
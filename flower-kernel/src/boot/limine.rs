@@ -1,7 +1,8 @@
 use limine::BaseRevision;
 use limine::request::{
-    FramebufferRequest, HhdmRequest, MemoryMapRequest, ModuleRequest,
-    RequestsEndMarker, RequestsStartMarker, RsdpRequest,
+    ExecutableCmdlineRequest, FramebufferRequest, HhdmRequest,
+    MemoryMapRequest, ModuleRequest, RequestsEndMarker, RequestsStartMarker,
+    RsdpRequest,
 };
 
 #[used]
@@ -32,6 +33,11 @@ pub static MODULE_REQUESTS: ModuleRequest = ModuleRequest::new();
 #[unsafe(link_section = ".limine_requests")]
 pub static RSDP_REQUEST: RsdpRequest = RsdpRequest::new();
 
+#[used]
+#[unsafe(link_section = ".limine_requests")]
+pub static CMDLINE_REQUEST: ExecutableCmdlineRequest =
+    ExecutableCmdlineRequest::new();
+
 #[used]
 #[unsafe(link_section = ".limine_requests_end")]
 pub static _END_MARKER: RequestsEndMarker = RequestsEndMarker::new();
@@ -1 +1,2 @@
+pub mod cmdline;
 pub mod limine;
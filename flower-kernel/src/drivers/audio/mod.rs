@@ -0,0 +1,493 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+
+use crate::system::proc;
+
+/// frames a stream can have waiting before its writer has to sleep
+const STREAM_QUEUE_FRAMES: usize = 8192;
+
+/// card buffers the mixer keeps filled ahead of playback. more survives
+/// a busy system better, fewer lets a new stream be heard sooner.
+const MIX_AHEAD_BUFFERS: usize = 8;
+
+/// frames mixed in one go, one card buffer worth
+const MIX_FRAMES: usize = 1024;
+
+pub const MIN_RATE: u32 = 8000;
+pub const MAX_RATE: u32 = 48000;
+
+/// sample layout, the card itself always gets 16 bit stereo
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    U8,
+    S16Le,
+}
+
+impl SampleFormat {
+    pub fn bytes(self) -> usize {
+        match self {
+            SampleFormat::U8 => 1,
+            SampleFormat::S16Le => 2,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SampleFormat::U8 => "u8",
+            SampleFormat::S16Le => "s16le",
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> i16 {
+        match self {
+            SampleFormat::U8 => ((bytes[0] as i16) - 128) << 8,
+            SampleFormat::S16Le => i16::from_le_bytes([bytes[0], bytes[1]]),
+        }
+    }
+
+    fn encode(self, sample: i16, out: &mut [u8]) {
+        match self {
+            SampleFormat::U8 => out[0] = ((sample >> 8) + 128) as u8,
+            SampleFormat::S16Le => {
+                out[..2].copy_from_slice(&sample.to_le_bytes())
+            },
+        }
+    }
+}
+
+/// where recording comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordSource {
+    Mic,
+    LineIn,
+}
+
+/// what the mixer needs from a sound card. everything goes in and out as
+/// 16 bit stereo at `rate`.
+pub struct AudioCard {
+    pub name: &'static str,
+    pub rate: fn() -> u32,
+    /// card buffers written but not played yet
+    pub queued: fn() -> usize,
+    /// queues as much as fits and returns the bytes taken
+    pub write: fn(&[u8]) -> usize,
+    /// sleeps until fewer than this many buffers are queued
+    pub wait_queued_below: fn(usize),
+    pub master_volume: fn() -> usize,
+    pub set_master_volume: fn(usize),
+    /// cards that can't record leave this out
+    pub capture: Option<AudioCapture>,
+}
+
+pub struct AudioCapture {
+    /// copies out whatever has been recorded, 0 if nothing yet
+    pub read: fn(&mut [u8]) -> usize,
+    pub wait_readable: fn(),
+    pub source: fn() -> RecordSource,
+    pub set_source: fn(RecordSource),
+    pub gain: fn() -> usize,
+    pub set_gain: fn(usize),
+}
+
+static CARD: Mutex<Option<&'static AudioCard>> = Mutex::new(None);
+
+/// makes the card the one /dev/audio plays on, only one at a time
+pub fn register_card(card: &'static AudioCard) -> Result<(), &'static str> {
+    let mut current = CARD.lock();
+    if current.is_some() {
+        return Err("another sound card is already in use");
+    }
+
+    log::info!("audio: playing through {}", card.name);
+    *current = Some(card);
+    Ok(())
+}
+
+pub fn unregister_card(card: &'static AudioCard) {
+    let mut current = CARD.lock();
+    if current.is_some_and(|current| core::ptr::eq(current, card)) {
+        *current = None;
+    }
+}
+
+pub fn card() -> Option<&'static AudioCard> { *CARD.lock() }
+
+type Frame = [i16; 2];
+
+/// linear interpolation between two rates, one frame at a time
+#[derive(Default)]
+struct Resampler {
+    /// how far we are between `prev` and the next input, 32.32 fixed point
+    phase: u64,
+    prev: Frame,
+}
+
+impl Resampler {
+    fn push(
+        &mut self,
+        from: u32,
+        to: u32,
+        frame: Frame,
+        out: &mut VecDeque<Frame>,
+    ) {
+        let step = (u64::from(from) << 32) / u64::from(to);
+
+        while self.phase < 1 << 32 {
+            // a full swing times a 16 bit fraction doesn't fit an i32
+            let t = (self.phase >> 16) as i64;
+            let lerp = |a: i16, b: i16| {
+                (i64::from(a) + (((i64::from(b) - i64::from(a)) * t) >> 16))
+                    as i16
+            };
+            out.push_back([
+                lerp(self.prev[0], frame[0]),
+                lerp(self.prev[1], frame[1]),
+            ]);
+            self.phase += step;
+        }
+
+        self.phase -= 1 << 32;
+        self.prev = frame;
+    }
+}
+
+/// one open of /dev/audio
+struct Stream {
+    id: usize,
+    pid: u64,
+    rate: u32,
+    channels: usize,
+    format: SampleFormat,
+    volume: usize,
+    /// a frame split across two writes
+    partial: [u8; 4],
+    partial_len: usize,
+    /// already at the card's rate, waiting to be mixed
+    queue: VecDeque<Frame>,
+    resampler: Resampler,
+    /// recorded frames at the stream's rate, waiting to be read
+    captured: VecDeque<Frame>,
+    capture_resampler: Resampler,
+}
+
+impl Stream {
+    fn frame_size(&self) -> usize { self.channels * self.format.bytes() }
+
+    fn decode(&self, bytes: &[u8]) -> Frame {
+        let left = self.format.decode(bytes);
+        if self.channels == 2 {
+            [left, self.format.decode(&bytes[self.format.bytes()..])]
+        } else {
+            [left, left]
+        }
+    }
+
+    fn encode(&self, frame: Frame, out: &mut [u8]) {
+        if self.channels == 2 {
+            self.format.encode(frame[0], out);
+            self.format.encode(frame[1], &mut out[self.format.bytes()..]);
+        } else {
+            let mono = (i32::from(frame[0]) + i32::from(frame[1])) / 2;
+            self.format.encode(mono as i16, out);
+        }
+    }
+
+    /// takes as much of `buf` as fits in the queue, returns bytes taken
+    fn push(&mut self, buf: &[u8], hw_rate: u32) -> usize {
+        let frame_size = self.frame_size();
+        let mut consumed = 0;
+
+        while self.queue.len() < STREAM_QUEUE_FRAMES && consumed < buf.len() {
+            let take =
+                (frame_size - self.partial_len).min(buf.len() - consumed);
+            self.partial[self.partial_len..self.partial_len + take]
+                .copy_from_slice(&buf[consumed..consumed + take]);
+            self.partial_len += take;
+            consumed += take;
+
+            if self.partial_len < frame_size {
+                break;
+            }
+            self.partial_len = 0;
+
+            let frame = self.decode(&self.partial[..frame_size]);
+            self.resampler.push(self.rate, hw_rate, frame, &mut self.queue);
+        }
+
+        consumed
+    }
+
+    /// hands out recorded frames in the stream's format
+    fn pop_captured(&mut self, buf: &mut [u8]) -> usize {
+        let frame_size = self.frame_size();
+        let mut read = 0;
+
+        while read + frame_size <= buf.len() {
+            let Some(frame) = self.captured.pop_front() else {
+                break;
+            };
+            self.encode(frame, &mut buf[read..read + frame_size]);
+            read += frame_size;
+        }
+
+        read
+    }
+}
+
+/// what /dev/mixer shows for each stream
+pub struct StreamInfo {
+    pub id: usize,
+    pub pid: u64,
+    pub rate: u32,
+    pub channels: usize,
+    pub format: SampleFormat,
+    pub volume: usize,
+    pub queued: usize,
+}
+
+struct Mixer {
+    streams: Vec<Stream>,
+    next_id: usize,
+    /// mixed already but the card didn't take it yet, goes out first
+    pending: Vec<u8>,
+}
+
+static MIXER: Mutex<Mixer> =
+    Mutex::new(Mixer { streams: Vec::new(), next_id: 0, pending: Vec::new() });
+
+impl Mixer {
+    fn stream(&mut self, id: usize) -> Result<&mut Stream, &'static str> {
+        self.streams
+            .iter_mut()
+            .find(|stream| stream.id == id)
+            .ok_or("no such stream")
+    }
+
+    /// sums whatever the streams have queued into the card, as long as it's
+    /// not too far ahead already
+    fn pump(&mut self) {
+        let Some(card) = card() else {
+            self.pending.clear();
+            return;
+        };
+
+        if !self.pending.is_empty() {
+            let taken = (card.write)(&self.pending);
+            self.pending.drain(..taken);
+            if !self.pending.is_empty() {
+                return;
+            }
+        }
+
+        let mut mix: Vec<[i32; 2]> = Vec::with_capacity(MIX_FRAMES);
+        let mut out: Vec<u8> = Vec::with_capacity(MIX_FRAMES * 4);
+
+        while (card.queued)() < MIX_AHEAD_BUFFERS {
+            // streams that are behind just come in a bit later
+            let frames = self
+                .streams
+                .iter()
+                .map(|stream| stream.queue.len())
+                .max()
+                .unwrap_or(0)
+                .min(MIX_FRAMES);
+            if frames == 0 {
+                break;
+            }
+
+            mix.clear();
+            mix.resize(frames, [0; 2]);
+            for stream in &mut self.streams {
+                let volume = stream.volume as i32;
+                let count = frames.min(stream.queue.len());
+                for (mixed, frame) in
+                    mix.iter_mut().zip(stream.queue.drain(..count))
+                {
+                    mixed[0] += i32::from(frame[0]) * volume / 100;
+                    mixed[1] += i32::from(frame[1]) * volume / 100;
+                }
+            }
+
+            out.clear();
+            for frame in &mix {
+                for sample in frame {
+                    let clipped =
+                        (*sample).clamp(i16::MIN as i32, i16::MAX as i32);
+                    out.extend_from_slice(&(clipped as i16).to_le_bytes());
+                }
+            }
+
+            // the streams have been drained, so keep what didn't fit
+            let taken = (card.write)(&out);
+            if taken < out.len() {
+                self.pending.extend_from_slice(&out[taken..]);
+                break;
+            }
+        }
+    }
+}
+
+/// rate everything gets mixed at
+fn hw_rate() -> u32 { card().map(|card| (card.rate)()).unwrap_or(MAX_RATE) }
+
+/// new stream in the card's native format at full volume
+pub fn open() -> usize {
+    let mut mixer = MIXER.lock();
+    let id = mixer.next_id;
+    mixer.next_id += 1;
+
+    mixer.streams.push(Stream {
+        id,
+        pid: proc::current_id().unwrap_or(0),
+        rate: MAX_RATE,
+        channels: 2,
+        format: SampleFormat::S16Le,
+        volume: 100,
+        partial: [0; 4],
+        partial_len: 0,
+        queue: VecDeque::new(),
+        resampler: Resampler::default(),
+        captured: VecDeque::new(),
+        capture_resampler: Resampler::default(),
+    });
+    id
+}
+
+/// drops the stream, anything it still had queued is lost
+pub fn close(id: usize) {
+    MIXER.lock().streams.retain(|stream| stream.id != id);
+}
+
+/// queues all of `buf` on the stream, sleeping while its queue is full.
+/// it only returns once everything has been mixed into the card, so
+/// closing right after doesn't cut off the end.
+pub fn write(id: usize, buf: &[u8]) -> usize {
+    let hw_rate = hw_rate();
+    let mut written = 0;
+
+    loop {
+        let drained = {
+            let mut mixer = MIXER.lock();
+            let Ok(stream) = mixer.stream(id) else {
+                return written;
+            };
+            written += stream.push(&buf[written..], hw_rate);
+            mixer.pump();
+            mixer.pending.is_empty()
+                && mixer.stream(id).is_ok_and(|stream| stream.queue.is_empty())
+        };
+
+        let Some(card) = card() else {
+            return written;
+        };
+        if written >= buf.len() && drained {
+            return written;
+        }
+        (card.wait_queued_below)(MIX_AHEAD_BUFFERS);
+    }
+}
+
+/// reads recorded audio in the stream's format. capture isn't shared,
+/// whichever stream reads first gets what the card had.
+pub fn read(id: usize, buf: &mut [u8]) -> Result<usize, &'static str> {
+    let hw_rate = hw_rate();
+    let mut raw = [0u8; 4096];
+
+    loop {
+        let capture = card()
+            .ok_or("no sound card")?
+            .capture
+            .as_ref()
+            .ok_or("sound card can't record")?;
+
+        {
+            let mut mixer = MIXER.lock();
+            let stream = mixer.stream(id)?;
+            if buf.len() < stream.frame_size() {
+                return Ok(0);
+            }
+
+            if stream.captured.is_empty() {
+                let len = (capture.read)(&mut raw);
+                for frame in raw[..len].as_chunks::<4>().0 {
+                    let frame = [
+                        i16::from_le_bytes([frame[0], frame[1]]),
+                        i16::from_le_bytes([frame[2], frame[3]]),
+                    ];
+                    stream.capture_resampler.push(
+                        hw_rate,
+                        stream.rate,
+                        frame,
+                        &mut stream.captured,
+                    );
+                }
+            }
+
+            let read = stream.pop_captured(buf);
+            if read > 0 {
+                return Ok(read);
+            }
+        }
+
+        (capture.wait_readable)();
+    }
+}
+
+pub fn set_rate(id: usize, rate: u32) -> Result<(), &'static str> {
+    if !(MIN_RATE..=MAX_RATE).contains(&rate) {
+        return Err("sample rate out of range");
+    }
+    MIXER.lock().stream(id)?.rate = rate;
+    Ok(())
+}
+
+pub fn set_channels(id: usize, channels: usize) -> Result<(), &'static str> {
+    if !(1..=2).contains(&channels) {
+        return Err("only mono and stereo are supported");
+    }
+    let mut mixer = MIXER.lock();
+    let stream = mixer.stream(id)?;
+    stream.channels = channels;
+    stream.partial_len = 0;
+    Ok(())
+}
+
+pub fn set_format(id: usize, format: SampleFormat) -> Result<(), &'static str> {
+    let mut mixer = MIXER.lock();
+    let stream = mixer.stream(id)?;
+    stream.format = format;
+    stream.partial_len = 0;
+    Ok(())
+}
+
+pub fn set_volume(id: usize, volume: usize) -> Result<(), &'static str> {
+    if volume > 100 {
+        return Err("volume goes from 0 to 100");
+    }
+    MIXER.lock().stream(id)?.volume = volume;
+    Ok(())
+}
+
+pub fn info(id: usize) -> Option<StreamInfo> {
+    streams().into_iter().find(|stream| stream.id == id)
+}
+
+/// every open stream, oldest first
+pub fn streams() -> Vec<StreamInfo> {
+    MIXER
+        .lock()
+        .streams
+        .iter()
+        .map(|stream| StreamInfo {
+            id: stream.id,
+            pid: stream.pid,
+            rate: stream.rate,
+            channels: stream.channels,
+            format: stream.format,
+            volume: stream.volume,
+            queued: stream.queue.len(),
+        })
+        .collect()
+}
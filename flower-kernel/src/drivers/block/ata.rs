@@ -0,0 +1,64 @@
+use alloc::string::String;
+
+// the commands both the ahci and the pio driver send
+pub const ATA_CMD_IDENTIFY: u8 = 0xEC;
+pub const ATA_CMD_READ_DMA_EXT: u8 = 0x25;
+pub const ATA_CMD_WRITE_DMA_EXT: u8 = 0x35;
+pub const ATA_CMD_READ_PIO: u8 = 0x20;
+pub const ATA_CMD_READ_PIO_EXT: u8 = 0x24;
+pub const ATA_CMD_WRITE_PIO: u8 = 0x30;
+pub const ATA_CMD_WRITE_PIO_EXT: u8 = 0x34;
+pub const ATA_CMD_FLUSH_CACHE: u8 = 0xE7;
+pub const ATA_CMD_FLUSH_CACHE_EXT: u8 = 0xEA;
+
+/// a logical sector bigger than this isn't a disk we believe in
+pub const MAX_SECTOR_SIZE: usize = 64 * 1024;
+
+/// what IDENTIFY says about a disk
+pub struct Identity {
+    pub model: String,
+    pub sectors: u64,
+    pub sector_size: usize,
+    pub lba48: bool,
+}
+
+impl Identity {
+    /// strings are space padded with the bytes of each word swapped
+    pub fn parse(words: &[u16; 256]) -> Result<Self, &'static str> {
+        let model: String = words[27..47]
+            .iter()
+            .flat_map(|word| [(word >> 8) as u8 as char, *word as u8 as char])
+            .collect();
+
+        let lba48 = words[83] & (1 << 10) != 0;
+        let sectors = if lba48 {
+            (0..4).fold(0u64, |acc, i| {
+                acc | (u64::from(words[100 + i]) << (16 * i))
+            })
+        } else {
+            u64::from(words[60]) | (u64::from(words[61]) << 16)
+        };
+
+        // word 106 says if the logical sector is bigger than 512 bytes
+        let sector_size =
+            if words[106] & 0xC000 == 0x4000 && words[106] & (1 << 12) != 0 {
+                2 * (usize::from(words[117]) | (usize::from(words[118]) << 16))
+            } else {
+                512
+            };
+
+        // everything divides buffers by it and counts chunks in it
+        if !sector_size.is_power_of_two()
+            || !(512..=MAX_SECTOR_SIZE).contains(&sector_size)
+        {
+            return Err("bad sector size");
+        }
+
+        Ok(Self {
+            model: String::from(model.trim()),
+            sectors,
+            sector_size,
+            lba48,
+        })
+    }
+}
@@ -0,0 +1,309 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+use spin::Mutex;
+
+use self::partition::Partition;
+use crate::system::cache::{self, BLOCK_SIZE, Backing};
+
+pub mod ata;
+pub mod partition;
+
+/// something that stores data in fixed size sectors, disks mostly
+pub trait BlockDevice: Send + Sync {
+    /// bytes per sector, reads and writes go in whole sectors
+    fn sector_size(&self) -> usize;
+
+    /// how many sectors the device has
+    fn sectors(&self) -> u64;
+
+    /// fills `buf` from `sector` onwards, `buf` is a multiple of the sector size
+    fn read(&self, sector: u64, buf: &mut [u8]) -> Result<(), &'static str>;
+
+    /// writes `buf` from `sector` onwards, `buf` is a multiple of the sector size
+    fn write(&self, sector: u64, buf: &[u8]) -> Result<(), &'static str>;
+
+    /// makes sure everything written so far is on the disk
+    fn flush(&self) -> Result<(), &'static str>;
+
+    fn read_only(&self) -> bool { false }
+
+    /// size of the whole device in bytes
+    fn size(&self) -> u64 { self.sectors() * self.sector_size() as u64 }
+}
+
+/// a registered device, everything but the drivers goes thru this so reads
+/// and writes hit the block cache
+pub struct Disk {
+    pub name: String,
+    pub dev: Arc<dyn BlockDevice>,
+    /// set if this is a partition of `dev` rather than all of it
+    pub partition: Option<Partition>,
+    /// where this starts on `dev` and how long it is, in bytes
+    start: u64,
+    size: u64,
+    /// owner id in the block cache, partitions share it with their disk
+    cache: usize,
+}
+
+/// feeds the cache straight from the device
+struct DiskBacking(Arc<dyn BlockDevice>);
+
+impl Backing for DiskBacking {
+    fn read_block(
+        &self,
+        block: u64,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        let offset = block * BLOCK_SIZE as u64;
+        let len = self.0.size().saturating_sub(offset).min(buf.len() as u64);
+        buf[len as usize..].fill(0);
+        read_at(self.0.as_ref(), offset, &mut buf[..len as usize])
+    }
+
+    fn write_block(&self, block: u64, buf: &[u8]) -> Result<(), &'static str> {
+        let offset = block * BLOCK_SIZE as u64;
+        let len = self.0.size().saturating_sub(offset).min(buf.len() as u64);
+        write_at(self.0.as_ref(), offset, &buf[..len as usize])
+    }
+
+    fn flush(&self) -> Result<(), &'static str> {
+        if self.0.read_only() {
+            return Ok(());
+        }
+        self.0.flush()
+    }
+}
+
+impl Disk {
+    fn check_range(&self, offset: u64, len: usize) -> Result<(), &'static str> {
+        match offset.checked_add(len as u64) {
+            Some(end) if end <= self.size => Ok(()),
+            _ => Err("past the end of the device"),
+        }
+    }
+
+    pub fn size(&self) -> u64 { self.size }
+
+    pub fn sector_size(&self) -> usize { self.dev.sector_size() }
+
+    pub fn read_only(&self) -> bool { self.dev.read_only() }
+
+    /// reads from any byte offset thru the cache
+    pub fn read_at(
+        &self,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        self.check_range(offset, buf.len())?;
+
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = self.start + offset + done as u64;
+            let skip = (pos % BLOCK_SIZE as u64) as usize;
+            let len = (buf.len() - done).min(BLOCK_SIZE - skip);
+            cache::read(
+                self.cache,
+                pos / BLOCK_SIZE as u64,
+                skip,
+                &mut buf[done..done + len],
+            )?;
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// writes at any byte offset into the cache, the disk sees it on the
+    /// next writeback or sync
+    pub fn write_at(
+        &self,
+        offset: u64,
+        buf: &[u8],
+    ) -> Result<(), &'static str> {
+        if self.read_only() {
+            return Err("device is read only");
+        }
+        self.check_range(offset, buf.len())?;
+
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = self.start + offset + done as u64;
+            let skip = (pos % BLOCK_SIZE as u64) as usize;
+            let len = (buf.len() - done).min(BLOCK_SIZE - skip);
+            cache::write(
+                self.cache,
+                pos / BLOCK_SIZE as u64,
+                skip,
+                &buf[done..done + len],
+            )?;
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// writes back whatever is dirty and flushes the device
+    pub fn flush(&self) -> Result<(), &'static str> {
+        cache::sync_owner(self.cache)
+    }
+}
+
+/// every registered disk, by the name it shows up as in /dev
+static DEVICES: Mutex<Vec<Arc<Disk>>> = Mutex::new(Vec::new());
+
+/// adds a device under the first free `<prefix><letter>` name, vda, vdb...
+pub fn register(
+    prefix: &str,
+    dev: Arc<dyn BlockDevice>,
+) -> Result<String, &'static str> {
+    let mut devices = DEVICES.lock();
+    let name = ('a'..='z')
+        .map(|letter| format!("{}{}", prefix, letter))
+        .find(|name| devices.iter().all(|disk| disk.name != *name))
+        .ok_or("out of device names")?;
+
+    log::info!(
+        "block: {} is {} sectors of {} bytes ({} MiB){}",
+        name,
+        dev.sectors(),
+        dev.sector_size(),
+        dev.size() / (1024 * 1024),
+        if dev.read_only() { ", read only" } else { "" }
+    );
+    let cache = cache::register(Arc::new(DiskBacking(dev.clone())));
+    let disk = Arc::new(Disk {
+        name: name.clone(),
+        partition: None,
+        start: 0,
+        size: dev.size(),
+        dev,
+        cache,
+    });
+    devices.push(disk.clone());
+    drop(devices);
+
+    // partitions show up as disks of their own, vda1, vda2...
+    let sector_size = disk.sector_size() as u64;
+    for part in partition::scan(&disk) {
+        let name = format!("{}{}", disk.name, part.number);
+        log::info!(
+            "block: {} is sectors {}..{} of {}",
+            name,
+            part.start,
+            part.start + part.sectors,
+            disk.name
+        );
+        DEVICES.lock().push(Arc::new(Disk {
+            name,
+            dev: disk.dev.clone(),
+            start: part.start * sector_size,
+            size: part.sectors * sector_size,
+            partition: Some(part),
+            cache,
+        }));
+    }
+
+    Ok(name)
+}
+
+/// drops the disk and its partitions, writing back anything it still had
+/// cached first
+pub fn unregister(name: &str) {
+    let mut devices = DEVICES.lock();
+    let Some(disk) = devices.iter().find(|disk| disk.name == name).cloned()
+    else {
+        return;
+    };
+    devices.retain(|other| other.cache != disk.cache);
+    drop(devices);
+
+    cache::unregister(disk.cache);
+}
+
+pub fn get(name: &str) -> Option<Arc<Disk>> {
+    DEVICES.lock().iter().find(|disk| disk.name == name).cloned()
+}
+
+pub fn devices() -> Vec<Arc<Disk>> { DEVICES.lock().clone() }
+
+fn check_range(
+    dev: &dyn BlockDevice,
+    offset: u64,
+    len: usize,
+) -> Result<(), &'static str> {
+    match offset.checked_add(len as u64) {
+        Some(end) if end <= dev.size() => Ok(()),
+        _ => Err("past the end of the device"),
+    }
+}
+
+/// reads from any byte offset, the sectors on the edges get read whole.
+/// goes straight to the device, skipping the cache
+fn read_at(
+    dev: &dyn BlockDevice,
+    offset: u64,
+    buf: &mut [u8],
+) -> Result<(), &'static str> {
+    check_range(dev, offset, buf.len())?;
+    let sector_size = dev.sector_size() as u64;
+
+    let mut done = 0;
+    while done < buf.len() {
+        let pos = offset + done as u64;
+        let (sector, skip) = (pos / sector_size, (pos % sector_size) as usize);
+        let rest = &mut buf[done..];
+
+        // aligned runs go straight into the caller's buffer
+        if skip == 0 && rest.len() >= sector_size as usize {
+            let len = rest.len() - rest.len() % sector_size as usize;
+            dev.read(sector, &mut rest[..len])?;
+            done += len;
+            continue;
+        }
+
+        let mut tmp = vec![0u8; sector_size as usize];
+        dev.read(sector, &mut tmp)?;
+        let len = rest.len().min(tmp.len() - skip);
+        rest[..len].copy_from_slice(&tmp[skip..skip + len]);
+        done += len;
+    }
+
+    Ok(())
+}
+
+/// writes at any byte offset, partial sectors are read, patched and written
+fn write_at(
+    dev: &dyn BlockDevice,
+    offset: u64,
+    buf: &[u8],
+) -> Result<(), &'static str> {
+    if dev.read_only() {
+        return Err("device is read only");
+    }
+    check_range(dev, offset, buf.len())?;
+    let sector_size = dev.sector_size() as u64;
+
+    let mut done = 0;
+    while done < buf.len() {
+        let pos = offset + done as u64;
+        let (sector, skip) = (pos / sector_size, (pos % sector_size) as usize);
+        let rest = &buf[done..];
+
+        if skip == 0 && rest.len() >= sector_size as usize {
+            let len = rest.len() - rest.len() % sector_size as usize;
+            dev.write(sector, &rest[..len])?;
+            done += len;
+            continue;
+        }
+
+        let mut tmp = vec![0u8; sector_size as usize];
+        dev.read(sector, &mut tmp)?;
+        let len = rest.len().min(tmp.len() - skip);
+        tmp[skip..skip + len].copy_from_slice(&rest[..len]);
+        dev.write(sector, &tmp)?;
+        done += len;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,304 @@
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::Disk;
+use crate::system::bytes::{u16_at, u32_at, u64_at};
+
+const MBR_SIGNATURE: u16 = 0xAA55;
+const MBR_TABLE: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+
+// mbr partition types we care about
+const MBR_EXTENDED_CHS: u8 = 0x05;
+const MBR_EXTENDED_LBA: u8 = 0x0F;
+const MBR_EXTENDED_LINUX: u8 = 0x85;
+const MBR_GPT_PROTECTIVE: u8 = 0xEE;
+
+/// logical partitions are numbered from here, like linux does
+const FIRST_LOGICAL: usize = 5;
+/// a broken ebr chain could loop forever, so stop somewhere
+const MAX_LOGICAL: usize = 64;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+/// anything past this is silly, and would take ages to read
+const GPT_MAX_ENTRIES: usize = 256;
+/// the usual 128 entries of 128 bytes, times a bit of headroom
+const GPT_MAX_TABLE: usize = 64 * 1024;
+
+/// mixed endian like efi does it, the first three fields are little endian
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    fn is_zero(&self) -> bool { self.0.iter().all(|&b| b == 0) }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let b = &self.0;
+        write!(
+            f,
+            "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-",
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            u16::from_le_bytes([b[4], b[5]]),
+            u16::from_le_bytes([b[6], b[7]]),
+            b[8],
+            b[9]
+        )?;
+        for byte in &b[10..] {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum PartitionKind {
+    /// the type byte out of the mbr
+    Mbr(u8),
+    Gpt {
+        typ: Guid,
+        guid: Guid,
+        label: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Partition {
+    /// 1 based, what goes after the disk name
+    pub number: usize,
+    /// first sector and length in sectors of the disk
+    pub start: u64,
+    pub sectors: u64,
+    pub kind: PartitionKind,
+}
+
+fn guid_at(buf: &[u8], at: usize) -> Guid {
+    Guid(buf[at..at + 16].try_into().unwrap())
+}
+
+/// the crc gpt uses, same as zlib's
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc =
+                if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn read_sectors(
+    disk: &Disk,
+    sector: u64,
+    count: usize,
+) -> Result<Vec<u8>, &'static str> {
+    let sector_size = disk.sector_size();
+    let mut buf = vec![0u8; count * sector_size];
+    disk.read_at(sector * sector_size as u64, &mut buf)?;
+    Ok(buf)
+}
+
+/// one of the four 16 byte entries in an mbr or ebr, None if it's unused
+fn mbr_entry(sector: &[u8], index: usize) -> Option<(u8, u64, u64)> {
+    let entry = &sector[MBR_TABLE + index * MBR_ENTRY_SIZE..];
+    let (typ, start, sectors) = (entry[4], u32_at(entry, 8), u32_at(entry, 12));
+    if typ == 0 || sectors == 0 {
+        return None;
+    }
+    Some((typ, u64::from(start), u64::from(sectors)))
+}
+
+fn is_extended(typ: u8) -> bool {
+    matches!(typ, MBR_EXTENDED_CHS | MBR_EXTENDED_LBA | MBR_EXTENDED_LINUX)
+}
+
+/// walks the ebr chain, every ebr has the partition first and a link to
+/// the next ebr second, both relative to different things
+fn parse_logical(
+    disk: &Disk,
+    extended: u64,
+    partitions: &mut Vec<Partition>,
+) -> Result<(), &'static str> {
+    let mut ebr = extended;
+    for number in FIRST_LOGICAL..FIRST_LOGICAL + MAX_LOGICAL {
+        let sector = read_sectors(disk, ebr, 1)?;
+        if u16_at(&sector, 510) != MBR_SIGNATURE {
+            return Err("bad ebr signature");
+        }
+
+        // the partition is relative to this ebr
+        if let Some((typ, start, sectors)) = mbr_entry(&sector, 0) {
+            partitions.push(Partition {
+                number,
+                start: ebr + start,
+                sectors,
+                kind: PartitionKind::Mbr(typ),
+            });
+        }
+
+        // and the next ebr is relative to the start of the extended one
+        match mbr_entry(&sector, 1) {
+            Some((_, next, _)) => ebr = extended + next,
+            None => return Ok(()),
+        }
+    }
+
+    Err("ebr chain too long")
+}
+
+/// None if sector 0 isn't an mbr, Some(empty) if it's a protective one
+fn parse_mbr(disk: &Disk) -> Result<Option<Vec<Partition>>, &'static str> {
+    let sector = read_sectors(disk, 0, 1)?;
+    if u16_at(&sector, 510) != MBR_SIGNATURE {
+        return Ok(None);
+    }
+
+    // fat boot sectors end in 55aa too, the boot flag tells them apart
+    if (0..4)
+        .any(|i| !matches!(sector[MBR_TABLE + i * MBR_ENTRY_SIZE], 0x00 | 0x80))
+    {
+        return Ok(None);
+    }
+
+    let mut partitions = Vec::new();
+    for index in 0..4 {
+        let Some((typ, start, sectors)) = mbr_entry(&sector, index) else {
+            continue;
+        };
+
+        if typ == MBR_GPT_PROTECTIVE {
+            return Ok(Some(Vec::new()));
+        }
+        if is_extended(typ) {
+            if let Err(e) = parse_logical(disk, start, &mut partitions) {
+                log::warn!("{}: logical partitions: {}", disk.name, e);
+            }
+            continue;
+        }
+
+        partitions.push(Partition {
+            number: index + 1,
+            start,
+            sectors,
+            kind: PartitionKind::Mbr(typ),
+        });
+    }
+
+    Ok(Some(partitions))
+}
+
+/// None if there's no valid gpt header at lba 1
+fn parse_gpt(disk: &Disk) -> Result<Option<Vec<Partition>>, &'static str> {
+    let sector_size = disk.sector_size();
+    let mut header = read_sectors(disk, 1, 1)?;
+    if &header[..8] != GPT_SIGNATURE {
+        return Ok(None);
+    }
+
+    let header_size = u32_at(&header, 12) as usize;
+    if !(92..=sector_size).contains(&header_size) {
+        return Err("bad gpt header size");
+    }
+    let header_crc = u32_at(&header, 16);
+    header[16..20].fill(0);
+    if crc32(&header[..header_size]) != header_crc {
+        return Err("bad gpt header crc");
+    }
+
+    let entries_lba = u64_at(&header, 72);
+    let count = u32_at(&header, 80) as usize;
+    let entry_size = u32_at(&header, 84) as usize;
+    let entries_crc = u32_at(&header, 88);
+    if !entry_size.is_power_of_two()
+        || !(128..=sector_size).contains(&entry_size)
+        || count > GPT_MAX_ENTRIES
+        || count * entry_size > GPT_MAX_TABLE
+    {
+        return Err("bad gpt entry table");
+    }
+
+    let table_sectors = (count * entry_size).div_ceil(sector_size);
+    let table = read_sectors(disk, entries_lba, table_sectors)?;
+    if crc32(&table[..count * entry_size]) != entries_crc {
+        return Err("bad gpt entry table crc");
+    }
+
+    let mut partitions = Vec::new();
+    for (index, entry) in
+        table[..count * entry_size].chunks(entry_size).enumerate()
+    {
+        let typ = guid_at(entry, 0);
+        if typ.is_zero() {
+            continue;
+        }
+
+        let (first, last) = (u64_at(entry, 32), u64_at(entry, 40));
+        if last < first {
+            log::warn!(
+                "{}: gpt entry {} ends before it starts",
+                disk.name,
+                index
+            );
+            continue;
+        }
+
+        // utf-16, nul padded
+        let units = entry[56..128]
+            .chunks(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .take_while(|&unit| unit != 0);
+        let label = char::decode_utf16(units)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect();
+
+        partitions.push(Partition {
+            number: index + 1,
+            start: first,
+            sectors: last - first + 1,
+            kind: PartitionKind::Gpt { typ, guid: guid_at(entry, 16), label },
+        });
+    }
+
+    Ok(Some(partitions))
+}
+
+/// reads the partition table off a whole disk, gpt wins over mbr. a disk
+/// without one (or with a broken one) just has no partitions
+pub fn scan(disk: &Disk) -> Vec<Partition> {
+    let gpt = parse_gpt(disk).unwrap_or_else(|e| {
+        log::warn!("{}: {}", disk.name, e);
+        None
+    });
+    let mut partitions = match gpt {
+        Some(partitions) => partitions,
+        None => match parse_mbr(disk) {
+            Ok(partitions) => partitions.unwrap_or_default(),
+            Err(e) => {
+                log::warn!("{}: {}", disk.name, e);
+                Vec::new()
+            },
+        },
+    };
+
+    let sectors = disk.size() / disk.sector_size() as u64;
+    partitions.retain(|part| {
+        let fits = part
+            .start
+            .checked_add(part.sectors)
+            .is_some_and(|end| part.start > 0 && end <= sectors);
+        if !fits {
+            log::warn!(
+                "{}: partition {} is off the disk",
+                disk.name,
+                part.number
+            );
+        }
+        fits
+    });
+    partitions
+}
@@ -1,3 +1,7 @@
+pub mod audio;
+pub mod block;
 pub mod pci;
 pub mod ps2;
+pub mod rtc;
 pub mod tty;
+pub mod video;
@@ -1,15 +1,19 @@
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
 
 use pci_types::ConfigRegionAccess;
 use spin::MutexGuard;
 use spin::mutex::Mutex;
-use x86_64::VirtAddr;
 use x86_64::instructions::port::Port;
 use x86_64::structures::paging::PageTableFlags;
+use x86_64::{PhysAddr, VirtAddr};
 
+use crate::drivers::audio::{self, AudioCapture, AudioCard, RecordSource};
+use crate::drivers::pci::driver::{PciDriver, PciMatch};
 use crate::drivers::pci::io::PciIO;
-use crate::drivers::pci::parser::PciBus;
+use crate::drivers::pci::msi;
+use crate::drivers::pci::parser::PciDevice;
 use crate::system;
+use crate::system::proc::WaitQueue;
 
 #[repr(C, packed)]
 struct BDL_Entry {
@@ -25,8 +29,72 @@ const AC97_BDL_VIRT_BASE: u64 = 0xFFFF_FF00_0100_0000;
 const AC97_BUFFER_VIRT_BASE: u64 = 0xFFFF_FF00_0101_0000;
 const AC97_BUFFER_STRIDE: u64 = 0x1000;
 
+// capture gets its own ring right after the playback one
+const AC97_CAPTURE_BDL_VIRT_BASE: u64 = 0xFFFF_FF00_0104_0000;
+const AC97_CAPTURE_BUFFER_VIRT_BASE: u64 = 0xFFFF_FF00_0105_0000;
+
+// mixer registers
+const NAM_RESET: u16 = 0x00;
+const NAM_MASTER_VOLUME: u16 = 0x02;
+const NAM_MIC_VOLUME: u16 = 0x0E;
+const NAM_PCM_VOLUME: u16 = 0x18;
+const NAM_RECORD_SELECT: u16 = 0x1A;
+const NAM_RECORD_GAIN: u16 = 0x1C;
+const NAM_FRONT_DAC_RATE: u16 = 0x2C;
+const NAM_ADC_RATE: u16 = 0x32;
+
+/// mute bit shared by the volume and gain registers
+const NAM_MUTE: u16 = 1 << 15;
+/// +20db on the mic, it's very quiet without it
+const MIC_BOOST: u16 = 1 << 6;
+
+// pcm in box on the bus master
+const PI_BDBAR: u16 = 0x00;
+const PI_CIV: u16 = 0x04;
+const PI_LVI: u16 = 0x05;
+const PI_SR: u16 = 0x06;
+const PI_CR: u16 = 0x0B;
+
+// pcm out box on the bus master
+const PO_BDBAR: u16 = 0x10;
+const PO_CIV: u16 = 0x14;
+const PO_LVI: u16 = 0x15;
+const PO_SR: u16 = 0x16;
+const PO_CR: u16 = 0x1B;
+
+const CR_RUN: u8 = 1 << 0;
+const CR_RESET: u8 = 1 << 1;
+const CR_LVBIE: u8 = 1 << 2;
+const CR_FEIE: u8 = 1 << 3;
+const CR_IOCE: u8 = 1 << 4;
+
+/// the dma engine stopped, for capture that means we fell behind
+const SR_DCH: u16 = 1 << 0;
+const SR_LVBCI: u16 = 1 << 2;
+const SR_BCIS: u16 = 1 << 3;
+const SR_FIFOE: u16 = 1 << 4;
+
+/// interrupt once the buffer has been played
+const BDL_IOC: u16 = 1 << 15;
+
+/// the codec always runs at 48k, the mixer resamples everything to it
+const RATE: u32 = 48000;
+
+/// how long a writer sleeps before checking again, in case an interrupt
+/// went missing or the card doesn't have one
+const WAIT_TIMEOUT: u64 = 20;
+
 static AC97_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
+/// the bus master base for the interrupt handler, 0 when there's no card
+static AC97_NABM: AtomicU16 = AtomicU16::new(0);
+
+/// writers waiting for a buffer to free up
+static AC97_WAIT: WaitQueue = WaitQueue::new();
+
+/// readers waiting for a buffer to fill
+static AC97_CAPTURE_WAIT: WaitQueue = WaitQueue::new();
+
 struct AudioBuffer {
     virt: VirtAddr,
     phys: u32,
@@ -34,16 +102,30 @@ struct AudioBuffer {
     data_written: usize,
 }
 
+/// the pcm in side, the card fills buffers and `entry` chases it
+struct Capture {
+    buffers: [AudioBuffer; AC97_BUFFERS],
+    bdl_phys: u32,
+    /// next buffer to hand out and how far into it we are, in bytes
+    entry: usize,
+    offset: usize,
+    running: bool,
+    source: RecordSource,
+    gain: usize,
+}
+
 pub struct Ac97 {
     nam: u16,
     nabm: u16,
     entry: usize,
     buffers: [AudioBuffer; AC97_BUFFERS],
     bdl_virt: VirtAddr,
-    temp_buffer: [u8; AC97_BUFFER_SIZE],
-    temp_count: usize,
     lock: Mutex<()>,
     volume: usize,
+    master_volume: usize,
+    capture: Capture,
+    /// the vector `interrupt` is on, if it got one
+    irq: Option<u8>,
 }
 
 impl Ac97 {
@@ -62,34 +144,84 @@ impl Ac97 {
         unsafe { port.read() }
     }
 
-    pub fn set_volume(&mut self, vol: usize) {
-        assert!(vol <= 100);
-        self.volume = vol;
+    fn nabm_read16(&self, reg: u16) -> u16 {
+        let mut port = Port::<u16>::new(self.nabm + reg);
+        unsafe { port.read() }
+    }
+
+    fn nam_read(&self, reg: u16) -> u16 {
+        let mut port = Port::<u16>::new(self.nam + reg);
+        unsafe { port.read() }
+    }
+
+    /// volume is 0-100, the codec wants attenuation in 1.5db steps
+    fn attenuation(vol: usize) -> u16 {
         let s = if vol == 0 { 31 } else { (31 * vol) / 100 };
         let chan = 31 - s;
-        let combined = (chan as u16) | ((chan as u16) << 8);
-        self.nam_write(0x18, combined);
+        (chan as u16) | ((chan as u16) << 8)
+    }
+
+    /// pcm out volume, anything over 100 is 100
+    pub fn set_volume(&mut self, vol: usize) {
+        let vol = vol.min(100);
+        self.volume = vol;
+        self.nam_write(NAM_PCM_VOLUME, Self::attenuation(vol));
+    }
+
+    pub fn volume(&self) -> usize { self.volume }
+
+    pub fn set_master_volume(&mut self, vol: usize) {
+        let vol = vol.min(100);
+        self.master_volume = vol;
+
+        // all the way down is muted rather than just quiet
+        let mute = if vol == 0 { NAM_MUTE } else { 0 };
+        self.nam_write(NAM_MASTER_VOLUME, Self::attenuation(vol) | mute);
+    }
+
+    pub fn master_volume(&self) -> usize { self.master_volume }
+
+    pub fn set_record_source(&mut self, source: RecordSource) {
+        self.capture.source = source;
+        // the values are what the codec wants in each half
+        let select = match source {
+            RecordSource::Mic => 0,
+            RecordSource::LineIn => 4,
+        };
+        self.nam_write(NAM_RECORD_SELECT, select | (select << 8));
     }
 
+    pub fn record_source(&self) -> RecordSource { self.capture.source }
+
+    /// record gain is 0-100, the codec does 0 to +22.5db in 1.5db steps
+    pub fn set_record_gain(&mut self, gain: usize) {
+        let gain = gain.min(100);
+        self.capture.gain = gain;
+
+        let step = ((15 * gain) / 100) as u16;
+        let mute = if gain == 0 { NAM_MUTE } else { 0 };
+        self.nam_write(NAM_RECORD_GAIN, step | (step << 8) | mute);
+    }
+
+    pub fn record_gain(&self) -> usize { self.capture.gain }
+
     fn flush(&self) {
         if !AC97_INITIALIZED.load(Ordering::SeqCst) {
             return;
         }
 
-        let mut s = self.nabm_read(0x10 + 0xB);
-        if s & 1 != 0 {
+        let mut s = self.nabm_read(PO_CR);
+        if s & CR_RUN != 0 {
             return;
         }
-        s |= 1;
-        self.nabm_write(0x10 + 0xB, s);
+        s |= CR_RUN;
+        self.nabm_write(PO_CR, s);
     }
 
-    pub fn can_write(&self) -> bool {
-        if !AC97_INITIALIZED.load(Ordering::SeqCst) {
-            return false;
-        }
-        let read_ptr = self.nabm_read(0x10 + 0x4);
-        let buffer_left = if self.entry >= read_ptr as usize {
+    /// buffers the card isn't going to play before we get to them
+    fn buffers_left(&self) -> usize {
+        let read_ptr = self.nabm_read(PO_CIV);
+        if self.entry >= read_ptr as usize {
             let mut left = AC97_BUFFERS - self.entry;
             if read_ptr == 0 && left > 0 {
                 left -= 1;
@@ -97,193 +229,488 @@ impl Ac97 {
             left
         } else {
             (read_ptr as usize) - self.entry - 1
-        };
-        buffer_left > 0
+        }
     }
 
-    fn setup_buffers(&mut self) {
+    /// buffers handed to the card that it hasn't finished playing
+    pub fn queued(&self) -> usize {
+        let civ = self.nabm_read(PO_CIV) as usize;
+        (self.entry + AC97_BUFFERS - civ) % AC97_BUFFERS
+    }
+
+    fn setup_buffers(&mut self) -> Result<(), &'static str> {
+        setup_ring(self.bdl_virt, AC97_BUFFER_VIRT_BASE, &mut self.buffers)?;
+
         unsafe {
             let bdl_entries = self.bdl_virt.as_mut_ptr::<BDL_Entry>();
+            bdl_entries.add(AC97_BUFFERS - 1).as_mut().unwrap().flags |= 0x8000;
+        }
+        Ok(())
+    }
 
-            for i in 0..AC97_BUFFERS {
-                let vaddr = VirtAddr::new(
-                    AC97_BUFFER_VIRT_BASE + (i as u64) * AC97_BUFFER_STRIDE,
-                );
-
-                assert!(
-                    !system::mem::vmm::page_is_mapped(vaddr),
-                    "AC97 buffer virt collision at {:#x}",
-                    vaddr.as_u64()
+    /// queues as much of `buf` as there are free buffers for and returns
+    /// how many bytes were taken, 0 means everything is full. it's 16 bit
+    /// stereo at 48k, which is what the mixer hands over.
+    pub fn write(&mut self, buf: &[u8]) -> usize {
+        let _lock = self.lock.lock();
+        let mut consumed = 0;
+
+        while buf.len() - consumed >= 4 && self.buffers_left() > 0 {
+            let len = (buf.len() - consumed).min(AC97_BUFFER_SIZE) & !3;
+            let out = self.buffers[self.entry].virt.as_mut_ptr::<u8>();
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    buf[consumed..].as_ptr(),
+                    out,
+                    len,
                 );
 
-                let phys = system::mem::vmm::page_map_alloc(
-                    vaddr,
-                    PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
-                )
-                .expect("failed to allocate ac97 buffers");
-
-                self.buffers[i] = AudioBuffer {
-                    virt: vaddr,
-                    phys: phys.as_u64() as u32,
-                    has_played: false,
-                    data_written: 0,
-                };
-
-                let entry = BDL_Entry {
-                    addr: phys.as_u64() as u32,
-                    length: (AC97_BUFFER_SIZE / 2) as u16,
-                    flags: 0,
-                };
-                bdl_entries.add(i).write(entry);
+                let bdl_entries = self.bdl_virt.as_mut_ptr::<BDL_Entry>();
+                let entry = bdl_entries.add(self.entry).as_mut().unwrap();
+                // in samples, not bytes
+                entry.length = (len / 2) as u16;
+                entry.flags = BDL_IOC;
             }
 
-            bdl_entries.add(AC97_BUFFERS - 1).as_mut().unwrap().flags |= 0x8000;
+            self.nabm_write(PO_LVI, self.entry as u8);
+            self.entry = (self.entry + 1) % AC97_BUFFERS;
+            consumed += len;
+        }
+
+        if consumed > 0 {
+            self.flush();
         }
+        consumed
     }
 
-    pub fn write_buffer(&mut self, buf: &[u8]) -> usize {
-        assert!(buf.len() < AC97_BUFFER_SIZE);
+    /// buffers the card has filled that we haven't finished reading
+    fn captured(&self) -> usize {
+        if !self.capture.running {
+            return 0;
+        }
 
-        let _lock = self.lock.lock();
-        let read_ptr = self.nabm_read(0x10 + 0x4);
-        let buffer_left = if self.entry >= read_ptr as usize {
-            let mut left = AC97_BUFFERS - self.entry;
-            if read_ptr == 0 && left > 0 {
-                left -= 1;
-            }
-            left
+        let civ = self.nabm_read(PI_CIV) as usize;
+        let filled = (civ + AC97_BUFFERS - self.capture.entry) % AC97_BUFFERS;
+
+        if self.nabm_read16(PI_SR) & SR_DCH == 0 {
+            return filled;
+        }
+
+        // once stopped the current buffer is full as well, unless we've
+        // already read past it
+        if self.capture.entry == (civ + 1) % AC97_BUFFERS {
+            0
         } else {
-            (read_ptr as usize) - self.entry - 1
+            filled + 1
+        }
+    }
+
+    pub fn can_read(&self) -> bool {
+        AC97_INITIALIZED.load(Ordering::SeqCst) && self.captured() > 0
+    }
+
+    /// resets the pcm in box and lets the card fill every buffer. anything
+    /// captured but not read yet is dropped.
+    fn start_capture(&mut self) {
+        let ctrl = self.nabm_read(PI_CR) & !CR_RUN;
+        self.nabm_write(PI_CR, ctrl);
+        self.nabm_write(PI_CR, CR_RESET);
+        while self.nabm_read(PI_CR) & CR_RESET != 0 {
+            core::hint::spin_loop();
+        }
+
+        let mut port = Port::<u32>::new(self.nabm + PI_BDBAR);
+        unsafe { port.write(self.capture.bdl_phys) };
+        self.nabm_write(PI_LVI, (AC97_BUFFERS - 1) as u8);
+
+        self.capture.entry = 0;
+        self.capture.offset = 0;
+        self.capture.running = true;
+
+        let irqs = if AC97_NABM.load(Ordering::SeqCst) != 0 {
+            CR_IOCE | CR_LVBIE | CR_FEIE
+        } else {
+            0
         };
+        self.nabm_write(PI_CR, CR_RUN | irqs);
+    }
 
-        if buffer_left == 0 {
-            panic!("no buffer left");
+    /// copies out as many whole frames as have been captured, 0 means
+    /// nothing is ready yet. the first read starts the recording.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let filled = self.captured();
+        if filled == 0 {
+            // not started yet, or it ran out of room and stopped
+            if !self.capture.running || self.nabm_read16(PI_SR) & SR_DCH != 0 {
+                self.start_capture();
+            }
+            return 0;
         }
 
-        unsafe {
-            let bdl_entries = self.bdl_virt.as_mut_ptr::<BDL_Entry>();
-            core::ptr::write_bytes(
-                self.buffers[self.entry].virt.as_mut_ptr::<u8>(),
-                0,
-                AC97_BUFFER_SIZE,
-            );
-            core::ptr::copy_nonoverlapping(
-                buf.as_ptr(),
-                self.buffers[self.entry].virt.as_mut_ptr::<u8>(),
-                buf.len(),
-            );
+        let mut read = 0;
+
+        for _ in 0..filled {
+            let input =
+                self.capture.buffers[self.capture.entry].virt.as_ptr::<u8>();
+            let len = (AC97_BUFFER_SIZE - self.capture.offset)
+                .min((buf.len() - read) & !3);
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    input.add(self.capture.offset),
+                    buf[read..].as_mut_ptr(),
+                    len,
+                );
+            }
+            self.capture.offset += len;
+            read += len;
+
+            if self.capture.offset < AC97_BUFFER_SIZE {
+                break;
+            }
 
-            let sample_len = buf.len().div_ceil(2) as u16;
-            bdl_entries.add(self.entry).as_mut().unwrap().length = sample_len;
+            // done with it, the card can fill it again
+            self.nabm_write(PI_LVI, self.capture.entry as u8);
+            self.capture.entry = (self.capture.entry + 1) % AC97_BUFFERS;
+            self.capture.offset = 0;
         }
 
-        let lvi = self.entry;
-        self.nabm_write(0x10 + 0x05, lvi as u8);
-        self.entry = (self.entry + 1) % AC97_BUFFERS;
-        self.flush();
-        buf.len()
+        read
     }
 }
 
-static AC97_DRIVER: Mutex<Option<Ac97>> = Mutex::new(None);
+/// maps a fresh page for the card to dma to and from
+fn map_dma(virt: VirtAddr) -> Result<PhysAddr, &'static str> {
+    if system::mem::vmm::page_is_mapped(virt) {
+        log::error!("AC97 virt collision at {:#x}", virt.as_u64());
+        return Err("ac97 dma memory is already mapped");
+    }
 
-pub fn get_driver() -> MutexGuard<'static, Option<Ac97>> { AC97_DRIVER.lock() }
+    system::mem::vmm::page_map_alloc(
+        virt,
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+    )
+    .map_err(|_| "failed to allocate ac97 dma memory")
+}
 
-pub fn install(pci: &PciBus) {
-    if let Some(ac97) = pci.find_by_class(0x04, 0x01) {
-        let nam = ac97.bars[0].unwrap().unwrap_io() as u16;
-        let nabm = ac97.bars[1].unwrap().unwrap_io() as u16;
+/// unmaps a page from `map_dma` and gives it back
+fn unmap_dma(virt: VirtAddr) {
+    if let Ok(phys) = system::mem::vmm::page_unmap(virt) {
+        system::mem::pmm::free(phys.as_u64());
+    }
+}
+
+/// the descriptor list and every buffer of a ring
+fn free_ring(bdl_virt: VirtAddr, buffer_base: u64) {
+    for i in 0..AC97_BUFFERS {
+        unmap_dma(VirtAddr::new(buffer_base + (i as u64) * AC97_BUFFER_STRIDE));
+    }
+    unmap_dma(bdl_virt);
+}
 
-        log::debug!("AC97 found, NAM={:#x}, NABM={:#x}", nam, nabm);
+/// maps a page per buffer and points the descriptor list at them, the
+/// pages mapped so far go back if one fails
+fn setup_ring(
+    bdl_virt: VirtAddr,
+    buffer_base: u64,
+    buffers: &mut [AudioBuffer; AC97_BUFFERS],
+) -> Result<(), &'static str> {
+    let bdl_entries = bdl_virt.as_mut_ptr::<BDL_Entry>();
+
+    for (i, buffer) in buffers.iter_mut().enumerate() {
+        let vaddr =
+            VirtAddr::new(buffer_base + (i as u64) * AC97_BUFFER_STRIDE);
+
+        let phys = match map_dma(vaddr) {
+            Ok(phys) => phys,
+            Err(e) => {
+                for j in 0..i {
+                    unmap_dma(VirtAddr::new(
+                        buffer_base + (j as u64) * AC97_BUFFER_STRIDE,
+                    ));
+                }
+                return Err(e);
+            },
+        };
 
-        let bdl_virt_addr = VirtAddr::new(AC97_BDL_VIRT_BASE);
-        assert!(
-            !system::mem::vmm::page_is_mapped(bdl_virt_addr),
-            "AC97 BDL virt collision at {:#x}",
-            bdl_virt_addr.as_u64()
-        );
+        *buffer = AudioBuffer {
+            virt: vaddr,
+            phys: phys.as_u64() as u32,
+            has_played: false,
+            data_written: 0,
+        };
 
-        let bdl_phys = system::mem::vmm::page_map_alloc(
-            bdl_virt_addr,
-            PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
-        )
-        .expect("failed to allocate ac97 bdl");
-        let bdl_virt = bdl_virt_addr;
-
-        let mut driver = Ac97 {
-            nam,
-            nabm,
-            entry: 0,
-            buffers: unsafe { core::mem::zeroed() },
-            bdl_virt,
-            temp_buffer: [0; AC97_BUFFER_SIZE],
-            temp_count: 0,
-            lock: Mutex::new(()),
-            volume: 50,
+        let entry = BDL_Entry {
+            addr: phys.as_u64() as u32,
+            length: (AC97_BUFFER_SIZE / 2) as u16,
+            flags: 0,
         };
+        unsafe { bdl_entries.add(i).write(entry) };
+    }
+    Ok(())
+}
 
-        unsafe {
-            log::debug!("AC97::MASTERING");
-            let pci_io = PciIO;
-            let mut cmd = pci_io.read(ac97.addr, 0x04);
-            cmd |= 1 << 0;
-            cmd |= 1 << 2;
-            pci_io.write(ac97.addr, 0x04, cmd);
-
-            log::debug!("AC97::INIT");
-            driver.nabm_write(0x2C, 1 << 1);
-
-            log::debug!("AC97::RESET");
-            driver.nam_write(0x00, 1);
-
-            log::debug!("AC97::CAPABILITIES");
-            driver.nam_write(0x2C, 48000);
-            driver.nam_write(0x2E, 48000);
-            driver.nam_write(0x30, 48000);
-            driver.nam_write(0x32, 48000);
-
-            log::debug!("AC97::VOLUME");
-            driver.set_volume(50);
-
-            log::debug!("AC97::PLAY_RESET");
-            driver.nam_write(0x02, 0);
-            driver.nam_write(0x04, 0);
-
-            log::debug!("AC97::RESET_BIT");
-            driver.nabm_write(
-                0x10 + 0xB,
-                driver.nabm_read(0x10 + 0xB) | (1 << 1),
-            );
+/// the capture ring, every buffer interrupts when it's full
+fn setup_capture() -> Result<Capture, &'static str> {
+    let bdl_virt = VirtAddr::new(AC97_CAPTURE_BDL_VIRT_BASE);
+    let bdl_phys = map_dma(bdl_virt)?;
+
+    let mut buffers: [AudioBuffer; AC97_BUFFERS] =
+        unsafe { core::mem::zeroed() };
+    if let Err(e) =
+        setup_ring(bdl_virt, AC97_CAPTURE_BUFFER_VIRT_BASE, &mut buffers)
+    {
+        unmap_dma(bdl_virt);
+        return Err(e);
+    }
 
-            log::debug!("AC97::WAITING");
-            let mut control = Port::<u8>::new(nabm + 0x10 + 0xB);
-            while control.read() & (1 << 0) != 0 {
-                core::hint::spin_loop();
-            }
+    unsafe {
+        let bdl_entries = bdl_virt.as_mut_ptr::<BDL_Entry>();
+        for i in 0..AC97_BUFFERS {
+            bdl_entries.add(i).as_mut().unwrap().flags |= BDL_IOC;
+        }
+    }
 
-            log::debug!("AC97::BUFFER");
-            {
-                driver.setup_buffers();
-            }
+    Ok(Capture {
+        buffers,
+        bdl_phys: bdl_phys.as_u64() as u32,
+        entry: 0,
+        offset: 0,
+        running: false,
+        source: RecordSource::Mic,
+        gain: 0,
+    })
+}
+
+static AC97_DRIVER: Mutex<Option<Ac97>> = Mutex::new(None);
 
-            log::debug!("AC97::BDL_ADDR");
-            {
-                let mut port = Port::<u32>::new(driver.nabm + 0x10);
-                port.write(bdl_phys.as_u64() as u32);
+pub fn get_driver() -> MutexGuard<'static, Option<Ac97>> { AC97_DRIVER.lock() }
+
+/// hooks for the mixer, they all go through the driver lock
+pub static CARD: AudioCard = AudioCard {
+    name: "ac97",
+    rate: || RATE,
+    queued: || get_driver().as_ref().map_or(0, |d| d.queued()),
+    write: |buf| get_driver().as_mut().map_or(0, |d| d.write(buf)),
+    wait_queued_below: |n| wait_playback(|d| d.queued() < n),
+    master_volume: || get_driver().as_ref().map_or(0, |d| d.master_volume()),
+    set_master_volume: |vol| {
+        if let Some(d) = get_driver().as_mut() {
+            d.set_master_volume(vol);
+        }
+    },
+    capture: Some(AudioCapture {
+        read: |buf| get_driver().as_mut().map_or(0, |d| d.read(buf)),
+        wait_readable,
+        source: || {
+            get_driver()
+                .as_ref()
+                .map_or(RecordSource::Mic, |d| d.record_source())
+        },
+        set_source: |source| {
+            if let Some(d) = get_driver().as_mut() {
+                d.set_record_source(source);
+            }
+        },
+        gain: || get_driver().as_ref().map_or(0, |d| d.record_gain()),
+        set_gain: |gain| {
+            if let Some(d) = get_driver().as_mut() {
+                d.set_record_gain(gain);
             }
+        },
+    }),
+};
+
+pub static DRIVER: PciDriver = PciDriver {
+    name: "ac97",
+    ids: &[PciMatch::class(0x04, 0x01)],
+    probe,
+    remove: Some(remove),
+};
+
+fn probe(ac97: &'static PciDevice) -> Result<(), &'static str> {
+    // one card is plenty, /dev/audio only knows about one anyway
+    if AC97_INITIALIZED.load(Ordering::SeqCst) || audio::card().is_some() {
+        return Err("already driving a sound card");
+    }
 
-            log::debug!("AC97::START");
-            driver.nabm_write(
-                0x10 + 0xB,
-                driver.nabm_read(0x10 + 0xB) | (1 << 0),
+    let nam = ac97.bars[0].ok_or("no mixer bar")?.unwrap_io() as u16;
+    let nabm = ac97.bars[1].ok_or("no bus master bar")?.unwrap_io() as u16;
+
+    log::debug!("AC97 found, NAM={:#x}, NABM={:#x}", nam, nabm);
+
+    let bdl_virt = VirtAddr::new(AC97_BDL_VIRT_BASE);
+    let bdl_phys = map_dma(bdl_virt)?;
+    let capture = setup_capture().inspect_err(|_| unmap_dma(bdl_virt))?;
+
+    let mut driver = Ac97 {
+        nam,
+        nabm,
+        entry: 0,
+        buffers: unsafe { core::mem::zeroed() },
+        bdl_virt,
+        lock: Mutex::new(()),
+        volume: 50,
+        master_volume: 100,
+        capture,
+        irq: None,
+    };
+
+    unsafe {
+        log::debug!("AC97::MASTERING");
+        let pci_io = PciIO;
+        let mut cmd = pci_io.read(ac97.addr, 0x04);
+        cmd |= 1 << 0;
+        cmd |= 1 << 2;
+        pci_io.write(ac97.addr, 0x04, cmd);
+
+        log::debug!("AC97::INIT");
+        driver.nabm_write(0x2C, 1 << 1);
+
+        log::debug!("AC97::RESET");
+        driver.nam_write(NAM_RESET, 1);
+
+        log::debug!("AC97::RATE");
+        driver.nam_write(NAM_FRONT_DAC_RATE, RATE as u16);
+        driver.nam_write(0x2E, RATE as u16);
+        driver.nam_write(0x30, RATE as u16);
+        driver.nam_write(NAM_ADC_RATE, RATE as u16);
+
+        log::debug!("AC97::VOLUME");
+        driver.set_volume(50);
+
+        log::debug!("AC97::PLAY_RESET");
+        driver.set_master_volume(100);
+        driver.nam_write(0x04, 0);
+
+        log::debug!("AC97::RECORD");
+        driver.nam_write(NAM_MIC_VOLUME, MIC_BOOST);
+        driver.set_record_source(RecordSource::Mic);
+        driver.set_record_gain(50);
+
+        log::debug!("AC97::RESET_BIT");
+        driver.nabm_write(PO_CR, driver.nabm_read(PO_CR) | CR_RESET);
+
+        log::debug!("AC97::WAITING");
+        let mut control = Port::<u8>::new(nabm + PO_CR);
+        while control.read() & CR_RUN != 0 {
+            core::hint::spin_loop();
+        }
+
+        log::debug!("AC97::BUFFER");
+        if let Err(e) = driver.setup_buffers() {
+            unmap_dma(bdl_virt);
+            free_ring(
+                VirtAddr::new(AC97_CAPTURE_BDL_VIRT_BASE),
+                AC97_CAPTURE_BUFFER_VIRT_BASE,
             );
+            return Err(e);
+        }
 
-            log::info!("AC97 initialized successfully.");
+        log::debug!("AC97::BDL_ADDR");
+        {
+            let mut port = Port::<u32>::new(driver.nabm + PO_BDBAR);
+            port.write(bdl_phys.as_u64() as u32);
+        }
 
-            AC97_INITIALIZED.store(true, Ordering::SeqCst);
-            *AC97_DRIVER.lock() = Some(driver);
+        log::debug!("AC97::INTERRUPTS");
+        AC97_NABM.store(nabm, Ordering::SeqCst);
+        match msi::request_interrupt(ac97, "ac97", interrupt) {
+            Ok(vector) => {
+                driver.irq = Some(vector);
+                driver.nabm_write(
+                    PO_CR,
+                    driver.nabm_read(PO_CR) | CR_IOCE | CR_LVBIE | CR_FEIE,
+                );
+            },
+            // writers still get woken by their timeout, just later
+            Err(e) => log::warn!("AC97 has no interrupt: {}", e),
         }
-    } else {
-        log::error!("AC97 device not found");
+
+        log::debug!("AC97::START");
+        driver.nabm_write(PO_CR, driver.nabm_read(PO_CR) | CR_RUN);
+
+        log::info!("AC97 initialized successfully.");
+
+        AC97_INITIALIZED.store(true, Ordering::SeqCst);
+        *AC97_DRIVER.lock() = Some(driver);
+    }
+
+    audio::register_card(&CARD)
+}
+
+/// acks whatever a box has pending, true if there was anything
+fn ack(nabm: u16, sr: u16) -> bool {
+    let mut status = Port::<u16>::new(nabm + sr);
+    let pending = unsafe { status.read() } & (SR_LVBCI | SR_BCIS | SR_FIFOE);
+    if pending == 0 {
+        return false;
+    }
+
+    // the bits are write one to clear
+    unsafe { status.write(pending) };
+    true
+}
+
+/// a buffer finished playing or filled up, or the card ran out of them.
+/// either way someone waiting can get going again.
+fn interrupt() -> bool {
+    let nabm = AC97_NABM.load(Ordering::SeqCst);
+    if nabm == 0 {
+        return false;
+    }
+
+    let playback = ack(nabm, PO_SR);
+    if playback {
+        AC97_WAIT.wake_all();
+    }
+
+    let capture = ack(nabm, PI_SR);
+    if capture {
+        AC97_CAPTURE_WAIT.wake_all();
+    }
+
+    playback || capture
+}
+
+/// sleeps until `ready` is happy with the card, it's rechecked every time
+/// a buffer finishes playing
+pub fn wait_playback(mut ready: impl FnMut(&Ac97) -> bool) {
+    AC97_WAIT.wait_until(WAIT_TIMEOUT, || {
+        // try_lock since someone else may be mid write, they'll be done soon
+        AC97_DRIVER
+            .try_lock()
+            .is_some_and(|driver| driver.as_ref().is_none_or(&mut ready))
+    });
+}
+
+/// sleeps until a captured buffer is ready to read
+pub fn wait_readable() {
+    AC97_CAPTURE_WAIT.wait_until(WAIT_TIMEOUT, || {
+        AC97_DRIVER
+            .try_lock()
+            .is_some_and(|driver| driver.as_ref().is_none_or(|d| d.can_read()))
+    });
+}
+
+/// stops the dma engine, drops the driver and gives the rings back
+fn remove(dev: &'static PciDevice) {
+    audio::unregister_card(&CARD);
+    if let Some(driver) = AC97_DRIVER.lock().take() {
+        driver.nabm_write(PO_CR, driver.nabm_read(PO_CR) & !(CR_RUN | CR_IOCE));
+        driver.nabm_write(PI_CR, driver.nabm_read(PI_CR) & !(CR_RUN | CR_IOCE));
+        if let Some(vector) = driver.irq {
+            msi::free_interrupt(dev, vector, interrupt);
+        }
+
+        // the card has stopped, nothing reads the pages anymore
+        free_ring(driver.bdl_virt, AC97_BUFFER_VIRT_BASE);
+        free_ring(
+            VirtAddr::new(AC97_CAPTURE_BDL_VIRT_BASE),
+            AC97_CAPTURE_BUFFER_VIRT_BASE,
+        );
     }
+    AC97_NABM.store(0, Ordering::SeqCst);
+    AC97_INITIALIZED.store(false, Ordering::SeqCst);
+    AC97_WAIT.wake_all();
+    AC97_CAPTURE_WAIT.wake_all();
 }
@@ -0,0 +1,508 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use acpi::PciAddress;
+use pci_types::ConfigRegionAccess;
+use spin::Mutex;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::arch::delay::wait_for;
+use crate::arch::layout::PAGE_SIZE;
+use crate::drivers::block::ata::{
+    ATA_CMD_FLUSH_CACHE_EXT, ATA_CMD_IDENTIFY, ATA_CMD_READ_DMA_EXT,
+    ATA_CMD_WRITE_DMA_EXT, Identity,
+};
+use crate::drivers::block::{self, BlockDevice};
+use crate::drivers::pci::driver::{PciDriver, PciMatch};
+use crate::drivers::pci::io::PciIO;
+use crate::drivers::pci::parser::PciDevice;
+use crate::system::mem::{pmm, vmm};
+
+// generic host control
+const HBA_CAP: usize = 0x00;
+const HBA_GHC: usize = 0x04;
+const HBA_PI: usize = 0x0C;
+
+const CAP_S64A: u32 = 1 << 31;
+const GHC_AE: u32 = 1 << 31;
+const GHC_IE: u32 = 1 << 1;
+
+// port registers, every port gets 0x80 bytes from 0x100 on
+const PORT_BASE: usize = 0x100;
+const PORT_SIZE: usize = 0x80;
+const PORT_CLB: usize = 0x00;
+const PORT_CLBU: usize = 0x04;
+const PORT_FB: usize = 0x08;
+const PORT_FBU: usize = 0x0C;
+const PORT_IS: usize = 0x10;
+const PORT_IE: usize = 0x14;
+const PORT_CMD: usize = 0x18;
+const PORT_TFD: usize = 0x20;
+const PORT_SIG: usize = 0x24;
+const PORT_SSTS: usize = 0x28;
+const PORT_SERR: usize = 0x30;
+const PORT_CI: usize = 0x38;
+
+const CMD_ST: u32 = 1 << 0;
+const CMD_FRE: u32 = 1 << 4;
+const CMD_FR: u32 = 1 << 14;
+const CMD_CR: u32 = 1 << 15;
+
+const IS_TFES: u32 = 1 << 30;
+
+const TFD_ERR: u32 = 1 << 0;
+const TFD_DRQ: u32 = 1 << 3;
+const TFD_BSY: u32 = 1 << 7;
+
+const SSTS_DET_PRESENT: u32 = 3;
+const SSTS_IPM_ACTIVE: u32 = 1;
+
+const SIG_ATA: u32 = 0x0000_0101;
+const SIG_ATAPI: u32 = 0xEB14_0101;
+
+const FIS_TYPE_REG_H2D: u8 = 0x27;
+/// the fis carries a command rather than a control update
+const FIS_COMMAND: u8 = 1 << 7;
+const DEVICE_LBA: u8 = 1 << 6;
+
+/// one page holds the command list (1k) and the received fis (256 bytes)
+const FIS_OFFSET: usize = 0x400;
+/// the prdt starts here in the command table
+const PRDT_OFFSET: usize = 0x80;
+
+/// data goes thru a bounce buffer this big, larger requests get split
+const DMA_PAGES: usize = 16;
+const DMA_SIZE: usize = DMA_PAGES * PAGE_SIZE;
+
+/// ms to wait for a port or a command before giving up
+const TIMEOUT: u64 = 5000;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CommandHeader {
+    /// fis length in dwords, write bit and prdt entry count
+    flags: u32,
+    /// bytes transferred, filled in by the hba
+    prdbc: u32,
+    ctba: u64,
+    reserved: [u32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PrdtEntry {
+    dba: u64,
+    reserved: u32,
+    /// byte count minus one
+    dbc: u32,
+}
+
+/// one port with a disk on it, only command slot 0 is ever used
+struct AhciPort {
+    regs: VirtAddr,
+    /// command list and received fis
+    base: PhysAddr,
+    /// command table for slot 0
+    table: PhysAddr,
+    dma: PhysAddr,
+    /// cleared when the disk goes away or the port won't stop
+    alive: bool,
+}
+
+impl AhciPort {
+    fn read(&self, reg: usize) -> u32 {
+        unsafe {
+            ((self.regs.as_u64() as usize + reg) as *const u32).read_volatile()
+        }
+    }
+
+    fn write(&self, reg: usize, val: u32) {
+        unsafe {
+            ((self.regs.as_u64() as usize + reg) as *mut u32)
+                .write_volatile(val)
+        }
+    }
+
+    /// the port has to be idle before the command list can move
+    fn stop(&self) -> Result<(), &'static str> {
+        self.write(PORT_CMD, self.read(PORT_CMD) & !CMD_ST);
+        wait_for(TIMEOUT, || self.read(PORT_CMD) & CMD_CR == 0)?;
+        self.write(PORT_CMD, self.read(PORT_CMD) & !CMD_FRE);
+        wait_for(TIMEOUT, || self.read(PORT_CMD) & CMD_FR == 0)
+    }
+
+    fn start(&self) -> Result<(), &'static str> {
+        wait_for(TIMEOUT, || self.read(PORT_CMD) & CMD_CR == 0)?;
+        self.write(PORT_CMD, self.read(PORT_CMD) | CMD_FRE);
+        self.write(PORT_CMD, self.read(PORT_CMD) | CMD_ST);
+        Ok(())
+    }
+
+    fn setup(&self) -> Result<(), &'static str> {
+        self.stop()?;
+
+        let fis = self.base + FIS_OFFSET as u64;
+        self.write(PORT_CLB, self.base.as_u64() as u32);
+        self.write(PORT_CLBU, (self.base.as_u64() >> 32) as u32);
+        self.write(PORT_FB, fis.as_u64() as u32);
+        self.write(PORT_FBU, (fis.as_u64() >> 32) as u32);
+
+        // everything is polled, and the error bits are write one to clear
+        self.write(PORT_IE, 0);
+        self.write(PORT_SERR, 0xFFFF_FFFF);
+        self.write(PORT_IS, 0xFFFF_FFFF);
+
+        self.start()
+    }
+
+    /// runs one ata command thru slot 0, `data` is how much of the bounce
+    /// buffer goes along and whether it's going to the disk
+    fn command(
+        &mut self,
+        command: u8,
+        lba: u64,
+        count: u16,
+        data: Option<(usize, bool)>,
+    ) -> Result<(), &'static str> {
+        if !self.alive {
+            return Err("disk is gone");
+        }
+        wait_for(TIMEOUT, || self.read(PORT_TFD) & (TFD_BSY | TFD_DRQ) == 0)
+            .map_err(|_| "port is stuck busy")?;
+
+        let write = data.is_some_and(|(_, write)| write);
+        let header = CommandHeader {
+            // a register fis is 5 dwords
+            flags: 5
+                | if write { 1 << 6 } else { 0 }
+                | if data.is_some() { 1 << 16 } else { 0 },
+            prdbc: 0,
+            ctba: self.table.as_u64(),
+            reserved: [0; 4],
+        };
+        unsafe {
+            vmm::phys_to_virt(self.base)
+                .as_mut_ptr::<CommandHeader>()
+                .write_volatile(header)
+        };
+
+        let table = vmm::phys_to_virt(self.table).as_mut_ptr::<u8>();
+        let fis: [u8; 20] = [
+            FIS_TYPE_REG_H2D,
+            FIS_COMMAND,
+            command,
+            0,
+            lba as u8,
+            (lba >> 8) as u8,
+            (lba >> 16) as u8,
+            DEVICE_LBA,
+            (lba >> 24) as u8,
+            (lba >> 32) as u8,
+            (lba >> 40) as u8,
+            0,
+            count as u8,
+            (count >> 8) as u8,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+        unsafe {
+            core::ptr::write_bytes(table, 0, PRDT_OFFSET);
+            core::ptr::copy_nonoverlapping(fis.as_ptr(), table, fis.len());
+        }
+
+        if let Some((len, _)) = data {
+            let entry = PrdtEntry {
+                dba: self.dma.as_u64(),
+                reserved: 0,
+                dbc: len as u32 - 1,
+            };
+            unsafe {
+                (table.add(PRDT_OFFSET) as *mut PrdtEntry).write_volatile(entry)
+            };
+        }
+
+        self.write(PORT_IS, 0xFFFF_FFFF);
+        self.write(PORT_CI, 1);
+
+        let mut failed = false;
+        let done = wait_for(TIMEOUT, || {
+            failed = self.read(PORT_IS) & IS_TFES != 0;
+            failed || self.read(PORT_CI) & 1 == 0
+        });
+        if done.is_err() {
+            // clearing st takes the command back out of slot 0, if the port
+            // won't even stop it may still be using the buffers
+            if self.stop().and_then(|_| self.start()).is_err() {
+                self.alive = false;
+            }
+            return Err("command timed out");
+        }
+
+        if failed || self.read(PORT_TFD) & TFD_ERR != 0 {
+            // a task file error stops the port, kick it back into life
+            self.write(PORT_SERR, 0xFFFF_FFFF);
+            self.write(PORT_IS, 0xFFFF_FFFF);
+            let _ = self.stop().and_then(|_| self.start());
+            return Err("command failed");
+        }
+        Ok(())
+    }
+
+    fn dma(&self) -> *mut u8 { vmm::phys_to_virt(self.dma).as_mut_ptr() }
+}
+
+impl Drop for AhciPort {
+    /// the port is stopped by now, so the hba is done with these
+    fn drop(&mut self) {
+        pmm::free_dma(self.base, 1);
+        pmm::free_dma(self.table, 1);
+        pmm::free_dma(self.dma, DMA_PAGES);
+    }
+}
+
+pub struct AhciDisk {
+    sectors: u64,
+    sector_size: usize,
+    port: Mutex<AhciPort>,
+}
+
+impl AhciDisk {
+    fn check(&self, sector: u64, len: usize) -> Result<(), &'static str> {
+        if !len.is_multiple_of(self.sector_size) {
+            return Err("not a whole number of sectors");
+        }
+        match sector.checked_add((len / self.sector_size) as u64) {
+            Some(end) if end <= self.sectors => Ok(()),
+            _ => Err("past the end of the disk"),
+        }
+    }
+}
+
+impl BlockDevice for AhciDisk {
+    fn sector_size(&self) -> usize { self.sector_size }
+
+    fn sectors(&self) -> u64 { self.sectors }
+
+    fn read(&self, sector: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+        self.check(sector, buf.len())?;
+
+        let mut port = self.port.lock();
+        for (i, chunk) in buf.chunks_mut(DMA_SIZE).enumerate() {
+            let lba = sector + (i * DMA_SIZE / self.sector_size) as u64;
+            let count = (chunk.len() / self.sector_size) as u16;
+            port.command(
+                ATA_CMD_READ_DMA_EXT,
+                lba,
+                count,
+                Some((chunk.len(), false)),
+            )?;
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    port.dma(),
+                    chunk.as_mut_ptr(),
+                    chunk.len(),
+                )
+            };
+        }
+        Ok(())
+    }
+
+    fn write(&self, sector: u64, buf: &[u8]) -> Result<(), &'static str> {
+        self.check(sector, buf.len())?;
+
+        let mut port = self.port.lock();
+        for (i, chunk) in buf.chunks(DMA_SIZE).enumerate() {
+            let lba = sector + (i * DMA_SIZE / self.sector_size) as u64;
+            let count = (chunk.len() / self.sector_size) as u16;
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    chunk.as_ptr(),
+                    port.dma(),
+                    chunk.len(),
+                )
+            };
+            port.command(
+                ATA_CMD_WRITE_DMA_EXT,
+                lba,
+                count,
+                Some((chunk.len(), true)),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), &'static str> {
+        self.port.lock().command(ATA_CMD_FLUSH_CACHE_EXT, 0, 0, None)
+    }
+}
+
+/// every disk we drive, by controller and the name each one got
+static DISKS: Mutex<Vec<(PciAddress, String, Arc<AhciDisk>)>> =
+    Mutex::new(Vec::new());
+
+pub static DRIVER: PciDriver = PciDriver {
+    name: "ahci",
+    ids: &[PciMatch::class(0x01, 0x06)],
+    probe,
+    remove: Some(remove),
+};
+
+/// sets a port up and asks the disk on it who it is
+fn probe_port(
+    regs: VirtAddr,
+    can_64bit: bool,
+) -> Result<AhciDisk, &'static str> {
+    let base = pmm::alloc_dma(1)?;
+    let table = pmm::alloc_dma(1).inspect_err(|_| pmm::free_dma(base, 1))?;
+    let dma = pmm::alloc_dma(DMA_PAGES).inspect_err(|_| {
+        pmm::free_dma(base, 1);
+        pmm::free_dma(table, 1);
+    })?;
+
+    // from here on dropping the port gives the pages back
+    let mut port = AhciPort { regs, base, table, dma, alive: true };
+    if !can_64bit
+        && [base, table, dma]
+            .iter()
+            .any(|phys| phys.as_u64() + DMA_SIZE as u64 > u64::from(u32::MAX))
+    {
+        return Err("dma memory is above 4g and the hba can't reach it");
+    }
+
+    port.setup()?;
+    if let Err(e) = port.command(ATA_CMD_IDENTIFY, 0, 0, Some((512, false))) {
+        let _ = port.stop();
+        return Err(e);
+    }
+
+    let mut words = [0u16; 256];
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            port.dma() as *const u16,
+            words.as_mut_ptr(),
+            256,
+        )
+    };
+    let identity = Identity::parse(&words).inspect_err(|_| {
+        let _ = port.stop();
+    })?;
+    if !identity.lba48 {
+        let _ = port.stop();
+        return Err("disk doesn't do lba48");
+    }
+    // a transfer is chunked by the bounce buffer, a sector has to fit
+    if identity.sector_size > DMA_SIZE {
+        let _ = port.stop();
+        return Err("sector bigger than the dma buffer");
+    }
+
+    log::info!("ahci: {}", identity.model);
+    Ok(AhciDisk {
+        sectors: identity.sectors,
+        sector_size: identity.sector_size,
+        port: Mutex::new(port),
+    })
+}
+
+fn probe(dev: &'static PciDevice) -> Result<(), &'static str> {
+    // an ide mode controller has no abar, that's the ata pio driver's job
+    let (bar, _) = match dev.bars[5] {
+        Some(
+            bar @ (pci_types::Bar::Memory32 { .. }
+            | pci_types::Bar::Memory64 { .. }),
+        ) => bar.unwrap_mem(),
+        _ => return Err("no abar"),
+    };
+    let hba = vmm::map_mmio(PhysAddr::new(bar as u64), 0x1100)?;
+    let read = |reg: usize| unsafe {
+        ((hba.as_u64() as usize + reg) as *const u32).read_volatile()
+    };
+    let write = |reg: usize, val: u32| unsafe {
+        ((hba.as_u64() as usize + reg) as *mut u32).write_volatile(val)
+    };
+
+    // memory space and bus master, commands and data are dma
+    unsafe {
+        let cmd = PciIO.read(dev.addr, 0x04);
+        PciIO.write(dev.addr, 0x04, cmd | (1 << 1) | (1 << 2));
+    }
+
+    write(HBA_GHC, (read(HBA_GHC) | GHC_AE) & !GHC_IE);
+    let can_64bit = read(HBA_CAP) & CAP_S64A != 0;
+    let implemented = read(HBA_PI);
+
+    let mut found = 0;
+    for i in (0..32).filter(|i| implemented & (1 << i) != 0) {
+        let regs = hba + (PORT_BASE + i * PORT_SIZE) as u64;
+        let reg = |reg: usize| unsafe {
+            ((regs.as_u64() as usize + reg) as *const u32).read_volatile()
+        };
+
+        let ssts = reg(PORT_SSTS);
+        if ssts & 0xF != SSTS_DET_PRESENT
+            || (ssts >> 8) & 0xF != SSTS_IPM_ACTIVE
+        {
+            continue;
+        }
+        match reg(PORT_SIG) {
+            SIG_ATA => {},
+            SIG_ATAPI => {
+                log::info!("ahci: port {} is atapi, skipping", i);
+                continue;
+            },
+            sig => {
+                log::info!("ahci: port {} has an unknown device {:#x}", i, sig);
+                continue;
+            },
+        }
+
+        let disk = match probe_port(regs, can_64bit) {
+            Ok(disk) => Arc::new(disk),
+            Err(e) => {
+                log::warn!("ahci: port {}: {}", i, e);
+                continue;
+            },
+        };
+        match block::register("sd", disk.clone()) {
+            Ok(name) => {
+                log::info!("ahci: {} on port {}", name, i);
+                DISKS.lock().push((dev.addr, name, disk));
+                found += 1;
+            },
+            Err(e) => {
+                let _ = disk.port.lock().stop();
+                log::warn!("ahci: port {}: {}", i, e);
+            },
+        }
+    }
+
+    log::info!(
+        "AHCI initialized, {} disk(s) on {} ports.",
+        found,
+        implemented.count_ones()
+    );
+    Ok(())
+}
+
+/// stops every port, whoever still holds a disk gets errors from now on
+fn remove(dev: &'static PciDevice) {
+    let mut disks = DISKS.lock();
+    let (gone, kept) =
+        disks.drain(..).partition(|(addr, ..)| *addr == dev.addr);
+    *disks = kept;
+    drop(disks);
+
+    for (_, name, disk) in gone {
+        block::unregister(&name);
+        let mut port = disk.port.lock();
+        if let Err(e) = port.stop() {
+            log::warn!("ahci: {}: {}", name, e);
+        }
+        port.alive = false;
+    }
+}
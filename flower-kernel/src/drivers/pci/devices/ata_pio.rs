@@ -0,0 +1,373 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use acpi::PciAddress;
+use pci_types::ConfigRegionAccess;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+use crate::arch::delay::udelay;
+use crate::drivers::block::ata::{
+    ATA_CMD_FLUSH_CACHE, ATA_CMD_FLUSH_CACHE_EXT, ATA_CMD_IDENTIFY,
+    ATA_CMD_READ_PIO, ATA_CMD_READ_PIO_EXT, ATA_CMD_WRITE_PIO,
+    ATA_CMD_WRITE_PIO_EXT, Identity,
+};
+use crate::drivers::block::{self, BlockDevice};
+use crate::drivers::pci::driver::{PciDriver, PciMatch};
+use crate::drivers::pci::io::PciIO;
+use crate::drivers::pci::parser::PciDevice;
+
+// task file, offsets from the command block
+const REG_DATA: u16 = 0;
+const REG_SECTOR_COUNT: u16 = 2;
+const REG_LBA_LOW: u16 = 3;
+const REG_LBA_MID: u16 = 4;
+const REG_LBA_HIGH: u16 = 5;
+const REG_DRIVE: u16 = 6;
+const REG_COMMAND: u16 = 7;
+const REG_STATUS: u16 = 7;
+
+const STATUS_ERR: u8 = 1 << 0;
+const STATUS_DRQ: u8 = 1 << 3;
+const STATUS_DF: u8 = 1 << 5;
+const STATUS_BSY: u8 = 1 << 7;
+
+const DRIVE_LBA: u8 = 0xE0;
+const DRIVE_SLAVE: u8 = 1 << 4;
+/// in the control register, keeps the drive from raising interrupts
+const CONTROL_NIEN: u8 = 1 << 1;
+
+/// where the channels sit when the controller is in compatibility mode
+const LEGACY_CHANNELS: [(u16, u16); 2] = [(0x1F0, 0x3F6), (0x170, 0x376)];
+
+/// sectors per command, well under what lba28 can do in one go
+const MAX_SECTORS: usize = 128;
+
+/// ms to wait for a drive before giving up
+const TIMEOUT: u64 = 5000;
+
+/// one ide channel, the master and slave on it share the registers
+struct Channel {
+    io: u16,
+    control: u16,
+    alive: bool,
+}
+
+impl Channel {
+    fn read(&self, reg: u16) -> u8 {
+        unsafe { Port::<u8>::new(self.io + reg).read() }
+    }
+
+    fn write(&self, reg: u16, val: u8) {
+        unsafe { Port::<u8>::new(self.io + reg).write(val) }
+    }
+
+    /// the alternate status, reading it doesn't ack anything
+    fn alt_status(&self) -> u8 {
+        unsafe { Port::<u8>::new(self.control).read() }
+    }
+
+    /// a drive needs 400ns after being picked before its status means anything
+    fn select(&self, drive: u8) {
+        self.write(REG_DRIVE, drive);
+        for _ in 0..4 {
+            self.alt_status();
+        }
+    }
+
+    fn wait_idle(&self) -> Result<u8, &'static str> {
+        for _ in 0..TIMEOUT * 1000 {
+            let status = self.alt_status();
+            if status & STATUS_BSY == 0 {
+                return Ok(status);
+            }
+            udelay(1);
+        }
+        Err("drive is stuck busy")
+    }
+
+    /// waits for the drive to want the next sector of data
+    fn wait_data(&self) -> Result<(), &'static str> {
+        let status = self.wait_idle()?;
+        if status & (STATUS_ERR | STATUS_DF) != 0 {
+            return Err("drive reported an error");
+        }
+        if status & STATUS_DRQ == 0 {
+            return Err("drive has no data");
+        }
+        Ok(())
+    }
+
+    /// picks the drive and loads the task file, lba48 goes in two passes
+    /// with the high bytes first
+    fn setup(&self, slave: bool, lba48: bool, lba: u64, count: u16) {
+        let slave = if slave { DRIVE_SLAVE } else { 0 };
+        if lba48 {
+            self.select(DRIVE_LBA | slave);
+            self.write(REG_SECTOR_COUNT, (count >> 8) as u8);
+            self.write(REG_LBA_LOW, (lba >> 24) as u8);
+            self.write(REG_LBA_MID, (lba >> 32) as u8);
+            self.write(REG_LBA_HIGH, (lba >> 40) as u8);
+        } else {
+            self.select(DRIVE_LBA | slave | ((lba >> 24) as u8 & 0xF));
+        }
+
+        self.write(REG_SECTOR_COUNT, count as u8);
+        self.write(REG_LBA_LOW, lba as u8);
+        self.write(REG_LBA_MID, (lba >> 8) as u8);
+        self.write(REG_LBA_HIGH, (lba >> 16) as u8);
+    }
+
+    fn read_words(&self, words: &mut [u16]) {
+        let mut data = Port::<u16>::new(self.io + REG_DATA);
+        for word in words {
+            *word = unsafe { data.read() };
+        }
+    }
+
+    /// asks a drive who it is, None if nothing ata is there
+    fn identify(&self, slave: bool) -> Option<Identity> {
+        self.select(if slave { DRIVE_SLAVE } else { 0 });
+        self.write(REG_SECTOR_COUNT, 0);
+        self.write(REG_LBA_LOW, 0);
+        self.write(REG_LBA_MID, 0);
+        self.write(REG_LBA_HIGH, 0);
+        self.write(REG_COMMAND, ATA_CMD_IDENTIFY);
+
+        // nothing on the wire reads as 0, a floating bus as 0xFF
+        let status = self.read(REG_STATUS);
+        if status == 0 || status == 0xFF {
+            return None;
+        }
+        self.wait_idle().ok()?;
+
+        // atapi and sata answer with a signature here and abort
+        if self.read(REG_LBA_MID) != 0 || self.read(REG_LBA_HIGH) != 0 {
+            return None;
+        }
+        self.wait_data().ok()?;
+
+        let mut words = [0u16; 256];
+        self.read_words(&mut words);
+        Identity::parse(&words)
+            .inspect_err(|e| log::warn!("ata-pio: skipping drive: {}", e))
+            .ok()
+    }
+}
+
+pub struct PioDisk {
+    slave: bool,
+    sectors: u64,
+    sector_size: usize,
+    lba48: bool,
+    channel: Arc<Mutex<Channel>>,
+}
+
+impl PioDisk {
+    fn check(&self, sector: u64, len: usize) -> Result<(), &'static str> {
+        if !len.is_multiple_of(self.sector_size) {
+            return Err("not a whole number of sectors");
+        }
+        match sector.checked_add((len / self.sector_size) as u64) {
+            Some(end) if end <= self.sectors => Ok(()),
+            _ => Err("past the end of the disk"),
+        }
+    }
+}
+
+impl BlockDevice for PioDisk {
+    fn sector_size(&self) -> usize { self.sector_size }
+
+    fn sectors(&self) -> u64 { self.sectors }
+
+    fn read(&self, sector: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+        self.check(sector, buf.len())?;
+        let command =
+            if self.lba48 { ATA_CMD_READ_PIO_EXT } else { ATA_CMD_READ_PIO };
+
+        let channel = self.channel.lock();
+        if !channel.alive {
+            return Err("disk was removed");
+        }
+
+        let mut words = vec![0u16; self.sector_size / 2];
+        for (i, chunk) in
+            buf.chunks_mut(MAX_SECTORS * self.sector_size).enumerate()
+        {
+            let lba = sector + (i * MAX_SECTORS) as u64;
+            let count = chunk.len() / self.sector_size;
+            channel.wait_idle()?;
+            channel.setup(self.slave, self.lba48, lba, count as u16);
+            channel.write(REG_COMMAND, command);
+
+            for data in chunk.chunks_mut(self.sector_size) {
+                channel.wait_data()?;
+                channel.read_words(&mut words);
+                for (bytes, word) in
+                    data.as_chunks_mut::<2>().0.iter_mut().zip(&words)
+                {
+                    *bytes = word.to_le_bytes();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&self, sector: u64, buf: &[u8]) -> Result<(), &'static str> {
+        self.check(sector, buf.len())?;
+        let command =
+            if self.lba48 { ATA_CMD_WRITE_PIO_EXT } else { ATA_CMD_WRITE_PIO };
+
+        let channel = self.channel.lock();
+        if !channel.alive {
+            return Err("disk was removed");
+        }
+
+        let mut port = Port::<u16>::new(channel.io + REG_DATA);
+        for (i, chunk) in buf.chunks(MAX_SECTORS * self.sector_size).enumerate()
+        {
+            let lba = sector + (i * MAX_SECTORS) as u64;
+            let count = chunk.len() / self.sector_size;
+            channel.wait_idle()?;
+            channel.setup(self.slave, self.lba48, lba, count as u16);
+            channel.write(REG_COMMAND, command);
+
+            for data in chunk.chunks(self.sector_size) {
+                channel.wait_data()?;
+                for bytes in data.as_chunks::<2>().0 {
+                    unsafe { port.write(u16::from_le_bytes(*bytes)) };
+                }
+            }
+        }
+
+        // the last sector is only done once the drive stops being busy
+        let status = channel.wait_idle()?;
+        if status & (STATUS_ERR | STATUS_DF) != 0 {
+            return Err("drive reported an error");
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), &'static str> {
+        let channel = self.channel.lock();
+        if !channel.alive {
+            return Err("disk was removed");
+        }
+
+        channel.wait_idle()?;
+        channel.select(if self.slave { DRIVE_SLAVE } else { 0 });
+        channel.write(
+            REG_COMMAND,
+            if self.lba48 {
+                ATA_CMD_FLUSH_CACHE_EXT
+            } else {
+                ATA_CMD_FLUSH_CACHE
+            },
+        );
+
+        let status = channel.wait_idle()?;
+        if status & (STATUS_ERR | STATUS_DF) != 0 {
+            return Err("flush failed");
+        }
+        Ok(())
+    }
+}
+
+/// every disk we drive, by controller and the name each one got
+static DISKS: Mutex<Vec<(PciAddress, String, Arc<PioDisk>)>> =
+    Mutex::new(Vec::new());
+
+pub static DRIVER: PciDriver = PciDriver {
+    name: "ata-pio",
+    ids: &[PciMatch::class(0x01, 0x01)],
+    probe,
+    remove: Some(remove),
+};
+
+/// the two channels, either at the legacy ports or wherever the bars say
+fn channels(dev: &PciDevice) -> Result<[(u16, u16); 2], &'static str> {
+    let mut channels = LEGACY_CHANNELS;
+    for (i, channel) in channels.iter_mut().enumerate() {
+        // prog if bits 0 and 2 say the channel runs in native mode
+        if dev.interface & (1 << (i * 2)) == 0 {
+            continue;
+        }
+
+        let bar = |n: usize| match dev.bars[n] {
+            Some(bar @ pci_types::Bar::Io { .. }) => Ok(bar.unwrap_io() as u16),
+            _ => Err("native mode channel without io bars"),
+        };
+        // the control block bar is 4 ports, the register is the third
+        *channel = (bar(i * 2)?, bar(i * 2 + 1)? + 2);
+    }
+    Ok(channels)
+}
+
+fn probe(dev: &'static PciDevice) -> Result<(), &'static str> {
+    let channels = channels(dev)?;
+
+    // io space, the data all goes thru the cpu
+    unsafe {
+        let cmd = PciIO.read(dev.addr, 0x04);
+        PciIO.write(dev.addr, 0x04, cmd | (1 << 0));
+    }
+
+    let mut found = 0;
+    for (i, (io, control)) in channels.into_iter().enumerate() {
+        let channel = Channel { io, control, alive: true };
+        unsafe { Port::<u8>::new(control).write(CONTROL_NIEN) };
+        if channel.read(REG_STATUS) == 0xFF {
+            continue;
+        }
+
+        let channel = Arc::new(Mutex::new(channel));
+        for slave in [false, true] {
+            let Some(identity) = channel.lock().identify(slave) else {
+                continue;
+            };
+
+            let disk = Arc::new(PioDisk {
+                slave,
+                sectors: identity.sectors,
+                sector_size: identity.sector_size,
+                lba48: identity.lba48,
+                channel: channel.clone(),
+            });
+            match block::register("hd", disk.clone()) {
+                Ok(name) => {
+                    log::info!(
+                        "ata-pio: {} is {} on channel {} {}",
+                        name,
+                        identity.model,
+                        i,
+                        if slave { "slave" } else { "master" }
+                    );
+                    DISKS.lock().push((dev.addr, name, disk));
+                    found += 1;
+                },
+                Err(e) => log::warn!("ata-pio: {}", e),
+            }
+        }
+    }
+
+    if found == 0 {
+        return Err("no ata disks");
+    }
+    Ok(())
+}
+
+/// whoever still holds a disk gets errors from now on
+fn remove(dev: &'static PciDevice) {
+    let mut disks = DISKS.lock();
+    let (gone, kept) =
+        disks.drain(..).partition(|(addr, ..)| *addr == dev.addr);
+    *disks = kept;
+    drop(disks);
+
+    for (_, name, disk) in gone {
+        block::unregister(&name);
+        disk.channel.lock().alive = false;
+    }
+}
@@ -0,0 +1,242 @@
+use alloc::boxed::Box;
+
+use flower_mono::structs::{FramebufferInfo, FramebufferMode};
+use pci_types::ConfigRegionAccess;
+use x86_64::instructions::port::Port;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::drivers::pci::driver::{PciDriver, PciMatch};
+use crate::drivers::pci::io::PciIO;
+use crate::drivers::pci::parser::PciDevice;
+use crate::drivers::video::{self, DisplayDriver, Framebuffer};
+use crate::{arch, system};
+
+const BOCHS_VENDOR_ID: u16 = 0x1234;
+const BOCHS_DEVICE_ID: u16 = 0x1111;
+
+const VBE_DISPI_IOPORT_INDEX: u16 = 0x01CE;
+const VBE_DISPI_IOPORT_DATA: u16 = 0x01CF;
+const VGA_INPUT_STATUS_1: u16 = 0x03DA;
+
+const VBE_DISPI_INDEX_ID: u16 = 0x0;
+const VBE_DISPI_INDEX_XRES: u16 = 0x1;
+const VBE_DISPI_INDEX_YRES: u16 = 0x2;
+const VBE_DISPI_INDEX_BPP: u16 = 0x3;
+const VBE_DISPI_INDEX_ENABLE: u16 = 0x4;
+const VBE_DISPI_INDEX_VIRT_WIDTH: u16 = 0x6;
+const VBE_DISPI_INDEX_VIRT_HEIGHT: u16 = 0x7;
+const VBE_DISPI_INDEX_X_OFFSET: u16 = 0x8;
+const VBE_DISPI_INDEX_Y_OFFSET: u16 = 0x9;
+
+const VBE_DISPI_ID0: u16 = 0xB0C0;
+const VBE_DISPI_DISABLED: u16 = 0x00;
+const VBE_DISPI_ENABLED: u16 = 0x01;
+const VBE_DISPI_LFB_ENABLED: u16 = 0x40;
+
+const VBE_DISPI_MAX_XRES: u32 = 2560;
+const VBE_DISPI_MAX_YRES: u32 = 1600;
+
+// front + back buffer
+const BOCHS_BUFFERS: u32 = 2;
+
+// this is where the whole of vram lives in kernel space
+const BOCHS_VRAM_VIRT_BASE: u64 = 0xFFFF_FF00_1000_0000;
+
+pub struct Bochs {
+    vram: *mut u8,
+    vram_size: usize,
+}
+
+unsafe impl Send for Bochs {}
+
+impl Bochs {
+    fn dispi_write(&self, index: u16, value: u16) {
+        let mut index_port = Port::<u16>::new(VBE_DISPI_IOPORT_INDEX);
+        let mut data_port = Port::<u16>::new(VBE_DISPI_IOPORT_DATA);
+        unsafe {
+            index_port.write(index);
+            data_port.write(value);
+        }
+    }
+
+    fn dispi_read(&self, index: u16) -> u16 {
+        let mut index_port = Port::<u16>::new(VBE_DISPI_IOPORT_INDEX);
+        let mut data_port = Port::<u16>::new(VBE_DISPI_IOPORT_DATA);
+        unsafe {
+            index_port.write(index);
+            data_port.read()
+        }
+    }
+
+    /// waits for the start of the next vertical retrace, bounded so a
+    /// device that never reports one can't hang us.
+    fn wait_for_vblank(&self) {
+        let mut status = Port::<u8>::new(VGA_INPUT_STATUS_1);
+
+        for _ in 0..100_000 {
+            if unsafe { status.read() } & (1 << 3) == 0 {
+                break;
+            }
+        }
+        for _ in 0..100_000 {
+            if unsafe { status.read() } & (1 << 3) != 0 {
+                break;
+            }
+        }
+    }
+
+    /// sets up the virtual height for as many buffers as vram fits and
+    /// describes the mode currently programmed into the device.
+    fn current_framebuffer(&self) -> Framebuffer {
+        let width = self.dispi_read(VBE_DISPI_INDEX_XRES) as u32;
+        let height = self.dispi_read(VBE_DISPI_INDEX_YRES) as u32;
+        let bpp = self.dispi_read(VBE_DISPI_INDEX_BPP);
+        let pitch = width * (bpp as u32).div_ceil(8);
+
+        let fits = (self.vram_size / (pitch * height).max(1) as usize) as u32;
+        let buffers = fits.clamp(1, BOCHS_BUFFERS);
+
+        self.dispi_write(VBE_DISPI_INDEX_VIRT_WIDTH, width as u16);
+        self.dispi_write(
+            VBE_DISPI_INDEX_VIRT_HEIGHT,
+            (height * buffers) as u16,
+        );
+        self.dispi_write(VBE_DISPI_INDEX_X_OFFSET, 0);
+        self.dispi_write(VBE_DISPI_INDEX_Y_OFFSET, 0);
+
+        let (red, green, blue) = match bpp {
+            15 => ((5, 10), (5, 5), (5, 0)),
+            16 => ((5, 11), (6, 5), (5, 0)),
+            _ => ((8, 16), (8, 8), (8, 0)),
+        };
+
+        Framebuffer {
+            addr: self.vram,
+            info: FramebufferInfo {
+                width,
+                height,
+                pitch,
+                bpp,
+                buffers,
+                front: 0,
+                red_mask_size: red.0,
+                red_mask_shift: red.1,
+                green_mask_size: green.0,
+                green_mask_shift: green.1,
+                blue_mask_size: blue.0,
+                blue_mask_shift: blue.1,
+            },
+        }
+    }
+}
+
+impl DisplayDriver for Bochs {
+    fn set_mode(
+        &mut self,
+        mode: &FramebufferMode,
+    ) -> Result<Framebuffer, &'static str> {
+        if !matches!(mode.bpp, 15 | 16 | 24 | 32) {
+            return Err("unsupported depth");
+        }
+
+        if mode.width == 0
+            || mode.height == 0
+            || mode.width > VBE_DISPI_MAX_XRES
+            || mode.height > VBE_DISPI_MAX_YRES
+        {
+            return Err("unsupported resolution");
+        }
+
+        let pitch = mode.width as usize * (mode.bpp as usize).div_ceil(8);
+        if pitch * mode.height as usize > self.vram_size {
+            return Err("mode does not fit in vram");
+        }
+
+        self.dispi_write(VBE_DISPI_INDEX_ENABLE, VBE_DISPI_DISABLED);
+        self.dispi_write(VBE_DISPI_INDEX_XRES, mode.width as u16);
+        self.dispi_write(VBE_DISPI_INDEX_YRES, mode.height as u16);
+        self.dispi_write(VBE_DISPI_INDEX_BPP, mode.bpp);
+        self.dispi_write(
+            VBE_DISPI_INDEX_ENABLE,
+            VBE_DISPI_ENABLED | VBE_DISPI_LFB_ENABLED,
+        );
+
+        Ok(self.current_framebuffer())
+    }
+
+    fn flip(
+        &mut self,
+        fb: &Framebuffer,
+        buffer: u32,
+    ) -> Result<(), &'static str> {
+        self.wait_for_vblank();
+        self.dispi_write(
+            VBE_DISPI_INDEX_Y_OFFSET,
+            (buffer * fb.info.height) as u16,
+        );
+        Ok(())
+    }
+}
+
+pub static DRIVER: PciDriver = PciDriver {
+    name: "bochs-display",
+    ids: &[PciMatch::device(BOCHS_VENDOR_ID, BOCHS_DEVICE_ID)],
+    probe,
+    // the console is drawing on it, there's no letting go
+    remove: None,
+};
+
+fn probe(device: &'static PciDevice) -> Result<(), &'static str> {
+    let Some(bar) = device.bars[0] else {
+        return Err("no framebuffer bar");
+    };
+    let (vram_phys, vram_size) = bar.unwrap_mem();
+
+    unsafe {
+        let pci_io = PciIO;
+        let mut cmd = pci_io.read(device.addr, 0x04);
+        cmd |= 1 << 1;
+        pci_io.write(device.addr, 0x04, cmd);
+    }
+
+    let bochs = Bochs { vram: BOCHS_VRAM_VIRT_BASE as *mut u8, vram_size };
+
+    let id = bochs.dispi_read(VBE_DISPI_INDEX_ID);
+    if id & 0xFFF0 != VBE_DISPI_ID0 {
+        log::error!("bochs display has an unknown dispi id {:#x}", id);
+        return Err("unknown dispi id");
+    }
+
+    for offset in (0..vram_size).step_by(arch::layout::PAGE_SIZE) {
+        let virt = VirtAddr::new(BOCHS_VRAM_VIRT_BASE + offset as u64);
+        assert!(
+            !system::mem::vmm::page_is_mapped(virt),
+            "bochs vram virt collision at {:#x}",
+            virt.as_u64()
+        );
+
+        system::mem::vmm::page_map(
+            virt,
+            PhysAddr::new((vram_phys + offset) as u64),
+            PageTableFlags::PRESENT
+                | PageTableFlags::WRITABLE
+                | PageTableFlags::WRITE_THROUGH
+                | PageTableFlags::NO_EXECUTE,
+        )
+        .expect("failed to map bochs vram");
+    }
+
+    let fb = bochs.current_framebuffer();
+    log::info!(
+        "bochs display: {} KiB vram at {:#x}, {}x{}x{}",
+        vram_size / 1024,
+        vram_phys,
+        fb.info.width,
+        fb.info.height,
+        fb.info.bpp
+    );
+
+    video::register_driver(Box::new(bochs), fb);
+    Ok(())
+}
@@ -0,0 +1,944 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use pci_types::ConfigRegionAccess;
+use spin::mutex::Mutex;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::arch::delay::{udelay, wait_for};
+use crate::drivers::audio::{self, AudioCard};
+use crate::drivers::pci::driver::{PciDriver, PciMatch};
+use crate::drivers::pci::io::PciIO;
+use crate::drivers::pci::msi;
+use crate::drivers::pci::parser::PciDevice;
+use crate::system::mem::{pmm, vmm};
+use crate::system::proc::WaitQueue;
+
+/// size of the register window in bar 0
+const REGS_SIZE: u64 = 0x4000;
+
+// controller registers
+const GCAP: usize = 0x00;
+const GCTL: usize = 0x08;
+const STATESTS: usize = 0x0E;
+const INTCTL: usize = 0x20;
+const INTSTS: usize = 0x24;
+const CORBLBASE: usize = 0x40;
+const CORBUBASE: usize = 0x44;
+const CORBWP: usize = 0x48;
+const CORBRP: usize = 0x4A;
+const CORBCTL: usize = 0x4C;
+const CORBSIZE: usize = 0x4E;
+const RIRBLBASE: usize = 0x50;
+const RIRBUBASE: usize = 0x54;
+const RIRBWP: usize = 0x58;
+const RINTCNT: usize = 0x5A;
+const RIRBCTL: usize = 0x5C;
+const RIRBSIZE: usize = 0x5E;
+
+const GCTL_CRST: u32 = 1 << 0;
+const INTCTL_GIE: u32 = 1 << 31;
+const CORBRP_RST: u16 = 1 << 15;
+const RIRBWP_RST: u16 = 1 << 15;
+const DMA_RUN: u8 = 1 << 1;
+
+// stream descriptors, input ones come first then output
+const SD_BASE: usize = 0x80;
+const SD_SIZE: usize = 0x20;
+const SD_CTL: usize = 0x00;
+const SD_STS: usize = 0x03;
+const SD_LPIB: usize = 0x04;
+const SD_CBL: usize = 0x08;
+const SD_LVI: usize = 0x0C;
+const SD_FMT: usize = 0x12;
+const SD_BDPL: usize = 0x18;
+const SD_BDPU: usize = 0x1C;
+
+const SD_CTL_SRST: u32 = 1 << 0;
+const SD_CTL_RUN: u32 = 1 << 1;
+const SD_CTL_IOCE: u32 = 1 << 2;
+const SD_STS_BCIS: u8 = 1 << 2;
+const SD_STS_FIFOE: u8 = 1 << 3;
+const SD_STS_DESE: u8 = 1 << 4;
+
+/// 48khz, 16 bit, 2 channels, same for the stream and the converters
+const STREAM_FORMAT: u16 = (1 << 4) | 1;
+const STREAM_TAG: u32 = 1;
+const RATE: u32 = 48000;
+
+// codec verbs, the 12 bit ones carry an 8 bit payload
+const VERB_GET_PARAMETER: u32 = 0xF00;
+const VERB_GET_CONN_LIST: u32 = 0xF02;
+const VERB_GET_CONFIG_DEFAULT: u32 = 0xF1C;
+const VERB_SET_CONN_SELECT: u32 = 0x701;
+const VERB_SET_POWER_STATE: u32 = 0x705;
+const VERB_SET_STREAM_CHANNEL: u32 = 0x706;
+const VERB_SET_PIN_CONTROL: u32 = 0x707;
+const VERB_SET_EAPD: u32 = 0x70C;
+// and the 4 bit ones a 16 bit payload
+const VERB_SET_FORMAT: u32 = 0x2;
+const VERB_SET_AMP: u32 = 0x3;
+
+const PARAM_NODE_COUNT: u32 = 0x04;
+const PARAM_FUNCTION_TYPE: u32 = 0x05;
+const PARAM_WIDGET_CAPS: u32 = 0x09;
+const PARAM_PIN_CAPS: u32 = 0x0C;
+const PARAM_CONN_LIST_LEN: u32 = 0x0E;
+const PARAM_OUT_AMP_CAPS: u32 = 0x12;
+
+const FUNCTION_AUDIO: u32 = 0x01;
+
+const WIDGET_CAP_IN_AMP: u32 = 1 << 1;
+const WIDGET_CAP_OUT_AMP: u32 = 1 << 2;
+const WIDGET_CAP_AMP_OVERRIDE: u32 = 1 << 3;
+
+const PIN_CAP_HP: u32 = 1 << 3;
+const PIN_CAP_OUT: u32 = 1 << 4;
+const PIN_CAP_EAPD: u32 = 1 << 16;
+
+const PIN_CTL_OUT: u32 = 1 << 6;
+const PIN_CTL_HP: u32 = 1 << 7;
+
+const AMP_OUT: u32 = 1 << 15;
+const AMP_IN: u32 = 1 << 14;
+const AMP_LEFT: u32 = 1 << 13;
+const AMP_RIGHT: u32 = 1 << 12;
+const AMP_MUTE: u32 = 1 << 7;
+
+/// how deep the search from a pin back to a dac goes
+const MAX_PATH: usize = 8;
+
+const HDA_BUFFERS: usize = 32;
+const HDA_BUFFER_SIZE: usize = 4096;
+
+const HDA_CORB_VIRT: u64 = 0xFFFF_FF00_0200_0000;
+const HDA_RIRB_VIRT: u64 = 0xFFFF_FF00_0200_1000;
+const HDA_BDL_VIRT: u64 = 0xFFFF_FF00_0200_2000;
+const HDA_BUFFER_VIRT_BASE: u64 = 0xFFFF_FF00_0201_0000;
+
+/// ms to wait for the controller or a codec before giving up
+const TIMEOUT: u64 = 100;
+
+/// ticks a writer sleeps before checking again
+const WAIT_TIMEOUT: u64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WidgetType {
+    Output,
+    Input,
+    Mixer,
+    Selector,
+    Pin,
+    Other,
+}
+
+struct Widget {
+    nid: u8,
+    typ: WidgetType,
+    caps: u32,
+    pin_caps: u32,
+    config: u32,
+    conns: Vec<u8>,
+}
+
+impl Widget {
+    /// default device from the pin config, 0 line out, 1 speaker, 2 hp
+    fn device(&self) -> u32 { (self.config >> 20) & 0xF }
+
+    /// the bios says nothing is plugged into this one
+    fn unconnected(&self) -> bool { (self.config >> 30) == 1 }
+
+    fn is_output_pin(&self) -> bool {
+        self.typ == WidgetType::Pin
+            && self.pin_caps & PIN_CAP_OUT != 0
+            && !self.unconnected()
+            && self.device() <= 2
+    }
+}
+
+#[repr(C)]
+struct BdlEntry {
+    addr: u64,
+    length: u32,
+    flags: u32,
+}
+
+pub struct Hda {
+    regs: VirtAddr,
+    corb: *mut u32,
+    rirb: *mut u64,
+    corb_entries: u16,
+    rirb_entries: u16,
+    rirb_rp: u16,
+    codec: u32,
+    /// dacs playing our stream, with their out amp step count
+    dacs: Vec<(u8, u32)>,
+    /// offset of the output stream descriptor
+    sd: usize,
+    buffers: [VirtAddr; HDA_BUFFERS],
+    /// bytes written into the ring and whole buffers played, since start
+    written: u64,
+    played: u64,
+    master_volume: usize,
+    /// the vector `interrupt` is on, if it got one
+    irq: Option<u8>,
+}
+
+unsafe impl Send for Hda {}
+
+static HDA_DRIVER: Mutex<Option<Hda>> = Mutex::new(None);
+
+/// the register base for the interrupt handler, 0 when there's no card
+static HDA_REGS: AtomicU64 = AtomicU64::new(0);
+static HDA_SD: AtomicU64 = AtomicU64::new(0);
+
+/// writers waiting for a buffer to play out
+static HDA_WAIT: WaitQueue = WaitQueue::new();
+
+impl Hda {
+    fn read8(&self, reg: usize) -> u8 {
+        unsafe { (self.regs.as_u64() as *const u8).add(reg).read_volatile() }
+    }
+
+    fn read16(&self, reg: usize) -> u16 {
+        unsafe {
+            ((self.regs.as_u64() as usize + reg) as *const u16).read_volatile()
+        }
+    }
+
+    fn read32(&self, reg: usize) -> u32 {
+        unsafe {
+            ((self.regs.as_u64() as usize + reg) as *const u32).read_volatile()
+        }
+    }
+
+    fn write8(&self, reg: usize, val: u8) {
+        unsafe { (self.regs.as_u64() as *mut u8).add(reg).write_volatile(val) }
+    }
+
+    fn write16(&self, reg: usize, val: u16) {
+        unsafe {
+            ((self.regs.as_u64() as usize + reg) as *mut u16)
+                .write_volatile(val)
+        }
+    }
+
+    fn write32(&self, reg: usize, val: u32) {
+        unsafe {
+            ((self.regs.as_u64() as usize + reg) as *mut u32)
+                .write_volatile(val)
+        }
+    }
+
+    /// takes the controller through reset, codecs announce themselves after
+    fn reset(&self) -> Result<(), &'static str> {
+        self.write32(GCTL, self.read32(GCTL) & !GCTL_CRST);
+        wait_for(TIMEOUT, || self.read32(GCTL) & GCTL_CRST == 0)?;
+        self.write32(GCTL, self.read32(GCTL) | GCTL_CRST);
+        wait_for(TIMEOUT, || self.read32(GCTL) & GCTL_CRST != 0)?;
+
+        // codecs get 521us after reset to ask for an address
+        udelay(2000);
+        Ok(())
+    }
+
+    /// picks the biggest ring the controller can do, returns the entries
+    fn ring_size(&self, reg: usize) -> u16 {
+        let caps = self.read8(reg) >> 4;
+        let (bits, entries) = if caps & 0b100 != 0 {
+            (2, 256)
+        } else if caps & 0b010 != 0 {
+            (1, 16)
+        } else {
+            (0, 2)
+        };
+        self.write8(reg, (self.read8(reg) & !0b11) | bits);
+        entries
+    }
+
+    fn setup_corb_rirb(
+        &mut self,
+        corb: PhysAddr,
+        rirb: PhysAddr,
+    ) -> Result<(), &'static str> {
+        self.write8(CORBCTL, 0);
+        self.write8(RIRBCTL, 0);
+        wait_for(TIMEOUT, || {
+            self.read8(CORBCTL) & DMA_RUN == 0
+                && self.read8(RIRBCTL) & DMA_RUN == 0
+        })?;
+
+        self.write32(CORBLBASE, corb.as_u64() as u32);
+        self.write32(CORBUBASE, (corb.as_u64() >> 32) as u32);
+        self.corb_entries = self.ring_size(CORBSIZE);
+
+        // some controllers never show the reset bit, that's fine too
+        self.write16(CORBRP, CORBRP_RST);
+        let _ = wait_for(TIMEOUT, || self.read16(CORBRP) & CORBRP_RST != 0);
+        self.write16(CORBRP, 0);
+        let _ = wait_for(TIMEOUT, || self.read16(CORBRP) & CORBRP_RST == 0);
+        self.write16(CORBWP, 0);
+
+        self.write32(RIRBLBASE, rirb.as_u64() as u32);
+        self.write32(RIRBUBASE, (rirb.as_u64() >> 32) as u32);
+        self.rirb_entries = self.ring_size(RIRBSIZE);
+        self.write16(RIRBWP, RIRBWP_RST);
+        self.write16(RINTCNT, 1);
+        self.rirb_rp = 0;
+
+        self.write8(CORBCTL, DMA_RUN);
+        self.write8(RIRBCTL, DMA_RUN);
+        Ok(())
+    }
+
+    /// sends one verb and waits for the codec to answer it
+    fn command(&mut self, nid: u8, verb: u32) -> Result<u32, &'static str> {
+        let cmd = (self.codec << 28) | (u32::from(nid) << 20) | verb;
+
+        let wp =
+            (self.read16(CORBWP) & 0xFF).wrapping_add(1) % self.corb_entries;
+        unsafe { self.corb.add(wp as usize).write_volatile(cmd) };
+        self.write16(CORBWP, wp);
+
+        let rp = (self.rirb_rp + 1) % self.rirb_entries;
+        wait_for(TIMEOUT, || self.read16(RIRBWP) & 0xFF == rp)
+            .map_err(|_| "codec didn't answer")?;
+        self.rirb_rp = rp;
+
+        let response = unsafe { self.rirb.add(rp as usize).read_volatile() };
+        Ok(response as u32)
+    }
+
+    fn verb(
+        &mut self,
+        nid: u8,
+        verb: u32,
+        payload: u8,
+    ) -> Result<u32, &'static str> {
+        self.command(nid, (verb << 8) | u32::from(payload))
+    }
+
+    fn verb16(
+        &mut self,
+        nid: u8,
+        verb: u32,
+        payload: u16,
+    ) -> Result<u32, &'static str> {
+        self.command(nid, (verb << 16) | u32::from(payload))
+    }
+
+    fn parameter(&mut self, nid: u8, param: u32) -> Result<u32, &'static str> {
+        self.verb(nid, VERB_GET_PARAMETER, param as u8)
+    }
+
+    /// the first node and how many there are under `nid`
+    fn sub_nodes(&mut self, nid: u8) -> Result<(u8, u8), &'static str> {
+        let count = self.parameter(nid, PARAM_NODE_COUNT)?;
+        Ok(((count >> 16) as u8, count as u8))
+    }
+
+    fn connections(&mut self, nid: u8) -> Result<Vec<u8>, &'static str> {
+        let len = self.parameter(nid, PARAM_CONN_LIST_LEN)?;
+        let long = len & (1 << 7) != 0;
+        let count = (len & 0x7F) as usize;
+        let per_response = if long { 2 } else { 4 };
+
+        let mut conns = Vec::with_capacity(count);
+        for offset in (0..count).step_by(per_response) {
+            let entries = self.verb(nid, VERB_GET_CONN_LIST, offset as u8)?;
+            for i in 0..per_response.min(count - offset) {
+                // ranges are rare enough that the end points will do
+                let entry = if long {
+                    (entries >> (i * 16)) & 0x7FFF
+                } else {
+                    (entries >> (i * 8)) & 0x7F
+                };
+                conns.push(entry as u8);
+            }
+        }
+        Ok(conns)
+    }
+
+    fn widgets(&mut self, afg: u8) -> Result<Vec<Widget>, &'static str> {
+        let (start, count) = self.sub_nodes(afg)?;
+        let mut widgets = Vec::new();
+
+        for nid in start..start.saturating_add(count) {
+            let caps = self.parameter(nid, PARAM_WIDGET_CAPS)?;
+            let typ = match (caps >> 20) & 0xF {
+                0 => WidgetType::Output,
+                1 => WidgetType::Input,
+                2 => WidgetType::Mixer,
+                3 => WidgetType::Selector,
+                4 => WidgetType::Pin,
+                _ => WidgetType::Other,
+            };
+
+            let (pin_caps, config) = if typ == WidgetType::Pin {
+                (
+                    self.parameter(nid, PARAM_PIN_CAPS)?,
+                    self.verb(nid, VERB_GET_CONFIG_DEFAULT, 0)?,
+                )
+            } else {
+                (0, 0)
+            };
+
+            let conns = self.connections(nid)?;
+            widgets.push(Widget { nid, typ, caps, pin_caps, config, conns });
+        }
+
+        Ok(widgets)
+    }
+
+    /// depth first from a pin to any dac, the path comes back pin first
+    fn find_path(widgets: &[Widget], nid: u8, path: &mut Vec<u8>) -> bool {
+        let Some(widget) = widgets.iter().find(|w| w.nid == nid) else {
+            return false;
+        };
+        if path.contains(&nid) || path.len() >= MAX_PATH {
+            return false;
+        }
+
+        path.push(nid);
+        if widget.typ == WidgetType::Output {
+            return true;
+        }
+        if matches!(
+            widget.typ,
+            WidgetType::Pin | WidgetType::Mixer | WidgetType::Selector
+        ) && widget
+            .conns
+            .iter()
+            .any(|conn| Self::find_path(widgets, *conn, path))
+        {
+            return true;
+        }
+
+        path.pop();
+        false
+    }
+
+    fn out_amp_steps(
+        &mut self,
+        afg: u8,
+        widget: &Widget,
+    ) -> Result<u32, &'static str> {
+        let node = if widget.caps & WIDGET_CAP_AMP_OVERRIDE != 0 {
+            widget.nid
+        } else {
+            afg
+        };
+        Ok((self.parameter(node, PARAM_OUT_AMP_CAPS)? >> 8) & 0x7F)
+    }
+
+    /// unmutes and selects everything between a pin and its dac
+    fn enable_path(
+        &mut self,
+        afg: u8,
+        widgets: &[Widget],
+        path: &[u8],
+    ) -> Result<(), &'static str> {
+        for (i, nid) in path.iter().enumerate() {
+            let widget = widgets
+                .iter()
+                .find(|w| w.nid == *nid)
+                .ok_or("widget went missing")?;
+            self.verb(*nid, VERB_SET_POWER_STATE, 0)?;
+
+            // the next hop towards the dac, if this node picks between several
+            if let Some(next) = path.get(i + 1)
+                && let Some(index) =
+                    widget.conns.iter().position(|conn| conn == next)
+            {
+                if widget.typ != WidgetType::Mixer && widget.conns.len() > 1 {
+                    self.verb(*nid, VERB_SET_CONN_SELECT, index as u8)?;
+                }
+                if widget.caps & WIDGET_CAP_IN_AMP != 0 {
+                    let amp =
+                        AMP_IN | AMP_LEFT | AMP_RIGHT | ((index as u32) << 8);
+                    self.verb16(*nid, VERB_SET_AMP, amp as u16)?;
+                }
+            }
+
+            if widget.caps & WIDGET_CAP_OUT_AMP != 0
+                && widget.typ != WidgetType::Output
+            {
+                let steps = self.out_amp_steps(afg, widget)?;
+                let amp = AMP_OUT | AMP_LEFT | AMP_RIGHT | steps;
+                self.verb16(*nid, VERB_SET_AMP, amp as u16)?;
+            }
+
+            match widget.typ {
+                WidgetType::Pin => {
+                    let mut ctl = PIN_CTL_OUT;
+                    if widget.pin_caps & PIN_CAP_HP != 0 {
+                        ctl |= PIN_CTL_HP;
+                    }
+                    self.verb(*nid, VERB_SET_PIN_CONTROL, ctl as u8)?;
+                    if widget.pin_caps & PIN_CAP_EAPD != 0 {
+                        self.verb(*nid, VERB_SET_EAPD, 0x02)?;
+                    }
+                },
+                WidgetType::Output => {
+                    self.verb(
+                        *nid,
+                        VERB_SET_STREAM_CHANNEL,
+                        (STREAM_TAG << 4) as u8,
+                    )?;
+                    self.verb16(*nid, VERB_SET_FORMAT, STREAM_FORMAT)?;
+                    if !self.dacs.iter().any(|(dac, _)| dac == nid) {
+                        let steps = if widget.caps & WIDGET_CAP_OUT_AMP != 0 {
+                            self.out_amp_steps(afg, widget)?
+                        } else {
+                            0
+                        };
+                        self.dacs.push((*nid, steps));
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        Ok(())
+    }
+
+    /// finds a codec with an audio function and wires every output pin up
+    fn setup_codec(&mut self, codec: u32) -> Result<(), &'static str> {
+        self.codec = codec;
+
+        let (start, count) = self.sub_nodes(0)?;
+        let afg = (start..start.saturating_add(count))
+            .find(|nid| {
+                self.parameter(*nid, PARAM_FUNCTION_TYPE)
+                    .is_ok_and(|typ| typ & 0xFF == FUNCTION_AUDIO)
+            })
+            .ok_or("codec has no audio function")?;
+        self.verb(afg, VERB_SET_POWER_STATE, 0)?;
+
+        let widgets = self.widgets(afg)?;
+        let mut pins: Vec<&Widget> =
+            widgets.iter().filter(|w| w.is_output_pin()).collect();
+        pins.sort_by_key(|pin| pin.device());
+
+        for pin in pins {
+            let mut path = Vec::new();
+            if Self::find_path(&widgets, pin.nid, &mut path) {
+                log::debug!("hda: codec {} output path {:?}", codec, path);
+                self.enable_path(afg, &widgets, &path)?;
+            }
+        }
+
+        if self.dacs.is_empty() {
+            return Err("no path from a dac to an output pin");
+        }
+        Ok(())
+    }
+
+    fn sd_read32(&self, reg: usize) -> u32 { self.read32(self.sd + reg) }
+
+    fn sd_write32(&self, reg: usize, val: u32) {
+        self.write32(self.sd + reg, val)
+    }
+
+    /// points the output stream at the ring and starts it, it plays
+    /// silence until the mixer has something
+    fn setup_stream(&mut self, bdl_phys: PhysAddr) -> Result<(), &'static str> {
+        let ctl = self.sd_read32(SD_CTL) & !SD_CTL_RUN;
+        self.sd_write32(SD_CTL, ctl | SD_CTL_SRST);
+        wait_for(TIMEOUT, || self.sd_read32(SD_CTL) & SD_CTL_SRST != 0)?;
+        self.sd_write32(SD_CTL, ctl & !SD_CTL_SRST);
+        wait_for(TIMEOUT, || self.sd_read32(SD_CTL) & SD_CTL_SRST == 0)?;
+
+        self.sd_write32(SD_BDPL, bdl_phys.as_u64() as u32);
+        self.sd_write32(SD_BDPU, (bdl_phys.as_u64() >> 32) as u32);
+        self.sd_write32(SD_CBL, (HDA_BUFFERS * HDA_BUFFER_SIZE) as u32);
+        self.write16(self.sd + SD_LVI, (HDA_BUFFERS - 1) as u16);
+        self.write16(self.sd + SD_FMT, STREAM_FORMAT);
+
+        // the stream tag lives in the top byte of the control register
+        let ctl = (self.sd_read32(SD_CTL) & !(0xF << 20)) | (STREAM_TAG << 20);
+        self.sd_write32(SD_CTL, ctl | SD_CTL_IOCE | SD_CTL_RUN);
+        Ok(())
+    }
+
+    /// stops the stream and both command rings so nothing dmas into the
+    /// pages anymore, and masks the controller's interrupts
+    fn stop(&self) {
+        // no stream was picked if probe gave up that early
+        if self.sd != 0 {
+            self.sd_write32(
+                SD_CTL,
+                self.sd_read32(SD_CTL) & !(SD_CTL_RUN | SD_CTL_IOCE),
+            );
+        }
+        self.write32(INTCTL, 0);
+        self.write8(CORBCTL, 0);
+        self.write8(RIRBCTL, 0);
+        let _ = wait_for(TIMEOUT, || {
+            (self.sd == 0 || self.sd_read32(SD_CTL) & SD_CTL_RUN == 0)
+                && self.read8(CORBCTL) & DMA_RUN == 0
+                && self.read8(RIRBCTL) & DMA_RUN == 0
+        });
+    }
+
+    /// which buffer the controller is playing right now
+    fn position(&self) -> u64 {
+        u64::from(self.sd_read32(SD_LPIB)) / HDA_BUFFER_SIZE as u64
+    }
+
+    /// catches `played` up with the controller and clears what it played,
+    /// so running out of data plays silence instead of the last lap again
+    fn reap(&mut self) {
+        let current = self.position();
+        while self.played % HDA_BUFFERS as u64 != current {
+            let buffer =
+                self.buffers[(self.played % HDA_BUFFERS as u64) as usize];
+            unsafe {
+                core::ptr::write_bytes(
+                    buffer.as_mut_ptr::<u8>(),
+                    0,
+                    HDA_BUFFER_SIZE,
+                )
+            };
+            self.played += 1;
+        }
+    }
+
+    pub fn queued(&mut self) -> usize {
+        self.reap();
+        let playing = self.played * HDA_BUFFER_SIZE as u64;
+        self.written.saturating_sub(playing).div_ceil(HDA_BUFFER_SIZE as u64)
+            as usize
+    }
+
+    /// copies 16 bit stereo into the ring, returns how much fit
+    pub fn write(&mut self, buf: &[u8]) -> usize {
+        self.reap();
+
+        // fell behind, start again just after what's playing
+        let next = (self.played + 1) * HDA_BUFFER_SIZE as u64;
+        if self.written < next {
+            self.written = next;
+        }
+
+        let limit =
+            (self.played + HDA_BUFFERS as u64 - 1) * HDA_BUFFER_SIZE as u64;
+        let mut consumed = 0;
+        while consumed < buf.len() && self.written < limit {
+            let ring = (self.written % (HDA_BUFFERS * HDA_BUFFER_SIZE) as u64)
+                as usize;
+            let (index, offset) =
+                (ring / HDA_BUFFER_SIZE, ring % HDA_BUFFER_SIZE);
+            let len = (HDA_BUFFER_SIZE - offset)
+                .min(buf.len() - consumed)
+                .min((limit - self.written) as usize);
+
+            unsafe {
+                let dst = self.buffers[index].as_mut_ptr::<u8>().add(offset);
+                core::ptr::copy_nonoverlapping(
+                    buf[consumed..].as_ptr(),
+                    dst,
+                    len,
+                );
+            }
+            consumed += len;
+            self.written += len as u64;
+        }
+
+        consumed
+    }
+
+    pub fn master_volume(&self) -> usize { self.master_volume }
+
+    /// scales the dac amps, their range is whatever the codec says
+    pub fn set_master_volume(&mut self, vol: usize) {
+        let vol = vol.min(100);
+        self.master_volume = vol;
+
+        for (dac, steps) in self.dacs.clone() {
+            let gain = (steps * vol as u32) / 100;
+            let mute = if vol == 0 { AMP_MUTE } else { 0 };
+            let amp = AMP_OUT | AMP_LEFT | AMP_RIGHT | mute | gain;
+            if let Err(e) = self.verb16(dac, VERB_SET_AMP, amp as u16) {
+                log::warn!("hda: failed to set volume on {}: {}", dac, e);
+            }
+        }
+    }
+}
+
+/// where the controller finds its command rings and descriptor list
+struct Rings {
+    corb: PhysAddr,
+    rirb: PhysAddr,
+    bdl: PhysAddr,
+}
+
+/// allocates a page of dma memory at a fixed spot, `mapped` remembers it
+/// so a failed probe can give it back
+fn dma_page(
+    virt: u64,
+    mapped: &mut Vec<u64>,
+) -> Result<PhysAddr, &'static str> {
+    let virt = VirtAddr::new(virt);
+    if vmm::page_is_mapped(virt) {
+        return Err("hda dma memory is already mapped");
+    }
+
+    let phys = vmm::page_map_alloc(
+        virt,
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+    )?;
+    mapped.push(virt.as_u64());
+    unsafe { core::ptr::write_bytes(virt.as_mut_ptr::<u8>(), 0, 4096) };
+    Ok(phys)
+}
+
+/// unmaps pages from `dma_page` and frees them
+fn free_dma(mapped: &[u64]) {
+    for virt in mapped {
+        if let Ok(phys) = vmm::page_unmap(VirtAddr::new(*virt)) {
+            pmm::free(phys.as_u64());
+        }
+    }
+}
+
+/// unmaps register pages the probe mapped itself, the frames are mmio and
+/// never go back to the pmm
+fn unmap_regs(fresh: &[u64]) {
+    for virt in fresh {
+        let _ = vmm::page_unmap(VirtAddr::new(*virt));
+    }
+}
+
+/// every page a running controller has mapped
+fn dma_pages() -> Vec<u64> {
+    let mut pages = alloc::vec![HDA_CORB_VIRT, HDA_RIRB_VIRT, HDA_BDL_VIRT];
+    pages.extend(
+        (0..HDA_BUFFERS)
+            .map(|i| HDA_BUFFER_VIRT_BASE + (i * HDA_BUFFER_SIZE) as u64),
+    );
+    pages
+}
+
+pub fn get_driver() -> spin::MutexGuard<'static, Option<Hda>> {
+    HDA_DRIVER.lock()
+}
+
+/// hooks for the mixer, hda output only, no recording yet
+pub static CARD: AudioCard = AudioCard {
+    name: "hda",
+    rate: || RATE,
+    queued: || get_driver().as_mut().map_or(0, |d| d.queued()),
+    write: |buf| get_driver().as_mut().map_or(0, |d| d.write(buf)),
+    wait_queued_below,
+    master_volume: || get_driver().as_ref().map_or(0, |d| d.master_volume()),
+    set_master_volume: |vol| {
+        if let Some(d) = get_driver().as_mut() {
+            d.set_master_volume(vol);
+        }
+    },
+    capture: None,
+};
+
+pub static DRIVER: PciDriver = PciDriver {
+    name: "hda",
+    ids: &[PciMatch::class(0x04, 0x03)],
+    probe,
+    remove: Some(remove),
+};
+
+fn probe(dev: &'static PciDevice) -> Result<(), &'static str> {
+    if HDA_DRIVER.lock().is_some() || audio::card().is_some() {
+        return Err("already driving a sound card");
+    }
+
+    let (bar, _) = dev.bars[0].ok_or("no register bar")?.unwrap_mem();
+
+    // machines often have an hdmi only controller before the real one, so
+    // a failed probe has to leave everything the way it found it
+    let fresh: Vec<u64> = (bar as u64..bar as u64 + REGS_SIZE)
+        .step_by(4096)
+        .map(|page| vmm::phys_to_virt(PhysAddr::new(page)).as_u64())
+        .filter(|virt| !vmm::page_is_mapped(VirtAddr::new(*virt)))
+        .collect();
+    let regs =
+        match vmm::map_mmio(PhysAddr::new(bar as u64), REGS_SIZE as usize) {
+            Ok(regs) => regs,
+            Err(e) => {
+                unmap_regs(&fresh);
+                return Err(e);
+            },
+        };
+
+    let mut mapped = Vec::new();
+    let (mut hda, rings) = match map_rings(regs, &mut mapped) {
+        Ok(hda) => hda,
+        Err(e) => {
+            free_dma(&mapped);
+            unmap_regs(&fresh);
+            return Err(e);
+        },
+    };
+
+    if let Err(e) = start(dev, &mut hda, rings) {
+        hda.stop();
+        free_dma(&mapped);
+        unmap_regs(&fresh);
+        return Err(e);
+    }
+
+    *HDA_DRIVER.lock() = Some(hda);
+    audio::register_card(&CARD)
+}
+
+/// maps the command rings, the descriptor list and the buffers
+fn map_rings(
+    regs: VirtAddr,
+    mapped: &mut Vec<u64>,
+) -> Result<(Hda, Rings), &'static str> {
+    let corb = dma_page(HDA_CORB_VIRT, mapped)?;
+    let rirb = dma_page(HDA_RIRB_VIRT, mapped)?;
+    let bdl_phys = dma_page(HDA_BDL_VIRT, mapped)?;
+
+    let mut buffers = [VirtAddr::zero(); HDA_BUFFERS];
+    let bdl = VirtAddr::new(HDA_BDL_VIRT).as_mut_ptr::<BdlEntry>();
+    for (i, buffer) in buffers.iter_mut().enumerate() {
+        let virt = HDA_BUFFER_VIRT_BASE + (i * HDA_BUFFER_SIZE) as u64;
+        let phys = dma_page(virt, mapped)?;
+        *buffer = VirtAddr::new(virt);
+
+        let entry = BdlEntry {
+            addr: phys.as_u64(),
+            length: HDA_BUFFER_SIZE as u32,
+            flags: 1,
+        };
+        unsafe { bdl.add(i).write(entry) };
+    }
+
+    let hda = Hda {
+        regs,
+        corb: VirtAddr::new(HDA_CORB_VIRT).as_mut_ptr(),
+        rirb: VirtAddr::new(HDA_RIRB_VIRT).as_mut_ptr(),
+        corb_entries: 0,
+        rirb_entries: 0,
+        rirb_rp: 0,
+        codec: 0,
+        dacs: Vec::new(),
+        sd: 0,
+        buffers,
+        written: 0,
+        played: 0,
+        master_volume: 100,
+        irq: None,
+    };
+    Ok((hda, Rings { corb, rirb, bdl: bdl_phys }))
+}
+
+/// takes the controller out of reset, finds a codec and starts the stream.
+/// the interrupt handler only finds out about the card once it's running
+fn start(
+    dev: &'static PciDevice,
+    hda: &mut Hda,
+    rings: Rings,
+) -> Result<(), &'static str> {
+    let gcap = hda.read16(GCAP);
+    let (inputs, outputs) =
+        (((gcap >> 8) & 0xF) as usize, ((gcap >> 12) & 0xF) as usize);
+    if outputs == 0 {
+        return Err("controller has no output streams");
+    }
+    hda.sd = SD_BASE + inputs * SD_SIZE;
+
+    // memory space and bus master, the ring and the streams are dma
+    unsafe {
+        let cmd = PciIO.read(dev.addr, 0x04);
+        PciIO.write(dev.addr, 0x04, cmd | (1 << 1) | (1 << 2));
+    }
+
+    hda.reset()?;
+    hda.setup_corb_rirb(rings.corb, rings.rirb)?;
+
+    let codecs = hda.read16(STATESTS);
+    let codec = (0..15)
+        .filter(|codec| codecs & (1 << codec) != 0)
+        .find(|codec| {
+            hda.dacs.clear();
+            hda.setup_codec(*codec)
+                .inspect_err(|e| log::warn!("hda: codec {}: {}", codec, e))
+                .is_ok()
+        })
+        .ok_or("no usable codec")?;
+
+    hda.setup_stream(rings.bdl)?;
+
+    HDA_SD.store(hda.sd as u64, Ordering::SeqCst);
+    HDA_REGS.store(hda.regs.as_u64(), Ordering::SeqCst);
+    match msi::request_interrupt(dev, "hda", interrupt) {
+        Ok(vector) => {
+            hda.irq = Some(vector);
+            hda.write32(INTCTL, INTCTL_GIE | (1 << inputs));
+        },
+        Err(e) => log::warn!("hda has no interrupt: {}", e),
+    }
+    hda.set_master_volume(100);
+
+    log::info!(
+        "HDA initialized, codec {} with {} dac(s), {} in / {} out streams.",
+        codec,
+        hda.dacs.len(),
+        inputs,
+        outputs
+    );
+    Ok(())
+}
+/// a buffer finished, catch up and wake anyone waiting on room
+fn interrupt() -> bool {
+    let regs = HDA_REGS.load(Ordering::SeqCst);
+    if regs == 0 {
+        return false;
+    }
+
+    let sd = regs as usize + HDA_SD.load(Ordering::SeqCst) as usize;
+    let intsts =
+        unsafe { ((regs as usize + INTSTS) as *const u32).read_volatile() };
+    if intsts == 0 {
+        return false;
+    }
+
+    // stream status bits are write one to clear
+    let status = unsafe { ((sd + SD_STS) as *const u8).read_volatile() }
+        & (SD_STS_BCIS | SD_STS_FIFOE | SD_STS_DESE);
+    unsafe { ((sd + SD_STS) as *mut u8).write_volatile(status) };
+
+    if let Some(mut driver) = HDA_DRIVER.try_lock()
+        && let Some(driver) = driver.as_mut()
+    {
+        driver.reap();
+    }
+    HDA_WAIT.wake_all();
+    true
+}
+
+fn wait_queued_below(count: usize) {
+    HDA_WAIT.wait_until(WAIT_TIMEOUT, || {
+        HDA_DRIVER.try_lock().is_some_and(|mut driver| {
+            driver.as_mut().is_none_or(|d| d.queued() < count)
+        })
+    });
+}
+
+/// stops the stream and the command rings and gives their memory back
+fn remove(dev: &'static PciDevice) {
+    audio::unregister_card(&CARD);
+    HDA_REGS.store(0, Ordering::SeqCst);
+
+    if let Some(hda) = HDA_DRIVER.lock().take() {
+        hda.stop();
+        if let Some(vector) = hda.irq {
+            msi::free_interrupt(dev, vector, interrupt);
+        }
+        free_dma(&dma_pages());
+    }
+    HDA_WAIT.wake_all();
+}
@@ -1 +1,12 @@
+use crate::drivers::pci::driver::PciDriver;
+
 pub mod ac97;
+pub mod ahci;
+pub mod ata_pio;
+pub mod bochs;
+pub mod hda;
+pub mod virtio_blk;
+
+/// every built in driver, registered in this order
+pub const DRIVERS: &[&PciDriver] =
+    &[&ac97::DRIVER, &hda::DRIVER, &bochs::DRIVER, &virtio_blk::DRIVER];
@@ -0,0 +1,278 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use acpi::PciAddress;
+use spin::Mutex;
+use x86_64::PhysAddr;
+
+use crate::arch::layout::PAGE_SIZE;
+use crate::drivers::block::{self, BlockDevice};
+use crate::drivers::pci::driver::{PciDriver, PciMatch};
+use crate::drivers::pci::parser::PciDevice;
+use crate::drivers::pci::virtio::{self, Buffer, Virtio, Virtqueue};
+use crate::system::mem::{pmm, vmm};
+
+const VIRTIO_BLK_TRANSITIONAL_ID: u16 = 0x1001;
+const VIRTIO_BLK_MODERN_ID: u16 = 0x1042;
+
+// features
+const F_RO: u64 = 1 << 5;
+const F_FLUSH: u64 = 1 << 9;
+
+// device config
+const CFG_CAPACITY: usize = 0x00;
+
+// request types
+const REQ_IN: u32 = 0;
+const REQ_OUT: u32 = 1;
+const REQ_FLUSH: u32 = 4;
+
+const STATUS_OK: u8 = 0;
+
+/// virtio always counts in 512 byte sectors, whatever the disk says
+const SECTOR_SIZE: usize = 512;
+
+/// data goes thru a bounce buffer this big, larger requests get split
+const DMA_PAGES: usize = 16;
+const DMA_SIZE: usize = DMA_PAGES * PAGE_SIZE;
+
+/// the header the device reads first, the status byte sits right after it
+#[repr(C)]
+struct RequestHeader {
+    typ: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+const HEADER_SIZE: u32 = size_of::<RequestHeader>() as u32;
+
+/// the parts that need a lock, one request is in flight at a time
+struct Queue {
+    queue: Virtqueue,
+    /// header then status, one page
+    request: PhysAddr,
+    dma: PhysAddr,
+    /// cleared when the device goes away or stops answering
+    alive: bool,
+}
+
+pub struct VirtioBlk {
+    virtio: Virtio,
+    sectors: u64,
+    read_only: bool,
+    can_flush: bool,
+    queue: Mutex<Queue>,
+}
+
+impl Queue {
+    /// sends one request and waits for it, `data` is the length of the
+    /// bounce buffer to send along and whether the device fills it
+    fn request(
+        &mut self,
+        virtio: &Virtio,
+        typ: u32,
+        sector: u64,
+        data: Option<(usize, bool)>,
+    ) -> Result<(), &'static str> {
+        if !self.alive {
+            return Err("disk is gone");
+        }
+
+        let header = vmm::phys_to_virt(self.request);
+        let status = (header.as_u64() + u64::from(HEADER_SIZE)) as *mut u8;
+        unsafe {
+            header
+                .as_mut_ptr::<RequestHeader>()
+                .write_volatile(RequestHeader { typ, reserved: 0, sector });
+            // anything but ok, in case the device never writes it
+            status.write_volatile(0xFF);
+        }
+
+        let mut buffers = Vec::with_capacity(3);
+        buffers.push(Buffer {
+            addr: self.request,
+            len: HEADER_SIZE,
+            writable: false,
+        });
+        if let Some((len, writable)) = data {
+            buffers.push(Buffer { addr: self.dma, len: len as u32, writable });
+        }
+        buffers.push(Buffer {
+            addr: self.request + u64::from(HEADER_SIZE),
+            len: 1,
+            writable: true,
+        });
+
+        let head = self.queue.submit(&buffers)?;
+        if let Err(e) = self.queue.wait(head) {
+            // the device may still write into the header and the bounce
+            // buffer, so it's stopped and the queue never gets used again
+            virtio.reset();
+            self.alive = false;
+            return Err(e);
+        }
+
+        match unsafe { status.read_volatile() } {
+            STATUS_OK => Ok(()),
+            _ => Err("request failed"),
+        }
+    }
+
+    fn dma(&self) -> *mut u8 { vmm::phys_to_virt(self.dma).as_mut_ptr() }
+}
+
+impl Drop for Queue {
+    /// the device is reset by now, so it's done with these
+    fn drop(&mut self) {
+        pmm::free_dma(self.request, 1);
+        pmm::free_dma(self.dma, DMA_PAGES);
+    }
+}
+
+impl VirtioBlk {
+    fn check(&self, sector: u64, len: usize) -> Result<(), &'static str> {
+        if !len.is_multiple_of(SECTOR_SIZE) {
+            return Err("not a whole number of sectors");
+        }
+        match sector.checked_add((len / SECTOR_SIZE) as u64) {
+            Some(end) if end <= self.sectors => Ok(()),
+            _ => Err("past the end of the disk"),
+        }
+    }
+}
+
+impl BlockDevice for VirtioBlk {
+    fn sector_size(&self) -> usize { SECTOR_SIZE }
+
+    fn sectors(&self) -> u64 { self.sectors }
+
+    fn read(&self, sector: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+        self.check(sector, buf.len())?;
+
+        let mut queue = self.queue.lock();
+        for (i, chunk) in buf.chunks_mut(DMA_SIZE).enumerate() {
+            let sector = sector + (i * DMA_SIZE / SECTOR_SIZE) as u64;
+            queue.request(
+                &self.virtio,
+                REQ_IN,
+                sector,
+                Some((chunk.len(), true)),
+            )?;
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    queue.dma(),
+                    chunk.as_mut_ptr(),
+                    chunk.len(),
+                )
+            };
+        }
+        Ok(())
+    }
+
+    fn write(&self, sector: u64, buf: &[u8]) -> Result<(), &'static str> {
+        if self.read_only {
+            return Err("disk is read only");
+        }
+        self.check(sector, buf.len())?;
+
+        let mut queue = self.queue.lock();
+        for (i, chunk) in buf.chunks(DMA_SIZE).enumerate() {
+            let sector = sector + (i * DMA_SIZE / SECTOR_SIZE) as u64;
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    chunk.as_ptr(),
+                    queue.dma(),
+                    chunk.len(),
+                )
+            };
+            queue.request(
+                &self.virtio,
+                REQ_OUT,
+                sector,
+                Some((chunk.len(), false)),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// without the flush feature the device writes thru, nothing to do
+    fn flush(&self) -> Result<(), &'static str> {
+        if !self.can_flush {
+            return Ok(());
+        }
+        self.queue.lock().request(&self.virtio, REQ_FLUSH, 0, None)
+    }
+
+    fn read_only(&self) -> bool { self.read_only }
+}
+
+/// every disk we drive, by pci address and the name it got
+static DISKS: Mutex<Vec<(PciAddress, String, Arc<VirtioBlk>)>> =
+    Mutex::new(Vec::new());
+
+pub static DRIVER: PciDriver = PciDriver {
+    name: "virtio-blk",
+    ids: &[
+        PciMatch::device(virtio::VENDOR_ID, VIRTIO_BLK_TRANSITIONAL_ID),
+        PciMatch::device(virtio::VENDOR_ID, VIRTIO_BLK_MODERN_ID),
+    ],
+    probe,
+    remove: Some(remove),
+};
+
+fn probe(dev: &'static PciDevice) -> Result<(), &'static str> {
+    let virtio = Virtio::new(dev)?;
+    let features = virtio.negotiate(F_RO | F_FLUSH)?;
+    let queue = virtio.setup_queue(0).inspect_err(|_| virtio.reset())?;
+
+    let request = pmm::alloc_dma(1).inspect_err(|_| virtio.reset())?;
+    let dma = match pmm::alloc_dma(DMA_PAGES) {
+        Ok(dma) => dma,
+        Err(e) => {
+            virtio.reset();
+            pmm::free_dma(request, 1);
+            return Err(e);
+        },
+    };
+
+    virtio.driver_ok();
+    let sectors = virtio.config_read64(CFG_CAPACITY);
+    let modern = virtio.is_modern();
+
+    let disk = Arc::new(VirtioBlk {
+        virtio,
+        sectors,
+        read_only: features & F_RO != 0,
+        can_flush: features & F_FLUSH != 0,
+        queue: Mutex::new(Queue { queue, request, dma, alive: true }),
+    });
+
+    let name = block::register("vd", disk.clone())
+        .inspect_err(|_| disk.virtio.reset())?;
+    log::info!(
+        "virtio-blk: {} on {} ({})",
+        name,
+        dev.addr,
+        if modern { "modern" } else { "legacy" }
+    );
+
+    DISKS.lock().push((dev.addr, name, disk));
+    Ok(())
+}
+
+/// stops the device, whoever still holds the disk gets errors from now on
+fn remove(dev: &'static PciDevice) {
+    let mut disks = DISKS.lock();
+    let Some(index) = disks.iter().position(|(addr, ..)| *addr == dev.addr)
+    else {
+        return;
+    };
+    let (_, name, disk) = disks.remove(index);
+    drop(disks);
+
+    block::unregister(&name);
+    let mut queue = disk.queue.lock();
+    disk.virtio.reset();
+    queue.alive = false;
+}
@@ -0,0 +1,129 @@
+use alloc::vec::Vec;
+
+use acpi::PciAddress;
+use spin::Mutex;
+
+use crate::drivers::pci::parser::PciDevice;
+
+/// what a driver binds to, None matches anything
+#[derive(Debug, Clone, Copy)]
+pub struct PciMatch {
+    pub vendor_id: Option<u16>,
+    pub device_id: Option<u16>,
+    pub class: Option<(u8, u8)>,
+}
+
+impl PciMatch {
+    pub const fn device(vendor_id: u16, device_id: u16) -> Self {
+        Self {
+            vendor_id: Some(vendor_id),
+            device_id: Some(device_id),
+            class: None,
+        }
+    }
+
+    pub const fn class(class: u8, sub_class: u8) -> Self {
+        Self {
+            vendor_id: None,
+            device_id: None,
+            class: Some((class, sub_class)),
+        }
+    }
+
+    pub fn matches(&self, dev: &PciDevice) -> bool {
+        self.vendor_id.is_none_or(|id| id == dev.vendor_id)
+            && self.device_id.is_none_or(|id| id == dev.device_id)
+            && self.class.is_none_or(|(class, sub_class)| {
+                class == dev.base_class && sub_class == dev.sub_class
+            })
+    }
+}
+
+pub type ProbeFn = fn(&'static PciDevice) -> Result<(), &'static str>;
+pub type RemoveFn = fn(&'static PciDevice);
+
+pub struct PciDriver {
+    pub name: &'static str,
+    pub ids: &'static [PciMatch],
+    /// sets the device up, an error leaves it free for the next driver
+    pub probe: ProbeFn,
+    /// stops the device, drivers that can't let go leave this out
+    pub remove: Option<RemoveFn>,
+}
+
+impl PciDriver {
+    pub fn matches(&self, dev: &PciDevice) -> bool {
+        self.ids.iter().any(|id| id.matches(dev))
+    }
+}
+
+/// which driver owns which device
+static BINDINGS: Mutex<Vec<(PciAddress, &'static PciDriver)>> =
+    Mutex::new(Vec::new());
+
+fn is_bound(addr: PciAddress) -> bool {
+    BINDINGS.lock().iter().any(|(bound, _)| *bound == addr)
+}
+
+fn try_bind(driver: &'static PciDriver, dev: &'static PciDevice) -> bool {
+    if is_bound(dev.addr) || !driver.matches(dev) {
+        return false;
+    }
+
+    // no locks held, probe can take as long as it wants
+    match (driver.probe)(dev) {
+        Ok(()) => {
+            BINDINGS.lock().push((dev.addr, driver));
+            log::info!("pci {}: bound to {}", dev.addr, driver.name);
+            true
+        },
+        Err(e) => {
+            log::warn!("pci {}: {} probe failed: {}", dev.addr, driver.name, e);
+            false
+        },
+    }
+}
+
+/// hands the driver every matching device nobody has taken yet
+pub fn register(driver: &'static PciDriver) {
+    for dev in super::devices() {
+        try_bind(driver, dev);
+    }
+}
+
+/// detaches the driver from a device, fails if it can't be removed
+fn unbind(addr: PciAddress) -> Result<(), &'static str> {
+    let driver = driver_of(addr).ok_or("device has no driver")?;
+    let remove = driver.remove.ok_or("driver can't be removed")?;
+    let dev = super::devices()
+        .iter()
+        .find(|dev| dev.addr == addr)
+        .ok_or("no such device")?;
+
+    remove(dev);
+    BINDINGS.lock().retain(|(bound, _)| *bound != addr);
+    log::info!("pci {}: unbound from {}", addr, driver.name);
+    Ok(())
+}
+
+pub fn driver_of(addr: PciAddress) -> Option<&'static PciDriver> {
+    BINDINGS
+        .lock()
+        .iter()
+        .find(|(bound, _)| *bound == addr)
+        .map(|(_, driver)| *driver)
+}
+
+/// detaches every driver that can let go, so nothing is still doing dma
+/// when the machine goes down
+pub fn unbind_all() {
+    let bound: Vec<PciAddress> =
+        BINDINGS.lock().iter().map(|(addr, _)| *addr).collect();
+    for addr in bound {
+        if driver_of(addr).is_some_and(|driver| driver.remove.is_some())
+            && let Err(e) = unbind(addr)
+        {
+            log::warn!("pci {}: {}", addr, e);
+        }
+    }
+}
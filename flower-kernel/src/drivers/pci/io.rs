@@ -1,41 +1,106 @@
+use alloc::collections::BTreeMap;
+
+use acpi::PciAddress;
 use pci_types::ConfigRegionAccess;
+use spin::Mutex;
+use x86_64::PhysAddr;
+use x86_64::instructions::interrupts::without_interrupts;
 use x86_64::instructions::port::Port;
 
+use crate::arch;
+use crate::system::mem::vmm;
+
 const CMD_PORT: u16 = 0xCF8;
 const DATA_PORT: u16 = 0xCFC;
 
+/// the legacy ports only reach the first 256 bytes of config space
+const LEGACY_CONFIG_SIZE: u16 = 0x100;
+
+/// every bus gets 1MiB of ecam, 32 devices of 8 functions of 4k
+const ECAM_BUS_SIZE: usize = 1 << 20;
+
+/// where each (segment, bus) ecam window got mapped
+static BUSES: Mutex<BTreeMap<(u16, u8), u64>> = Mutex::new(BTreeMap::new());
+
+/// config space access, through ecam when the mcfg covers the bus and the
+/// 0xCF8/0xCFC ports otherwise.
 pub struct PciIO;
 
-impl ConfigRegionAccess for PciIO {
-    unsafe fn read(&self, address: acpi::PciAddress, offset: u16) -> u32 {
-        let addr: u32 = (1 << 31)
+impl PciIO {
+    /// the bus's ecam window, the whole bus gets mapped on first use so
+    /// later accesses skip the page walk
+    fn bus_window(segment: u16, bus: u8) -> Option<u64> {
+        // config space gets touched from interrupt handlers too
+        without_interrupts(|| {
+            let mut buses = BUSES.lock();
+            if let Some(virt) = buses.get(&(segment, bus)) {
+                return Some(*virt);
+            }
+
+            let region = arch::acpi::try_get()?.ecam_region(segment, bus)?;
+            let phys = region.base + (u64::from(bus - region.bus_start) << 20);
+            let virt =
+                vmm::map_mmio(PhysAddr::new(phys), ECAM_BUS_SIZE).ok()?;
+            buses.insert((segment, bus), virt.as_u64());
+            Some(virt.as_u64())
+        })
+    }
+
+    /// where a function's 4k of config space lives
+    fn ecam(address: PciAddress, offset: u16) -> Option<*mut u32> {
+        let bus = Self::bus_window(address.segment(), address.bus())?;
+        let function = (u64::from(address.device()) << 15)
+            | (u64::from(address.function()) << 12);
+        Some((bus + function + u64::from(offset & 0xFFC)) as *mut u32)
+    }
+
+    fn legacy_address(address: PciAddress, offset: u16) -> u32 {
+        (1 << 31)
             | ((address.bus() as u32) << 16)
             | ((address.device() as u32) << 11)
             | ((address.function() as u32) << 8)
-            | ((offset as u32) & 0xFC);
+            | ((offset as u32) & 0xFC)
+    }
+}
+
+impl ConfigRegionAccess for PciIO {
+    unsafe fn read(&self, address: PciAddress, offset: u16) -> u32 {
+        if let Some(ptr) = Self::ecam(address, offset) {
+            return unsafe { ptr.read_volatile() };
+        }
+        if offset >= LEGACY_CONFIG_SIZE || address.segment() != 0 {
+            return 0xFFFF_FFFF;
+        }
 
         let mut cmd = Port::<u32>::new(CMD_PORT);
         let mut data = Port::<u32>::new(DATA_PORT);
 
         unsafe {
-            cmd.write(addr);
+            cmd.write(Self::legacy_address(address, offset));
             data.read()
         }
     }
 
-    unsafe fn write(&self, address: acpi::PciAddress, offset: u16, value: u32) {
-        let addr: u32 = (1 << 31)
-            | ((address.bus() as u32) << 16)
-            | ((address.device() as u32) << 11)
-            | ((address.function() as u32) << 8)
-            | ((offset as u32) & 0xFC);
+    unsafe fn write(&self, address: PciAddress, offset: u16, value: u32) {
+        if let Some(ptr) = Self::ecam(address, offset) {
+            unsafe { ptr.write_volatile(value) };
+            return;
+        }
+        if offset >= LEGACY_CONFIG_SIZE || address.segment() != 0 {
+            return;
+        }
 
         let mut cmd = Port::<u32>::new(CMD_PORT);
         let mut data = Port::<u32>::new(DATA_PORT);
 
         unsafe {
-            cmd.write(addr);
+            cmd.write(Self::legacy_address(address, offset));
             data.write(value)
         }
     }
 }
+
+/// true if config access goes through ecam rather than the ports
+pub fn has_ecam() -> bool {
+    arch::acpi::try_get().is_some_and(|tables| !tables.ecam.is_empty())
+}
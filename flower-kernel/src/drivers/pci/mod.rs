@@ -1,12 +1,36 @@
-use crate::drivers::pci::parser::PciBus;
+use spin::Once;
+
+use crate::drivers::pci::parser::{PciBus, PciDevice};
 
 pub mod devices;
-mod io;
-mod parser;
+pub mod driver;
+pub mod io;
+pub mod msi;
+pub mod parser;
+pub mod virtio;
+
+static PCI_BUS: Once<PciBus> = Once::new();
 
 pub fn install() {
-    let mut pci_bus = PciBus::new();
-    pci_bus.parse();
+    let pci_bus = PCI_BUS.call_once(|| {
+        let mut pci_bus = PciBus::new();
+        pci_bus.parse();
+        pci_bus
+    });
+
+    for driver in devices::DRIVERS {
+        driver::register(driver);
+    }
+
+    let unclaimed = pci_bus
+        .devices
+        .iter()
+        .filter(|dev| driver::driver_of(dev.addr).is_none())
+        .count();
+    log::info!("PCI: {} devices without a driver.", unclaimed);
+}
 
-    devices::ac97::install(&pci_bus);
+/// every device found during the scan, empty before install
+pub fn devices() -> &'static [PciDevice] {
+    PCI_BUS.get().map(|bus| bus.devices.as_slice()).unwrap_or(&[])
 }
@@ -0,0 +1,216 @@
+use alloc::vec::Vec;
+
+use pci_types::ConfigRegionAccess;
+use pci_types::capability::{
+    MsiCapability, MsixCapability, MultipleMessageSupport, PciCapability,
+    TriggerMode,
+};
+use x86_64::PhysAddr;
+
+use crate::arch::irq::{self, IrqHandler, IrqSource, Polarity, Trigger};
+use crate::arch::{acpi, apic};
+use crate::drivers::pci::io::PciIO;
+use crate::drivers::pci::parser::PciDevice;
+use crate::system::mem::vmm;
+
+/// messages written here land in the lapic picked by bits 12-19
+const MSI_ADDRESS_BASE: u64 = 0xFEE0_0000;
+
+const PCI_COMMAND: u16 = 0x04;
+const COMMAND_BUS_MASTER: u32 = 1 << 2;
+const COMMAND_INTX_DISABLE: u32 = 1 << 10;
+
+/// each msi-x table entry is address low, address high, data and control
+const MSIX_ENTRY_SIZE: usize = 16;
+const MSIX_ENTRY_MASKED: u32 = 1 << 0;
+
+fn msi_address() -> u64 {
+    MSI_ADDRESS_BASE | (u64::from(apic::lapic_id()) << 12)
+}
+
+/// msi is a memory write from the device, so it has to be a bus master.
+/// the pin interrupt gets turned off so it doesn't fire as well.
+fn prepare(dev: &PciDevice) {
+    unsafe {
+        let cmd = PciIO.read(dev.addr, PCI_COMMAND);
+        PciIO.write(
+            dev.addr,
+            PCI_COMMAND,
+            cmd | COMMAND_BUS_MASTER | COMMAND_INTX_DISABLE,
+        );
+    }
+}
+
+pub fn msi_capability(dev: &PciDevice) -> Option<MsiCapability> {
+    dev.capabilities.iter().find_map(|cap| match cap {
+        PciCapability::Msi(msi) => Some(*msi),
+        _ => None,
+    })
+}
+
+pub fn msix_capability(dev: &PciDevice) -> Option<MsixCapability> {
+    dev.capabilities.iter().find_map(|cap| match cap {
+        PciCapability::MsiX(msix) => Some(*msix),
+        _ => None,
+    })
+}
+
+/// points the device's msi at a fresh vector, only one message is used
+pub fn request_msi(
+    dev: &PciDevice,
+    name: &'static str,
+    handler: IrqHandler,
+) -> Result<u8, &'static str> {
+    let msi = msi_capability(dev).ok_or("device has no msi capability")?;
+    let vector = irq::request_msi(name, handler)?;
+
+    msi.set_enabled(false, PciIO);
+    msi.set_message_info_lapic(msi_address(), vector, TriggerMode::Edge, PciIO);
+    msi.set_multiple_message_enable(MultipleMessageSupport::Int1, PciIO);
+    prepare(dev);
+    msi.set_enabled(true, PciIO);
+
+    log::info!("pci {}: msi on vector {} for {}", dev.addr, vector, name);
+    Ok(vector)
+}
+
+/// fills the first entries of the msi-x table, one vector per handler.
+/// the rest of the table stays masked.
+pub fn request_msix(
+    dev: &PciDevice,
+    handlers: &[(&'static str, IrqHandler)],
+) -> Result<Vec<u8>, &'static str> {
+    let mut msix =
+        msix_capability(dev).ok_or("device has no msi-x capability")?;
+    if handlers.len() > msix.table_size() as usize {
+        return Err("device has fewer msi-x entries than asked for");
+    }
+
+    let bar = dev.bars[msix.table_bar() as usize]
+        .ok_or("msi-x table bar isn't there")?;
+    let (bar_addr, _) = bar.unwrap_mem();
+    let table_phys = bar_addr as u64 + u64::from(msix.table_offset());
+    let table_len = msix.table_size() as usize * MSIX_ENTRY_SIZE;
+    let table = vmm::map_mmio(PhysAddr::new(table_phys), table_len)?
+        .as_mut_ptr::<u32>();
+
+    let mut vectors = Vec::new();
+    for &(name, handler) in handlers {
+        match irq::request_msi(name, handler) {
+            Ok(vector) => vectors.push(vector),
+            Err(e) => {
+                vectors.iter().for_each(|vector| irq::free_msi(*vector));
+                return Err(e);
+            },
+        }
+    }
+
+    // mask the whole function while the table is being written
+    msix.set_function_mask(true, PciIO);
+    msix.set_enabled(true, PciIO);
+
+    let address = msi_address();
+    for entry in 0..msix.table_size() as usize {
+        let (data, control) = match vectors.get(entry) {
+            Some(vector) => (u32::from(*vector), 0),
+            None => (0, MSIX_ENTRY_MASKED),
+        };
+
+        unsafe {
+            let slot = table.add(entry * MSIX_ENTRY_SIZE / 4);
+            slot.write_volatile(address as u32);
+            slot.add(1).write_volatile((address >> 32) as u32);
+            slot.add(2).write_volatile(data);
+            slot.add(3).write_volatile(control);
+        }
+    }
+
+    prepare(dev);
+    msix.set_function_mask(false, PciIO);
+
+    log::info!(
+        "pci {}: msi-x on vectors {:?} ({} entries)",
+        dev.addr,
+        vectors,
+        msix.table_size()
+    );
+    Ok(vectors)
+}
+
+/// the best interrupt the device can do, msi-x then msi then the legacy
+/// pin through the ioapic.
+pub fn request_interrupt(
+    dev: &PciDevice,
+    name: &'static str,
+    handler: IrqHandler,
+) -> Result<u8, &'static str> {
+    if msix_capability(dev).is_some() {
+        match request_msix(dev, &[(name, handler)]) {
+            Ok(vectors) => return Ok(vectors[0]),
+            Err(e) => log::warn!("pci {}: msi-x failed: {}", dev.addr, e),
+        }
+    }
+
+    if msi_capability(dev).is_some() {
+        match request_msi(dev, name, handler) {
+            Ok(vector) => return Ok(vector),
+            Err(e) => log::warn!("pci {}: msi failed: {}", dev.addr, e),
+        }
+    }
+
+    if dev.interrupt_pin == 0 {
+        return Err("device has no interrupt pin");
+    }
+    if let Some((gsi, trigger, polarity)) = pin_route(dev) {
+        return irq::request_gsi(gsi, trigger, polarity, name, handler);
+    }
+
+    // no acpi routing, all that's left is what the firmware put in the line
+    if dev.interrupt_line == 0xFF {
+        return Err("device has no usable interrupt");
+    }
+    irq::request_pci_irq(dev.interrupt_line, name, handler)
+}
+
+/// asks acpi where the pin ends up, every bridge on the way up to the root
+/// bus rotates it by the device number behind it
+fn pin_route(dev: &PciDevice) -> Option<(u32, Trigger, Polarity)> {
+    let (mut addr, mut pin) = (dev.addr, dev.interrupt_pin);
+    let mut parent = dev.parent;
+    while let Some(bridge) = parent {
+        pin = (pin - 1 + addr.device()) % 4 + 1;
+        addr = bridge;
+        parent = super::devices()
+            .iter()
+            .find(|dev| dev.addr == bridge)
+            .and_then(|dev| dev.parent);
+    }
+
+    acpi::pci::route(
+        addr.segment(),
+        addr.bus(),
+        addr.device(),
+        addr.function(),
+        pin,
+    )
+}
+
+/// undoes `request_interrupt`, turns msi and msi-x off and lets the vector
+/// or the shared pin go
+pub fn free_interrupt(dev: &PciDevice, vector: u8, handler: IrqHandler) {
+    if let Some(mut msix) = msix_capability(dev) {
+        msix.set_enabled(false, PciIO);
+    }
+    if let Some(msi) = msi_capability(dev) {
+        msi.set_enabled(false, PciIO);
+    }
+
+    let source = irq::lines(|lines| {
+        lines.iter().find(|line| line.vector == vector).map(|line| line.source)
+    });
+    match source {
+        Some(IrqSource::Gsi(gsi)) => irq::free_irq(gsi, handler),
+        Some(IrqSource::Msi) => irq::free_msi(vector),
+        None => {},
+    }
+}
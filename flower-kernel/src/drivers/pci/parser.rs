@@ -1,9 +1,14 @@
+use alloc::vec;
 use alloc::vec::Vec;
 
 use acpi::PciAddress;
-use pci_types::{Bar, EndpointHeader, HeaderType, PciHeader};
+use pci_types::capability::PciCapability;
+use pci_types::{
+    Bar, EndpointHeader, HeaderType, PciHeader, PciPciBridgeHeader,
+};
 
-use crate::drivers::pci::io::PciIO;
+use crate::arch;
+use crate::drivers::pci::io::{self, PciIO};
 
 #[derive(Debug, Clone)]
 pub struct PciDevice {
@@ -15,78 +20,143 @@ pub struct PciDevice {
     pub sub_class: u8,
     pub interface: u8,
     pub bars: [Option<Bar>; 6],
+    /// INTA# through INTD# as 1-4, 0 if the device has no legacy interrupt
+    pub interrupt_pin: u8,
+    /// the legacy irq the firmware wired the pin to
+    pub interrupt_line: u8,
+    pub capabilities: Vec<PciCapability>,
+    /// the bridge this device sits behind, None on a root bus
+    pub parent: Option<PciAddress>,
+    /// the bus behind this device if it's a pci to pci bridge
+    pub secondary_bus: Option<u8>,
 }
 
 pub struct PciBus {
     pub devices: Vec<PciDevice>,
+    /// buses already walked, so a bad bridge can't send us in circles
+    scanned: Vec<(u16, u8)>,
 }
 
 impl PciBus {
-    pub fn new() -> Self { Self { devices: Vec::new() } }
+    pub fn new() -> Self { Self { devices: Vec::new(), scanned: Vec::new() } }
 
     pub fn parse(&mut self) {
         let pci = PciIO;
-        for bus in 0..=255 {
-            for device in 0..32 {
-                let addr0 = PciAddress::new(0, bus, device, 0);
-                let header0 = PciHeader::new(addr0);
-                let (device_id, _) = header0.id(&pci);
-
-                if device_id == 0xFFFF {
-                    continue;
+
+        // every ecam region is a root bus, without ecam there's just bus 0
+        let roots: Vec<(u16, u8)> = match arch::acpi::try_get() {
+            Some(tables) if !tables.ecam.is_empty() => tables
+                .ecam
+                .iter()
+                .map(|region| (region.segment, region.bus_start))
+                .collect(),
+            _ => vec![(0, 0)],
+        };
+
+        for (segment, bus) in roots {
+            // a multi function host bridge means one root bus per function
+            let host = PciHeader::new(PciAddress::new(segment, bus, 0, 0));
+            if host.has_multiple_functions(&pci) {
+                for function in 0..8 {
+                    let addr = PciAddress::new(segment, bus, 0, function);
+                    if PciHeader::new(addr).id(&pci).0 != 0xFFFF {
+                        self.scan_bus(segment, bus + function, None);
+                    }
                 }
+            } else {
+                self.scan_bus(segment, bus, None);
+            }
+        }
 
-                let functions =
-                    if header0.has_multiple_functions(&pci) { 8 } else { 1 };
+        log::info!(
+            "PCI found {} devices on {} buses ({}).",
+            self.devices.len(),
+            self.scanned.len(),
+            if io::has_ecam() { "ecam" } else { "port io" }
+        );
+    }
 
-                for function in 0..functions {
-                    let addr = PciAddress::new(0, bus, device, function);
-                    let header = PciHeader::new(addr);
-                    let (vendor_id, device_id) = header.id(&pci);
+    fn scan_bus(&mut self, segment: u16, bus: u8, parent: Option<PciAddress>) {
+        if self.scanned.contains(&(segment, bus)) {
+            return;
+        }
+        self.scanned.push((segment, bus));
 
-                    if vendor_id == 0xFFFF {
-                        continue;
-                    }
+        let pci = PciIO;
+        for device in 0..32 {
+            let header0 =
+                PciHeader::new(PciAddress::new(segment, bus, device, 0));
+            if header0.id(&pci).0 == 0xFFFF {
+                continue;
+            }
 
-                    let (revision, base_class, sub_class, interface) =
-                        header.revision_and_class(&pci);
-
-                    let mut bars: [Option<Bar>; 6] = Default::default();
-
-                    let header_type = header.header_type(&pci);
-                    match header_type {
-                        HeaderType::Endpoint => {
-                            let endp =
-                                EndpointHeader::from_header(header, &pci)
-                                    .unwrap();
-                            for (i, item) in bars.iter_mut().enumerate() {
-                                *item = endp.bar(i as u8, &pci);
-                            }
-                        },
-
-                        _ => {
-                            unimplemented!(
-                                "unsupported header: {:?}",
-                                header_type
-                            )
-                        },
-                    }
+            let functions =
+                if header0.has_multiple_functions(&pci) { 8 } else { 1 };
+            for function in 0..functions {
+                let addr = PciAddress::new(segment, bus, device, function);
+                self.scan_function(addr, parent);
+            }
+        }
+    }
+
+    fn scan_function(&mut self, addr: PciAddress, parent: Option<PciAddress>) {
+        let pci = PciIO;
+        let header = PciHeader::new(addr);
+        let (vendor_id, device_id) = header.id(&pci);
+        if vendor_id == 0xFFFF {
+            return;
+        }
+
+        let (revision, base_class, sub_class, interface) =
+            header.revision_and_class(&pci);
+
+        let mut bars: [Option<Bar>; 6] = Default::default();
+        let mut interrupt = (0, 0);
+        let mut capabilities = Vec::new();
+        let mut secondary_bus = None;
 
-                    self.devices.push(PciDevice {
-                        addr,
-                        vendor_id,
-                        device_id,
-                        revision,
-                        base_class,
-                        sub_class,
-                        interface,
-                        bars,
-                    });
+        let header_type = header.header_type(&pci);
+        match header_type {
+            HeaderType::Endpoint => {
+                let endp = EndpointHeader::from_header(header, &pci).unwrap();
+                for (i, item) in bars.iter_mut().enumerate() {
+                    *item = endp.bar(i as u8, &pci);
                 }
-            }
+                interrupt = endp.interrupt(&pci);
+                capabilities = endp.capabilities(&pci).collect();
+            },
+            HeaderType::PciPciBridge => {
+                let bridge =
+                    PciPciBridgeHeader::from_header(header, &pci).unwrap();
+                secondary_bus = Some(bridge.secondary_bus_number(&pci));
+            },
+            _ => {
+                log::warn!("PCI {}: unsupported header {:?}", addr, header_type)
+            },
         }
 
-        log::info!("PCI found {} devices.", self.devices.len());
+        self.devices.push(PciDevice {
+            addr,
+            vendor_id,
+            device_id,
+            revision,
+            base_class,
+            sub_class,
+            interface,
+            bars,
+            interrupt_pin: interrupt.0,
+            interrupt_line: interrupt.1,
+            capabilities,
+            parent,
+            secondary_bus,
+        });
+
+        // firmware already numbered the buses, just follow them
+        if let Some(bus) = secondary_bus
+            && bus != 0
+        {
+            self.scan_bus(addr.segment(), bus, Some(addr));
+        }
     }
 
     /// finds the first device matching the given class and subclass. returns None if no such device exists.
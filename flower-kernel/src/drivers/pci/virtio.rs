@@ -0,0 +1,525 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{Ordering, fence};
+
+use pci_types::ConfigRegionAccess;
+use pci_types::capability::PciCapability;
+use x86_64::instructions::port::Port;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::arch::delay::udelay;
+use crate::arch::layout::PAGE_SIZE;
+use crate::drivers::pci::io::PciIO;
+use crate::drivers::pci::parser::PciDevice;
+use crate::system::mem::{pmm, vmm};
+
+pub const VENDOR_ID: u16 = 0x1AF4;
+
+// device status
+const STATUS_ACKNOWLEDGE: u8 = 1 << 0;
+const STATUS_DRIVER: u8 = 1 << 1;
+const STATUS_DRIVER_OK: u8 = 1 << 2;
+const STATUS_FEATURES_OK: u8 = 1 << 3;
+const STATUS_FAILED: u8 = 1 << 7;
+
+/// modern devices won't work unless the driver agrees to this
+const F_VERSION_1: u64 = 1 << 32;
+
+// vendor capabilities that point the modern interface at a bar
+const CAP_COMMON_CFG: u8 = 1;
+const CAP_NOTIFY_CFG: u8 = 2;
+const CAP_DEVICE_CFG: u8 = 4;
+
+// modern common config
+const COMMON_DEVICE_FEATURE_SELECT: usize = 0x00;
+const COMMON_DEVICE_FEATURE: usize = 0x04;
+const COMMON_DRIVER_FEATURE_SELECT: usize = 0x08;
+const COMMON_DRIVER_FEATURE: usize = 0x0C;
+const COMMON_STATUS: usize = 0x14;
+const COMMON_QUEUE_SELECT: usize = 0x16;
+const COMMON_QUEUE_SIZE: usize = 0x18;
+const COMMON_QUEUE_ENABLE: usize = 0x1C;
+const COMMON_QUEUE_NOTIFY_OFF: usize = 0x1E;
+const COMMON_QUEUE_DESC: usize = 0x20;
+const COMMON_QUEUE_DRIVER: usize = 0x28;
+const COMMON_QUEUE_DEVICE: usize = 0x30;
+
+// legacy registers in the io bar
+const LEGACY_DEVICE_FEATURES: u16 = 0x00;
+const LEGACY_DRIVER_FEATURES: u16 = 0x04;
+const LEGACY_QUEUE_ADDRESS: u16 = 0x08;
+const LEGACY_QUEUE_SIZE: u16 = 0x0C;
+const LEGACY_QUEUE_SELECT: u16 = 0x0E;
+const LEGACY_QUEUE_NOTIFY: u16 = 0x10;
+const LEGACY_STATUS: u16 = 0x12;
+/// device config starts here as long as msi-x is off
+const LEGACY_DEVICE_CFG: u16 = 0x14;
+
+/// modern devices let us pick a smaller queue than they offer
+const MAX_QUEUE_SIZE: u16 = 256;
+
+const DESC_F_NEXT: u16 = 1 << 0;
+const DESC_F_WRITE: u16 = 1 << 1;
+const AVAIL_F_NO_INTERRUPT: u16 = 1 << 0;
+
+/// how long a request gets before the device is given up on, in ms
+const TIMEOUT: u64 = 5000;
+
+enum Transport {
+    /// virtio 1.0, everything is mmio found thru vendor capabilities
+    Modern {
+        common: VirtAddr,
+        notify: VirtAddr,
+        notify_multiplier: u32,
+        device: VirtAddr,
+    },
+    /// virtio 0.9.5, one io bar with the registers at fixed offsets
+    Legacy { port: u16 },
+}
+
+/// the pci side of a virtio device, features, status, config and queues
+pub struct Virtio {
+    transport: Transport,
+}
+
+/// maps the part of a bar a vendor capability points at
+fn cap_region(dev: &PciDevice, offset: u16) -> Result<VirtAddr, &'static str> {
+    let (bar, bar_offset, length) = unsafe {
+        (
+            PciIO.read(dev.addr, offset + 4) as u8,
+            PciIO.read(dev.addr, offset + 8),
+            PciIO.read(dev.addr, offset + 12),
+        )
+    };
+
+    let (bar_addr, _) = dev
+        .bars
+        .get(bar as usize)
+        .copied()
+        .flatten()
+        .ok_or("capability points at a missing bar")?
+        .unwrap_mem();
+    vmm::map_mmio(
+        PhysAddr::new(bar_addr as u64 + u64::from(bar_offset)),
+        length as usize,
+    )
+}
+
+impl Virtio {
+    /// picks the modern interface if the device has one, legacy otherwise
+    pub fn new(dev: &PciDevice) -> Result<Self, &'static str> {
+        let (mut common, mut notify, mut device) = (None, None, None);
+        let mut notify_multiplier = 0;
+
+        for cap in &dev.capabilities {
+            let PciCapability::Vendor(addr) = cap else {
+                continue;
+            };
+
+            // the first of each type is the one to use
+            let typ =
+                unsafe { (PciIO.read(dev.addr, addr.offset) >> 24) as u8 };
+            match typ {
+                CAP_COMMON_CFG if common.is_none() => {
+                    common = Some(cap_region(dev, addr.offset)?)
+                },
+                CAP_NOTIFY_CFG if notify.is_none() => {
+                    notify = Some(cap_region(dev, addr.offset)?);
+                    notify_multiplier =
+                        unsafe { PciIO.read(dev.addr, addr.offset + 16) };
+                },
+                CAP_DEVICE_CFG if device.is_none() => {
+                    device = Some(cap_region(dev, addr.offset)?)
+                },
+                _ => {},
+            }
+        }
+
+        // memory and io space plus bus master, the queues are dma. the
+        // queues get polled so the pin interrupt stays off.
+        unsafe {
+            let cmd = PciIO.read(dev.addr, 0x04);
+            PciIO.write(
+                dev.addr,
+                0x04,
+                cmd | (1 << 0) | (1 << 1) | (1 << 2) | (1 << 10),
+            );
+        }
+
+        let transport = match (common, notify, device) {
+            (Some(common), Some(notify), Some(device)) => {
+                Transport::Modern { common, notify, notify_multiplier, device }
+            },
+            _ => match dev.bars[0] {
+                Some(bar @ pci_types::Bar::Io { .. }) => {
+                    Transport::Legacy { port: bar.unwrap_io() as u16 }
+                },
+                _ => return Err("no modern capabilities and no legacy io bar"),
+            },
+        };
+
+        let virtio = Self { transport };
+        virtio.set_status(0);
+        virtio.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        Ok(virtio)
+    }
+
+    pub fn is_modern(&self) -> bool {
+        matches!(self.transport, Transport::Modern { .. })
+    }
+
+    fn common<T>(&self, reg: usize) -> *mut T {
+        match self.transport {
+            Transport::Modern { common, .. } => {
+                (common.as_u64() as usize + reg) as *mut T
+            },
+            Transport::Legacy { .. } => {
+                unreachable!("legacy has no common config")
+            },
+        }
+    }
+
+    /// 64 bit fields go in as two halves, not every device takes 8 bytes
+    fn write_common64(&self, reg: usize, addr: PhysAddr) {
+        unsafe {
+            self.common::<u32>(reg).write_volatile(addr.as_u64() as u32);
+            self.common::<u32>(reg + 4)
+                .write_volatile((addr.as_u64() >> 32) as u32);
+        }
+    }
+
+    fn status(&self) -> u8 {
+        match self.transport {
+            Transport::Modern { .. } => unsafe {
+                self.common::<u8>(COMMON_STATUS).read_volatile()
+            },
+            Transport::Legacy { port } => unsafe {
+                Port::<u8>::new(port + LEGACY_STATUS).read()
+            },
+        }
+    }
+
+    fn set_status(&self, status: u8) {
+        match self.transport {
+            Transport::Modern { .. } => unsafe {
+                self.common::<u8>(COMMON_STATUS).write_volatile(status)
+            },
+            Transport::Legacy { port } => unsafe {
+                Port::<u8>::new(port + LEGACY_STATUS).write(status)
+            },
+        }
+    }
+
+    /// takes whatever part of `wanted` the device offers and returns it
+    pub fn negotiate(&self, wanted: u64) -> Result<u64, &'static str> {
+        let features = match self.transport {
+            Transport::Modern { .. } => unsafe {
+                let mut offered = 0u64;
+                for half in 0..2u32 {
+                    self.common::<u32>(COMMON_DEVICE_FEATURE_SELECT)
+                        .write_volatile(half);
+                    let bits = self
+                        .common::<u32>(COMMON_DEVICE_FEATURE)
+                        .read_volatile();
+                    offered |= u64::from(bits) << (half * 32);
+                }
+                if offered & F_VERSION_1 == 0 {
+                    self.set_status(STATUS_FAILED);
+                    return Err("modern device without VERSION_1");
+                }
+
+                let features = offered & (wanted | F_VERSION_1);
+                for half in 0..2u32 {
+                    self.common::<u32>(COMMON_DRIVER_FEATURE_SELECT)
+                        .write_volatile(half);
+                    self.common::<u32>(COMMON_DRIVER_FEATURE)
+                        .write_volatile((features >> (half * 32)) as u32);
+                }
+                features
+            },
+            // legacy only has the low 32 feature bits
+            Transport::Legacy { port } => unsafe {
+                let offered = u64::from(
+                    Port::<u32>::new(port + LEGACY_DEVICE_FEATURES).read(),
+                );
+                let features = offered & wanted & 0xFFFF_FFFF;
+                Port::<u32>::new(port + LEGACY_DRIVER_FEATURES)
+                    .write(features as u32);
+                return Ok(features);
+            },
+        };
+
+        self.set_status(self.status() | STATUS_FEATURES_OK);
+        if self.status() & STATUS_FEATURES_OK == 0 {
+            self.set_status(STATUS_FAILED);
+            return Err("device didn't accept the features");
+        }
+        Ok(features)
+    }
+
+    /// the device is set up, it can start using the queues
+    pub fn driver_ok(&self) {
+        self.set_status(self.status() | STATUS_DRIVER_OK);
+    }
+
+    /// gives up on the device, it stops touching our memory
+    pub fn reset(&self) { self.set_status(0); }
+
+    pub fn config_read32(&self, offset: usize) -> u32 {
+        match self.transport {
+            Transport::Modern { device, .. } => unsafe {
+                ((device.as_u64() as usize + offset) as *const u32)
+                    .read_volatile()
+            },
+            Transport::Legacy { port } => unsafe {
+                Port::<u32>::new(port + LEGACY_DEVICE_CFG + offset as u16)
+                    .read()
+            },
+        }
+    }
+
+    /// read as two halves, fine for things that don't change like capacity
+    pub fn config_read64(&self, offset: usize) -> u64 {
+        u64::from(self.config_read32(offset))
+            | (u64::from(self.config_read32(offset + 4)) << 32)
+    }
+
+    /// allocates queue `index` and tells the device where it lives
+    pub fn setup_queue(&self, index: u16) -> Result<Virtqueue, &'static str> {
+        let size = match self.transport {
+            Transport::Modern { .. } => unsafe {
+                self.common::<u16>(COMMON_QUEUE_SELECT).write_volatile(index);
+                let max = self.common::<u16>(COMMON_QUEUE_SIZE).read_volatile();
+                let size = max.min(MAX_QUEUE_SIZE);
+                self.common::<u16>(COMMON_QUEUE_SIZE).write_volatile(size);
+                size
+            },
+            Transport::Legacy { port } => unsafe {
+                Port::<u16>::new(port + LEGACY_QUEUE_SELECT).write(index);
+                Port::<u16>::new(port + LEGACY_QUEUE_SIZE).read()
+            },
+        };
+        if size == 0 {
+            return Err("queue doesn't exist");
+        }
+
+        let mut queue = Virtqueue::new(index, size)?;
+
+        queue.notify = match self.transport {
+            Transport::Modern { notify, notify_multiplier, .. } => unsafe {
+                self.write_common64(COMMON_QUEUE_DESC, queue.desc_phys());
+                self.write_common64(COMMON_QUEUE_DRIVER, queue.avail_phys());
+                self.write_common64(COMMON_QUEUE_DEVICE, queue.used_phys());
+
+                let off =
+                    self.common::<u16>(COMMON_QUEUE_NOTIFY_OFF).read_volatile();
+                let notify = notify.as_u64()
+                    + u64::from(off) * u64::from(notify_multiplier);
+                self.common::<u16>(COMMON_QUEUE_ENABLE).write_volatile(1);
+                Notify::Mmio(notify)
+            },
+            Transport::Legacy { port } => unsafe {
+                let pfn = queue.desc_phys().as_u64() / PAGE_SIZE as u64;
+                Port::<u32>::new(port + LEGACY_QUEUE_ADDRESS).write(pfn as u32);
+                Notify::Port(port + LEGACY_QUEUE_NOTIFY)
+            },
+        };
+        Ok(queue)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Desc {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[derive(Clone, Copy)]
+enum Notify {
+    None,
+    Mmio(u64),
+    Port(u16),
+}
+
+/// one buffer of a request, `writable` ones are filled in by the device
+#[derive(Clone, Copy)]
+pub struct Buffer {
+    pub addr: PhysAddr,
+    pub len: u32,
+    pub writable: bool,
+}
+
+/// a split virtqueue, laid out the legacy way so both transports can use it:
+/// descriptors, then the avail ring, then the used ring on the next page.
+pub struct Virtqueue {
+    index: u16,
+    size: u16,
+    phys: PhysAddr,
+    pages: usize,
+    desc: *mut Desc,
+    /// flags, idx, then `size` ring entries
+    avail: *mut u16,
+    /// flags, idx, then `size` (id, len) pairs
+    used: *mut u16,
+    used_offset: usize,
+    free: Vec<u16>,
+    avail_idx: u16,
+    last_used: u16,
+    notify: Notify,
+}
+
+unsafe impl Send for Virtqueue {}
+
+fn align_page(n: usize) -> usize { n.div_ceil(PAGE_SIZE) * PAGE_SIZE }
+
+impl Virtqueue {
+    fn new(index: u16, size: u16) -> Result<Self, &'static str> {
+        let n = size as usize;
+        let used_offset = align_page(16 * n + 2 * (3 + n));
+        let pages = (used_offset + align_page(2 * 3 + 8 * n)) / PAGE_SIZE;
+
+        let phys = PhysAddr::new(pmm::alloc_contiguous(pages).ok_or("oom")?);
+        let virt = vmm::phys_to_virt(phys);
+        unsafe {
+            core::ptr::write_bytes(
+                virt.as_mut_ptr::<u8>(),
+                0,
+                pages * PAGE_SIZE,
+            );
+            // nothing is listening, see `wait`
+            virt.as_mut_ptr::<u16>()
+                .byte_add(16 * n)
+                .write_volatile(AVAIL_F_NO_INTERRUPT);
+        }
+
+        Ok(Self {
+            index,
+            size,
+            phys,
+            pages,
+            desc: virt.as_mut_ptr(),
+            avail: (virt.as_u64() as usize + 16 * n) as *mut u16,
+            used: (virt.as_u64() as usize + used_offset) as *mut u16,
+            used_offset,
+            free: (0..size).rev().collect(),
+            avail_idx: 0,
+            last_used: 0,
+            notify: Notify::None,
+        })
+    }
+
+    fn desc_phys(&self) -> PhysAddr { self.phys }
+
+    fn avail_phys(&self) -> PhysAddr { self.phys + 16 * self.size as u64 }
+
+    fn used_phys(&self) -> PhysAddr { self.phys + self.used_offset as u64 }
+
+    /// chains the buffers into one request and hands it to the device,
+    /// returns the head descriptor that comes back in the used ring
+    pub fn submit(&mut self, buffers: &[Buffer]) -> Result<u16, &'static str> {
+        if buffers.is_empty() || buffers.len() > self.free.len() {
+            return Err("not enough free descriptors");
+        }
+
+        let ids: Vec<u16> =
+            (0..buffers.len()).filter_map(|_| self.free.pop()).collect();
+        for (i, (buffer, id)) in buffers.iter().zip(&ids).enumerate() {
+            let next = ids.get(i + 1).copied();
+            let mut flags = if buffer.writable { DESC_F_WRITE } else { 0 };
+            if next.is_some() {
+                flags |= DESC_F_NEXT;
+            }
+
+            let desc = Desc {
+                addr: buffer.addr.as_u64(),
+                len: buffer.len,
+                flags,
+                next: next.unwrap_or(0),
+            };
+            unsafe { self.desc.add(*id as usize).write_volatile(desc) };
+        }
+
+        let head = ids[0];
+        unsafe {
+            let slot = 2 + (self.avail_idx % self.size) as usize;
+            self.avail.add(slot).write_volatile(head);
+            // the entry has to be there before the device sees the new idx
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            self.avail.add(1).write_volatile(self.avail_idx);
+            fence(Ordering::SeqCst);
+        }
+
+        self.kick();
+        Ok(head)
+    }
+
+    fn kick(&self) {
+        match self.notify {
+            Notify::Mmio(addr) => unsafe {
+                (addr as *mut u16).write_volatile(self.index)
+            },
+            Notify::Port(port) => unsafe {
+                Port::<u16>::new(port).write(self.index)
+            },
+            Notify::None => {},
+        }
+    }
+
+    /// the next finished request as (head, bytes written by the device),
+    /// its descriptors go back on the free list
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        let used_idx = unsafe { self.used.add(1).read_volatile() };
+        if used_idx == self.last_used {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+
+        let slot = (self.last_used % self.size) as usize;
+        let elem = unsafe { (self.used.add(2) as *const u32).add(slot * 2) };
+        let (id, len) = unsafe {
+            (elem.read_volatile() as u16, elem.add(1).read_volatile())
+        };
+        self.last_used = self.last_used.wrapping_add(1);
+
+        let mut desc = id;
+        loop {
+            self.free.push(desc);
+            let entry = unsafe { self.desc.add(desc as usize).read_volatile() };
+            if entry.flags & DESC_F_NEXT == 0 {
+                break;
+            }
+            desc = entry.next;
+        }
+
+        Some((id, len))
+    }
+
+    /// polls until `head` comes back. disks get read for their partition
+    /// tables before the scheduler is up, and the caller holds the queue's
+    /// spinlock, so sleeping here would leave everyone else spinning on it.
+    /// no queue interrupt gets requested either, nothing would wake us
+    /// if it times out the device may still own the buffers, so the caller
+    /// has to reset it before any of them are used again
+    pub fn wait(&mut self, head: u16) -> Result<u32, &'static str> {
+        for _ in 0..TIMEOUT * 1000 {
+            match self.pop_used() {
+                Some((id, len)) if id == head => return Ok(len),
+                Some(_) => continue,
+                None => udelay(1),
+            }
+        }
+        Err("device didn't answer")
+    }
+}
+
+impl Drop for Virtqueue {
+    /// only safe once the device has been reset
+    fn drop(&mut self) {
+        for page in 0..self.pages {
+            pmm::free(self.phys.as_u64() + (page * PAGE_SIZE) as u64);
+        }
+    }
+}
@@ -1,8 +1,7 @@
 use spin::{Lazy, Mutex};
 use x86_64::instructions::port::Port;
-use x86_64::structures::idt::InterruptStackFrame;
 
-use crate::arch::apic;
+use crate::arch::irq;
 use crate::drivers::ps2::keyboard_defs::{
     scancode_to_ascii, scancode_to_keycode,
 };
@@ -55,16 +54,13 @@ pub static KEYBOARD: Lazy<Mutex<KeyboardPublisher>> =
 
 static SHIFT_PRESSED: Lazy<Mutex<bool>> = Lazy::new(|| Mutex::new(false));
 
-pub extern "x86-interrupt" fn keyboard_interrupt_handler(
-    _frame: InterruptStackFrame,
-) {
+fn keyboard_interrupt_handler() -> bool {
     let mut pending_port: Port<u8> = Port::new(KB_PENDING);
     let mut data_port: Port<u8> = Port::new(KB_DEVICE);
 
     let pending = unsafe { pending_port.read() };
     if pending & 0x1 == 0 {
-        apic::eoi();
-        return;
+        return false;
     }
 
     let scancode = unsafe { data_port.read() };
@@ -90,7 +86,7 @@ pub extern "x86-interrupt" fn keyboard_interrupt_handler(
         KEYBOARD.lock().publish(KeyEvent::Ascii(ascii));
     }
 
-    apic::eoi();
+    true
 }
 
 const MAX_DRAIN: usize = 32;
@@ -107,5 +103,11 @@ pub fn install() {
         }
         let _ = unsafe { data_port.read() };
     }
+
+    if let Err(e) = irq::request_irq(1, "keyboard", keyboard_interrupt_handler)
+    {
+        log::error!("ps2::keyboard: failed to get irq 1: {}", e);
+        return;
+    }
     log::debug!("ps2::keyboard installed!");
 }
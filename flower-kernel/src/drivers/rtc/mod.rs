@@ -0,0 +1,146 @@
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+// setting the top bit on the address port keeps nmi off while we poke it
+const CMOS_NMI_DISABLE: u8 = 0x80;
+
+const RTC_SECONDS: u8 = 0x00;
+const RTC_MINUTES: u8 = 0x02;
+const RTC_HOURS: u8 = 0x04;
+const RTC_DAY: u8 = 0x07;
+const RTC_MONTH: u8 = 0x08;
+const RTC_YEAR: u8 = 0x09;
+const RTC_STATUS_A: u8 = 0x0A;
+const RTC_STATUS_B: u8 = 0x0B;
+
+const STATUS_A_UPDATING: u8 = 0x80;
+const STATUS_B_24_HOUR: u8 = 0x02;
+const STATUS_B_BINARY: u8 = 0x04;
+const HOUR_PM: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RtcTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl RtcTime {
+    /// seconds since 1970-01-01 00:00:00 utc
+    pub fn unix_timestamp(&self) -> u64 {
+        // days_from_civil, from howard hinnant's date algorithms
+        let (year, month) = (self.year as i64, self.month as i64);
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let yoe = year - era * 400;
+        let mp = (month + 9) % 12;
+        let doy = (153 * mp + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+
+        let secs = days * 86400
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64;
+        secs.max(0) as u64
+    }
+
+    /// the other way around, civil_from_days from the same place
+    pub fn from_unix(secs: u64) -> Self {
+        let days = (secs / 86400) as i64 + 719468;
+        let secs = secs % 86400;
+        let era = days.div_euclid(146097);
+        let doe = days - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (secs / 3600) as u8,
+            minute: (secs / 60 % 60) as u8,
+            second: (secs % 60) as u8,
+        }
+    }
+}
+
+fn read_register(reg: u8) -> u8 {
+    let mut address: Port<u8> = Port::new(CMOS_ADDRESS);
+    let mut data: Port<u8> = Port::new(CMOS_DATA);
+    unsafe {
+        address.write(CMOS_NMI_DISABLE | reg);
+        data.read()
+    }
+}
+
+fn updating() -> bool { read_register(RTC_STATUS_A) & STATUS_A_UPDATING != 0 }
+
+fn read_raw() -> RtcTime {
+    while updating() {
+        core::hint::spin_loop();
+    }
+
+    RtcTime {
+        year: read_register(RTC_YEAR) as u16,
+        month: read_register(RTC_MONTH),
+        day: read_register(RTC_DAY),
+        hour: read_register(RTC_HOURS),
+        minute: read_register(RTC_MINUTES),
+        second: read_register(RTC_SECONDS),
+    }
+}
+
+fn from_bcd(value: u8) -> u8 { (value & 0x0F) + (value >> 4) * 10 }
+
+/// reads the wall clock from the cmos. the rtc has no time zone, we treat
+/// it as utc like everyone else.
+pub fn read() -> RtcTime {
+    interrupts::without_interrupts(|| {
+        // the clock can tick between reads, so read until two agree
+        let mut time = read_raw();
+        loop {
+            let again = read_raw();
+            if again == time {
+                break;
+            }
+            time = again;
+        }
+
+        let status = read_register(RTC_STATUS_B);
+        let pm = time.hour & HOUR_PM != 0;
+        time.hour &= !HOUR_PM;
+
+        if status & STATUS_B_BINARY == 0 {
+            time.second = from_bcd(time.second);
+            time.minute = from_bcd(time.minute);
+            time.hour = from_bcd(time.hour);
+            time.day = from_bcd(time.day);
+            time.month = from_bcd(time.month);
+            time.year = from_bcd(time.year as u8) as u16;
+        }
+
+        if status & STATUS_B_24_HOUR == 0 {
+            // 12 hour clock, 12am is 0 and 12pm is 12
+            time.hour %= 12;
+            if pm {
+                time.hour += 12;
+            }
+        }
+
+        // no century register without digging through the fadt, and this
+        // won't be running in the 1900s
+        time.year += if time.year < 70 { 2000 } else { 1900 };
+        time
+    })
+}
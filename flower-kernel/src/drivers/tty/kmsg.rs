@@ -0,0 +1,157 @@
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use log::{Level, LevelFilter};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::arch;
+
+const KMSG_RECORDS: usize = 1024;
+const KMSG_MESSAGE_MAX: usize = 160;
+
+#[derive(Clone, Copy)]
+pub struct KmsgRecord {
+    pub seq: u64,
+    pub level: u8,
+    pub ticks: u64,
+    pub file: Option<&'static str>,
+    pub line: u32,
+    len: u8,
+    message: [u8; KMSG_MESSAGE_MAX],
+}
+
+impl KmsgRecord {
+    const EMPTY: Self = Self {
+        seq: 0,
+        level: 0,
+        ticks: 0,
+        file: None,
+        line: 0,
+        len: 0,
+        message: [0; KMSG_MESSAGE_MAX],
+    };
+
+    pub fn message(&self) -> &str {
+        // the writer only ever cuts on a char boundary
+        core::str::from_utf8(&self.message[..self.len as usize]).unwrap_or("")
+    }
+}
+
+impl fmt::Display for KmsgRecord {
+    /// `<level>[seconds.millis] file:line message`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "<{}>[{:>5}.{:03}] {}:{} {}",
+            self.level,
+            self.ticks / 1000,
+            self.ticks % 1000,
+            self.file.unwrap_or("unknown"),
+            self.line,
+            self.message()
+        )
+    }
+}
+
+/// writes into a fixed buffer, silently dropping whatever doesn't fit
+struct TruncatingWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for TruncatingWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let space = self.buf.len() - self.len;
+        let mut take = s.len().min(space);
+        while !s.is_char_boundary(take) {
+            take -= 1;
+        }
+
+        self.buf[self.len..self.len + take]
+            .copy_from_slice(&s.as_bytes()[..take]);
+        self.len += take;
+        Ok(())
+    }
+}
+
+struct KmsgRing {
+    records: [KmsgRecord; KMSG_RECORDS],
+    // sequence number of the next record
+    next: u64,
+}
+
+impl KmsgRing {
+    fn oldest(&self) -> u64 { self.next.saturating_sub(KMSG_RECORDS as u64) }
+}
+
+static KMSG: Mutex<KmsgRing> = Mutex::new(KmsgRing {
+    records: [KmsgRecord::EMPTY; KMSG_RECORDS],
+    next: 0,
+});
+
+static CONSOLE_LEVEL: AtomicUsize =
+    AtomicUsize::new(LevelFilter::Info as usize);
+
+/// stores a log record in the ring, overwriting the oldest one when full
+pub fn push(record: &log::Record) {
+    let mut entry = KmsgRecord {
+        level: record.level() as u8,
+        ticks: arch::ticks(),
+        file: record.file_static(),
+        line: record.line().unwrap_or(0),
+        ..KmsgRecord::EMPTY
+    };
+
+    let mut writer = TruncatingWriter { buf: &mut entry.message, len: 0 };
+    let _ = writer.write_fmt(*record.args());
+    entry.len = writer.len as u8;
+
+    interrupts::without_interrupts(|| {
+        let mut ring = KMSG.lock();
+        entry.seq = ring.next;
+        let slot = (ring.next % KMSG_RECORDS as u64) as usize;
+        ring.records[slot] = entry;
+        ring.next += 1;
+    });
+}
+
+/// gets the record with the given sequence number, or the oldest one still
+/// around if it was overwritten. returns None once we're past the newest.
+pub fn read(seq: u64) -> Option<KmsgRecord> {
+    interrupts::without_interrupts(|| {
+        let ring = KMSG.lock();
+        if seq >= ring.next {
+            return None;
+        }
+
+        let seq = seq.max(ring.oldest());
+        Some(ring.records[(seq % KMSG_RECORDS as u64) as usize])
+    })
+}
+
+/// sequence number of the oldest record still in the ring
+pub fn first_seq() -> u64 {
+    interrupts::without_interrupts(|| KMSG.lock().oldest())
+}
+
+/// sequence number the next record will get
+pub fn next_seq() -> u64 { interrupts::without_interrupts(|| KMSG.lock().next) }
+
+/// records at or below this level are also printed to the console
+pub fn console_level() -> LevelFilter {
+    match CONSOLE_LEVEL.load(Ordering::Relaxed) {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+pub fn set_console_level(level: LevelFilter) {
+    CONSOLE_LEVEL.store(level as usize, Ordering::Relaxed);
+}
+
+pub fn should_print(level: Level) -> bool { level <= console_level() }
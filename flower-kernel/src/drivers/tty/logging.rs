@@ -1,6 +1,7 @@
 use log::{Level, LevelFilter};
 use owo_colors::OwoColorize;
 
+use crate::drivers::tty::kmsg;
 use crate::println;
 
 struct FlowerLogger;
@@ -9,7 +10,13 @@ impl log::Log for FlowerLogger {
     fn enabled(&self, _: &log::Metadata) -> bool { true }
 
     fn log(&self, record: &log::Record) {
-        if self.enabled(record.metadata()) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        kmsg::push(record);
+
+        if kmsg::should_print(record.level()) {
             let content = record.args();
             match record.level() {
                 Level::Error => {
@@ -63,7 +70,10 @@ impl log::Log for FlowerLogger {
 }
 
 static LOG: FlowerLogger = FlowerLogger;
-const LOG_LEVEL: LevelFilter = LevelFilter::Info;
+
+// everything up to this level ends up in kmsg, the console level decides
+// what actually gets printed
+const LOG_LEVEL: LevelFilter = LevelFilter::Debug;
 
 pub fn install() {
     log::set_logger(&LOG).unwrap();
@@ -3,6 +3,7 @@ use core::fmt::Write;
 
 use x86_64::instructions::interrupts;
 
+pub mod kmsg;
 pub mod logging;
 pub mod serial;
 pub mod terminal;
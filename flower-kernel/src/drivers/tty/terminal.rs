@@ -4,8 +4,9 @@ use os_terminal::font::BitmapFont;
 use os_terminal::{DrawTarget, Terminal};
 use spin::MutexGuard;
 use spin::mutex::Mutex;
+use x86_64::instructions::interrupts;
 
-use crate::boot::limine::FRAMEBUFFER_REQUEST;
+use crate::drivers::video::{self, Framebuffer};
 
 pub struct FramebufferTerminal {
     buffer: *mut u8,
@@ -14,6 +15,32 @@ pub struct FramebufferTerminal {
 
     bpp: usize,
     pitch: usize,
+
+    // (size, shift) for each channel
+    red: (u8, u8),
+    green: (u8, u8),
+    blue: (u8, u8),
+}
+
+impl FramebufferTerminal {
+    fn new(fb: &Framebuffer) -> Self {
+        let info = &fb.info;
+        Self {
+            buffer: fb.addr,
+            width: info.width as usize,
+            height: info.height as usize,
+            bpp: info.bpp as usize,
+            pitch: info.pitch as usize,
+            red: (info.red_mask_size, info.red_mask_shift),
+            green: (info.green_mask_size, info.green_mask_shift),
+            blue: (info.blue_mask_size, info.blue_mask_shift),
+        }
+    }
+}
+
+#[inline(always)]
+fn pack_channel(value: u8, (size, shift): (u8, u8)) -> u32 {
+    ((value as u32) >> (8 - size.min(8))) << shift
 }
 
 unsafe impl Send for FramebufferTerminal {}
@@ -24,11 +51,26 @@ impl DrawTarget for FramebufferTerminal {
 
     #[inline(always)]
     fn draw_pixel(&mut self, x: usize, y: usize, rgb: os_terminal::Rgb) {
-        let offset = y * self.pitch + x * self.bpp / 8;
+        let bytes = self.bpp.div_ceil(8);
+        let offset = y * self.pitch + x * bytes;
+        let color = pack_channel(rgb.0, self.red)
+            | pack_channel(rgb.1, self.green)
+            | pack_channel(rgb.2, self.blue);
+
         unsafe {
-            let pixel = self.buffer.add(offset) as *mut u32;
-            *pixel =
-                (rgb.0 as u32) << 16 | (rgb.1 as u32) << 8 | (rgb.2 as u32);
+            let pixel = self.buffer.add(offset);
+            match bytes {
+                4 => *(pixel as *mut u32) = color,
+                2 => *(pixel as *mut u16) = color as u16,
+                _ => {
+                    let color = color.to_le_bytes();
+                    core::ptr::copy_nonoverlapping(
+                        color.as_ptr(),
+                        pixel,
+                        bytes,
+                    );
+                },
+            }
         }
     }
 }
@@ -36,27 +78,29 @@ impl DrawTarget for FramebufferTerminal {
 pub static CONTEXT: Mutex<Option<Terminal<FramebufferTerminal>>> =
     Mutex::new(None);
 
+fn create(fb: &Framebuffer) -> Terminal<FramebufferTerminal> {
+    let mut terminal =
+        Terminal::new(FramebufferTerminal::new(fb), Box::new(BitmapFont));
+    terminal.set_color_scheme(7);
+    terminal
+}
+
 pub fn install() {
-    if let Some(framebuffer) = FRAMEBUFFER_REQUEST
-        .get_response()
-        .expect("no framebuffer")
-        .framebuffers()
-        .next()
-    {
-        let term = FramebufferTerminal {
-            buffer: framebuffer.addr(),
-            width: framebuffer.width() as usize,
-            height: framebuffer.height() as usize,
-            bpp: framebuffer.bpp() as usize,
-            pitch: framebuffer.pitch() as usize,
-        };
-
-        let mut terminal = Terminal::new(term, Box::new(BitmapFont));
-        terminal.set_color_scheme(7);
-        *CONTEXT.lock() = Some(terminal);
+    if let Some(fb) = video::framebuffer() {
+        *CONTEXT.lock() = Some(create(&fb));
     }
 }
 
+/// rebuilds the terminal after the framebuffer changed size or format
+pub fn relayout(fb: &Framebuffer) {
+    interrupts::without_interrupts(|| {
+        let mut context = CONTEXT.lock();
+        if context.is_some() {
+            *context = Some(create(fb));
+        }
+    });
+}
+
 pub fn get() -> MutexGuard<'static, Option<Terminal<FramebufferTerminal>>> {
     CONTEXT.lock()
 }
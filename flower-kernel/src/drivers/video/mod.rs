@@ -0,0 +1,129 @@
+use alloc::boxed::Box;
+
+use flower_mono::structs::{FramebufferInfo, FramebufferMode};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::boot::limine::FRAMEBUFFER_REQUEST;
+use crate::drivers::tty::terminal;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Framebuffer {
+    /// start of buffer 0, the rest follow it
+    pub addr: *mut u8,
+    pub info: FramebufferInfo,
+}
+
+unsafe impl Send for Framebuffer {}
+unsafe impl Sync for Framebuffer {}
+
+impl Framebuffer {
+    /// start of the given buffer
+    pub fn buffer(&self, index: u32) -> *mut u8 {
+        unsafe { self.addr.add(index as usize * self.info.size()) }
+    }
+}
+
+pub trait DisplayDriver: Send {
+    /// switches to the given mode and returns the new framebuffer
+    fn set_mode(
+        &mut self,
+        mode: &FramebufferMode,
+    ) -> Result<Framebuffer, &'static str>;
+
+    /// makes the given buffer visible
+    fn flip(
+        &mut self,
+        fb: &Framebuffer,
+        buffer: u32,
+    ) -> Result<(), &'static str>;
+}
+
+static FRAMEBUFFER: Mutex<Option<Framebuffer>> = Mutex::new(None);
+static DRIVER: Mutex<Option<Box<dyn DisplayDriver>>> = Mutex::new(None);
+
+pub fn install() {
+    let Some(fb) = FRAMEBUFFER_REQUEST
+        .get_response()
+        .and_then(|response| response.framebuffers().next())
+    else {
+        log::error!("no framebuffer from the bootloader");
+        return;
+    };
+
+    let info = FramebufferInfo {
+        width: fb.width() as u32,
+        height: fb.height() as u32,
+        pitch: fb.pitch() as u32,
+        bpp: fb.bpp(),
+        buffers: 1,
+        front: 0,
+        red_mask_size: fb.red_mask_size(),
+        red_mask_shift: fb.red_mask_shift(),
+        green_mask_size: fb.green_mask_size(),
+        green_mask_shift: fb.green_mask_shift(),
+        blue_mask_size: fb.blue_mask_size(),
+        blue_mask_shift: fb.blue_mask_shift(),
+    };
+
+    *FRAMEBUFFER.lock() = Some(Framebuffer { addr: fb.addr(), info });
+}
+
+/// hands the display over to a driver that can change modes.
+/// `fb` replaces the bootloader framebuffer if the driver remapped it.
+pub fn register_driver(driver: Box<dyn DisplayDriver>, fb: Framebuffer) {
+    interrupts::without_interrupts(|| {
+        *DRIVER.lock() = Some(driver);
+        *FRAMEBUFFER.lock() = Some(fb);
+    });
+}
+
+/// gets the current framebuffer
+pub fn framebuffer() -> Option<Framebuffer> {
+    interrupts::without_interrupts(|| *FRAMEBUFFER.lock())
+}
+
+/// switches the display mode, the console is rebuilt for the new size
+pub fn set_mode(
+    mode: &FramebufferMode,
+) -> Result<FramebufferInfo, &'static str> {
+    let fb = interrupts::without_interrupts(|| {
+        let mut driver = DRIVER.lock();
+        let driver = driver.as_mut().ok_or("no display driver")?;
+
+        let fb = driver.set_mode(mode)?;
+        *FRAMEBUFFER.lock() = Some(fb);
+        Ok::<_, &'static str>(fb)
+    })?;
+
+    terminal::relayout(&fb);
+
+    log::info!(
+        "video: switched to {}x{}x{} ({} buffers)",
+        fb.info.width,
+        fb.info.height,
+        fb.info.bpp,
+        fb.info.buffers
+    );
+
+    Ok(fb.info)
+}
+
+/// makes the given buffer visible
+pub fn flip(buffer: u32) -> Result<(), &'static str> {
+    interrupts::without_interrupts(|| {
+        let mut driver = DRIVER.lock();
+        let driver = driver.as_mut().ok_or("no display driver")?;
+
+        let mut fb = FRAMEBUFFER.lock();
+        let fb = fb.as_mut().ok_or("no framebuffer")?;
+
+        if buffer >= fb.info.buffers {
+            return Err("no such buffer");
+        }
+
+        driver.flip(fb, buffer)?;
+        fb.info.front = buffer;
+        Ok(())
+    })
+}
@@ -29,8 +29,12 @@ unsafe extern "C" fn kmain() -> ! {
 
     arch::acpi::install();
     arch::apic::install();
+    arch::acpi::power::install();
+    system::random::install();
+    system::time::install();
 
     drivers::ps2::install();
+    drivers::video::install();
     drivers::pci::install();
 
     system::syscalls::install();
@@ -41,6 +45,8 @@ unsafe extern "C" fn kmain() -> ! {
     system::vfs::install();
     drivers::tty::terminal::install();
     system::mem::self_test();
+    system::proc::spawn("acpi-events", arch::acpi::power::event_loop);
+    system::proc::spawn("writeback", system::cache::writeback_loop);
     system::proc::spawn("userland-entry", user::entry);
     arch::halt();
 }
@@ -0,0 +1,439 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+use x86_64::PhysAddr;
+use x86_64::instructions::interrupts;
+
+use crate::arch;
+use crate::arch::layout::PAGE_SIZE;
+use crate::system::mem::{pmm, vmm};
+use crate::system::proc;
+
+/// every cached block is one page
+pub const BLOCK_SIZE: usize = PAGE_SIZE;
+
+/// below this many free pages the cache starts giving pages back
+const LOW_FREE_PAGES: usize = 512;
+
+/// how often the writeback thread wakes up, in ms
+const WRITEBACK_INTERVAL: u64 = 1000;
+
+/// dirty blocks older than this get written back, in ms
+const DIRTY_EXPIRE: u64 = 5000;
+
+/// where cached blocks come from and go back to, a disk or a file
+pub trait Backing: Send + Sync {
+    /// fills a whole block, anything past the end reads as zeroes
+    fn read_block(
+        &self,
+        block: u64,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str>;
+
+    /// writes a whole block back, anything past the end is dropped
+    fn write_block(&self, block: u64, buf: &[u8]) -> Result<(), &'static str>;
+
+    /// called after a sync wrote everything back
+    fn flush(&self) -> Result<(), &'static str> { Ok(()) }
+}
+
+struct Entry {
+    page: PhysAddr,
+    /// tick it got dirty at, None if it matches the backing
+    dirty: Option<u64>,
+    /// stamp of the last write, a writeback only cleans the block if
+    /// nobody wrote to it while it was out
+    written: u64,
+    /// position in the lru, bigger is more recent
+    stamp: u64,
+}
+
+impl Entry {
+    fn data(&mut self) -> &mut [u8] {
+        let virt = vmm::phys_to_virt(self.page);
+        unsafe {
+            core::slice::from_raw_parts_mut(virt.as_mut_ptr(), BLOCK_SIZE)
+        }
+    }
+}
+
+/// a dirty block copied out for writing back
+struct Dirty {
+    owner: Arc<dyn Backing>,
+    data: Vec<u8>,
+    written: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub blocks: usize,
+    pub dirty: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub writebacks: u64,
+}
+
+struct Cache {
+    owners: BTreeMap<usize, Arc<dyn Backing>>,
+    next_owner: usize,
+    blocks: BTreeMap<(usize, u64), Entry>,
+    /// stamp to key, the first one is the least recently used
+    lru: BTreeMap<u64, (usize, u64)>,
+    next_stamp: u64,
+    stats: CacheStats,
+}
+
+static CACHE: Mutex<Cache> = Mutex::new(Cache {
+    owners: BTreeMap::new(),
+    next_owner: 1,
+    blocks: BTreeMap::new(),
+    lru: BTreeMap::new(),
+    next_stamp: 0,
+    stats: CacheStats {
+        blocks: 0,
+        dirty: 0,
+        hits: 0,
+        misses: 0,
+        evictions: 0,
+        writebacks: 0,
+    },
+});
+
+impl Cache {
+    fn touch(&mut self, key: (usize, u64)) {
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        if let Some(entry) = self.blocks.get_mut(&key) {
+            self.lru.remove(&entry.stamp);
+            entry.stamp = stamp;
+            self.lru.insert(stamp, key);
+        }
+    }
+
+    fn remove(&mut self, key: (usize, u64)) {
+        if let Some(entry) = self.blocks.remove(&key) {
+            self.lru.remove(&entry.stamp);
+            pmm::free(entry.page.as_u64());
+            self.stats.blocks -= 1;
+            if entry.dirty.is_some() {
+                self.stats.dirty -= 1;
+            }
+        }
+    }
+
+    /// copies a dirty block out so it can be written back unlocked
+    fn dirty_copy(
+        &mut self,
+        key: (usize, u64),
+    ) -> Result<Option<Dirty>, &'static str> {
+        let owner = self.owners.get(&key.0).ok_or("no such cache owner")?;
+        let Some(entry) = self.blocks.get_mut(&key) else {
+            return Ok(None);
+        };
+        if entry.dirty.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Dirty {
+            owner: owner.clone(),
+            data: entry.data().to_vec(),
+            written: entry.written,
+        }))
+    }
+
+    /// marks a block clean after its copy from `written` made it out
+    fn written_back(&mut self, key: (usize, u64), written: u64) {
+        let Some(entry) = self.blocks.get_mut(&key) else {
+            return;
+        };
+        if entry.dirty.is_some() && entry.written == written {
+            entry.dirty = None;
+            self.stats.dirty -= 1;
+        }
+        self.stats.writebacks += 1;
+    }
+
+    /// drops the least recently used clean block, dirty ones stay until
+    /// they've made it to the backing
+    fn evict_one(&mut self) -> bool {
+        let clean = self
+            .lru
+            .values()
+            .find(|key| self.blocks.get(key).is_some_and(|e| e.dirty.is_none()))
+            .copied();
+        let Some(key) = clean else {
+            return false;
+        };
+
+        self.remove(key);
+        self.stats.evictions += 1;
+        true
+    }
+
+    /// dirty blocks, least recently used first
+    fn dirty(&self, owner: Option<usize>) -> Vec<(usize, u64)> {
+        self.lru
+            .values()
+            .filter(|key| owner.is_none_or(|owner| key.0 == owner))
+            .filter(|key| {
+                self.blocks.get(key).is_some_and(|e| e.dirty.is_some())
+            })
+            .copied()
+            .collect()
+    }
+}
+
+/// writes a block back with the cache unlocked, the device can take a
+/// while and interrupts have to get through meanwhile
+fn write_back(key: (usize, u64)) -> Result<(), &'static str> {
+    let Some(dirty) =
+        interrupts::without_interrupts(|| CACHE.lock().dirty_copy(key))?
+    else {
+        return Ok(());
+    };
+
+    dirty.owner.write_block(key.1, &dirty.data)?;
+    interrupts::without_interrupts(|| {
+        CACHE.lock().written_back(key, dirty.written)
+    });
+    Ok(())
+}
+
+/// frees up a block, writing dirty ones back if nothing is clean. a dirty
+/// block whose write fails stays, false if nothing could go
+fn reclaim() -> bool {
+    if interrupts::without_interrupts(|| CACHE.lock().evict_one()) {
+        return true;
+    }
+
+    let dirty = interrupts::without_interrupts(|| CACHE.lock().dirty(None));
+    for key in dirty {
+        if let Err(e) = write_back(key) {
+            log::error!(
+                "cache: writeback of block {} of {}: {}",
+                key.1,
+                key.0,
+                e
+            );
+            continue;
+        }
+        if interrupts::without_interrupts(|| CACHE.lock().evict_one()) {
+            return true;
+        }
+    }
+    false
+}
+
+/// gives pages back while the pmm is running low
+fn shrink() {
+    while pmm::free_pages().unwrap_or(0) < LOW_FREE_PAGES && reclaim() {}
+}
+
+/// reads a block into the cache, the read happens with the cache unlocked
+fn load(key: (usize, u64)) -> Result<(), &'static str> {
+    let owner = interrupts::without_interrupts(|| {
+        let mut cache = CACHE.lock();
+        cache.stats.misses += 1;
+        cache.owners.get(&key.0).cloned()
+    })
+    .ok_or("no such cache owner")?;
+    shrink();
+
+    let page = loop {
+        if let Some(page) = pmm::alloc() {
+            break PhysAddr::new(page);
+        }
+        if !reclaim() {
+            return Err("oom");
+        }
+    };
+    let mut entry = Entry { page, dirty: None, written: 0, stamp: 0 };
+    if let Err(e) = owner.read_block(key.1, entry.data()) {
+        pmm::free(page.as_u64());
+        return Err(e);
+    }
+
+    interrupts::without_interrupts(|| {
+        let mut cache = CACHE.lock();
+        // someone else read it in meanwhile, or the owner went away
+        if cache.blocks.contains_key(&key) || !cache.owners.contains_key(&key.0)
+        {
+            pmm::free(page.as_u64());
+            return;
+        }
+        cache.blocks.insert(key, entry);
+        cache.stats.blocks += 1;
+        cache.touch(key);
+    });
+    Ok(())
+}
+
+/// runs `f` on a cached block with the cache locked, reading it in first
+/// if it isn't cached yet
+fn with_block<R>(
+    key: (usize, u64),
+    mut f: impl FnMut(&mut Cache, (usize, u64)) -> R,
+) -> Result<R, &'static str> {
+    let mut missed = false;
+    loop {
+        let done = interrupts::without_interrupts(|| {
+            let mut cache = CACHE.lock();
+            if !cache.blocks.contains_key(&key) {
+                return None;
+            }
+            if !missed {
+                cache.stats.hits += 1;
+            }
+            cache.touch(key);
+            Some(f(&mut cache, key))
+        });
+        if let Some(result) = done {
+            return Ok(result);
+        }
+
+        // it can get evicted again before we look, then just go around
+        missed = true;
+        load(key)?;
+    }
+}
+
+/// hands out an owner id for `backing`, its blocks get cached under it
+pub fn register(backing: Arc<dyn Backing>) -> usize {
+    interrupts::without_interrupts(|| {
+        let mut cache = CACHE.lock();
+        let id = cache.next_owner;
+        cache.next_owner += 1;
+        cache.owners.insert(id, backing);
+        id
+    })
+}
+
+/// writes back and forgets everything `owner` had cached
+pub fn unregister(owner: usize) {
+    if let Err(e) = sync_owner(owner) {
+        log::error!("cache: owner {} went away unsynced: {}", owner, e);
+    }
+
+    interrupts::without_interrupts(|| {
+        let mut cache = CACHE.lock();
+        let keys: Vec<(usize, u64)> = cache
+            .blocks
+            .range((owner, 0)..=(owner, u64::MAX))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            cache.remove(key);
+        }
+        cache.owners.remove(&owner);
+    })
+}
+
+/// copies out of a block starting `offset` bytes in
+pub fn read(
+    owner: usize,
+    block: u64,
+    offset: usize,
+    buf: &mut [u8],
+) -> Result<(), &'static str> {
+    if offset + buf.len() > BLOCK_SIZE {
+        return Err("read crosses a block");
+    }
+
+    with_block((owner, block), |cache, key| {
+        let entry = cache.blocks.get_mut(&key).unwrap();
+        buf.copy_from_slice(&entry.data()[offset..offset + buf.len()]);
+    })
+}
+
+/// copies into a block starting `offset` bytes in, it goes to the backing
+/// on the next writeback or sync
+pub fn write(
+    owner: usize,
+    block: u64,
+    offset: usize,
+    buf: &[u8],
+) -> Result<(), &'static str> {
+    if offset + buf.len() > BLOCK_SIZE {
+        return Err("write crosses a block");
+    }
+
+    with_block((owner, block), |cache, key| {
+        let entry = cache.blocks.get_mut(&key).unwrap();
+        entry.data()[offset..offset + buf.len()].copy_from_slice(buf);
+        entry.written = entry.stamp;
+
+        if entry.dirty.is_none() {
+            entry.dirty = Some(arch::ticks());
+            cache.stats.dirty += 1;
+        }
+    })
+}
+
+/// writes back everything `owner` has dirty, then flushes it
+pub fn sync_owner(owner: usize) -> Result<(), &'static str> {
+    let (dirty, backing) = interrupts::without_interrupts(|| {
+        let cache = CACHE.lock();
+        (cache.dirty(Some(owner)), cache.owners.get(&owner).cloned())
+    });
+
+    let mut result = Ok(());
+    for key in dirty {
+        result = result.and(write_back(key));
+    }
+    if let Some(backing) = backing {
+        result = result.and(backing.flush());
+    }
+    result
+}
+
+/// writes back every dirty block there is
+pub fn sync() {
+    let owners: Vec<usize> = interrupts::without_interrupts(|| {
+        CACHE.lock().owners.keys().copied().collect()
+    });
+
+    for owner in owners {
+        if let Err(e) = sync_owner(owner) {
+            log::error!("cache: sync of owner {} failed: {}", owner, e);
+        }
+    }
+}
+
+pub fn stats() -> CacheStats {
+    interrupts::without_interrupts(|| CACHE.lock().stats)
+}
+
+/// the writeback thread, writes out blocks that have been dirty a while
+/// and gives memory back if someone else ate it
+pub fn writeback_loop() {
+    loop {
+        proc::sleep(WRITEBACK_INTERVAL);
+        shrink();
+
+        let now = arch::ticks();
+        let expired: Vec<(usize, u64)> = interrupts::without_interrupts(|| {
+            CACHE
+                .lock()
+                .blocks
+                .iter()
+                .filter(|(_, entry)| {
+                    entry.dirty.is_some_and(|since| now - since >= DIRTY_EXPIRE)
+                })
+                .map(|(key, _)| *key)
+                .collect()
+        });
+
+        for key in expired {
+            if let Err(e) = write_back(key) {
+                log::error!(
+                    "cache: writeback of block {} of {}: {}",
+                    key.1,
+                    key.0,
+                    e
+                );
+            }
+        }
+    }
+}
@@ -1,9 +1,10 @@
 use limine::memory_map::EntryType;
 use spin::Mutex;
-use x86_64::{VirtAddr, align_up};
+use x86_64::{PhysAddr, VirtAddr, align_up};
 
 use crate::arch::layout::PAGE_SIZE;
 use crate::boot;
+use crate::system::mem::vmm;
 
 static PMM: Mutex<Option<BitmapAllocator>> = Mutex::new(None);
 
@@ -56,6 +57,28 @@ impl BitmapAllocator {
         None
     }
 
+    /// first fit for `count` pages in a row
+    fn alloc_pages(&mut self, count: usize) -> Option<u64> {
+        let mut run = 0;
+        for i in 0..self.total_pages {
+            if self.test_bit(i) {
+                run = 0;
+                continue;
+            }
+
+            run += 1;
+            if run == count {
+                let start = i + 1 - count;
+                for page in start..=i {
+                    self.set_bit(page);
+                }
+                self.free_pages -= count;
+                return Some((start * PAGE_SIZE) as u64);
+            }
+        }
+        None
+    }
+
     fn free_page(&mut self, addr: u64) {
         let page = (addr as usize) / PAGE_SIZE;
         if page < self.total_pages && self.test_bit(page) {
@@ -175,12 +198,46 @@ pub fn alloc() -> Option<u64> {
     if let Some(pmm) = PMM.lock().as_mut() { pmm.alloc_page() } else { None }
 }
 
+/// physically contiguous pages, for devices that dma into more than a page
+pub fn alloc_contiguous(count: usize) -> Option<u64> {
+    if count == 0 {
+        return None;
+    }
+    PMM.lock().as_mut().and_then(|pmm| pmm.alloc_pages(count))
+}
+
+/// zeroed physically contiguous pages for a device to dma to
+pub fn alloc_dma(count: usize) -> Result<PhysAddr, &'static str> {
+    let phys = PhysAddr::new(alloc_contiguous(count).ok_or("oom")?);
+    unsafe {
+        core::ptr::write_bytes(
+            vmm::phys_to_virt(phys).as_mut_ptr::<u8>(),
+            0,
+            count * PAGE_SIZE,
+        )
+    };
+    Ok(phys)
+}
+
+/// gives back pages from `alloc_dma`
+pub fn free_dma(phys: PhysAddr, count: usize) {
+    for page in 0..count {
+        free(phys.as_u64() + (page * PAGE_SIZE) as u64);
+    }
+}
+
+/// only pages from usable memory come back, anything else (the kernel,
+/// modules like the initramfs, mmio) was never ours to hand out
 pub fn free(addr: u64) {
     // if address is not aligned, reject it
     if !addr.is_multiple_of(PAGE_SIZE as u64) {
         log::error!("attempted to free unaligned address: {:#x}", addr);
         return;
     }
+    if !is_usable_address(addr) {
+        log::error!("attempted to free reserved address: {:#x}", addr);
+        return;
+    }
 
     if let Some(pmm) = PMM.lock().as_mut() {
         pmm.free_page(addr);
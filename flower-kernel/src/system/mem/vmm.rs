@@ -1,3 +1,5 @@
+use alloc::vec::Vec;
+
 use spin::Mutex;
 use x86_64::registers::control::Cr3;
 use x86_64::structures::paging::mapper::TranslateResult;
@@ -9,6 +11,10 @@ use x86_64::{PhysAddr, VirtAddr};
 
 use crate::{boot, system};
 
+/// marks leaf pages an address space only borrows, like a file's mmap.
+/// they stay put when it's unmapped or the address space goes away
+pub const BORROWED: PageTableFlags = PageTableFlags::BIT_9;
+
 static HHDM: Mutex<Option<u64>> = Mutex::new(None);
 static PML4: Mutex<Option<PhysAddr>> = Mutex::new(None);
 
@@ -121,6 +127,28 @@ pub fn page_map_alloc(
     Ok(phys)
 }
 
+/// maps device registers into the HHDM uncached, pages that are already mapped are left alone
+pub fn map_mmio(phys: PhysAddr, len: usize) -> Result<VirtAddr, &'static str> {
+    let start = phys.align_down(4096u64).as_u64();
+    let end = phys.as_u64() + len.max(1) as u64;
+
+    for page in (start..end).step_by(4096) {
+        let virt = phys_to_virt(PhysAddr::new(page));
+        if !page_is_mapped(virt) {
+            page_map(
+                virt,
+                PhysAddr::new(page),
+                PageTableFlags::PRESENT
+                    | PageTableFlags::WRITABLE
+                    | PageTableFlags::NO_CACHE
+                    | PageTableFlags::NO_EXECUTE,
+            )?;
+        }
+    }
+
+    Ok(phys_to_virt(phys))
+}
+
 /// unmaps the page at the given virtual address and returns the physical address that was mapped there
 pub fn page_unmap(virt: VirtAddr) -> Result<PhysAddr, &'static str> {
     log::debug!("unmapping page at virt {:#x}", virt.as_u64(),);
@@ -199,7 +227,7 @@ unsafe fn page_table_free(table_phys: PhysAddr, level: u8) {
                 let child_phys = frame.start_address();
                 unsafe { page_table_free(child_phys, level - 1) };
                 system::mem::pmm::free(child_phys.as_u64());
-            } else if level == 1 {
+            } else if level == 1 && !flags.contains(BORROWED) {
                 let leaf_phys = frame.start_address();
                 system::mem::pmm::free(leaf_phys.as_u64());
             }
@@ -457,6 +485,16 @@ impl AddressSpace {
                         | PageTableFlags::NO_CACHE
                         | PageTableFlags::NO_EXECUTE);
 
+                // nobody owns a borrowed page to copy, so share it
+                if flags.contains(BORROWED) {
+                    dst.map_page(
+                        VirtAddr::new(entry_base),
+                        src_phys.start_address(),
+                        map_flags | BORROWED,
+                    )?;
+                    continue;
+                }
+
                 let dst_phys =
                     dst.map_page_alloc(VirtAddr::new(entry_base), map_flags)?;
 
@@ -486,6 +524,61 @@ impl AddressSpace {
         Ok(())
     }
 
+    fn collect_user_pages_recursive(
+        &self,
+        regions: &mut Vec<(u64, u64, PageTableFlags)>,
+        table_phys: PhysAddr,
+        level: u8,
+        base: u64,
+    ) {
+        let table = unsafe { &*phys_to_virt(table_phys).as_ptr::<PageTable>() };
+        let entry_limit = if level == 4 { 256 } else { 512 };
+        let interesting = PageTableFlags::WRITABLE
+            | PageTableFlags::USER_ACCESSIBLE
+            | PageTableFlags::NO_EXECUTE;
+
+        for index in 0..entry_limit {
+            let entry = &table[index];
+            let flags = entry.flags();
+
+            if !flags.contains(PageTableFlags::PRESENT) {
+                continue;
+            }
+
+            let level_shift = 12 + 9 * ((level as u64).saturating_sub(1));
+            let entry_base = base + ((index as u64) << level_shift);
+
+            if level == 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+                let entry_end = entry_base + (1u64 << level_shift);
+                let flags = flags & interesting;
+
+                // merge with the previous region if it's contiguous and alike
+                match regions.last_mut() {
+                    Some((_, end, last_flags))
+                        if *end == entry_base && *last_flags == flags =>
+                    {
+                        *end = entry_end;
+                    },
+                    _ => regions.push((entry_base, entry_end, flags)),
+                }
+            } else if let Ok(next_table) = entry.frame() {
+                self.collect_user_pages_recursive(
+                    regions,
+                    next_table.start_address(),
+                    level - 1,
+                    entry_base,
+                );
+            }
+        }
+    }
+
+    /// lists the mapped user regions as (start, end, flags), merging neighbouring pages with the same flags
+    pub fn user_regions(&self) -> Vec<(u64, u64, PageTableFlags)> {
+        let mut regions = Vec::new();
+        self.collect_user_pages_recursive(&mut regions, self.pml4_phys, 4, 0);
+        regions
+    }
+
     /// creates a new address space with the same mappings as the current one for the user portion
     pub fn clone_user(&self) -> Result<Self, &'static str> {
         let dst = AddressSpace::new()?;
@@ -1,10 +1,14 @@
 use crate::system::syscalls::SyscallError;
 use crate::system::vfs::VFSError;
 
+pub mod bytes;
+pub mod cache;
 pub mod elf;
 pub mod mem;
 pub mod proc;
+pub mod random;
 pub mod syscalls;
+pub mod time;
 pub mod vfs;
 
 pub enum KernelError {
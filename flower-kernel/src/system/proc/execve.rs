@@ -49,6 +49,7 @@ pub fn execve(
     let old_address_space = proc.address_space.take();
 
     proc.name = name;
+    proc.argv = argv_storage;
     proc.address_space = Some(address_space);
     proc.cr3 = new_cr3;
     proc.user_entry = user_entry;
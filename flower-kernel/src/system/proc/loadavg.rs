@@ -0,0 +1,30 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+// same fixed point math linux uses, 11 bits of fraction
+const FSHIFT: u64 = 11;
+const FIXED_1: u64 = 1 << FSHIFT;
+
+/// 1/exp(5s/1min), 1/exp(5s/5min) and 1/exp(5s/15min) in fixed point
+const EXP: [u64; 3] = [1884, 2014, 2037];
+
+/// how often the run queue gets sampled, in ticks
+pub const LOAD_FREQ: u64 = 5000;
+
+static LOADAVG: [AtomicU64; 3] = [const { AtomicU64::new(0) }; 3];
+
+/// folds the current run queue length into the averages
+pub fn sample(runnable: usize) {
+    let active = runnable as u64 * FIXED_1;
+    for (avg, exp) in LOADAVG.iter().zip(EXP) {
+        let load = avg.load(Ordering::Relaxed);
+        let load = (load * exp + active * (FIXED_1 - exp)) >> FSHIFT;
+        avg.store(load, Ordering::Relaxed);
+    }
+}
+
+/// the 1, 5 and 15 minute averages in hundredths
+pub fn get() -> [u64; 3] {
+    LOADAVG
+        .each_ref()
+        .map(|avg| (avg.load(Ordering::Relaxed) * 100 + FIXED_1 / 2) >> FSHIFT)
+}
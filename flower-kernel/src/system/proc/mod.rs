@@ -2,15 +2,18 @@ mod auxv;
 mod execve;
 mod exit;
 mod fork;
+mod loadavg;
 mod process;
 mod scheduler;
 mod sleep;
 mod trampoline;
 mod user;
 mod wait;
+mod waitqueue;
 
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 
 pub use process::*;
 use spin::Mutex;
@@ -21,6 +24,8 @@ pub use self::exit::exit;
 pub use self::fork::fork;
 pub use self::sleep::sleep;
 pub use self::wait::waitpid;
+pub use self::waitqueue::WaitQueue;
+use crate::arch;
 use crate::system::proc::scheduler::Scheduler;
 use crate::system::proc::user::build_user_image;
 use crate::system::vfs::{FdTable, VFSError, VFSResult};
@@ -47,6 +52,41 @@ pub fn schedule() {
     });
 }
 
+/// charges the current timer tick to whoever is running and samples the
+/// load average. called from the timer interrupt, so it never waits on a lock.
+pub fn tick() {
+    let Some(mut guard) = SCHEDULER.try_lock() else {
+        return;
+    };
+    let Some(sched) = guard.as_mut() else {
+        return;
+    };
+
+    if let Some(current) = sched.current()
+        && let Some(mut proc) = current.try_lock()
+    {
+        proc.cpu_ticks += 1;
+    }
+
+    if arch::ticks().is_multiple_of(loadavg::LOAD_FREQ) {
+        loadavg::sample(sched.runnable());
+    }
+}
+
+/// the 1, 5 and 15 minute load averages in hundredths
+pub fn loadavg() -> [u64; 3] { loadavg::get() }
+
+/// number of processes that want the cpu, and the total number of processes
+pub fn runnable() -> (usize, usize) {
+    interrupts::without_interrupts(|| {
+        SCHEDULER
+            .lock()
+            .as_ref()
+            .map(|sched| (sched.runnable(), sched.processes.len()))
+            .unwrap_or((0, 0))
+    })
+}
+
 /// spawns a new process with the given entry point and name.
 pub fn spawn(name: &str, entry: fn()) {
     let new_process = Process::new(name, entry);
@@ -102,6 +142,40 @@ pub fn current() -> Option<Arc<Mutex<Process>>> {
     })
 }
 
+/// returns the id of the current process
+pub fn current_id() -> Option<u64> { current().map(|proc| proc.lock().id) }
+
+/// returns the ids of every process in the scheduler
+pub fn ids() -> Vec<u64> {
+    interrupts::without_interrupts(|| {
+        SCHEDULER
+            .lock()
+            .as_ref()
+            .map(|sched| {
+                sched.processes.iter().map(|proc| proc.lock().id).collect()
+            })
+            .unwrap_or_default()
+    })
+}
+
+/// runs `f` on the process with the given id, if it exists
+pub fn with_process<F, R>(id: u64, f: F) -> Option<R>
+where F: FnOnce(&Process) -> R {
+    interrupts::without_interrupts(|| {
+        let guard = SCHEDULER.lock();
+        let proc = guard
+            .as_ref()?
+            .processes
+            .iter()
+            .find(|proc| proc.lock().id == id)?
+            .clone();
+        drop(guard);
+
+        let proc = proc.lock();
+        Some(f(&proc))
+    })
+}
+
 /// returns the current pid
 pub fn current_pid() -> Option<usize> {
     interrupts::without_interrupts(|| {
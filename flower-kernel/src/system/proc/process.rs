@@ -14,6 +14,9 @@ use crate::{arch, system};
 
 static NEXT_ID: AtomicU64 = AtomicU64::new(0);
 
+/// id of the most recently created process
+pub fn last_id() -> u64 { NEXT_ID.load(Ordering::Relaxed).saturating_sub(1) }
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ProcessState {
     Ready,
@@ -39,6 +42,11 @@ pub struct Process {
     pub parent_id: Option<u64>,
     pub exit_status: Option<u64>,
     pub fds: FdTable,
+    pub argv: Vec<String>,
+
+    // in ticks, for /proc
+    pub started_at: u64,
+    pub cpu_ticks: u64,
 
     pub cr3: u64,
 
@@ -62,6 +70,19 @@ impl Process {
     }
 }
 
+impl Process {
+    /// single letter state, like linux
+    pub fn state_char(&self) -> char {
+        match self.state {
+            ProcessState::Ready => 'R',
+            ProcessState::Running => 'R',
+            ProcessState::Sleeping => 'S',
+            ProcessState::Zombie => 'Z',
+            ProcessState::Dead => 'X',
+        }
+    }
+}
+
 impl Process {
     pub fn valid_stack(&self) -> bool {
         self.kernel_stack_top != 0 && self.stack_ptr != 0
@@ -125,6 +146,10 @@ impl Process {
             parent_id: None,
             exit_status: None,
             fds: FdTable::new(),
+            argv: alloc::vec![String::from(name)],
+
+            started_at: arch::ticks(),
+            cpu_ticks: 0,
 
             cr3: pml4_frame.start_address().as_u64(),
 
@@ -194,6 +219,11 @@ impl Process {
             parent_id: None,
             exit_status: None,
             fds: FdTable::new(),
+            argv: alloc::vec![String::from(name)],
+
+            started_at: arch::ticks(),
+            cpu_ticks: 0,
+
             cr3,
 
             stack_ptr,
@@ -283,6 +313,10 @@ impl Process {
             parent_id: Some(parent.id),
             exit_status: None,
             fds: parent.fds.clone(),
+            argv: parent.argv.clone(),
+
+            started_at: arch::ticks(),
+            cpu_ticks: 0,
 
             cr3,
 
@@ -315,6 +349,10 @@ pub fn null_process() -> Process {
         parent_id: None,
         exit_status: None,
         fds: FdTable::new(),
+        argv: Vec::new(),
+
+        started_at: 0,
+        cpu_ticks: 0,
 
         cr3: pml4_frame.start_address().as_u64(),
 
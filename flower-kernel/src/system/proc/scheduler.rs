@@ -37,6 +37,23 @@ impl Scheduler {
         None
     }
 
+    /// counts the processes that are running or waiting to, leaving out the
+    /// null process. locked processes are skipped since this runs from the
+    /// timer interrupt.
+    pub fn runnable(&self) -> usize {
+        self.processes
+            .iter()
+            .filter_map(|proc| proc.try_lock())
+            .filter(|proc| {
+                proc.id != 0
+                    && matches!(
+                        proc.state,
+                        ProcessState::Ready | ProcessState::Running
+                    )
+            })
+            .count()
+    }
+
     /// reaps any dead processes, removing them from the scheduler.
     pub fn reap(&mut self) {
         let mut i = self.processes.len();
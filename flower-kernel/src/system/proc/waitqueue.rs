@@ -0,0 +1,71 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::arch;
+use crate::system::proc::{Process, ProcessState, SCHEDULER, schedule};
+use crate::system::{self};
+
+/// processes waiting for something an interrupt handler will signal
+pub struct WaitQueue {
+    waiters: Mutex<Vec<Arc<Mutex<Process>>>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self { Self { waiters: Mutex::new(Vec::new()) } }
+
+    /// sleeps until `ready` says yes. it's checked with interrupts off so a
+    /// wake can't slip in between the check and going to sleep. `timeout`
+    /// is in ticks and bounds each sleep, in case a wake gets lost anyway.
+    pub fn wait_until(&self, timeout: u64, mut ready: impl FnMut() -> bool) {
+        loop {
+            let sleeping = interrupts::without_interrupts(|| {
+                if ready() {
+                    return false;
+                }
+
+                system::syscalls::write_cpu_context();
+                let Some(current) =
+                    SCHEDULER.lock().as_mut().and_then(|sched| sched.current())
+                else {
+                    panic!("trying to wait while no process is running!");
+                };
+
+                {
+                    let mut proc = current.lock();
+                    proc.wake_at = Some(arch::ticks() + timeout);
+                    proc.state = ProcessState::Sleeping;
+                }
+                let mut waiters = self.waiters.lock();
+                if !waiters.iter().any(|waiter| Arc::ptr_eq(waiter, &current)) {
+                    waiters.push(current);
+                }
+                true
+            });
+
+            if !sleeping {
+                return;
+            }
+            schedule();
+        }
+    }
+
+    /// makes every waiter runnable again, safe to call from an interrupt.
+    /// anyone whose lock is busy right now gets woken by their timeout.
+    pub fn wake_all(&self) {
+        let Some(mut waiters) = self.waiters.try_lock() else {
+            return;
+        };
+
+        for waiter in waiters.drain(..) {
+            if let Some(mut proc) = waiter.try_lock()
+                && proc.state == ProcessState::Sleeping
+            {
+                // the scheduler picks it up on its next pass
+                proc.wake_at = Some(0);
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! plain chacha20 (rfc 8439), just the block function and a keystream writer
+
+const CONSTANTS: [u32; 4] =
+    [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+#[inline(always)]
+fn quarter_round(
+    state: &mut [u32; 16],
+    a: usize,
+    b: usize,
+    c: usize,
+    d: usize,
+) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// runs the 20 rounds and returns the 64 byte block as words
+pub fn block(key: &[u32; 8], counter: u32, nonce: &[u32; 3]) -> [u32; 16] {
+    let mut input = [0u32; 16];
+    input[..4].copy_from_slice(&CONSTANTS);
+    input[4..12].copy_from_slice(key);
+    input[12] = counter;
+    input[13..].copy_from_slice(nonce);
+
+    let mut state = input;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    for (word, input) in state.iter_mut().zip(input) {
+        *word = word.wrapping_add(input);
+    }
+    state
+}
+
+/// fills the buffer with keystream for the key, starting at block 0
+pub fn keystream(key: &[u32; 8], nonce: &[u32; 3], buf: &mut [u8]) {
+    for (counter, chunk) in buf.chunks_mut(64).enumerate() {
+        let block = block(key, counter as u32, nonce);
+        let bytes = block.iter().flat_map(|word| word.to_le_bytes());
+        for (out, byte) in chunk.iter_mut().zip(bytes) {
+            *out = byte;
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use core::arch::x86_64::{_rdrand64_step, _rdseed64_step, _rdtsc};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use raw_cpuid::CpuId;
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts;
+
+use crate::arch;
+
+mod chacha;
+
+/// how often the key gets stirred with the entropy pool, in ticks
+const RESEED_INTERVAL: u64 = 1000;
+
+/// interrupt timings and whatever else gets fed in, mixed without locking
+/// so it can be touched from interrupt handlers.
+static POOL: [AtomicU64; 8] = [const { AtomicU64::new(0) }; 8];
+static POOL_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+static RNG: Mutex<Rng> =
+    Mutex::new(Rng { key: [0; 8], counter: 0, reseeded_at: 0 });
+
+/// which hardware sources the cpu has, checked once on install
+static HARDWARE: Once<(bool, bool)> = Once::new();
+
+struct Rng {
+    key: [u32; 8],
+    counter: u32,
+    reseeded_at: u64,
+}
+
+impl Rng {
+    /// folds the pool into the key, the block function does the hashing
+    fn reseed(&mut self) {
+        let mut nonce = [0u32; 3];
+        for (i, word) in POOL.iter().enumerate() {
+            let value = word.load(Ordering::Relaxed) ^ hardware_u64();
+            self.key[i] ^= value as u32;
+            nonce[i % 3] ^= (value >> 32) as u32;
+        }
+
+        let block = chacha::block(&self.key, self.counter, &nonce);
+        self.key.copy_from_slice(&block[..8]);
+        self.counter = 0;
+        self.reseeded_at = arch::ticks();
+    }
+
+    /// hands out a one-off key and moves our own key forward, so nothing
+    /// given out before can be recovered from the current state.
+    fn next_key(&mut self) -> [u32; 8] {
+        if arch::ticks().saturating_sub(self.reseeded_at) >= RESEED_INTERVAL {
+            self.reseed();
+        }
+
+        let block = chacha::block(&self.key, self.counter, &[0; 3]);
+        self.counter = self.counter.wrapping_add(1);
+        self.key.copy_from_slice(&block[..8]);
+
+        let mut key = [0u32; 8];
+        key.copy_from_slice(&block[8..]);
+        key
+    }
+}
+
+fn rdtsc() -> u64 { unsafe { _rdtsc() } }
+
+/// a value from rdseed or rdrand if the cpu has them, zero otherwise
+fn hardware_u64() -> u64 {
+    let (rdseed, rdrand) = HARDWARE.get().copied().unwrap_or((false, false));
+    let mut value = 0;
+
+    // both can fail when the hardware is drained, give it a few tries
+    for _ in 0..10 {
+        if rdseed && unsafe { _rdseed64_step(&mut value) } == 1 {
+            return value;
+        }
+        if rdrand && unsafe { _rdrand64_step(&mut value) } == 1 {
+            return value;
+        }
+    }
+
+    0
+}
+
+/// mixes a value into the pool, cheap enough to call from any interrupt
+pub fn add_entropy(value: u64) {
+    let idx = POOL_INDEX.fetch_add(1, Ordering::Relaxed) % POOL.len();
+    let word = POOL[idx].load(Ordering::Relaxed);
+    let mixed =
+        (word.rotate_left(23) ^ value).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    POOL[idx].store(mixed, Ordering::Relaxed);
+}
+
+/// records the timing of an interrupt
+pub fn add_interrupt(vector: u8) {
+    add_entropy(rdtsc() ^ ((vector as u64) << 56));
+}
+
+/// fills the buffer from the csprng, never blocks
+pub fn fill(buf: &mut [u8]) {
+    let key = interrupts::without_interrupts(|| RNG.lock().next_key());
+    chacha::keystream(&key, &[0; 3], buf);
+}
+
+pub fn u64() -> u64 {
+    let mut bytes = [0u8; 8];
+    fill(&mut bytes);
+    u64::from_le_bytes(bytes)
+}
+
+pub fn install() {
+    let cpuid = CpuId::new();
+    let rdseed =
+        cpuid.get_extended_feature_info().is_some_and(|f| f.has_rdseed());
+    let rdrand = cpuid.get_feature_info().is_some_and(|f| f.has_rdrand());
+    HARDWARE.call_once(|| (rdseed, rdrand));
+
+    // the time a handful of cpuid calls take wobbles a bit, even in an
+    // emulator. not much per sample but it's something without rdrand.
+    for _ in 0..256 {
+        let start = rdtsc();
+        let _ = CpuId::new().get_vendor_info();
+        add_entropy(rdtsc().wrapping_sub(start) ^ start);
+    }
+
+    interrupts::without_interrupts(|| RNG.lock().reseed());
+
+    log::info!(
+        "random: seeded (rdseed: {}, rdrand: {}, tsc jitter)",
+        rdseed,
+        rdrand
+    );
+}
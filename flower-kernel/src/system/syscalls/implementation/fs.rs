@@ -1,3 +1,5 @@
+use alloc::string::ToString;
+use alloc::sync::Arc;
 use core::ffi::{CStr, c_char};
 
 use flower_mono::structs::FileStat;
@@ -17,7 +19,10 @@ pub fn open(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
     match system::vfs::open(path, flags) {
         Ok(file) => {
             let result = system::proc::with_fd_table(|table| {
-                table.alloc(FdKind::File(file))
+                table.alloc(FdKind::File {
+                    file: Arc::from(file),
+                    path: path.to_string(),
+                })
             });
             Ok(result.map(|fd| fd as u64).unwrap_or(u64::MAX))
         },
@@ -30,17 +35,12 @@ pub fn read(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
     let buf = frame.rsi as *mut u8;
     let len = frame.rdx as usize;
 
-    let result =
-        system::proc::with_fd_table(|table| match table.get_mut(fd)? {
-            FdKind::File(file) => {
-                let slice =
-                    unsafe { core::slice::from_raw_parts_mut(buf, len) };
-                file.read(slice)
-            },
-            _ => {
-                log::error!("read syscall: fd {} is not readable", fd);
-                Err(VFSError::PermissionDenied)
-            },
+    // the read happens outside the fd table, it's allowed to sleep
+    let result = system::proc::with_fd_table(|table| table.file(fd))
+        .inspect_err(|_| log::error!("read syscall: fd {} is not readable", fd))
+        .and_then(|file| {
+            let slice = unsafe { core::slice::from_raw_parts_mut(buf, len) };
+            file.read(slice)
         });
 
     if let Ok(result) = result {
@@ -55,23 +55,28 @@ pub fn write(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
     let buf = frame.rsi as *mut u8;
     let len = frame.rdx as usize;
 
-    let result = system::proc::with_fd_table(|table| match table.get(fd)? {
-        FdKind::Stdout | FdKind::Stderr => {
+    let target = system::proc::with_fd_table(|table| match table.get(fd)? {
+        FdKind::Stdout | FdKind::Stderr => Ok(None),
+        FdKind::File { file, .. } => Ok(Some(file.clone())),
+        _ => {
+            log::error!("write syscall: fd {} is not writable", fd);
+            Err(VFSError::PermissionDenied)
+        },
+    });
+
+    // same as read, the fd table is let go before writing
+    let result = target.and_then(|file| match file {
+        Some(file) => {
+            let slice = unsafe { core::slice::from_raw_parts_mut(buf, len) };
+            file.write(slice)
+        },
+        None => {
             for i in 0..len {
                 let byte = unsafe { *buf.add(i) };
                 print!("{}", byte as char);
             }
             Ok(len)
         },
-        FdKind::File(file) => {
-            let slice = unsafe { core::slice::from_raw_parts_mut(buf, len) };
-            let written = file.write(slice)?;
-            Ok(written)
-        },
-        _ => {
-            log::error!("write syscall: fd {} is not writable", fd);
-            Err(VFSError::PermissionDenied)
-        },
     });
 
     if let Ok(result) = result {
@@ -92,19 +97,18 @@ pub fn seek(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
     let offset = frame.rsi as i64;
     let whence = frame.rdx as u32;
 
-    let result =
-        system::proc::with_fd_table(|table| match table.get_mut(fd)? {
-            FdKind::File(file) => file.seek(match whence {
-                0 => system::vfs::VFSSeek::Start(offset as usize),
-                1 => system::vfs::VFSSeek::Current(offset as usize),
-                2 => system::vfs::VFSSeek::End(offset as usize),
-                _ => return Err(VFSError::InvalidSeek),
-            }),
-            _ => {
-                log::error!("seek syscall: fd {} is not seekable", fd);
-                Err(VFSError::PermissionDenied)
-            },
-        });
+    let result = system::proc::with_fd_table(|table| match table.get(fd)? {
+        FdKind::File { file, .. } => file.seek(match whence {
+            0 => system::vfs::VFSSeek::Start(offset as usize),
+            1 => system::vfs::VFSSeek::Current(offset as usize),
+            2 => system::vfs::VFSSeek::End(offset as usize),
+            _ => return Err(VFSError::InvalidSeek),
+        }),
+        _ => {
+            log::error!("seek syscall: fd {} is not seekable", fd);
+            Err(VFSError::PermissionDenied)
+        },
+    });
 
     if let Ok(result) = result {
         Ok(result as u64)
@@ -120,12 +124,13 @@ pub fn stat(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
     log::debug!("stat syscall: fd={}, stat_buf.size={:?}", fd, stat_buf);
 
     let result = system::proc::with_fd_table(|table| match table.get(fd)? {
-        FdKind::File(file) => {
+        FdKind::File { file, .. } => {
             let stat = file.metadata()?;
             unsafe {
                 (*stat_buf).st_mode = 0; // TODO: set mode
                 (*stat_buf).st_dev = 0; // TODO: set device id
                 (*stat_buf).st_size = stat.size as u64;
+                (*stat_buf).st_mtime = stat.last_modified as u64;
             }
             Ok(0)
         },
@@ -141,3 +146,96 @@ pub fn stat(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
         Err(result.err().unwrap().to_syscall_error())
     }
 }
+
+pub fn ioctl(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
+    let fd = frame.rdi as usize;
+    let cmd = frame.rsi;
+    let arg = frame.rdx;
+
+    let result = system::proc::with_fd_table(|table| match table.get(fd)? {
+        FdKind::File { file, .. } => Ok(file.clone()),
+        _ => Err(VFSError::Unsupported),
+    })
+    .and_then(|file| file.ioctl(cmd, arg));
+
+    match result {
+        Ok(result) => Ok(result),
+        Err(VFSError::NotFound) => Err(SyscallError::BadFileDescriptor),
+        Err(VFSError::Unsupported) => Err(SyscallError::NotTypewriter),
+        Err(e) => Err(e.to_syscall_error()),
+    }
+}
+
+pub fn sync(_frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
+    system::cache::sync();
+    Ok(0)
+}
+
+pub fn fsync(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
+    let fd = frame.rdi as usize;
+
+    let result = system::proc::with_fd_table(|table| match table.get(fd)? {
+        FdKind::File { file, .. } => Ok(file.clone()),
+        _ => Err(VFSError::Unsupported),
+    })
+    .and_then(|file| file.sync());
+
+    match result {
+        Ok(()) => Ok(0),
+        Err(VFSError::NotFound) => Err(SyscallError::BadFileDescriptor),
+        // stdio has nothing to sync
+        Err(VFSError::Unsupported) => Ok(0),
+        Err(e) => Err(e.to_syscall_error()),
+    }
+}
+
+/// a nul terminated path from userspace
+fn user_path(ptr: u64) -> Result<&'static str, SyscallError> {
+    unsafe { CStr::from_ptr(ptr as *const c_char) }
+        .to_str()
+        .map_err(|_| SyscallError::InvalidArgument)
+}
+
+pub fn unlink(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
+    let path = user_path(frame.rdi)?;
+    system::vfs::unlink(path).map(|_| 0).map_err(|e| e.to_syscall_error())
+}
+
+pub fn mkdir(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
+    let path = user_path(frame.rdi)?;
+    let mode = frame.rsi as usize;
+    system::vfs::mkdir(path, mode).map(|_| 0).map_err(|e| e.to_syscall_error())
+}
+
+pub fn rmdir(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
+    let path = user_path(frame.rdi)?;
+    system::vfs::rmdir(path).map(|_| 0).map_err(|e| e.to_syscall_error())
+}
+
+pub fn symlink(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
+    let target = user_path(frame.rdi)?;
+    let path = user_path(frame.rsi)?;
+    system::vfs::symlink(target, path)
+        .map(|_| 0)
+        .map_err(|e| e.to_syscall_error())
+}
+
+/// a null pointer is an empty string, mount doesn't need every argument
+fn user_path_or_empty(ptr: u64) -> Result<&'static str, SyscallError> {
+    if ptr == 0 { Ok("") } else { user_path(ptr) }
+}
+
+pub fn mount(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
+    let source = user_path_or_empty(frame.rdi)?;
+    let target = user_path(frame.rsi)?;
+    let fstype = user_path_or_empty(frame.rdx)?;
+    let flags = frame.r10 as u32;
+    system::vfs::mount(source, target, fstype, flags)
+        .map(|_| 0)
+        .map_err(|e| e.to_syscall_error())
+}
+
+pub fn umount(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
+    let target = user_path(frame.rdi)?;
+    system::vfs::umount(target).map(|_| 0).map_err(|e| e.to_syscall_error())
+}
@@ -46,11 +46,18 @@ pub fn mmap(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
     if fd != -1 {
         let result =
             proc.with_fd_table(|table| match table.get(fd as usize)? {
-                FdKind::File(file) => file.mmap(size as usize, 0, 0),
+                FdKind::File { file, .. } => file
+                    .mmap(size as usize, 0, 0)
+                    .map(|data| (data, file.mmap_writable())),
                 _ => Err(VFSError::Unsupported),
             });
 
-        if let Ok(data) = result {
+        if let Ok((data, writable)) = result {
+            if data.is_null() {
+                log::error!("mmap failed: fd {} refused the mapping", fd);
+                return Err(SyscallError::InvalidArgument);
+            }
+
             log::debug!(
                 "mmap: mapping fd {} at offset {} to user heap position {:#x} with size {}",
                 fd,
@@ -65,6 +72,20 @@ pub fn mmap(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
                 data as u64
             );
 
+            // files don't have to start on a page, tarfs ones usually don't
+            let page_offset = data as u64 % arch::layout::PAGE_SIZE as u64;
+            let data = unsafe { data.sub(page_offset as usize) };
+            let heap_pages =
+                (size + page_offset).div_ceil(arch::layout::PAGE_SIZE as u64);
+            // the file owns the memory, the process only gets to look
+            let mut page_flags = PageTableFlags::PRESENT
+                | PageTableFlags::USER_ACCESSIBLE
+                | PageTableFlags::NO_EXECUTE
+                | vmm::BORROWED;
+            if writable {
+                page_flags |= PageTableFlags::WRITABLE;
+            }
+
             for i in 0..heap_pages {
                 let src_virt = VirtAddr::new(unsafe {
                     data.add(i as usize * arch::layout::PAGE_SIZE) as u64
@@ -80,10 +101,7 @@ pub fn mmap(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
                 proc.address_space.as_mut().unwrap().map_page(
                     VirtAddr::new(heap_ptr),
                     src_phys,
-                    PageTableFlags::PRESENT
-                        | PageTableFlags::USER_ACCESSIBLE
-                        | PageTableFlags::WRITABLE
-                        | PageTableFlags::NO_EXECUTE,
+                    page_flags,
                 ).map_err(|_| {
                     log::debug!(
                         "mmap failed: could not map page at user heap position {:#x}",
@@ -101,7 +119,7 @@ pub fn mmap(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
                 heap_start,
                 proc.user_heap_position
             );
-            Ok(heap_start)
+            Ok(heap_start + page_offset)
         } else {
             log::error!("mmap failed for fd {}", fd);
             Err(result.err().unwrap().to_syscall_error())
@@ -140,8 +158,8 @@ pub fn munmap(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
 
     let end = addr.checked_add(size).ok_or(SyscallError::InvalidArgument)?;
 
-    let pages = (size + arch::layout::PAGE_SIZE as u64 - 1)
-        / arch::layout::PAGE_SIZE as u64;
+    // an unaligned `addr` can push the end onto one more page
+    let pages = (addr - base + size).div_ceil(arch::layout::PAGE_SIZE as u64);
 
     log::debug!("munmap: addr={:#x}, size={}, pages={}", addr, size, pages);
 
@@ -163,6 +181,12 @@ pub fn munmap(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
 
         for i in 0..pages {
             let page_addr = base + i * arch::layout::PAGE_SIZE as u64;
+            let borrowed = proc
+                .address_space
+                .as_ref()
+                .unwrap()
+                .page_flags(VirtAddr::new(page_addr))
+                .is_ok_and(|flags| flags.contains(vmm::BORROWED));
             let phys = proc.address_space.as_mut().unwrap().unmap_page(VirtAddr::new(page_addr)).map_err(|_| {
                 log::error!(
                     "munmap failed: could not unmap page at user heap position {:#x}",
@@ -171,8 +195,12 @@ pub fn munmap(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
                 SyscallError::InvalidArgument
             })?;
 
-            // NOTE: this might fuck me later
-            if system::mem::pmm::is_usable_address(phys.as_u64()) {
+            if borrowed {
+                log::debug!(
+                    "munmap: leaving borrowed page {:#x} to its owner",
+                    phys.as_u64()
+                );
+            } else if system::mem::pmm::is_usable_address(phys.as_u64()) {
                 system::mem::pmm::free(phys.as_u64());
             } else {
                 log::debug!(
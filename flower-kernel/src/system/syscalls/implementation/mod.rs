@@ -1,13 +1,17 @@
 use flower_mono::syscalls::{
-    SYS_CLOSE, SYS_EXECVE, SYS_EXIT, SYS_FORK, SYS_MMAP, SYS_MSLEEP,
-    SYS_MUNMAP, SYS_OPEN, SYS_READ, SYS_SEEK, SYS_STAT, SYS_WAITPID, SYS_WRITE,
-    SYS_WRITE_FS_BASE,
+    SYS_CLOCK_GETTIME, SYS_CLOSE, SYS_EXECVE, SYS_EXIT, SYS_FORK, SYS_FSYNC,
+    SYS_GETRANDOM, SYS_GETTIMEOFDAY, SYS_IOCTL, SYS_MKDIR, SYS_MMAP, SYS_MOUNT,
+    SYS_MSLEEP, SYS_MUNMAP, SYS_NANOSLEEP, SYS_OPEN, SYS_POWEROFF, SYS_READ,
+    SYS_REBOOT, SYS_RMDIR, SYS_SEEK, SYS_STAT, SYS_SYMLINK, SYS_SYNC,
+    SYS_UMOUNT, SYS_UNLINK, SYS_WAITPID, SYS_WRITE, SYS_WRITE_FS_BASE,
 };
 
 mod arch;
 mod fs;
 mod mman;
 mod process;
+mod random;
+mod time;
 
 use crate::system::syscalls::types::SyscallHandler;
 
@@ -25,8 +29,28 @@ pub static SYSCALL_HANDLERS: [Option<SyscallHandler>; 256] = {
     handlers[SYS_CLOSE as usize] = Some(fs::close as SyscallHandler);
     handlers[SYS_SEEK as usize] = Some(fs::seek as SyscallHandler);
     handlers[SYS_STAT as usize] = Some(fs::stat as SyscallHandler);
+    handlers[SYS_IOCTL as usize] = Some(fs::ioctl as SyscallHandler);
+    handlers[SYS_SYNC as usize] = Some(fs::sync as SyscallHandler);
+    handlers[SYS_FSYNC as usize] = Some(fs::fsync as SyscallHandler);
+    handlers[SYS_UNLINK as usize] = Some(fs::unlink as SyscallHandler);
+    handlers[SYS_MKDIR as usize] = Some(fs::mkdir as SyscallHandler);
+    handlers[SYS_RMDIR as usize] = Some(fs::rmdir as SyscallHandler);
+    handlers[SYS_SYMLINK as usize] = Some(fs::symlink as SyscallHandler);
+    handlers[SYS_MOUNT as usize] = Some(fs::mount as SyscallHandler);
+    handlers[SYS_UMOUNT as usize] = Some(fs::umount as SyscallHandler);
+
+    handlers[SYS_GETRANDOM as usize] =
+        Some(random::getrandom as SyscallHandler);
 
     handlers[SYS_MSLEEP as usize] = Some(process::msleep as SyscallHandler);
+    handlers[SYS_REBOOT as usize] = Some(process::reboot as SyscallHandler);
+    handlers[SYS_POWEROFF as usize] = Some(process::poweroff as SyscallHandler);
+
+    handlers[SYS_CLOCK_GETTIME as usize] =
+        Some(time::clock_gettime as SyscallHandler);
+    handlers[SYS_GETTIMEOFDAY as usize] =
+        Some(time::gettimeofday as SyscallHandler);
+    handlers[SYS_NANOSLEEP as usize] = Some(time::nanosleep as SyscallHandler);
 
     handlers[SYS_WRITE_FS_BASE as usize] =
         Some(arch::write_fsbase as SyscallHandler);
@@ -3,6 +3,7 @@ use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use core::ffi::{CStr, c_char};
 
+use crate::arch;
 use crate::system::syscalls::types::{SyscallError, SyscallFrame};
 use crate::system::{self};
 
@@ -17,6 +18,14 @@ pub fn msleep(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
     Ok(0)
 }
 
+pub fn reboot(_frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
+    arch::acpi::power::reboot();
+}
+
+pub fn poweroff(_frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
+    arch::acpi::power::poweroff();
+}
+
 pub fn fork(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
     system::proc::fork(frame).map_err(|e| {
         log::error!("fork failed: {}", e);
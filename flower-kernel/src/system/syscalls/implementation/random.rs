@@ -0,0 +1,26 @@
+use flower_mono::syscalls::{GRND_NONBLOCK, GRND_RANDOM};
+
+use crate::system::random;
+use crate::system::syscalls::types::{SyscallError, SyscallFrame};
+
+pub fn getrandom(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
+    let buf = frame.rdi as *mut u8;
+    let len = frame.rsi as usize;
+    let flags = frame.rdx;
+
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM) != 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    if len == 0 {
+        return Ok(0);
+    }
+
+    if buf.is_null() {
+        return Err(SyscallError::BadAddress);
+    }
+
+    let slice = unsafe { core::slice::from_raw_parts_mut(buf, len) };
+    random::fill(slice);
+    Ok(len as u64)
+}
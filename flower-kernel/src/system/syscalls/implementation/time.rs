@@ -0,0 +1,71 @@
+use core::time::Duration;
+
+use flower_mono::structs::{Timespec, Timeval};
+use flower_mono::syscalls::{CLOCK_BOOTTIME, CLOCK_MONOTONIC, CLOCK_REALTIME};
+
+use crate::system::syscalls::types::{SyscallError, SyscallFrame};
+use crate::system::{self};
+
+pub fn clock_gettime(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
+    let clock = frame.rdi;
+    let tp = frame.rsi as *mut Timespec;
+
+    let time = match clock {
+        CLOCK_REALTIME => system::time::realtime(),
+        CLOCK_MONOTONIC => system::time::monotonic(),
+        CLOCK_BOOTTIME => system::time::boottime(),
+        _ => return Err(SyscallError::InvalidArgument),
+    };
+
+    if tp.is_null() {
+        return Err(SyscallError::BadAddress);
+    }
+
+    unsafe { tp.write(Timespec::from_nanos(time.as_nanos())) };
+    Ok(0)
+}
+
+pub fn gettimeofday(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
+    let tv = frame.rdi as *mut Timeval;
+
+    // the timezone argument is obsolete, linux ignores it too
+    if !tv.is_null() {
+        let time = system::time::realtime();
+        unsafe {
+            tv.write(Timeval {
+                tv_sec: time.as_secs() as i64,
+                tv_usec: time.subsec_micros() as i64,
+            })
+        };
+    }
+
+    Ok(0)
+}
+
+pub fn nanosleep(frame: &mut SyscallFrame) -> Result<u64, SyscallError> {
+    let req = frame.rdi as *const Timespec;
+    let rem = frame.rsi as *mut Timespec;
+
+    if req.is_null() {
+        return Err(SyscallError::BadAddress);
+    }
+
+    let req = unsafe { req.read() };
+    if req.tv_sec < 0 || !(0..1_000_000_000).contains(&req.tv_nsec) {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    // the tick is a millisecond, round up so we never sleep short. a huge
+    // tv_sec doesn't fit in u64 millis, that's forever either way
+    let duration = Duration::new(req.tv_sec as u64, req.tv_nsec as u32);
+    let millis = (duration.as_nanos() + 999_999) / 1_000_000;
+    let millis = u64::try_from(millis).unwrap_or(u64::MAX);
+    system::proc::sleep(millis);
+
+    // nothing can interrupt a sleep yet, so there's never time left over
+    if !rem.is_null() {
+        unsafe { rem.write(Timespec::default()) };
+    }
+
+    Ok(0)
+}
@@ -43,9 +43,15 @@ pub enum SyscallError {
     BadAddress,
     BlockDeviceRequired,
     ResourceBusy,
+    FileExists,
+    NotDirectory,
+    IsDirectory,
     InvalidArgument,
+    NotTypewriter,
     NoSpace,
+    ReadOnlyFileSystem,
     TooLong,
+    NotEmpty,
     Other(String),
 }
 
@@ -67,9 +73,15 @@ impl SyscallError {
             SyscallError::BadAddress => 14,          // EFAULT
             SyscallError::BlockDeviceRequired => 15, // ENOTBLK
             SyscallError::ResourceBusy => 16,        // EBUSY
+            SyscallError::FileExists => 17,          // EEXIST
+            SyscallError::NotDirectory => 20,        // ENOTDIR
+            SyscallError::IsDirectory => 21,         // EISDIR
             SyscallError::InvalidArgument => 22,     // EINVAL
+            SyscallError::NotTypewriter => 25,       // ENOTTY
             SyscallError::NoSpace => 28,             // ENOSPC
+            SyscallError::ReadOnlyFileSystem => 30,  // EROFS
             SyscallError::TooLong => 36,             // ENAMETOOLONG
+            SyscallError::NotEmpty => 39,            // ENOTEMPTY
             SyscallError::Other(_) => 255,
         }
     }
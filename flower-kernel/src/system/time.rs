@@ -0,0 +1,42 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use crate::arch;
+use crate::drivers::rtc;
+
+/// wall clock time at tick 0, in milliseconds since the epoch
+static BOOT_EPOCH_MS: AtomicU64 = AtomicU64::new(0);
+
+/// time since boot, driven by the lapic tick
+pub fn monotonic() -> Duration { Duration::from_millis(arch::ticks()) }
+
+/// same as monotonic, we don't suspend so nothing is ever missed
+pub fn boottime() -> Duration { monotonic() }
+
+/// wall clock time since the unix epoch
+pub fn realtime() -> Duration {
+    Duration::from_millis(BOOT_EPOCH_MS.load(Ordering::Relaxed) + arch::ticks())
+}
+
+/// seconds since the unix epoch, what file timestamps use
+pub fn now() -> u64 { realtime().as_secs() }
+
+/// wall clock time the kernel booted at, in seconds since the epoch
+pub fn boot_time() -> u64 { BOOT_EPOCH_MS.load(Ordering::Relaxed) / 1000 }
+
+/// reads the rtc once, from then on the lapic tick keeps time
+pub fn install() {
+    let time = rtc::read();
+    let epoch_ms = (time.unix_timestamp() * 1000).saturating_sub(arch::ticks());
+    BOOT_EPOCH_MS.store(epoch_ms, Ordering::Relaxed);
+
+    log::info!(
+        "rtc: {:04}-{:02}-{:02} {:02}:{:02}:{:02} utc",
+        time.year,
+        time.month,
+        time.day,
+        time.hour,
+        time.minute,
+        time.second
+    );
+}
@@ -1,76 +1,231 @@
-use alloc::string::ToString;
-
-use crate::drivers;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+
+use flower_mono::ioctl::{
+    AUDIO_FORMAT_S16_LE, AUDIO_FORMAT_U8, AUDIO_GET_CHANNELS, AUDIO_GET_FORMAT,
+    AUDIO_GET_MASTER_VOLUME, AUDIO_GET_PCM_VOLUME, AUDIO_GET_RATE,
+    AUDIO_GET_RECORD_GAIN, AUDIO_GET_RECORD_SOURCE, AUDIO_SET_CHANNELS,
+    AUDIO_SET_FORMAT, AUDIO_SET_MASTER_VOLUME, AUDIO_SET_PCM_VOLUME,
+    AUDIO_SET_RATE, AUDIO_SET_RECORD_GAIN, AUDIO_SET_RECORD_SOURCE,
+    AUDIO_SOURCE_LINE_IN, AUDIO_SOURCE_MIC,
+};
+
+use crate::drivers::audio::{self, RecordSource, SampleFormat};
+use crate::system::time;
 use crate::system::vfs::devfs::{DevFS, DevFile};
+use crate::system::vfs::{
+    VFSError, VFSFile, VFSFileType, VFSMetadata, VFSPermissions, VFSResult,
+    VFSSeek,
+};
+
+/// an open of /dev/audio, each one is its own stream in the mixer
+pub struct AudioStream {
+    id: usize,
+}
 
-struct DevFSAudio;
+impl Drop for AudioStream {
+    fn drop(&mut self) { audio::close(self.id); }
+}
 
-const AC97_WRITE_CHUNK_SIZE: usize = 4092;
-const AC97_FRAME_SIZE: usize = 4;
+impl VFSFile for AudioStream {
+    /// blocks until at least something has been recorded
+    fn read(&self, buf: &mut [u8]) -> VFSResult<usize> {
+        audio::read(self.id, buf).map_err(|e| {
+            log::error!("audio: stream {}: {}", self.id, e);
+            VFSError::Unsupported
+        })
+    }
 
-fn audio_read(_offset: usize, _buf: &mut [u8]) -> usize { unimplemented!() }
+    /// blocks until everything is queued in the mixer
+    fn write(&self, buf: &mut [u8]) -> VFSResult<usize> {
+        Ok(audio::write(self.id, buf))
+    }
 
-fn audio_write(_buf: &[u8]) -> usize {
-    let mut guard = drivers::pci::devices::ac97::get_driver();
-    if let Some(driver) = guard.as_mut() {
-        let mut total_written = 0;
+    fn seek(&self, _pos: VFSSeek) -> VFSResult<usize> {
+        Err(VFSError::InvalidSeek)
+    }
 
-        let mut chunks = _buf.chunks_exact(AC97_WRITE_CHUNK_SIZE);
-        for chunk in &mut chunks {
-            while !driver.can_write() {
-                core::hint::spin_loop();
-            }
+    fn mmap(
+        &self,
+        _len: usize,
+        _prot: core::ffi::c_int,
+        _flags: core::ffi::c_int,
+    ) -> VFSResult<*mut u8> {
+        Err(VFSError::Unsupported)
+    }
 
-            let written = driver.write_buffer(chunk);
-            if written == 0 {
-                return total_written;
-            }
+    fn metadata(&self) -> VFSResult<VFSMetadata> {
+        Ok(VFSMetadata {
+            name: "audio".to_string(),
+            typ: VFSFileType::Device,
+            size: 0,
+            last_modified: time::boot_time() as usize,
+            owner_id: 0,
+            group_id: 0,
+            permissions: VFSPermissions::from_unix(0o666),
+        })
+    }
 
-            total_written += written;
+    /// rate, channels, format and pcm volume belong to the stream, the
+    /// rest is the card's and shared by everyone
+    fn ioctl(&self, cmd: u64, arg: u64) -> VFSResult<u64> {
+        let invalid = |e: &'static str| {
+            log::error!("audio: stream {}: {}", self.id, e);
+            VFSError::InvalidArgument
+        };
+        let stream = audio::info(self.id).ok_or(VFSError::IOError)?;
+
+        match cmd {
+            AUDIO_GET_RATE => Ok(u64::from(stream.rate)),
+            AUDIO_SET_RATE => {
+                audio::set_rate(self.id, arg as u32).map_err(invalid)?;
+                Ok(arg)
+            },
+            AUDIO_GET_CHANNELS => Ok(stream.channels as u64),
+            AUDIO_SET_CHANNELS => {
+                audio::set_channels(self.id, arg as usize).map_err(invalid)?;
+                Ok(0)
+            },
+            AUDIO_GET_FORMAT => Ok(match stream.format {
+                SampleFormat::U8 => AUDIO_FORMAT_U8,
+                SampleFormat::S16Le => AUDIO_FORMAT_S16_LE,
+            }),
+            AUDIO_SET_FORMAT => {
+                let format = match arg {
+                    AUDIO_FORMAT_U8 => SampleFormat::U8,
+                    AUDIO_FORMAT_S16_LE => SampleFormat::S16Le,
+                    _ => return Err(VFSError::InvalidArgument),
+                };
+                audio::set_format(self.id, format).map_err(invalid)?;
+                Ok(0)
+            },
+            AUDIO_GET_PCM_VOLUME => Ok(stream.volume as u64),
+            AUDIO_SET_PCM_VOLUME => {
+                audio::set_volume(self.id, arg as usize).map_err(invalid)?;
+                Ok(0)
+            },
+            _ => card_ioctl(cmd, arg),
         }
+    }
+}
 
-        let tail = chunks.remainder();
-        let aligned_len = tail.len() - (tail.len() % AC97_FRAME_SIZE);
-        if aligned_len > 0 {
-            while !driver.can_write() {
-                core::hint::spin_loop();
-            }
+fn card_ioctl(cmd: u64, arg: u64) -> VFSResult<u64> {
+    let card = audio::card().ok_or(VFSError::IOError)?;
+    let capture = || card.capture.as_ref().ok_or(VFSError::Unsupported);
 
-            let written = driver.write_buffer(&tail[..aligned_len]);
-            if written == 0 {
-                return total_written;
+    match cmd {
+        AUDIO_GET_MASTER_VOLUME => Ok((card.master_volume)() as u64),
+        AUDIO_SET_MASTER_VOLUME => {
+            if arg > 100 {
+                return Err(VFSError::InvalidArgument);
             }
+            (card.set_master_volume)(arg as usize);
+            Ok(0)
+        },
+        AUDIO_GET_RECORD_SOURCE => Ok(match (capture()?.source)() {
+            RecordSource::Mic => AUDIO_SOURCE_MIC,
+            RecordSource::LineIn => AUDIO_SOURCE_LINE_IN,
+        }),
+        AUDIO_SET_RECORD_SOURCE => {
+            let source = match arg {
+                AUDIO_SOURCE_MIC => RecordSource::Mic,
+                AUDIO_SOURCE_LINE_IN => RecordSource::LineIn,
+                _ => return Err(VFSError::InvalidArgument),
+            };
+            (capture()?.set_source)(source);
+            Ok(0)
+        },
+        AUDIO_GET_RECORD_GAIN => Ok((capture()?.gain)() as u64),
+        AUDIO_SET_RECORD_GAIN => {
+            if arg > 100 {
+                return Err(VFSError::InvalidArgument);
+            }
+            (capture()?.set_gain)(arg as usize);
+            Ok(0)
+        },
+        _ => Err(VFSError::Unsupported),
+    }
+}
 
-            total_written += written;
-        }
+pub fn open() -> Box<dyn VFSFile> {
+    Box::new(AudioStream { id: audio::open() })
+}
 
-        let remainder = &tail[aligned_len..];
-        if !remainder.is_empty() {
-            while !driver.can_write() {
-                core::hint::spin_loop();
-            }
+/// `master volume` then one line per stream,
+/// `id pid rate channels format volume queued`
+fn mixer_text() -> String {
+    let master = audio::card().map(|card| (card.master_volume)()).unwrap_or(0);
+
+    let mut text = format!("master {}\n", master);
+    for stream in audio::streams() {
+        text += &format!(
+            "{} {} {} {} {} {} {}\n",
+            stream.id,
+            stream.pid,
+            stream.rate,
+            stream.channels,
+            stream.format.name(),
+            stream.volume,
+            stream.queued
+        );
+    }
+    text
+}
 
-            let mut padded = [0u8; AC97_FRAME_SIZE];
-            padded[..remainder.len()].copy_from_slice(remainder);
+fn mixer_read(offset: usize, buf: &mut [u8]) -> usize {
+    let text = mixer_text();
+    let Some(rest) = text.as_bytes().get(offset..) else {
+        return 0;
+    };
 
-            if driver.write_buffer(&padded) == 0 {
-                return total_written;
-            }
+    let len = rest.len().min(buf.len());
+    buf[..len].copy_from_slice(&rest[..len]);
+    len
+}
 
-            total_written += remainder.len();
+/// takes `<id> <volume>` or `master <volume>`, one per line
+fn mixer_write(_offset: usize, buf: &[u8]) -> usize {
+    let Ok(text) = core::str::from_utf8(buf) else {
+        return 0;
+    };
+
+    for line in text.lines() {
+        let mut parts = line.split_whitespace();
+        let (Some(target), Some(volume), None) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        let Ok(volume) = volume.parse::<usize>() else {
+            continue;
+        };
+
+        let result = match target {
+            "master" if volume <= 100 => audio::card()
+                .map(|card| (card.set_master_volume)(volume))
+                .ok_or("no sound card"),
+            "master" => Err("volume goes from 0 to 100"),
+            id => id
+                .parse()
+                .map_err(|_| "bad stream id")
+                .and_then(|id| audio::set_volume(id, volume)),
+        };
+
+        if let Err(e) = result {
+            log::error!("mixer: {}: {}", line, e);
         }
-
-        total_written
-    } else {
-        0
     }
+
+    buf.len()
 }
 
 pub fn install(dev: &mut DevFS) {
+    dev.bind_opener("/audio".to_string(), open);
     dev.bind(DevFile::new(
-        "/audio".to_string(),
-        Some(audio_read),
-        Some(audio_write),
+        "/mixer".to_string(),
+        Some(mixer_read),
+        Some(mixer_write),
         None,
     ));
 }
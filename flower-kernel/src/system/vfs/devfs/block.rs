@@ -0,0 +1,120 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use flower_mono::ioctl::{BLK_FLUSH, BLK_GET_SECTOR_SIZE, BLK_GET_SIZE};
+
+use crate::drivers::block::{self, Disk};
+use crate::system::time;
+use crate::system::vfs::{
+    VFSError, VFSFile, VFSFileType, VFSMetadata, VFSPermissions, VFSResult,
+    VFSSeek,
+};
+
+/// a whole disk as one big file, any offset works
+pub struct BlockFile {
+    disk: Arc<Disk>,
+    position: AtomicUsize,
+}
+
+impl BlockFile {
+    fn size(&self) -> usize { self.disk.size() as usize }
+
+    fn io_error(&self, e: &'static str) -> VFSError {
+        log::error!("{}: {}", self.disk.name, e);
+        VFSError::IOError
+    }
+}
+
+impl VFSFile for BlockFile {
+    fn read(&self, buf: &mut [u8]) -> VFSResult<usize> {
+        let position = self.position.load(Ordering::Acquire);
+        let len = buf.len().min(self.size().saturating_sub(position));
+        if len == 0 {
+            return Ok(0);
+        }
+
+        self.disk
+            .read_at(position as u64, &mut buf[..len])
+            .map_err(|e| self.io_error(e))?;
+        self.position.fetch_add(len, Ordering::AcqRel);
+        Ok(len)
+    }
+
+    fn write(&self, buf: &mut [u8]) -> VFSResult<usize> {
+        if self.disk.read_only() {
+            return Err(VFSError::PermissionDenied);
+        }
+
+        let position = self.position.load(Ordering::Acquire);
+        let len = buf.len().min(self.size().saturating_sub(position));
+        if len == 0 && !buf.is_empty() {
+            return Err(VFSError::NoSpace);
+        }
+
+        self.disk
+            .write_at(position as u64, &buf[..len])
+            .map_err(|e| self.io_error(e))?;
+        self.position.fetch_add(len, Ordering::AcqRel);
+        Ok(len)
+    }
+
+    fn seek(&self, pos: VFSSeek) -> VFSResult<usize> {
+        let current = self.position.load(Ordering::Acquire);
+        let new_pos = match pos {
+            VFSSeek::Start(n) => n,
+            VFSSeek::Current(n) => current.saturating_add(n),
+            VFSSeek::End(n) => self.size().saturating_add(n),
+        };
+
+        self.position.store(new_pos, Ordering::Release);
+        Ok(new_pos)
+    }
+
+    fn mmap(
+        &self,
+        _len: usize,
+        _prot: core::ffi::c_int,
+        _flags: core::ffi::c_int,
+    ) -> VFSResult<*mut u8> {
+        Err(VFSError::Unsupported)
+    }
+
+    fn metadata(&self) -> VFSResult<VFSMetadata> {
+        Ok(VFSMetadata {
+            name: self.disk.name.clone(),
+            typ: VFSFileType::Device,
+            size: self.size(),
+            last_modified: time::boot_time() as usize,
+            owner_id: 0,
+            group_id: 0,
+            permissions: VFSPermissions::from_unix(0o660),
+        })
+    }
+
+    fn sync(&self) -> VFSResult<()> {
+        self.disk.flush().map_err(|e| self.io_error(e))
+    }
+
+    fn ioctl(&self, cmd: u64, _arg: u64) -> VFSResult<u64> {
+        match cmd {
+            BLK_GET_SIZE => Ok(self.disk.size()),
+            BLK_GET_SECTOR_SIZE => Ok(self.disk.sector_size() as u64),
+            BLK_FLUSH => {
+                self.sync()?;
+                Ok(0)
+            },
+            _ => Err(VFSError::Unsupported),
+        }
+    }
+}
+
+/// block devices come and go with their drivers, so they're looked up on
+/// every open instead of being bound up front
+pub fn open(path: &str) -> Option<Box<dyn VFSFile>> {
+    let name = path.strip_prefix('/')?;
+    Some(Box::new(BlockFile {
+        disk: block::get(name)?,
+        position: AtomicUsize::new(0),
+    }))
+}
@@ -1,33 +1,121 @@
 use alloc::string::ToString;
 use core::ffi::c_int;
 
-use crate::boot::limine::FRAMEBUFFER_REQUEST;
+use flower_mono::ioctl::{FBIO_FLIP, FBIOGET_INFO, FBIOPUT_MODE};
+use flower_mono::structs::{FramebufferInfo, FramebufferMode};
+use x86_64::align_up;
+
+use crate::arch;
+use crate::drivers::video;
 use crate::system::vfs::devfs::{DevFS, DevFile};
+use crate::system::vfs::{VFSError, VFSResult};
 
-fn framebuffer_read(_offset: usize, _buf: &mut [u8]) -> usize {
-    unimplemented!()
+fn framebuffer_size() -> usize {
+    video::framebuffer().map(|fb| fb.info.total_size()).unwrap_or(0)
 }
-fn framebuffer_write(_buf: &[u8]) -> usize { unimplemented!() }
-
-fn framebuffer_mmap(_size: usize, _prot: c_int, _flags: c_int) -> *mut u8 {
-    // HACK: uhh
-    if let Some(fb) = FRAMEBUFFER_REQUEST
-        .get_response()
-        .expect("no valid framebuffer")
-        .framebuffers()
-        .next()
-    {
-        fb.addr()
-    } else {
-        core::ptr::null_mut()
+
+fn framebuffer_read(offset: usize, buf: &mut [u8]) -> usize {
+    let Some(fb) = video::framebuffer() else {
+        return 0;
+    };
+
+    let size = fb.info.total_size();
+    if offset >= size {
+        return 0;
+    }
+
+    let len = buf.len().min(size - offset);
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            fb.addr.add(offset),
+            buf.as_mut_ptr(),
+            len,
+        );
+    }
+    len
+}
+
+fn framebuffer_write(offset: usize, buf: &[u8]) -> usize {
+    let Some(fb) = video::framebuffer() else {
+        return 0;
+    };
+
+    let size = fb.info.total_size();
+    if offset >= size {
+        return 0;
+    }
+
+    let len = buf.len().min(size - offset);
+    unsafe {
+        core::ptr::copy_nonoverlapping(buf.as_ptr(), fb.addr.add(offset), len);
+    }
+    len
+}
+
+fn framebuffer_mmap(size: usize, _prot: c_int, _flags: c_int) -> *mut u8 {
+    let Some(fb) = video::framebuffer() else {
+        return core::ptr::null_mut();
+    };
+
+    // only hand out the pages that actually belong to the framebuffer
+    let fb_size = fb.info.total_size() as u64;
+    if size as u64 > align_up(fb_size, arch::layout::PAGE_SIZE as u64) {
+        log::error!(
+            "fb0: mmap of {} bytes exceeds framebuffer size of {} bytes",
+            size,
+            fb_size
+        );
+        return core::ptr::null_mut();
+    }
+
+    fb.addr
+}
+
+fn framebuffer_ioctl(cmd: u64, arg: u64) -> VFSResult<u64> {
+    match cmd {
+        FBIOGET_INFO => {
+            let out = arg as *mut FramebufferInfo;
+            if out.is_null() {
+                return Err(VFSError::InvalidArgument);
+            }
+
+            let fb = video::framebuffer().ok_or(VFSError::IOError)?;
+            unsafe { out.write(fb.info) };
+            Ok(0)
+        },
+        FBIOPUT_MODE => {
+            let mode = arg as *const FramebufferMode;
+            if mode.is_null() {
+                return Err(VFSError::InvalidArgument);
+            }
+
+            let mode = unsafe { mode.read() };
+            video::set_mode(&mode).map_err(|e| {
+                log::error!("fb0: failed to set mode: {}", e);
+                VFSError::InvalidArgument
+            })?;
+            Ok(0)
+        },
+        FBIO_FLIP => {
+            video::flip(arg as u32).map_err(|e| {
+                log::error!("fb0: failed to flip to buffer {}: {}", arg, e);
+                VFSError::InvalidArgument
+            })?;
+            Ok(0)
+        },
+        _ => Err(VFSError::Unsupported),
     }
 }
 
 pub fn install(dev: &mut DevFS) {
-    dev.bind(DevFile::new(
-        "/fb0".to_string(),
-        Some(framebuffer_read),
-        Some(framebuffer_write),
-        Some(framebuffer_mmap),
-    ));
+    dev.bind(
+        DevFile::new(
+            "/fb0".to_string(),
+            Some(framebuffer_read),
+            Some(framebuffer_write),
+            Some(framebuffer_mmap),
+        )
+        .with_ioctl(framebuffer_ioctl)
+        .with_size(framebuffer_size),
+    );
 }
@@ -2,13 +2,16 @@ use alloc::boxed::Box;
 use alloc::collections::VecDeque;
 use alloc::string::ToString;
 
-use spin::Mutex;
+use spin::{Mutex, Once};
 
 use crate::drivers::ps2::keyboard::{KEYBOARD, KeyEvent, KeyboardSubscriber};
 use crate::system::vfs::devfs::{DevFS, DevFile};
 
 static KB_BUFFER: Mutex<VecDeque<u8>> = Mutex::new(VecDeque::new());
 
+/// devfs can be mounted more than once, keys should only land once
+static SUBSCRIBED: Once = Once::new();
+
 struct DevFSKeyboard;
 
 impl KeyboardSubscriber for DevFSKeyboard {
@@ -35,11 +38,13 @@ fn kb_read(_offset: usize, buf: &mut [u8]) -> usize {
     read
 }
 
-fn kb_write(_buf: &[u8]) -> usize { 0 }
+fn kb_write(_offset: usize, _buf: &[u8]) -> usize { 0 }
 
 pub fn install(dev: &mut DevFS) {
-    let subscriber = Box::leak(Box::new(DevFSKeyboard));
-    KEYBOARD.lock().subscribe(subscriber);
+    SUBSCRIBED.call_once(|| {
+        let subscriber = Box::leak(Box::new(DevFSKeyboard));
+        KEYBOARD.lock().subscribe(subscriber);
+    });
 
     dev.bind(DevFile::new(
         "/keyboard".to_string(),
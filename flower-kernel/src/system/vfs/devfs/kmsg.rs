@@ -0,0 +1,113 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::ToString;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use flower_mono::ioctl::{KMSG_GET_CONSOLE_LEVEL, KMSG_SET_CONSOLE_LEVEL};
+use log::LevelFilter;
+
+use crate::drivers::tty::kmsg;
+use crate::system::time;
+use crate::system::vfs::devfs::DevFS;
+use crate::system::vfs::{
+    VFSError, VFSFile, VFSFileType, VFSMetadata, VFSPermissions, VFSResult,
+    VFSSeek,
+};
+
+/// a reader of the kernel log, each open keeps its own place in the ring
+pub struct KmsgFile {
+    seq: AtomicU64,
+}
+
+impl VFSFile for KmsgFile {
+    fn read(&self, buf: &mut [u8]) -> VFSResult<usize> {
+        let mut read = 0;
+
+        while let Some(record) = kmsg::read(self.seq.load(Ordering::Acquire)) {
+            let line = format!("{}\n", record);
+            let bytes = line.as_bytes();
+
+            if read + bytes.len() > buf.len() {
+                // never hand out half a record unless it can't fit at all
+                if read == 0 {
+                    buf.copy_from_slice(&bytes[..buf.len()]);
+                    read = buf.len();
+                    self.seq.store(record.seq + 1, Ordering::Release);
+                }
+                break;
+            }
+
+            buf[read..read + bytes.len()].copy_from_slice(bytes);
+            read += bytes.len();
+            self.seq.store(record.seq + 1, Ordering::Release);
+        }
+
+        Ok(read)
+    }
+
+    fn write(&self, buf: &mut [u8]) -> VFSResult<usize> {
+        let message =
+            core::str::from_utf8(buf).map_err(|_| VFSError::InvalidArgument)?;
+        log::info!("{}", message.trim_end());
+        Ok(buf.len())
+    }
+
+    fn seek(&self, pos: VFSSeek) -> VFSResult<usize> {
+        let seq = match pos {
+            VFSSeek::Start(0) => kmsg::first_seq(),
+            VFSSeek::End(0) => kmsg::next_seq(),
+            VFSSeek::Current(0) => self.seq.load(Ordering::Acquire),
+            _ => return Err(VFSError::InvalidSeek),
+        };
+
+        self.seq.store(seq, Ordering::Release);
+        Ok(seq as usize)
+    }
+
+    fn mmap(
+        &self,
+        _len: usize,
+        _prot: core::ffi::c_int,
+        _flags: core::ffi::c_int,
+    ) -> VFSResult<*mut u8> {
+        Err(VFSError::Unsupported)
+    }
+
+    fn metadata(&self) -> VFSResult<VFSMetadata> {
+        Ok(VFSMetadata {
+            name: "kmsg".to_string(),
+            typ: VFSFileType::Device,
+            size: 0,
+            last_modified: time::now() as usize,
+            owner_id: 0,
+            group_id: 0,
+            permissions: VFSPermissions::from_unix(0o644),
+        })
+    }
+
+    fn ioctl(&self, cmd: u64, arg: u64) -> VFSResult<u64> {
+        match cmd {
+            KMSG_GET_CONSOLE_LEVEL => Ok(kmsg::console_level() as u64),
+            KMSG_SET_CONSOLE_LEVEL => {
+                let level = match arg {
+                    0 => LevelFilter::Off,
+                    1 => LevelFilter::Error,
+                    2 => LevelFilter::Warn,
+                    3 => LevelFilter::Info,
+                    4 => LevelFilter::Debug,
+                    5 => LevelFilter::Trace,
+                    _ => return Err(VFSError::InvalidArgument),
+                };
+                kmsg::set_console_level(level);
+                Ok(0)
+            },
+            _ => Err(VFSError::Unsupported),
+        }
+    }
+}
+
+pub fn open() -> Box<dyn VFSFile> {
+    Box::new(KmsgFile { seq: AtomicU64::new(kmsg::first_seq()) })
+}
+
+pub fn install(dev: &mut DevFS) { dev.bind_opener("/kmsg".to_string(), open); }
@@ -0,0 +1,80 @@
+use alloc::boxed::Box;
+use alloc::string::ToString;
+
+use crate::system::time;
+use crate::system::vfs::devfs::DevFS;
+use crate::system::vfs::{
+    VFSError, VFSFile, VFSFileType, VFSMetadata, VFSPermissions, VFSResult,
+    VFSSeek,
+};
+
+/// the classic pseudo devices, none of them keep any state
+#[derive(Clone, Copy)]
+enum MemFile {
+    /// reads nothing, swallows writes
+    Null,
+    /// reads zeroes, swallows writes
+    Zero,
+    /// reads zeroes, every write fails as if the disk was full
+    Full,
+}
+
+impl VFSFile for MemFile {
+    fn read(&self, buf: &mut [u8]) -> VFSResult<usize> {
+        match self {
+            MemFile::Null => Ok(0),
+            MemFile::Zero | MemFile::Full => {
+                buf.fill(0);
+                Ok(buf.len())
+            },
+        }
+    }
+
+    fn write(&self, buf: &mut [u8]) -> VFSResult<usize> {
+        match self {
+            MemFile::Null | MemFile::Zero => Ok(buf.len()),
+            MemFile::Full => Err(VFSError::NoSpace),
+        }
+    }
+
+    fn seek(&self, _pos: VFSSeek) -> VFSResult<usize> { Ok(0) }
+
+    fn mmap(
+        &self,
+        _len: usize,
+        _prot: core::ffi::c_int,
+        _flags: core::ffi::c_int,
+    ) -> VFSResult<*mut u8> {
+        Err(VFSError::Unsupported)
+    }
+
+    fn metadata(&self) -> VFSResult<VFSMetadata> {
+        let name = match self {
+            MemFile::Null => "null",
+            MemFile::Zero => "zero",
+            MemFile::Full => "full",
+        };
+
+        Ok(VFSMetadata {
+            name: name.to_string(),
+            typ: VFSFileType::Device,
+            size: 0,
+            last_modified: time::boot_time() as usize,
+            owner_id: 0,
+            group_id: 0,
+            permissions: VFSPermissions::from_unix(0o666),
+        })
+    }
+}
+
+fn open_null() -> Box<dyn VFSFile> { Box::new(MemFile::Null) }
+
+fn open_zero() -> Box<dyn VFSFile> { Box::new(MemFile::Zero) }
+
+fn open_full() -> Box<dyn VFSFile> { Box::new(MemFile::Full) }
+
+pub fn install(dev: &mut DevFS) {
+    dev.bind_opener("/null".to_string(), open_null);
+    dev.bind_opener("/zero".to_string(), open_zero);
+    dev.bind_opener("/full".to_string(), open_full);
+}
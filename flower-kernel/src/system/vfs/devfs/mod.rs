@@ -1,7 +1,10 @@
 mod audio;
+mod block;
 mod framebuffer;
 mod keyboard;
-mod proc;
+pub mod kmsg;
+mod mem;
+mod random;
 
 use alloc::boxed::Box;
 use alloc::string::{String, ToString};
@@ -9,8 +12,7 @@ use alloc::vec::Vec;
 use core::ffi::c_int;
 use core::sync::atomic::{AtomicUsize, Ordering};
 
-pub use proc::create_procfs;
-
+use crate::system::time;
 use crate::system::vfs::{
     VFSError, VFSFile, VFSFileType, VFSImplementation, VFSMetadata,
     VFSPermissions, VFSResult, VFSSeek,
@@ -21,15 +23,17 @@ pub struct DevFile {
     position: AtomicUsize,
 
     fn_read: Option<fn(usize, &mut [u8]) -> usize>,
-    fn_write: Option<fn(&[u8]) -> usize>,
+    fn_write: Option<fn(usize, &[u8]) -> usize>,
     fn_mmap: Option<fn(usize, c_int, c_int) -> *mut u8>,
+    fn_ioctl: Option<fn(u64, u64) -> VFSResult<u64>>,
+    fn_size: Option<fn() -> usize>,
 }
 
 impl DevFile {
     pub fn new(
         path: String,
         read: Option<fn(usize, &mut [u8]) -> usize>,
-        write: Option<fn(&[u8]) -> usize>,
+        write: Option<fn(usize, &[u8]) -> usize>,
         mmap: Option<fn(usize, c_int, c_int) -> *mut u8>,
     ) -> Self {
         Self {
@@ -38,8 +42,24 @@ impl DevFile {
             fn_read: read,
             fn_write: write,
             fn_mmap: mmap,
+            fn_ioctl: None,
+            fn_size: None,
         }
     }
+
+    /// attaches an ioctl handler to the device
+    pub fn with_ioctl(mut self, ioctl: fn(u64, u64) -> VFSResult<u64>) -> Self {
+        self.fn_ioctl = Some(ioctl);
+        self
+    }
+
+    /// gives the device a size, used for seeking from the end and stat
+    pub fn with_size(mut self, size: fn() -> usize) -> Self {
+        self.fn_size = Some(size);
+        self
+    }
+
+    fn size(&self) -> usize { self.fn_size.map(|size| size()).unwrap_or(0) }
 }
 
 impl Clone for DevFile {
@@ -50,6 +70,8 @@ impl Clone for DevFile {
             fn_read: self.fn_read,
             fn_write: self.fn_write,
             fn_mmap: self.fn_mmap,
+            fn_ioctl: self.fn_ioctl,
+            fn_size: self.fn_size,
         }
     }
 }
@@ -70,18 +92,23 @@ impl VFSFile for DevFile {
 
     fn write(&self, buf: &mut [u8]) -> VFSResult<usize> {
         if let Some(write_fn) = self.fn_write {
-            Ok(write_fn(buf))
+            let position = self.position.load(Ordering::Acquire);
+            let written = write_fn(position, buf);
+            if written > 0 {
+                self.position.fetch_add(written, Ordering::AcqRel);
+            }
+            Ok(written)
         } else {
             Err(VFSError::Unsupported)
         }
     }
 
-    fn seek(&mut self, pos: VFSSeek) -> VFSResult<usize> {
+    fn seek(&self, pos: VFSSeek) -> VFSResult<usize> {
         let current = self.position.load(Ordering::Acquire);
         let new_pos = match pos {
             VFSSeek::Start(n) => n,
             VFSSeek::Current(n) => current.saturating_add(n),
-            VFSSeek::End(n) => n,
+            VFSSeek::End(n) => self.size().saturating_add(n),
         };
 
         self.position.store(new_pos, Ordering::Release);
@@ -101,6 +128,14 @@ impl VFSFile for DevFile {
         }
     }
 
+    fn ioctl(&self, cmd: u64, arg: u64) -> VFSResult<u64> {
+        if let Some(ioctl_fn) = self.fn_ioctl {
+            ioctl_fn(cmd, arg)
+        } else {
+            Err(VFSError::Unsupported)
+        }
+    }
+
     fn metadata(&self) -> VFSResult<VFSMetadata> {
         Ok(VFSMetadata {
             name: self
@@ -110,8 +145,8 @@ impl VFSFile for DevFile {
                 .unwrap_or(self.path.as_str())
                 .to_string(),
             typ: VFSFileType::Device,
-            size: 0,
-            last_modified: 0,
+            size: self.size(),
+            last_modified: time::boot_time() as usize,
             owner_id: 0,
             group_id: 0,
             permissions: VFSPermissions::new(),
@@ -119,31 +154,52 @@ impl VFSFile for DevFile {
     }
 }
 
+/// creates a fresh file for every open, for devices that keep per-open state
+pub type DevOpener = fn() -> Box<dyn VFSFile>;
+
 pub struct DevFS {
     files: Vec<DevFile>,
+    openers: Vec<(String, DevOpener)>,
 }
 
 impl DevFS {
-    pub fn new() -> Self { Self { files: Vec::new() } }
+    pub fn new() -> Self { Self { files: Vec::new(), openers: Vec::new() } }
 
     pub fn bind(&mut self, file: DevFile) { self.files.push(file); }
+
+    pub fn bind_opener(&mut self, path: String, opener: DevOpener) {
+        self.openers.push((path, opener));
+    }
+
+    fn find_opener(&self, path: &str) -> Option<DevOpener> {
+        self.openers.iter().find(|(p, _)| p == path).map(|(_, opener)| *opener)
+    }
 }
 
 impl VFSImplementation for DevFS {
+    fn fs_type(&self) -> &'static str { "devfs" }
+
     fn open(&self, path: &str, _flags: u32) -> VFSResult<Box<dyn VFSFile>> {
-        self.files
-            .iter()
-            .find(|f| f.path == path)
-            .map(|f| Box::new(f.clone()) as Box<dyn VFSFile>)
+        if let Some(file) = self.files.iter().find(|f| f.path == path) {
+            return Ok(Box::new(file.clone()));
+        }
+
+        self.find_opener(path)
+            .map(|open| open())
+            .or_else(|| block::open(path))
             .ok_or(VFSError::NotFound)
     }
 
     fn metadata(&self, path: &str) -> VFSResult<VFSMetadata> {
-        self.files
-            .iter()
-            .find(|f| f.path == path)
-            .map(|f| f.metadata())
-            .ok_or(VFSError::NotFound)?
+        if let Some(file) = self.files.iter().find(|f| f.path == path) {
+            return file.metadata();
+        }
+
+        if let Some(open) = self.find_opener(path) {
+            return open().metadata();
+        }
+
+        block::open(path).ok_or(VFSError::NotFound)?.metadata()
     }
 }
 
@@ -152,5 +208,8 @@ pub fn create_devfs() -> DevFS {
     keyboard::install(&mut mnt);
     audio::install(&mut mnt);
     framebuffer::install(&mut mnt);
+    kmsg::install(&mut mnt);
+    mem::install(&mut mnt);
+    random::install(&mut mnt);
     mnt
 }
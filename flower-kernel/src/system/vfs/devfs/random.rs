@@ -0,0 +1,32 @@
+use alloc::string::ToString;
+
+use crate::system::random;
+use crate::system::vfs::devfs::{DevFS, DevFile};
+
+fn random_read(_offset: usize, buf: &mut [u8]) -> usize {
+    random::fill(buf);
+    buf.len()
+}
+
+/// anything written gets mixed into the pool, it never hurts
+fn random_write(_offset: usize, buf: &[u8]) -> usize {
+    for chunk in buf.chunks(8) {
+        let mut bytes = [0u8; 8];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        random::add_entropy(u64::from_le_bytes(bytes));
+    }
+    buf.len()
+}
+
+pub fn install(dev: &mut DevFS) {
+    // both come from the same pool, the csprng never runs dry so there's
+    // no reason for /dev/random to block
+    for path in ["/random", "/urandom"] {
+        dev.bind(DevFile::new(
+            path.to_string(),
+            Some(random_read),
+            Some(random_write),
+            None,
+        ));
+    }
+}
@@ -0,0 +1,55 @@
+// based on https://www.nongnu.org/ext2-doc/ext2.html
+
+pub const SUPERBLOCK_OFFSET: u64 = 1024;
+pub const SUPERBLOCK_SIZE: usize = 1024;
+pub const EXT2_MAGIC: u16 = 0xEF53;
+
+pub const GROUP_DESC_SIZE: usize = 32;
+
+pub const ROOT_INO: u32 = 2;
+/// rev 0 filesystems don't say, these are the defaults
+pub const GOOD_OLD_FIRST_INO: u32 = 11;
+pub const GOOD_OLD_INODE_SIZE: usize = 128;
+
+// features we understand, anything else in incompat means hands off and
+// anything else in ro_compat means read only
+pub const INCOMPAT_FILETYPE: u32 = 0x0002;
+pub const RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
+pub const RO_COMPAT_LARGE_FILE: u32 = 0x0002;
+
+// inode modes
+pub const S_IFMT: u16 = 0xF000;
+pub const S_IFSOCK: u16 = 0xC000;
+pub const S_IFLNK: u16 = 0xA000;
+pub const S_IFREG: u16 = 0x8000;
+pub const S_IFBLK: u16 = 0x6000;
+pub const S_IFDIR: u16 = 0x4000;
+pub const S_IFCHR: u16 = 0x2000;
+pub const S_IFIFO: u16 = 0x1000;
+
+// inode flags
+/// hashed directory, we don't keep the hash up to date so it gets cleared
+pub const INDEX_FL: u32 = 0x1000;
+
+// directory entry file types
+pub const FT_UNKNOWN: u8 = 0;
+pub const FT_REG_FILE: u8 = 1;
+pub const FT_DIR: u8 = 2;
+pub const FT_SYMLINK: u8 = 7;
+
+pub const DIRECT_BLOCKS: usize = 12;
+pub const SINGLE_INDIRECT: usize = 12;
+pub const DOUBLE_INDIRECT: usize = 13;
+pub const TRIPLE_INDIRECT: usize = 14;
+
+/// symlinks shorter than this live in the block pointers
+pub const FAST_SYMLINK_MAX: usize = 60;
+/// longest symlink target we read, slow ones take a single block anyway
+pub const PATH_MAX: usize = 4096;
+
+pub const MAX_NAME_LEN: usize = 255;
+/// 8 TiB with 4k blocks, a superblock claiming more is garbage and the
+/// descriptor table would eat all the memory
+pub const MAX_GROUPS: usize = 1 << 16;
+/// how many symlinks a lookup follows before giving up
+pub const MAX_SYMLINKS: usize = 8;
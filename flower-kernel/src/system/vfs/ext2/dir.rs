@@ -0,0 +1,262 @@
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::system::bytes::{put_u16, put_u32, u16_at, u32_at};
+use crate::system::vfs::ext2::consts::*;
+use crate::system::vfs::ext2::inode::Inode;
+use crate::system::vfs::ext2::volume::{Volume, io_error, now};
+use crate::system::vfs::{VFSError, VFSResult};
+
+pub struct DirEntry {
+    pub ino: u32,
+    pub name: String,
+    pub typ: u8,
+}
+
+/// one record in a directory block, as found on disk
+struct Record {
+    offset: usize,
+    ino: u32,
+    rec_len: usize,
+    name_len: usize,
+}
+
+impl Record {
+    /// how much of the record the entry actually needs
+    fn used(&self) -> usize {
+        if self.ino == 0 { 0 } else { rec_size(self.name_len) }
+    }
+
+    fn name<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.offset + 8..self.offset + 8 + self.name_len]
+    }
+}
+
+/// entries are 8 bytes of header then the name, padded to 4 bytes
+fn rec_size(name_len: usize) -> usize { (8 + name_len + 3) & !3 }
+
+/// splits a directory block into records, refusing anything that doesn't
+/// add up
+fn records(data: &[u8]) -> VFSResult<Vec<Record>> {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while offset + 8 <= data.len() {
+        let ino = u32_at(data, offset);
+        let rec_len = u16_at(data, offset + 4) as usize;
+        let name_len = data[offset + 6] as usize;
+
+        if rec_len < 8
+            || offset + rec_len > data.len()
+            || 8 + name_len > rec_len
+        {
+            return Err(io_error("corrupt directory entry"));
+        }
+        records.push(Record { offset, ino, rec_len, name_len });
+        offset += rec_len;
+    }
+    Ok(records)
+}
+
+fn put_record(
+    data: &mut [u8],
+    offset: usize,
+    ino: u32,
+    rec_len: usize,
+    name: &[u8],
+    typ: u8,
+) {
+    put_u32(data, offset, ino);
+    put_u16(data, offset + 4, rec_len as u16);
+    data[offset + 6] = name.len() as u8;
+    data[offset + 7] = typ;
+    data[offset + 8..offset + 8 + name.len()].copy_from_slice(name);
+}
+
+impl Volume {
+    fn dir_blocks(&self, dir: &Inode) -> u64 {
+        dir.size / self.block_size as u64
+    }
+
+    /// every entry in use, `.` and `..` included
+    pub fn read_dir(&self, dir: &Inode) -> VFSResult<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        for index in 0..self.dir_blocks(dir) {
+            let block = self.bmap(dir, index)?;
+            if block == 0 {
+                continue;
+            }
+
+            let data = self.read_block(block)?;
+            for record in records(&data)? {
+                if record.ino == 0 {
+                    continue;
+                }
+                entries.push(DirEntry {
+                    ino: record.ino,
+                    name: String::from_utf8_lossy(record.name(&data)).into(),
+                    typ: if self.filetype {
+                        data[record.offset + 7]
+                    } else {
+                        FT_UNKNOWN
+                    },
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    pub fn lookup(&self, dir: &Inode, name: &str) -> VFSResult<Option<u32>> {
+        for index in 0..self.dir_blocks(dir) {
+            let block = self.bmap(dir, index)?;
+            if block == 0 {
+                continue;
+            }
+
+            let data = self.read_block(block)?;
+            for record in records(&data)? {
+                if record.ino != 0 && record.name(&data) == name.as_bytes() {
+                    return Ok(Some(record.ino));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// true if there's nothing but `.` and `..`
+    pub fn is_empty_dir(&self, dir: &Inode) -> VFSResult<bool> {
+        Ok(self
+            .read_dir(dir)?
+            .iter()
+            .all(|entry| entry.name == "." || entry.name == ".."))
+    }
+
+    /// marks the directory as changed, the hash index (if any) is stale now
+    fn touch_dir(&self, dir_ino: u32, dir: &mut Inode) -> VFSResult<()> {
+        dir.flags &= !INDEX_FL;
+        dir.mtime = now();
+        dir.ctime = dir.mtime;
+        self.write_inode(dir_ino, dir)
+    }
+
+    /// links `ino` into a directory, in the first gap big enough or a new
+    /// block at the end
+    pub fn add_entry(
+        &mut self,
+        dir_ino: u32,
+        name: &str,
+        ino: u32,
+        typ: u8,
+    ) -> VFSResult<()> {
+        self.check_writable()?;
+        let name = name.as_bytes();
+        let typ = if self.filetype { typ } else { FT_UNKNOWN };
+        let need = rec_size(name.len());
+        let mut dir = self.read_inode(dir_ino)?;
+
+        for index in 0..self.dir_blocks(&dir) {
+            let block = self.bmap(&dir, index)?;
+            if block == 0 {
+                continue;
+            }
+
+            let mut data = self.read_block(block)?;
+            let Some(record) = records(&data)?
+                .into_iter()
+                .find(|record| record.rec_len - record.used() >= need)
+            else {
+                continue;
+            };
+
+            if record.ino == 0 {
+                put_record(
+                    &mut data,
+                    record.offset,
+                    ino,
+                    record.rec_len,
+                    name,
+                    typ,
+                );
+            } else {
+                // split the record, the old entry keeps what it needs
+                let used = record.used();
+                put_u16(&mut data, record.offset + 4, used as u16);
+                put_record(
+                    &mut data,
+                    record.offset + used,
+                    ino,
+                    record.rec_len - used,
+                    name,
+                    typ,
+                );
+            }
+            self.write_block_at(block, 0, &data)?;
+            return self.touch_dir(dir_ino, &mut dir);
+        }
+
+        let index = self.dir_blocks(&dir);
+        let block = self.bmap_alloc(dir_ino, &mut dir, index)?;
+        let mut data = vec![0u8; self.block_size];
+        put_record(&mut data, 0, ino, self.block_size, name, typ);
+        self.write_block_at(block, 0, &data)?;
+
+        dir.size += self.block_size as u64;
+        self.touch_dir(dir_ino, &mut dir)
+    }
+
+    /// unlinks `name` from a directory and returns the inode it pointed to,
+    /// the record gets merged into the one before it
+    pub fn remove_entry(&mut self, dir_ino: u32, name: &str) -> VFSResult<u32> {
+        self.check_writable()?;
+        let mut dir = self.read_inode(dir_ino)?;
+
+        for index in 0..self.dir_blocks(&dir) {
+            let block = self.bmap(&dir, index)?;
+            if block == 0 {
+                continue;
+            }
+
+            let mut data = self.read_block(block)?;
+            let records = records(&data)?;
+            let Some(pos) = records.iter().position(|record| {
+                record.ino != 0 && record.name(&data) == name.as_bytes()
+            }) else {
+                continue;
+            };
+
+            let record = &records[pos];
+            match pos.checked_sub(1).map(|prev| &records[prev]) {
+                Some(prev) => {
+                    let merged = (prev.rec_len + record.rec_len) as u16;
+                    put_u16(&mut data, prev.offset + 4, merged);
+                },
+                None => data[record.offset..record.offset + 4].fill(0),
+            }
+            self.write_block_at(block, 0, &data)?;
+
+            let ino = record.ino;
+            self.touch_dir(dir_ino, &mut dir)?;
+            return Ok(ino);
+        }
+        Err(VFSError::NotFound)
+    }
+
+    /// gives a new directory its first block with `.` and `..`, the caller
+    /// writes the inode back
+    pub fn init_dir(
+        &mut self,
+        ino: u32,
+        dir: &mut Inode,
+        parent: u32,
+    ) -> VFSResult<()> {
+        let block = self.bmap_alloc(ino, dir, 0)?;
+        let typ = if self.filetype { FT_DIR } else { FT_UNKNOWN };
+        let mut data = vec![0u8; self.block_size];
+        put_record(&mut data, 0, ino, 12, b".", typ);
+        put_record(&mut data, 12, parent, self.block_size - 12, b"..", typ);
+        self.write_block_at(block, 0, &data)?;
+
+        dir.size = self.block_size as u64;
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Mutex;
+
+use crate::system::vfs::ext2::inode::Inode;
+use crate::system::vfs::ext2::volume::Volume;
+use crate::system::vfs::{
+    VFSError, VFSFile, VFSMetadata, VFSPermissions, VFSResult, VFSSeek,
+};
+
+pub fn metadata(name: &str, inode: &Inode) -> VFSMetadata {
+    VFSMetadata {
+        name: String::from(name),
+        typ: inode.file_type(),
+        size: inode.size as usize,
+        last_modified: inode.mtime as usize,
+        owner_id: inode.uid as usize,
+        group_id: inode.gid as usize,
+        permissions: VFSPermissions::from_unix(inode.mode as usize),
+    }
+}
+
+/// an open inode. the inode is read fresh on every call, so two opens of
+/// the same file see each other's writes
+pub struct Ext2File {
+    pub volume: Arc<Mutex<Volume>>,
+    pub ino: u32,
+    pub name: String,
+    /// every write goes to the end
+    pub append: bool,
+    pub position: AtomicUsize,
+}
+
+impl Ext2File {
+    /// directories read back as one name per line, like procfs
+    fn listing(volume: &Volume, inode: &Inode) -> VFSResult<Vec<u8>> {
+        let mut data = Vec::new();
+        for entry in volume.read_dir(inode)? {
+            if entry.name == "." || entry.name == ".." {
+                continue;
+            }
+            data.extend_from_slice(entry.name.as_bytes());
+            data.push(b'\n');
+        }
+        Ok(data)
+    }
+}
+
+impl VFSFile for Ext2File {
+    fn read(&self, buf: &mut [u8]) -> VFSResult<usize> {
+        let volume = self.volume.lock();
+        let inode = volume.read_inode(self.ino)?;
+        let position = self.position.load(Ordering::Acquire);
+
+        let read = if inode.is_dir() {
+            let listing = Self::listing(&volume, &inode)?;
+            if position >= listing.len() {
+                return Ok(0);
+            }
+            let len = buf.len().min(listing.len() - position);
+            buf[..len].copy_from_slice(&listing[position..position + len]);
+            len
+        } else {
+            volume.read_data(&inode, position as u64, buf)?
+        };
+
+        self.position.store(position + read, Ordering::Release);
+        Ok(read)
+    }
+
+    fn write(&self, buf: &mut [u8]) -> VFSResult<usize> {
+        let mut volume = self.volume.lock();
+        let mut inode = volume.read_inode(self.ino)?;
+        if inode.is_dir() {
+            return Err(VFSError::IsDirectory);
+        }
+
+        let position = if self.append {
+            inode.size as usize
+        } else {
+            self.position.load(Ordering::Acquire)
+        };
+        let written =
+            volume.write_data(self.ino, &mut inode, position as u64, buf);
+        // blocks may have been allocated even if it failed halfway
+        volume.write_inode(self.ino, &inode)?;
+
+        let written = written?;
+        self.position.store(position + written, Ordering::Release);
+        Ok(written)
+    }
+
+    fn seek(&self, pos: VFSSeek) -> VFSResult<usize> {
+        let current = self.position.load(Ordering::Acquire);
+        let size = {
+            let volume = self.volume.lock();
+            let inode = volume.read_inode(self.ino)?;
+            if inode.is_dir() {
+                Self::listing(&volume, &inode)?.len()
+            } else {
+                inode.size as usize
+            }
+        };
+        let new_pos = match pos {
+            VFSSeek::Start(n) => n,
+            VFSSeek::Current(n) => current.saturating_add(n),
+            VFSSeek::End(n) => size.saturating_add(n),
+        }
+        .min(size);
+
+        self.position.store(new_pos, Ordering::Release);
+        Ok(new_pos)
+    }
+
+    fn mmap(
+        &self,
+        _len: usize,
+        _prot: core::ffi::c_int,
+        _flags: core::ffi::c_int,
+    ) -> VFSResult<*mut u8> {
+        Err(VFSError::Unsupported)
+    }
+
+    fn metadata(&self) -> VFSResult<VFSMetadata> {
+        let inode = self.volume.lock().read_inode(self.ino)?;
+        Ok(metadata(&self.name, &inode))
+    }
+
+    fn sync(&self) -> VFSResult<()> { self.volume.lock().sync() }
+}
@@ -0,0 +1,124 @@
+use crate::system::bytes::{put_u16, put_u32, u16_at, u32_at};
+use crate::system::vfs::VFSFileType;
+use crate::system::vfs::ext2::consts::*;
+
+/// the parts of an on disk inode we use, the rest of the record is left
+/// alone when it's written back
+#[derive(Debug, Clone, Default)]
+pub struct Inode {
+    pub mode: u16,
+    pub uid: u16,
+    pub size: u64,
+    pub atime: u32,
+    pub ctime: u32,
+    pub mtime: u32,
+    pub dtime: u32,
+    pub gid: u16,
+    pub links: u16,
+    /// in 512 byte units, whatever the block size
+    pub sectors: u32,
+    pub flags: u32,
+    pub block: [u32; 15],
+}
+
+impl Inode {
+    pub fn parse(raw: &[u8]) -> Self {
+        let mode = u16_at(raw, 0);
+        let mut size = u64::from(u32_at(raw, 4));
+        // the high half only means size for regular files, it's the
+        // directory acl on old filesystems
+        if mode & S_IFMT == S_IFREG {
+            size |= u64::from(u32_at(raw, 108)) << 32;
+        }
+
+        let mut block = [0u32; 15];
+        for (i, ptr) in block.iter_mut().enumerate() {
+            *ptr = u32_at(raw, 40 + i * 4);
+        }
+
+        Self {
+            mode,
+            uid: u16_at(raw, 2),
+            size,
+            atime: u32_at(raw, 8),
+            ctime: u32_at(raw, 12),
+            mtime: u32_at(raw, 16),
+            dtime: u32_at(raw, 20),
+            gid: u16_at(raw, 24),
+            links: u16_at(raw, 26),
+            sectors: u32_at(raw, 28),
+            flags: u32_at(raw, 32),
+            block,
+        }
+    }
+
+    pub fn store(&self, raw: &mut [u8]) {
+        put_u16(raw, 0, self.mode);
+        put_u16(raw, 2, self.uid);
+        put_u32(raw, 4, self.size as u32);
+        put_u32(raw, 8, self.atime);
+        put_u32(raw, 12, self.ctime);
+        put_u32(raw, 16, self.mtime);
+        put_u32(raw, 20, self.dtime);
+        put_u16(raw, 24, self.gid);
+        put_u16(raw, 26, self.links);
+        put_u32(raw, 28, self.sectors);
+        put_u32(raw, 32, self.flags);
+        for (i, ptr) in self.block.iter().enumerate() {
+            put_u32(raw, 40 + i * 4, *ptr);
+        }
+        if self.is_file() {
+            put_u32(raw, 108, (self.size >> 32) as u32);
+        }
+    }
+
+    pub fn is_dir(&self) -> bool { self.mode & S_IFMT == S_IFDIR }
+
+    pub fn is_file(&self) -> bool { self.mode & S_IFMT == S_IFREG }
+
+    pub fn is_symlink(&self) -> bool { self.mode & S_IFMT == S_IFLNK }
+
+    /// short symlinks keep the target in the block pointers instead, the
+    /// nul has to fit too
+    pub fn is_fast_symlink(&self) -> bool {
+        self.is_symlink() && (self.size as usize) < FAST_SYMLINK_MAX
+    }
+
+    /// the block pointers as bytes, where a fast symlink's target lives
+    pub fn inline_data(&self) -> [u8; FAST_SYMLINK_MAX] {
+        let mut data = [0u8; FAST_SYMLINK_MAX];
+        for (i, ptr) in self.block.iter().enumerate() {
+            data[i * 4..i * 4 + 4].copy_from_slice(&ptr.to_le_bytes());
+        }
+        data
+    }
+
+    pub fn set_inline_data(&mut self, data: &[u8]) {
+        let mut padded = [0u8; FAST_SYMLINK_MAX];
+        padded[..data.len()].copy_from_slice(data);
+        for (i, ptr) in self.block.iter_mut().enumerate() {
+            *ptr = u32_at(&padded, i * 4);
+        }
+    }
+
+    pub fn file_type(&self) -> VFSFileType {
+        match self.mode & S_IFMT {
+            S_IFREG => VFSFileType::File,
+            S_IFDIR => VFSFileType::Directory,
+            S_IFLNK => VFSFileType::Symlink,
+            S_IFCHR | S_IFBLK => VFSFileType::Device,
+            S_IFIFO | S_IFSOCK => VFSFileType::Pipe,
+            _ => VFSFileType::Unknown,
+        }
+    }
+
+    /// the type byte directory entries carry
+    pub fn dir_entry_type(&self) -> u8 {
+        match self.mode & S_IFMT {
+            S_IFREG => FT_REG_FILE,
+            S_IFDIR => FT_DIR,
+            S_IFLNK => FT_SYMLINK,
+            _ => FT_UNKNOWN,
+        }
+    }
+}
@@ -0,0 +1,288 @@
+mod consts;
+mod dir;
+mod file;
+mod inode;
+mod volume;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use core::sync::atomic::AtomicUsize;
+
+use flower_mono::fcntl::{O_APPEND, O_CREAT, O_EXCL, O_TRUNC};
+use spin::Mutex;
+
+use crate::drivers::block::Disk;
+use crate::system::vfs::ext2::consts::*;
+use crate::system::vfs::ext2::file::Ext2File;
+use crate::system::vfs::ext2::inode::Inode;
+use crate::system::vfs::ext2::volume::{Volume, io_error, now};
+use crate::system::vfs::split_parent;
+use crate::system::vfs::types::*;
+
+pub struct Ext2FS {
+    volume: Arc<Mutex<Volume>>,
+}
+
+fn read_link(volume: &Volume, inode: &Inode) -> VFSResult<String> {
+    let size = inode.size as usize;
+    if size > volume.block_size.min(PATH_MAX) {
+        return Err(io_error("symlink target too long"));
+    }
+    let target = if inode.is_fast_symlink() {
+        inode.inline_data()[..size].to_vec()
+    } else {
+        let mut target = vec![0u8; size];
+        volume.read_data(inode, 0, &mut target)?;
+        target
+    };
+    Ok(String::from_utf8_lossy(&target).into())
+}
+
+/// walks `path` from `start`, following symlinks on the way and at the end
+/// if `follow` is set. absolute symlinks start over at the root of this
+/// filesystem, which is only right when it's mounted at /
+fn walk(
+    volume: &Volume,
+    start: u32,
+    path: &str,
+    follow: bool,
+    links: &mut usize,
+) -> VFSResult<u32> {
+    let mut current = if path.starts_with('/') { ROOT_INO } else { start };
+    let parts: alloc::vec::Vec<&str> = path
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect();
+
+    for (i, part) in parts.iter().enumerate() {
+        let dir = volume.read_inode(current)?;
+        if !dir.is_dir() {
+            return Err(VFSError::NotDirectory);
+        }
+
+        let next = volume.lookup(&dir, part)?.ok_or(VFSError::NotFound)?;
+        let inode = volume.read_inode(next)?;
+        if inode.is_symlink() && (follow || i + 1 < parts.len()) {
+            *links += 1;
+            if *links > MAX_SYMLINKS {
+                return Err(VFSError::InvalidArgument);
+            }
+            let target = read_link(volume, &inode)?;
+            current = walk(volume, current, &target, true, links)?;
+        } else {
+            current = next;
+        }
+    }
+
+    Ok(current)
+}
+
+fn lookup(volume: &Volume, path: &str, follow: bool) -> VFSResult<u32> {
+    walk(volume, ROOT_INO, path, follow, &mut 0)
+}
+
+/// makes a new inode and links it in at `path`, directories come with
+/// `.` and `..` already
+fn create(
+    volume: &mut Volume,
+    path: &str,
+    mode: u16,
+) -> VFSResult<(u32, Inode)> {
+    volume.check_writable()?;
+    let (parent, name) = split_parent(path)?;
+    if name.len() > MAX_NAME_LEN {
+        return Err(VFSError::NameTooLong);
+    }
+    let parent_ino = lookup(volume, parent, true)?;
+    let parent_inode = volume.read_inode(parent_ino)?;
+    if !parent_inode.is_dir() {
+        return Err(VFSError::NotDirectory);
+    }
+    if volume.lookup(&parent_inode, name)?.is_some() {
+        return Err(VFSError::AlreadyExists);
+    }
+
+    let is_dir = mode & S_IFMT == S_IFDIR;
+    let ino = volume.alloc_inode(parent_ino, is_dir)?;
+    let time = now();
+    let mut inode = Inode {
+        mode,
+        links: if is_dir { 2 } else { 1 },
+        atime: time,
+        ctime: time,
+        mtime: time,
+        ..Default::default()
+    };
+
+    let linked = (|| {
+        if is_dir {
+            volume.init_dir(ino, &mut inode, parent_ino)?;
+        }
+        volume.write_inode(ino, &inode)?;
+        volume.add_entry(parent_ino, name, ino, inode.dir_entry_type())
+    })();
+    if let Err(e) = linked {
+        // put everything back, the disk is probably full
+        let _ = volume.free_from(&mut inode, 0);
+        let _ = volume.free_inode(ino, is_dir);
+        return Err(e);
+    }
+
+    if is_dir {
+        let mut parent_inode = volume.read_inode(parent_ino)?;
+        parent_inode.links += 1;
+        volume.write_inode(parent_ino, &parent_inode)?;
+    }
+    Ok((ino, inode))
+}
+
+/// frees an inode and everything it had, once nothing links to it
+fn release(volume: &mut Volume, ino: u32, mut inode: Inode) -> VFSResult<()> {
+    if !inode.is_fast_symlink() {
+        volume.free_from(&mut inode, 0)?;
+    }
+    inode.size = 0;
+    inode.links = 0;
+    inode.dtime = now();
+    volume.write_inode(ino, &inode)?;
+    volume.free_inode(ino, inode.is_dir())
+}
+
+impl Ext2FS {
+    /// mounts the ext2 on `disk`, read only if asked to, if the disk is, or
+    /// if it uses ro_compat features we don't know
+    pub fn new(disk: Arc<Disk>, read_only: bool) -> Result<Self, &'static str> {
+        let name = disk.name.clone();
+        let mut volume = Volume::new(disk)?;
+        volume.read_only |= read_only;
+        log::info!(
+            "ext2: {} has {} byte blocks{}",
+            name,
+            volume.block_size,
+            if volume.read_only { ", read only" } else { "" }
+        );
+        Ok(Self { volume: Arc::new(Mutex::new(volume)) })
+    }
+}
+
+impl VFSImplementation for Ext2FS {
+    fn fs_type(&self) -> &'static str { "ext2" }
+
+    fn read_only(&self) -> bool { self.volume.lock().read_only }
+
+    fn open(&self, path: &str, flags: u32) -> VFSResult<Box<dyn VFSFile>> {
+        let mut volume = self.volume.lock();
+        let ino = match lookup(&volume, path, true) {
+            Ok(_) if flags & O_CREAT != 0 && flags & O_EXCL != 0 => {
+                return Err(VFSError::AlreadyExists);
+            },
+            Ok(ino) => ino,
+            Err(VFSError::NotFound) if flags & O_CREAT != 0 => {
+                create(&mut volume, path, S_IFREG | 0o644)?.0
+            },
+            Err(e) => return Err(e),
+        };
+
+        let mut inode = volume.read_inode(ino)?;
+        if flags & O_TRUNC != 0 && inode.is_file() && inode.size > 0 {
+            volume.truncate(&mut inode, 0)?;
+            volume.write_inode(ino, &inode)?;
+        }
+
+        let name =
+            path.rsplit('/').find(|part| !part.is_empty()).unwrap_or("/");
+        Ok(Box::new(Ext2File {
+            volume: self.volume.clone(),
+            ino,
+            name: String::from(name),
+            append: flags & O_APPEND != 0,
+            position: AtomicUsize::new(0),
+        }))
+    }
+
+    fn metadata(&self, path: &str) -> VFSResult<VFSMetadata> {
+        let volume = self.volume.lock();
+        let inode = volume.read_inode(lookup(&volume, path, true)?)?;
+        let name =
+            path.rsplit('/').find(|part| !part.is_empty()).unwrap_or("/");
+        Ok(file::metadata(name, &inode))
+    }
+
+    /// anyone who still has the file open is left holding a freed inode,
+    /// there's no orphan list
+    fn unlink(&self, path: &str) -> VFSResult<()> {
+        let mut volume = self.volume.lock();
+        volume.check_writable()?;
+        let (parent, name) = split_parent(path)?;
+        let parent_ino = lookup(&volume, parent, true)?;
+        let ino = volume
+            .lookup(&volume.read_inode(parent_ino)?, name)?
+            .ok_or(VFSError::NotFound)?;
+
+        let mut inode = volume.read_inode(ino)?;
+        if inode.is_dir() {
+            return Err(VFSError::IsDirectory);
+        }
+
+        volume.remove_entry(parent_ino, name)?;
+        inode.links = inode.links.saturating_sub(1);
+        inode.ctime = now();
+        if inode.links == 0 {
+            release(&mut volume, ino, inode)
+        } else {
+            volume.write_inode(ino, &inode)
+        }
+    }
+
+    fn mkdir(&self, path: &str, mode: usize) -> VFSResult<()> {
+        let mode = S_IFDIR | (mode & 0o777) as u16;
+        create(&mut self.volume.lock(), path, mode).map(|_| ())
+    }
+
+    fn rmdir(&self, path: &str) -> VFSResult<()> {
+        let mut volume = self.volume.lock();
+        volume.check_writable()?;
+        let (parent, name) = split_parent(path)?;
+        let parent_ino = lookup(&volume, parent, true)?;
+        let ino = volume
+            .lookup(&volume.read_inode(parent_ino)?, name)?
+            .ok_or(VFSError::NotFound)?;
+
+        let inode = volume.read_inode(ino)?;
+        if !inode.is_dir() {
+            return Err(VFSError::NotDirectory);
+        }
+        if !volume.is_empty_dir(&inode)? {
+            return Err(VFSError::NotEmpty);
+        }
+
+        volume.remove_entry(parent_ino, name)?;
+        release(&mut volume, ino, inode)?;
+
+        // the .. in it is gone
+        let mut parent_inode = volume.read_inode(parent_ino)?;
+        parent_inode.links = parent_inode.links.saturating_sub(1);
+        volume.write_inode(parent_ino, &parent_inode)
+    }
+
+    fn symlink(&self, target: &str, path: &str) -> VFSResult<()> {
+        let mut volume = self.volume.lock();
+        // read_link wouldn't take it back
+        if target.len() > volume.block_size.min(PATH_MAX) {
+            return Err(VFSError::NameTooLong);
+        }
+        let (ino, mut inode) = create(&mut volume, path, S_IFLNK | 0o777)?;
+
+        if target.len() < FAST_SYMLINK_MAX {
+            inode.set_inline_data(target.as_bytes());
+            inode.size = target.len() as u64;
+        } else {
+            volume.write_data(ino, &mut inode, 0, target.as_bytes())?;
+        }
+        volume.write_inode(ino, &inode)
+    }
+
+    fn sync(&self) -> VFSResult<()> { self.volume.lock().sync() }
+}
@@ -0,0 +1,667 @@
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::drivers::block::Disk;
+use crate::system::bytes::{put_u16, put_u32, u16_at, u32_at};
+use crate::system::time;
+use crate::system::vfs::ext2::consts::*;
+use crate::system::vfs::ext2::inode::Inode;
+use crate::system::vfs::{VFSError, VFSResult};
+
+struct Group {
+    block_bitmap: u32,
+    inode_bitmap: u32,
+    inode_table: u32,
+    free_blocks: u16,
+    free_inodes: u16,
+    used_dirs: u16,
+}
+
+/// a mounted ext2 filesystem, everything here goes thru the block cache
+pub struct Volume {
+    disk: Arc<Disk>,
+    pub block_size: usize,
+    blocks_count: u32,
+    first_data_block: u32,
+    blocks_per_group: u32,
+    inodes_per_group: u32,
+    inode_size: usize,
+    first_ino: u32,
+    free_blocks: u32,
+    free_inodes: u32,
+    /// directory entries carry a file type
+    pub filetype: bool,
+    large_file: bool,
+    pub read_only: bool,
+    groups: Vec<Group>,
+}
+
+pub fn io_error(e: &'static str) -> VFSError {
+    log::error!("ext2: {}", e);
+    VFSError::IOError
+}
+
+pub fn now() -> u32 { time::now() as u32 }
+
+impl Volume {
+    pub fn new(disk: Arc<Disk>) -> Result<Self, &'static str> {
+        let mut sb = vec![0u8; SUPERBLOCK_SIZE];
+        disk.read_at(SUPERBLOCK_OFFSET, &mut sb)?;
+        if u16_at(&sb, 56) != EXT2_MAGIC {
+            return Err("not an ext2 filesystem");
+        }
+
+        // bigger blocks don't fit a directory record length
+        let log_block_size = u32_at(&sb, 24);
+        if log_block_size > 2 {
+            return Err("unsupported block size");
+        }
+        let block_size = 1024usize << log_block_size;
+
+        let (first_ino, inode_size, incompat, ro_compat) = match u32_at(&sb, 76)
+        {
+            0 => (GOOD_OLD_FIRST_INO, GOOD_OLD_INODE_SIZE, 0, 0),
+            _ => (
+                u32_at(&sb, 84),
+                u16_at(&sb, 88) as usize,
+                u32_at(&sb, 96),
+                u32_at(&sb, 100),
+            ),
+        };
+        if incompat & !INCOMPAT_FILETYPE != 0 {
+            log::error!("ext2: unsupported incompat features {:#x}", incompat);
+            return Err("unsupported filesystem features");
+        }
+        if inode_size < GOOD_OLD_INODE_SIZE || inode_size > block_size {
+            return Err("bad inode size");
+        }
+
+        let known_ro = RO_COMPAT_SPARSE_SUPER | RO_COMPAT_LARGE_FILE;
+        let mut read_only = disk.read_only();
+        if ro_compat & !known_ro != 0 {
+            log::warn!(
+                "ext2: unsupported ro_compat features {:#x}, mounting read only",
+                ro_compat
+            );
+            read_only = true;
+        }
+        if u16_at(&sb, 58) != 1 {
+            log::warn!("ext2: filesystem wasn't unmounted cleanly, fsck it");
+        }
+
+        let blocks_count = u32_at(&sb, 4);
+        let first_data_block = u32_at(&sb, 20);
+        let blocks_per_group = u32_at(&sb, 32);
+        let inodes_per_group = u32_at(&sb, 40);
+        // each group's bitmaps are a single block
+        let bits = 8 * block_size as u32;
+        if blocks_per_group == 0
+            || inodes_per_group == 0
+            || blocks_per_group > bits
+            || inodes_per_group > bits
+        {
+            return Err("bad group sizes");
+        }
+        let group_count = blocks_count
+            .checked_sub(first_data_block)
+            .ok_or("bad superblock")?
+            .div_ceil(blocks_per_group) as usize;
+        if group_count > MAX_GROUPS {
+            return Err("too many block groups");
+        }
+        // inode numbers are 32 bits
+        if inodes_per_group.checked_mul(group_count as u32).is_none() {
+            return Err("too many inodes");
+        }
+
+        // the descriptors start in the block right after the superblock
+        let mut table = vec![0u8; group_count * GROUP_DESC_SIZE];
+        disk.read_at(
+            u64::from(first_data_block + 1) * block_size as u64,
+            &mut table,
+        )?;
+        let groups = table
+            .chunks(GROUP_DESC_SIZE)
+            .map(|desc| Group {
+                block_bitmap: u32_at(desc, 0),
+                inode_bitmap: u32_at(desc, 4),
+                inode_table: u32_at(desc, 8),
+                free_blocks: u16_at(desc, 12),
+                free_inodes: u16_at(desc, 14),
+                used_dirs: u16_at(desc, 16),
+            })
+            .collect();
+
+        Ok(Self {
+            disk,
+            block_size,
+            blocks_count,
+            first_data_block,
+            blocks_per_group,
+            inodes_per_group,
+            inode_size,
+            first_ino,
+            free_blocks: u32_at(&sb, 12),
+            free_inodes: u32_at(&sb, 16),
+            filetype: incompat & INCOMPAT_FILETYPE != 0,
+            large_file: ro_compat & RO_COMPAT_LARGE_FILE != 0,
+            read_only,
+            groups,
+        })
+    }
+
+    pub fn sync(&self) -> VFSResult<()> { self.disk.flush().map_err(io_error) }
+
+    pub fn check_writable(&self) -> VFSResult<()> {
+        if self.read_only { Err(VFSError::ReadOnly) } else { Ok(()) }
+    }
+
+    // raw access
+
+    fn block_offset(&self, block: u32) -> u64 {
+        u64::from(block) * self.block_size as u64
+    }
+
+    pub fn read_block_at(
+        &self,
+        block: u32,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> VFSResult<()> {
+        self.disk
+            .read_at(self.block_offset(block) + offset as u64, buf)
+            .map_err(io_error)
+    }
+
+    pub fn write_block_at(
+        &self,
+        block: u32,
+        offset: usize,
+        buf: &[u8],
+    ) -> VFSResult<()> {
+        self.disk
+            .write_at(self.block_offset(block) + offset as u64, buf)
+            .map_err(io_error)
+    }
+
+    pub fn read_block(&self, block: u32) -> VFSResult<Vec<u8>> {
+        let mut buf = vec![0u8; self.block_size];
+        self.read_block_at(block, 0, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_pointers(&self, block: u32) -> VFSResult<Vec<u32>> {
+        let data = self.read_block(block)?;
+        Ok(data.chunks(4).map(|ptr| u32_at(ptr, 0)).collect())
+    }
+
+    fn write_pointer(
+        &self,
+        block: u32,
+        index: usize,
+        value: u32,
+    ) -> VFSResult<()> {
+        self.write_block_at(block, index * 4, &value.to_le_bytes())
+    }
+
+    // inodes
+
+    fn inode_offset(&self, ino: u32) -> VFSResult<u64> {
+        let inodes =
+            u64::from(self.inodes_per_group) * self.groups.len() as u64;
+        if ino == 0 || u64::from(ino) > inodes {
+            return Err(io_error("inode number out of range"));
+        }
+        let group = &self.groups[((ino - 1) / self.inodes_per_group) as usize];
+        let index = u64::from((ino - 1) % self.inodes_per_group);
+        Ok(self.block_offset(group.inode_table)
+            + index * self.inode_size as u64)
+    }
+
+    pub fn read_inode(&self, ino: u32) -> VFSResult<Inode> {
+        let mut raw = vec![0u8; self.inode_size];
+        self.disk
+            .read_at(self.inode_offset(ino)?, &mut raw)
+            .map_err(io_error)?;
+        Ok(Inode::parse(&raw))
+    }
+
+    pub fn write_inode(&self, ino: u32, inode: &Inode) -> VFSResult<()> {
+        let offset = self.inode_offset(ino)?;
+        let mut raw = vec![0u8; self.inode_size];
+        self.disk.read_at(offset, &mut raw).map_err(io_error)?;
+        inode.store(&mut raw);
+        self.disk.write_at(offset, &raw).map_err(io_error)
+    }
+
+    fn group_of(&self, ino: u32) -> usize {
+        ((ino - 1) / self.inodes_per_group) as usize
+    }
+
+    // allocation
+
+    /// writes the free counts of a group and the superblock back
+    fn write_counts(&self, group: usize) -> VFSResult<()> {
+        let g = &self.groups[group];
+        let mut counts = [0u8; 6];
+        put_u16(&mut counts, 0, g.free_blocks);
+        put_u16(&mut counts, 2, g.free_inodes);
+        put_u16(&mut counts, 4, g.used_dirs);
+        let desc = self.block_offset(self.first_data_block + 1)
+            + (group * GROUP_DESC_SIZE) as u64;
+        self.disk.write_at(desc + 12, &counts).map_err(io_error)?;
+
+        let mut counts = [0u8; 8];
+        put_u32(&mut counts, 0, self.free_blocks);
+        put_u32(&mut counts, 4, self.free_inodes);
+        self.disk.write_at(SUPERBLOCK_OFFSET + 12, &counts).map_err(io_error)
+    }
+
+    /// finds a clear bit below `limit` in a bitmap block and sets it
+    fn take_bit(&self, bitmap: u32, limit: usize) -> VFSResult<Option<usize>> {
+        let data = self.read_block(bitmap)?;
+        let Some(bit) =
+            (0..limit).find(|bit| data[bit / 8] & (1 << (bit % 8)) == 0)
+        else {
+            return Ok(None);
+        };
+        self.write_block_at(
+            bitmap,
+            bit / 8,
+            &[data[bit / 8] | (1 << (bit % 8))],
+        )?;
+        Ok(Some(bit))
+    }
+
+    fn clear_bit(&self, bitmap: u32, bit: usize) -> VFSResult<()> {
+        let mut byte = [0u8];
+        self.read_block_at(bitmap, bit / 8, &mut byte)?;
+        if byte[0] & (1 << (bit % 8)) == 0 {
+            log::warn!("ext2: freeing something that was already free");
+        }
+        self.write_block_at(bitmap, bit / 8, &[byte[0] & !(1 << (bit % 8))])
+    }
+
+    /// a zeroed block, from `goal`'s group if there's room
+    pub fn alloc_block(&mut self, goal: usize) -> VFSResult<u32> {
+        self.check_writable()?;
+        for i in 0..self.groups.len() {
+            let group = (goal + i) % self.groups.len();
+            if self.groups[group].free_blocks == 0 {
+                continue;
+            }
+
+            let first =
+                self.first_data_block + group as u32 * self.blocks_per_group;
+            let limit = self.blocks_per_group.min(self.blocks_count - first);
+            let Some(bit) =
+                self.take_bit(self.groups[group].block_bitmap, limit as usize)?
+            else {
+                log::warn!("ext2: group {} said it had free blocks", group);
+                continue;
+            };
+
+            self.groups[group].free_blocks -= 1;
+            self.free_blocks = self.free_blocks.saturating_sub(1);
+            self.write_counts(group)?;
+
+            let block = first + bit as u32;
+            self.write_block_at(block, 0, &vec![0u8; self.block_size])?;
+            return Ok(block);
+        }
+        Err(VFSError::NoSpace)
+    }
+
+    pub fn free_block(&mut self, block: u32) -> VFSResult<()> {
+        if block < self.first_data_block || block >= self.blocks_count {
+            return Err(io_error("freeing a block that doesn't exist"));
+        }
+        let group =
+            ((block - self.first_data_block) / self.blocks_per_group) as usize;
+        let bit =
+            ((block - self.first_data_block) % self.blocks_per_group) as usize;
+        self.clear_bit(self.groups[group].block_bitmap, bit)?;
+
+        self.groups[group].free_blocks += 1;
+        self.free_blocks += 1;
+        self.write_counts(group)
+    }
+
+    /// a fresh inode number, near `near` if there's room. the record is
+    /// zeroed so nothing from the last owner sticks around
+    pub fn alloc_inode(&mut self, near: u32, dir: bool) -> VFSResult<u32> {
+        self.check_writable()?;
+        let goal = self.group_of(near);
+        for i in 0..self.groups.len() {
+            let group = (goal + i) % self.groups.len();
+            if self.groups[group].free_inodes == 0 {
+                continue;
+            }
+
+            // the reserved inodes at the start are never handed out
+            let data = self.read_block(self.groups[group].inode_bitmap)?;
+            let base = group as u32 * self.inodes_per_group;
+            let Some(bit) = (0..self.inodes_per_group as usize).find(|&bit| {
+                base + bit as u32 + 1 >= self.first_ino
+                    && data[bit / 8] & (1 << (bit % 8)) == 0
+            }) else {
+                continue;
+            };
+            self.write_block_at(
+                self.groups[group].inode_bitmap,
+                bit / 8,
+                &[data[bit / 8] | (1 << (bit % 8))],
+            )?;
+
+            self.groups[group].free_inodes -= 1;
+            if dir {
+                self.groups[group].used_dirs += 1;
+            }
+            self.free_inodes = self.free_inodes.saturating_sub(1);
+            self.write_counts(group)?;
+
+            let ino = base + bit as u32 + 1;
+            self.disk
+                .write_at(self.inode_offset(ino)?, &vec![0u8; self.inode_size])
+                .map_err(io_error)?;
+            return Ok(ino);
+        }
+        Err(VFSError::NoSpace)
+    }
+
+    pub fn free_inode(&mut self, ino: u32, dir: bool) -> VFSResult<()> {
+        let group = self.group_of(ino);
+        let bit = ((ino - 1) % self.inodes_per_group) as usize;
+        self.clear_bit(self.groups[group].inode_bitmap, bit)?;
+
+        self.groups[group].free_inodes += 1;
+        if dir {
+            self.groups[group].used_dirs =
+                self.groups[group].used_dirs.saturating_sub(1);
+        }
+        self.free_inodes += 1;
+        self.write_counts(group)
+    }
+
+    // block maps
+
+    fn pointers_per_block(&self) -> u64 { (self.block_size / 4) as u64 }
+
+    /// which pointer in the inode an index goes thru, how many levels of
+    /// indirection are under it and the index relative to it
+    fn map_path(&self, index: u64) -> VFSResult<(usize, u32, u64)> {
+        let per = self.pointers_per_block();
+        if index < DIRECT_BLOCKS as u64 {
+            return Ok((index as usize, 0, 0));
+        }
+
+        let mut index = index - DIRECT_BLOCKS as u64;
+        for (slot, depth) in
+            [(SINGLE_INDIRECT, 1), (DOUBLE_INDIRECT, 2), (TRIPLE_INDIRECT, 3)]
+        {
+            let span = per.pow(depth);
+            if index < span {
+                return Ok((slot, depth, index));
+            }
+            index -= span;
+        }
+        Err(VFSError::NoSpace)
+    }
+
+    /// the disk block holding block `index` of a file, 0 for a hole
+    pub fn bmap(&self, inode: &Inode, index: u64) -> VFSResult<u32> {
+        let (slot, depth, mut index) = self.map_path(index)?;
+        let mut block = inode.block[slot];
+
+        for level in (0..depth).rev() {
+            if block == 0 {
+                return Ok(0);
+            }
+            let span = self.pointers_per_block().pow(level);
+            let mut ptr = [0u8; 4];
+            self.read_block_at(block, (index / span) as usize * 4, &mut ptr)?;
+            block = u32::from_le_bytes(ptr);
+            index %= span;
+        }
+        Ok(block)
+    }
+
+    /// same as bmap but fills in anything missing on the way, the caller
+    /// writes the inode back
+    pub fn bmap_alloc(
+        &mut self,
+        ino: u32,
+        inode: &mut Inode,
+        index: u64,
+    ) -> VFSResult<u32> {
+        let (slot, depth, mut index) = self.map_path(index)?;
+        let goal = self.group_of(ino);
+        let sectors = (self.block_size / 512) as u32;
+
+        if inode.block[slot] == 0 {
+            inode.block[slot] = self.alloc_block(goal)?;
+            inode.sectors += sectors;
+        }
+        let mut block = inode.block[slot];
+
+        for level in (0..depth).rev() {
+            let span = self.pointers_per_block().pow(level);
+            let entry = (index / span) as usize;
+            let mut ptr = [0u8; 4];
+            self.read_block_at(block, entry * 4, &mut ptr)?;
+
+            let mut next = u32::from_le_bytes(ptr);
+            if next == 0 {
+                next = self.alloc_block(goal)?;
+                inode.sectors += sectors;
+                self.write_pointer(block, entry, next)?;
+            }
+            block = next;
+            index %= span;
+        }
+        Ok(block)
+    }
+
+    /// frees a whole tree of blocks, `depth` levels of indirection deep
+    fn free_tree(
+        &mut self,
+        block: u32,
+        depth: u32,
+        freed: &mut u32,
+    ) -> VFSResult<()> {
+        if depth > 0 {
+            for child in self.read_pointers(block)? {
+                if child != 0 {
+                    self.free_tree(child, depth - 1, freed)?;
+                }
+            }
+        }
+        self.free_block(block)?;
+        *freed += 1;
+        Ok(())
+    }
+
+    /// frees everything under an indirect block mapping file blocks from
+    /// `first` on, `base` is the first file block it maps. true if the
+    /// block ended up empty
+    fn free_partial(
+        &mut self,
+        block: u32,
+        depth: u32,
+        base: u64,
+        first: u64,
+        freed: &mut u32,
+    ) -> VFSResult<bool> {
+        let span = self.pointers_per_block().pow(depth - 1);
+        let mut pointers = self.read_pointers(block)?;
+
+        for (i, child) in pointers.iter_mut().enumerate() {
+            let child_base = base + i as u64 * span;
+            if *child == 0 || child_base + span <= first {
+                continue;
+            }
+
+            let gone = if child_base >= first {
+                self.free_tree(*child, depth - 1, freed)?;
+                true
+            } else if self.free_partial(
+                *child,
+                depth - 1,
+                child_base,
+                first,
+                freed,
+            )? {
+                self.free_block(*child)?;
+                *freed += 1;
+                true
+            } else {
+                false
+            };
+            if gone {
+                *child = 0;
+                self.write_pointer(block, i, 0)?;
+            }
+        }
+
+        Ok(pointers.iter().all(|&ptr| ptr == 0))
+    }
+
+    /// frees every block from file block `first` on, the caller sets the
+    /// size and writes the inode back
+    pub fn free_from(
+        &mut self,
+        inode: &mut Inode,
+        first: u64,
+    ) -> VFSResult<()> {
+        let mut freed = 0;
+
+        for ptr in inode.block[..DIRECT_BLOCKS].iter_mut().skip(first as usize)
+        {
+            if *ptr != 0 {
+                self.free_block(*ptr)?;
+                *ptr = 0;
+                freed += 1;
+            }
+        }
+
+        let per = self.pointers_per_block();
+        let mut base = DIRECT_BLOCKS as u64;
+        for (slot, depth) in
+            [(SINGLE_INDIRECT, 1), (DOUBLE_INDIRECT, 2), (TRIPLE_INDIRECT, 3)]
+        {
+            let span = per.pow(depth);
+            let block = inode.block[slot];
+            if block != 0 && base + span > first {
+                if base >= first {
+                    self.free_tree(block, depth, &mut freed)?;
+                    inode.block[slot] = 0;
+                } else if self
+                    .free_partial(block, depth, base, first, &mut freed)?
+                {
+                    self.free_block(block)?;
+                    inode.block[slot] = 0;
+                    freed += 1;
+                }
+            }
+            base += span;
+        }
+
+        let sectors = (self.block_size / 512) as u32;
+        inode.sectors = inode.sectors.saturating_sub(freed * sectors);
+        Ok(())
+    }
+
+    // file data
+
+    pub fn read_data(
+        &self,
+        inode: &Inode,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> VFSResult<usize> {
+        let len = buf.len().min(inode.size.saturating_sub(offset) as usize);
+        let bs = self.block_size as u64;
+
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let skip = (pos % bs) as usize;
+            let chunk = (len - done).min(self.block_size - skip);
+            let out = &mut buf[done..done + chunk];
+
+            match self.bmap(inode, pos / bs)? {
+                0 => out.fill(0),
+                block => self.read_block_at(block, skip, out)?,
+            }
+            done += chunk;
+        }
+        Ok(len)
+    }
+
+    /// writes file data, growing the file if it goes past the end. the
+    /// caller writes the inode back
+    pub fn write_data(
+        &mut self,
+        ino: u32,
+        inode: &mut Inode,
+        offset: u64,
+        buf: &[u8],
+    ) -> VFSResult<usize> {
+        self.check_writable()?;
+        let end = offset + buf.len() as u64;
+        if end > u64::from(u32::MAX) && !(self.large_file && inode.is_file()) {
+            return Err(VFSError::NoSpace);
+        }
+        let bs = self.block_size as u64;
+
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let skip = (pos % bs) as usize;
+            let chunk = (buf.len() - done).min(self.block_size - skip);
+
+            // stop at a full disk but keep what made it
+            let block = match self.bmap_alloc(ino, inode, pos / bs) {
+                Ok(block) => block,
+                Err(VFSError::NoSpace) if done > 0 => break,
+                Err(e) => return Err(e),
+            };
+            self.write_block_at(block, skip, &buf[done..done + chunk])?;
+            done += chunk;
+        }
+
+        inode.size = inode.size.max(offset + done as u64);
+        inode.mtime = now();
+        inode.ctime = inode.mtime;
+        Ok(done)
+    }
+
+    /// cuts a file down to `size` bytes, or grows it with a hole
+    pub fn truncate(&mut self, inode: &mut Inode, size: u64) -> VFSResult<()> {
+        self.check_writable()?;
+        if size < inode.size {
+            let bs = self.block_size as u64;
+            self.free_from(inode, size.div_ceil(bs))?;
+
+            // whatever's left of the last block reads as zeroes if the file
+            // grows again
+            let tail = (size % bs) as usize;
+            if tail != 0 {
+                let block = self.bmap(inode, size / bs)?;
+                if block != 0 {
+                    self.write_block_at(
+                        block,
+                        tail,
+                        &vec![0u8; self.block_size - tail],
+                    )?;
+                }
+            }
+        }
+
+        inode.size = size;
+        inode.mtime = now();
+        inode.ctime = inode.mtime;
+        Ok(())
+    }
+}
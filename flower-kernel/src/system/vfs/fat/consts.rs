@@ -0,0 +1,52 @@
+// based on microsoft's fat32 spec (fatgen103) and the vfat long name docs
+
+pub const BOOT_SIGNATURE: u16 = 0xAA55;
+
+// fat32 fsinfo sector
+pub const FSINFO_LEAD_SIG: u32 = 0x41615252;
+pub const FSINFO_STRUCT_SIG: u32 = 0x61417272;
+pub const FSINFO_FREE_COUNT: u64 = 488;
+pub const FSINFO_NEXT_FREE: u64 = 492;
+pub const FSINFO_UNKNOWN: u32 = 0xFFFFFFFF;
+
+/// anything with fewer clusters than this is fat12
+pub const FAT12_CLUSTERS: u32 = 4085;
+
+/// data clusters are numbered from 2
+pub const FIRST_CLUSTER: u32 = 2;
+
+// fat32 ext_flags
+pub const NO_MIRROR: u16 = 0x0080;
+pub const ACTIVE_FAT: u16 = 0x000F;
+
+pub const DIR_ENTRY_SIZE: usize = 32;
+
+// attributes
+pub const ATTR_READ_ONLY: u8 = 0x01;
+pub const ATTR_VOLUME_ID: u8 = 0x08;
+pub const ATTR_DIRECTORY: u8 = 0x10;
+pub const ATTR_ARCHIVE: u8 = 0x20;
+pub const ATTR_LONG_NAME: u8 = 0x0F;
+
+// first byte of the name
+pub const ENTRY_END: u8 = 0x00;
+pub const ENTRY_DELETED: u8 = 0xE5;
+/// stands in for a real 0xE5 at the start of a short name
+pub const ENTRY_KANJI: u8 = 0x05;
+
+// windows nt marks all lowercase short names here instead of using a
+// long name
+pub const NTRES_LOWER_BASE: u8 = 0x08;
+pub const NTRES_LOWER_EXT: u8 = 0x10;
+
+// long name entries
+pub const LAST_LONG_ENTRY: u8 = 0x40;
+pub const LONG_ORDER_MASK: u8 = 0x1F;
+pub const LONG_NAME_CHARS: usize = 13;
+/// where the 13 utf-16 characters sit in a long name entry
+pub const LONG_NAME_OFFSETS: [usize; LONG_NAME_CHARS] =
+    [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+pub const MAX_NAME_LEN: usize = 255;
+
+/// sizes are 32 bit
+pub const MAX_FILE_SIZE: u64 = u32::MAX as u64;
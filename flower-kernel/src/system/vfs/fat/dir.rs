@@ -0,0 +1,548 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::ops::Range;
+
+use crate::drivers::rtc::RtcTime;
+use crate::system::bytes::{put_u16, put_u32, u16_at, u32_at};
+use crate::system::time;
+use crate::system::vfs::fat::consts::*;
+use crate::system::vfs::fat::volume::{Dir, FatKind, Volume};
+use crate::system::vfs::{VFSError, VFSResult};
+
+/// a file or directory as its parent sees it
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    /// the 8.3 name as stored, padded with spaces
+    pub short: [u8; 11],
+    pub attr: u8,
+    pub cluster: u32,
+    pub size: u32,
+    pub mtime: u64,
+    /// where the short entry is on disk, the root doesn't have one
+    pub offset: Option<u64>,
+    /// the slots it takes up in its directory, long name included
+    slots: Range<usize>,
+}
+
+impl DirEntry {
+    pub fn root() -> Self {
+        Self {
+            name: String::from("/"),
+            short: [b' '; 11],
+            attr: ATTR_DIRECTORY,
+            cluster: 0,
+            size: 0,
+            mtime: 0,
+            offset: None,
+            slots: 0..0,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool { self.attr & ATTR_DIRECTORY != 0 }
+
+    pub fn is_root(&self) -> bool { self.offset.is_none() }
+
+    pub fn is_dot(&self) -> bool { self.name == "." || self.name == ".." }
+}
+
+/// fat keeps local time in 2 second steps, we treat it as utc
+fn to_unix(date: u16, time: u16) -> u64 {
+    if date == 0 {
+        return 0;
+    }
+
+    RtcTime {
+        year: 1980 + (date >> 9),
+        month: (date >> 5 & 0xF) as u8,
+        day: (date & 0x1F) as u8,
+        hour: (time >> 11) as u8,
+        minute: (time >> 5 & 0x3F) as u8,
+        second: (time & 0x1F) as u8 * 2,
+    }
+    .unix_timestamp()
+}
+
+/// (date, time) as fat wants them, anything before 1980 becomes 1980
+fn from_unix(secs: u64) -> (u16, u16) {
+    let t = RtcTime::from_unix(secs);
+    if t.year < 1980 {
+        return (1 << 5 | 1, 0);
+    }
+
+    let date =
+        (t.year - 1980).min(127) << 9 | (t.month as u16) << 5 | t.day as u16;
+    let time =
+        (t.hour as u16) << 11 | (t.minute as u16) << 5 | (t.second as u16 / 2);
+    (date, time)
+}
+
+/// one half of a short name, trailing spaces dropped
+fn short_part(bytes: &[u8], lower: bool) -> String {
+    let len = bytes.iter().rposition(|b| *b != b' ').map_or(0, |i| i + 1);
+    bytes[..len]
+        .iter()
+        .map(|b| if lower { b.to_ascii_lowercase() } else { *b } as char)
+        .collect()
+}
+
+/// `FOO     TXT` to `FOO.TXT`, lowercased where nt says so
+fn short_to_string(short: &[u8; 11], ntres: u8) -> String {
+    let mut short = *short;
+    if short[0] == ENTRY_KANJI {
+        short[0] = ENTRY_DELETED;
+    }
+
+    let base = short_part(&short[..8], ntres & NTRES_LOWER_BASE != 0);
+    let ext = short_part(&short[8..], ntres & NTRES_LOWER_EXT != 0);
+    if ext.is_empty() { base } else { format!("{}.{}", base, ext) }
+}
+
+/// ties long name entries to their short entry
+fn checksum(short: &[u8; 11]) -> u8 {
+    short.iter().fold(0u8, |sum, b| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(*b)
+    })
+}
+
+fn short_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"!#$%&'()-@^_`{}~".contains(&c)
+}
+
+/// things windows won't have in a name either
+fn check_name(name: &str) -> VFSResult<()> {
+    if name.encode_utf16().count() > MAX_NAME_LEN {
+        return Err(VFSError::NameTooLong);
+    }
+    if name.ends_with(' ')
+        || name.ends_with('.')
+        || name.chars().any(|c| c < ' ' || "\"*/:<>?\\|".contains(c))
+    {
+        return Err(VFSError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// the name itself if it's a valid 8.3 name, nt's lowercase bits cover
+/// halves that are all lowercase so those don't need a long name either
+fn exact_short(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 || base.contains('.')
+    {
+        return None;
+    }
+
+    let mut short = [b' '; 11];
+    let mut ntres = 0;
+    for (part, at, lower) in
+        [(base, 0, NTRES_LOWER_BASE), (ext, 8, NTRES_LOWER_EXT)]
+    {
+        if !part.bytes().all(short_char) {
+            return None;
+        }
+
+        let has_lower = part.bytes().any(|b| b.is_ascii_lowercase());
+        let has_upper = part.bytes().any(|b| b.is_ascii_uppercase());
+        match (has_lower, has_upper) {
+            (true, true) => return None,
+            (true, false) => ntres |= lower,
+            _ => {},
+        }
+        short[at..at + part.len()]
+            .copy_from_slice(part.to_ascii_uppercase().as_bytes());
+    }
+    Some((short, ntres))
+}
+
+/// a `BASIS~N.EXT` name for something that needs a long name
+fn numbered_short(name: &str, taken: &[[u8; 11]]) -> VFSResult<[u8; 11]> {
+    let clean = |part: &str| -> Vec<u8> {
+        part.chars()
+            .filter(|c| *c != ' ' && *c != '.')
+            .map(|c| match c.to_ascii_uppercase() {
+                c if c.is_ascii() && short_char(c as u8) => c as u8,
+                _ => b'_',
+            })
+            .collect()
+    };
+
+    let name = name.trim_start_matches('.');
+    let (base, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+    let mut base = clean(base);
+    let mut ext = clean(ext);
+    ext.truncate(3);
+    if base.is_empty() {
+        base.push(b'_');
+    }
+
+    for n in 1..1_000_000 {
+        let tail = format!("~{}", n);
+        let keep = base.len().min(8 - tail.len());
+        let mut short = [b' '; 11];
+        short[..keep].copy_from_slice(&base[..keep]);
+        short[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+        short[8..8 + ext.len()].copy_from_slice(&ext);
+        if !taken.contains(&short) {
+            return Ok(short);
+        }
+    }
+    Err(VFSError::AlreadyExists)
+}
+
+fn short_record(
+    short: &[u8; 11],
+    ntres: u8,
+    attr: u8,
+    cluster: u32,
+    secs: u64,
+) -> [u8; DIR_ENTRY_SIZE] {
+    let (date, time) = from_unix(secs);
+    let mut raw = [0u8; DIR_ENTRY_SIZE];
+    raw[..11].copy_from_slice(short);
+    raw[11] = attr;
+    raw[12] = ntres;
+    // creation, last access, then last write
+    put_u16(&mut raw, 14, time);
+    put_u16(&mut raw, 16, date);
+    put_u16(&mut raw, 18, date);
+    put_u16(&mut raw, 20, (cluster >> 16) as u16);
+    put_u16(&mut raw, 22, time);
+    put_u16(&mut raw, 24, date);
+    put_u16(&mut raw, 26, cluster as u16);
+    raw
+}
+
+/// the byte ranges on disk a directory is made of
+type Extents = Vec<(u64, usize)>;
+
+/// where slot `slot` of a directory is on disk
+fn slot_offset(extents: &[(u64, usize)], slot: usize) -> u64 {
+    let mut at = slot * DIR_ENTRY_SIZE;
+    for (offset, len) in extents {
+        if at < *len {
+            return offset + at as u64;
+        }
+        at -= len;
+    }
+    unreachable!("slot past the end of the directory")
+}
+
+impl Volume {
+    /// the whole directory in one go, plus where each piece of it is on disk
+    fn read_slots(&self, dir: Dir) -> VFSResult<(Vec<u8>, Extents)> {
+        let extents = self.dir_extents(dir)?;
+        let mut data = vec![0u8; extents.iter().map(|(_, len)| len).sum()];
+        let mut at = 0;
+        for (offset, len) in &extents {
+            self.read_at(*offset, &mut data[at..at + len])?;
+            at += len;
+        }
+        Ok((data, extents))
+    }
+
+    fn parse_short(&self, raw: &[u8], name: Option<String>) -> DirEntry {
+        let short: [u8; 11] = raw[..11].try_into().unwrap();
+        // the high half is something else on fat12/16
+        let high = match self.kind {
+            FatKind::Fat32 => (u16_at(raw, 20) as u32) << 16,
+            _ => 0,
+        };
+
+        DirEntry {
+            name: name.unwrap_or_else(|| short_to_string(&short, raw[12])),
+            short,
+            attr: raw[11],
+            cluster: high | u16_at(raw, 26) as u32,
+            size: u32_at(raw, 28),
+            mtime: to_unix(u16_at(raw, 24), u16_at(raw, 22)),
+            offset: None,
+            slots: 0..0,
+        }
+    }
+
+    /// every entry in use, `.` and `..` included. long names that don't
+    /// match their short entry get dropped, same as everyone else does
+    fn parse_dir(
+        &self,
+        data: &[u8],
+        extents: &[(u64, usize)],
+    ) -> Vec<DirEntry> {
+        let mut entries = Vec::new();
+        // (utf-16 name so far, checksum, first slot) and the order we want next
+        let mut long: Option<(Vec<u16>, u8, usize)> = None;
+        let mut next = 0;
+
+        for (slot, raw) in
+            data.as_chunks::<DIR_ENTRY_SIZE>().0.iter().enumerate()
+        {
+            if raw[0] == ENTRY_END {
+                break;
+            }
+            if raw[0] == ENTRY_DELETED {
+                long = None;
+                continue;
+            }
+
+            if raw[11] & 0x3F == ATTR_LONG_NAME {
+                let order = raw[0] & LONG_ORDER_MASK;
+                if raw[0] & LAST_LONG_ENTRY != 0 && order != 0 {
+                    // the end of the name comes first
+                    let chars = vec![0xFFFF; order as usize * LONG_NAME_CHARS];
+                    long = Some((chars, raw[13], slot));
+                    next = order;
+                }
+
+                match &mut long {
+                    Some((chars, sum, _))
+                        if order != 0 && order == next && raw[13] == *sum =>
+                    {
+                        let at = (order as usize - 1) * LONG_NAME_CHARS;
+                        for (i, offset) in LONG_NAME_OFFSETS.iter().enumerate()
+                        {
+                            chars[at + i] = u16_at(raw, *offset);
+                        }
+                        next -= 1;
+                    },
+                    _ => long = None,
+                }
+                continue;
+            }
+
+            if raw[11] & ATTR_VOLUME_ID != 0 {
+                long = None;
+                continue;
+            }
+
+            let short: [u8; 11] = raw[..11].try_into().unwrap();
+            let (name, first) = match long.take() {
+                Some((chars, sum, first))
+                    if next == 0 && sum == checksum(&short) =>
+                {
+                    let len = chars
+                        .iter()
+                        .position(|c| *c == 0)
+                        .unwrap_or(chars.len());
+                    let name = char::decode_utf16(chars[..len].iter().copied())
+                        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                        .collect();
+                    (Some(name), first)
+                },
+                _ => (None, slot),
+            };
+
+            let mut entry = self.parse_short(raw, name);
+            entry.offset = Some(slot_offset(extents, slot));
+            entry.slots = first..slot + 1;
+            entries.push(entry);
+        }
+        entries
+    }
+
+    pub fn read_dir(&self, dir: Dir) -> VFSResult<Vec<DirEntry>> {
+        let (data, extents) = self.read_slots(dir)?;
+        Ok(self.parse_dir(&data, &extents))
+    }
+
+    /// names are case insensitive, and the short name of something with a
+    /// long name works too
+    pub fn find(&self, dir: Dir, name: &str) -> VFSResult<Option<DirEntry>> {
+        Ok(self.read_dir(dir)?.into_iter().find(|entry| {
+            entry.name.eq_ignore_ascii_case(name)
+                || short_to_string(&entry.short, 0).eq_ignore_ascii_case(name)
+        }))
+    }
+
+    /// true if there's nothing but `.` and `..`
+    pub fn is_empty_dir(&self, dir: Dir) -> VFSResult<bool> {
+        Ok(self.read_dir(dir)?.iter().all(DirEntry::is_dot))
+    }
+
+    /// reads an entry back from where it sits on disk, for open files
+    pub fn entry_at(&self, offset: u64) -> VFSResult<DirEntry> {
+        let mut raw = [0u8; DIR_ENTRY_SIZE];
+        self.read_at(offset, &mut raw)?;
+        if raw[0] == ENTRY_END || raw[0] == ENTRY_DELETED {
+            return Err(VFSError::NotFound);
+        }
+
+        let mut entry = self.parse_short(&raw, None);
+        entry.offset = Some(offset);
+        Ok(entry)
+    }
+
+    /// writes the cluster, size and times of an entry back
+    pub fn update_entry(&self, entry: &DirEntry) -> VFSResult<()> {
+        let Some(offset) = entry.offset else {
+            return Ok(());
+        };
+
+        let mut raw = [0u8; DIR_ENTRY_SIZE];
+        self.read_at(offset, &mut raw)?;
+        let (date, time) = from_unix(entry.mtime);
+        raw[11] = entry.attr;
+        put_u16(&mut raw, 18, date);
+        put_u16(&mut raw, 20, (entry.cluster >> 16) as u16);
+        put_u16(&mut raw, 22, time);
+        put_u16(&mut raw, 24, date);
+        put_u16(&mut raw, 26, entry.cluster as u16);
+        put_u32(&mut raw, 28, entry.size);
+        self.write_at(offset, &raw)
+    }
+
+    /// links a new entry into a directory, with a long name if the name
+    /// doesn't fit 8.3 as it is. takes the first gap big enough, or grows
+    /// the directory
+    pub fn add_entry(
+        &mut self,
+        dir: Dir,
+        name: &str,
+        attr: u8,
+        cluster: u32,
+    ) -> VFSResult<DirEntry> {
+        self.check_writable()?;
+        check_name(name)?;
+
+        let (data, mut extents) = self.read_slots(dir)?;
+        let taken: Vec<[u8; 11]> = self
+            .parse_dir(&data, &extents)
+            .iter()
+            .map(|entry| entry.short)
+            .collect();
+
+        let (short, ntres, long) = match exact_short(name) {
+            Some((short, ntres)) if !taken.contains(&short) => {
+                (short, ntres, None)
+            },
+            _ => {
+                let long: Vec<u16> = name.encode_utf16().collect();
+                (numbered_short(name, &taken)?, 0, Some(long))
+            },
+        };
+        let pieces = long
+            .as_ref()
+            .map_or(0, |long| long.len().div_ceil(LONG_NAME_CHARS));
+        let need = pieces + 1;
+
+        // everything from the end marker on is free
+        let mut slots = data.len() / DIR_ENTRY_SIZE;
+        let mut end = None;
+        let mut run = 0;
+        let mut start = None;
+        for (slot, raw) in
+            data.as_chunks::<DIR_ENTRY_SIZE>().0.iter().enumerate()
+        {
+            if raw[0] == ENTRY_END && end.is_none() {
+                end = Some(slot);
+            }
+            if end.is_some() || raw[0] == ENTRY_DELETED {
+                run += 1;
+                if run == need {
+                    start = Some(slot + 1 - need);
+                    break;
+                }
+            } else {
+                run = 0;
+            }
+        }
+
+        let start = match (start, dir) {
+            (Some(start), _) => start,
+            (None, Dir::Root) => return Err(VFSError::NoSpace),
+            (None, Dir::Chain(first)) => {
+                // a directory can't have more than 65536 entries
+                let per_cluster = self.cluster_size / DIR_ENTRY_SIZE;
+                let grow = (need - run).div_ceil(per_cluster);
+                if slots + grow * per_cluster > 65536 {
+                    return Err(VFSError::NoSpace);
+                }
+
+                let mut last = *self.chain(first)?.last().unwrap();
+                for _ in 0..grow {
+                    last = self.alloc_cluster(Some(last))?;
+                    extents
+                        .push((self.cluster_offset(last), self.cluster_size));
+                }
+                slots += grow * per_cluster;
+                end.get_or_insert(slots - grow * per_cluster);
+                slots - grow * per_cluster - run
+            },
+        };
+
+        let sum = checksum(&short);
+        if let Some(long) = &long {
+            for piece in 0..pieces {
+                let mut raw = [0u8; DIR_ENTRY_SIZE];
+                raw[0] = piece as u8 + 1;
+                if piece + 1 == pieces {
+                    raw[0] |= LAST_LONG_ENTRY;
+                }
+                raw[11] = ATTR_LONG_NAME;
+                raw[13] = sum;
+                // nul terminated unless it fits exactly, then 0xffff padding
+                for (i, offset) in LONG_NAME_OFFSETS.iter().enumerate() {
+                    let at = piece * LONG_NAME_CHARS + i;
+                    let c = match at.cmp(&long.len()) {
+                        core::cmp::Ordering::Less => long[at],
+                        core::cmp::Ordering::Equal => 0,
+                        core::cmp::Ordering::Greater => 0xFFFF,
+                    };
+                    put_u16(&mut raw, *offset, c);
+                }
+                self.write_at(
+                    slot_offset(&extents, start + pieces - 1 - piece),
+                    &raw,
+                )?;
+            }
+        }
+
+        let now = time::now();
+        let offset = slot_offset(&extents, start + pieces);
+        self.write_at(
+            offset,
+            &short_record(&short, ntres, attr, cluster, now),
+        )?;
+
+        // took the end marker, whatever comes after might not be zeroed
+        if end.is_some_and(|end| start + need > end) && start + need < slots {
+            self.write_at(slot_offset(&extents, start + need), &[ENTRY_END])?;
+        }
+
+        Ok(DirEntry {
+            name: String::from(name),
+            short,
+            attr,
+            cluster,
+            size: 0,
+            mtime: now,
+            offset: Some(offset),
+            slots: start..start + need,
+        })
+    }
+
+    /// marks every slot of an entry deleted, its clusters are left alone
+    pub fn remove_entry(
+        &mut self,
+        dir: Dir,
+        entry: &DirEntry,
+    ) -> VFSResult<()> {
+        self.check_writable()?;
+        let extents = self.dir_extents(dir)?;
+        for slot in entry.slots.clone() {
+            self.write_at(slot_offset(&extents, slot), &[ENTRY_DELETED])?;
+        }
+        Ok(())
+    }
+
+    /// puts `.` and `..` in a freshly allocated directory cluster, `parent`
+    /// is 0 for the root
+    pub fn init_dir(&mut self, cluster: u32, parent: u32) -> VFSResult<()> {
+        let now = time::now();
+        let offset = self.cluster_offset(cluster);
+        let dot = short_record(b".          ", 0, ATTR_DIRECTORY, cluster, now);
+        let dotdot =
+            short_record(b"..         ", 0, ATTR_DIRECTORY, parent, now);
+        self.write_at(offset, &dot)?;
+        self.write_at(offset + DIR_ENTRY_SIZE as u64, &dotdot)
+    }
+}
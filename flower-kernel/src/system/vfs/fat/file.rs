@@ -0,0 +1,263 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Mutex;
+
+use crate::system::time;
+use crate::system::vfs::fat::consts::*;
+use crate::system::vfs::fat::dir::DirEntry;
+use crate::system::vfs::fat::volume::{Volume, io_error};
+use crate::system::vfs::{
+    VFSError, VFSFile, VFSFileType, VFSMetadata, VFSPermissions, VFSResult,
+    VFSSeek,
+};
+
+/// fat has no owners or modes, everything belongs to root and the read only
+/// attribute takes the write bits away
+pub fn metadata(name: &str, entry: &DirEntry) -> VFSMetadata {
+    let (typ, mut mode) = if entry.is_dir() {
+        (VFSFileType::Directory, 0o755)
+    } else {
+        (VFSFileType::File, 0o644)
+    };
+    if entry.attr & ATTR_READ_ONLY != 0 {
+        mode &= !0o222;
+    }
+
+    VFSMetadata {
+        name: String::from(name),
+        typ,
+        size: entry.size as usize,
+        last_modified: entry.mtime as usize,
+        owner_id: 0,
+        group_id: 0,
+        permissions: VFSPermissions::from_unix(mode),
+    }
+}
+
+impl Volume {
+    /// the cluster and offset in it for byte `pos` of a chain
+    fn locate(&self, chain: &[u32], pos: u64) -> VFSResult<u64> {
+        let size = self.cluster_size as u64;
+        let cluster = chain
+            .get((pos / size) as usize)
+            .ok_or_else(|| io_error("file is bigger than its clusters"))?;
+        Ok(self.cluster_offset(*cluster) + pos % size)
+    }
+
+    fn read_span(
+        &self,
+        chain: &[u32],
+        pos: u64,
+        buf: &mut [u8],
+    ) -> VFSResult<()> {
+        let mut done = 0;
+        while done < buf.len() {
+            let at = pos + done as u64;
+            let skip = (at % self.cluster_size as u64) as usize;
+            let len = (buf.len() - done).min(self.cluster_size - skip);
+            self.read_at(self.locate(chain, at)?, &mut buf[done..done + len])?;
+            done += len;
+        }
+        Ok(())
+    }
+
+    fn write_span(&self, chain: &[u32], pos: u64, buf: &[u8]) -> VFSResult<()> {
+        let mut done = 0;
+        while done < buf.len() {
+            let at = pos + done as u64;
+            let skip = (at % self.cluster_size as u64) as usize;
+            let len = (buf.len() - done).min(self.cluster_size - skip);
+            self.write_at(self.locate(chain, at)?, &buf[done..done + len])?;
+            done += len;
+        }
+        Ok(())
+    }
+
+    pub fn read_data(
+        &self,
+        entry: &DirEntry,
+        pos: u64,
+        buf: &mut [u8],
+    ) -> VFSResult<usize> {
+        let size = entry.size as u64;
+        if pos >= size {
+            return Ok(0);
+        }
+
+        let len = buf.len().min((size - pos) as usize);
+        let chain = self.chain(entry.cluster)?;
+        self.read_span(&chain, pos, &mut buf[..len])?;
+        Ok(len)
+    }
+
+    /// writes at `pos`, growing the chain as it goes. the caller writes the
+    /// entry back
+    pub fn write_data(
+        &mut self,
+        entry: &mut DirEntry,
+        pos: u64,
+        buf: &[u8],
+    ) -> VFSResult<usize> {
+        self.check_writable()?;
+        let end = pos + buf.len() as u64;
+        if end > MAX_FILE_SIZE {
+            return Err(VFSError::NoSpace);
+        }
+
+        let mut chain = self.chain(entry.cluster)?;
+        let cluster_size = self.cluster_size as u64;
+
+        // new clusters come zeroed, but the rest of the last one might
+        // have anything in it
+        let size = entry.size as u64;
+        let allocated = chain.len() as u64 * cluster_size;
+        if pos > size && size < allocated {
+            let gap = vec![0u8; (pos.min(allocated) - size) as usize];
+            self.write_span(&chain, size, &gap)?;
+        }
+
+        while (chain.len() as u64) * cluster_size < end {
+            let cluster = self.alloc_cluster(chain.last().copied())?;
+            if chain.is_empty() {
+                entry.cluster = cluster;
+            }
+            chain.push(cluster);
+        }
+
+        self.write_span(&chain, pos, buf)?;
+        entry.size = entry.size.max(end as u32);
+        entry.mtime = time::now();
+        entry.attr |= ATTR_ARCHIVE;
+        Ok(buf.len())
+    }
+
+    /// drops everything in a file
+    pub fn truncate(&mut self, entry: &mut DirEntry) -> VFSResult<()> {
+        self.free_chain(entry.cluster)?;
+        entry.cluster = 0;
+        entry.size = 0;
+        entry.mtime = time::now();
+        self.update_entry(entry)
+    }
+}
+
+/// an open file or directory. it holds on to where its entry is on disk and
+/// reads it fresh every call, so two opens of the same file see each
+/// other's writes. there's no rename, so the entry never moves
+pub struct FatFile {
+    pub volume: Arc<Mutex<Volume>>,
+    /// `None` for the root directory
+    pub entry: Option<u64>,
+    pub name: String,
+    /// every write goes to the end
+    pub append: bool,
+    pub position: AtomicUsize,
+}
+
+impl FatFile {
+    fn entry(&self, volume: &Volume) -> VFSResult<DirEntry> {
+        match self.entry {
+            Some(offset) => volume.entry_at(offset),
+            None => Ok(DirEntry::root()),
+        }
+    }
+
+    /// directories read back as one name per line, like procfs
+    fn listing(volume: &Volume, entry: &DirEntry) -> VFSResult<Vec<u8>> {
+        let mut data = Vec::new();
+        for entry in volume.read_dir(volume.dir_at(entry.cluster))? {
+            if entry.is_dot() {
+                continue;
+            }
+            data.extend_from_slice(entry.name.as_bytes());
+            data.push(b'\n');
+        }
+        Ok(data)
+    }
+}
+
+impl VFSFile for FatFile {
+    fn read(&self, buf: &mut [u8]) -> VFSResult<usize> {
+        let volume = self.volume.lock();
+        let entry = self.entry(&volume)?;
+        let position = self.position.load(Ordering::Acquire);
+
+        let read = if entry.is_dir() {
+            let listing = Self::listing(&volume, &entry)?;
+            if position >= listing.len() {
+                return Ok(0);
+            }
+            let len = buf.len().min(listing.len() - position);
+            buf[..len].copy_from_slice(&listing[position..position + len]);
+            len
+        } else {
+            volume.read_data(&entry, position as u64, buf)?
+        };
+
+        self.position.store(position + read, Ordering::Release);
+        Ok(read)
+    }
+
+    fn write(&self, buf: &mut [u8]) -> VFSResult<usize> {
+        let mut volume = self.volume.lock();
+        let mut entry = self.entry(&volume)?;
+        if entry.is_dir() {
+            return Err(VFSError::IsDirectory);
+        }
+
+        let position = if self.append {
+            entry.size as usize
+        } else {
+            self.position.load(Ordering::Acquire)
+        };
+        let written = volume.write_data(&mut entry, position as u64, buf);
+        // clusters may have been linked in even if it failed halfway
+        volume.update_entry(&entry)?;
+
+        let written = written?;
+        self.position.store(position + written, Ordering::Release);
+        Ok(written)
+    }
+
+    fn seek(&self, pos: VFSSeek) -> VFSResult<usize> {
+        let current = self.position.load(Ordering::Acquire);
+        let size = {
+            let volume = self.volume.lock();
+            let entry = self.entry(&volume)?;
+            if entry.is_dir() {
+                Self::listing(&volume, &entry)?.len()
+            } else {
+                entry.size as usize
+            }
+        };
+        let new_pos = match pos {
+            VFSSeek::Start(n) => n,
+            VFSSeek::Current(n) => current.saturating_add(n),
+            VFSSeek::End(n) => size.saturating_add(n),
+        }
+        .min(size);
+
+        self.position.store(new_pos, Ordering::Release);
+        Ok(new_pos)
+    }
+
+    fn mmap(
+        &self,
+        _len: usize,
+        _prot: core::ffi::c_int,
+        _flags: core::ffi::c_int,
+    ) -> VFSResult<*mut u8> {
+        Err(VFSError::Unsupported)
+    }
+
+    fn metadata(&self) -> VFSResult<VFSMetadata> {
+        let volume = self.volume.lock();
+        Ok(metadata(&self.name, &self.entry(&volume)?))
+    }
+
+    fn sync(&self) -> VFSResult<()> { self.volume.lock().sync() }
+}
@@ -0,0 +1,187 @@
+mod consts;
+mod dir;
+mod file;
+mod volume;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::sync::atomic::AtomicUsize;
+
+use flower_mono::fcntl::{O_APPEND, O_CREAT, O_EXCL, O_TRUNC};
+use spin::Mutex;
+
+use crate::drivers::block::Disk;
+use crate::system::vfs::fat::consts::*;
+use crate::system::vfs::fat::dir::DirEntry;
+use crate::system::vfs::fat::file::FatFile;
+use crate::system::vfs::fat::volume::{Dir, Volume};
+use crate::system::vfs::split_parent;
+use crate::system::vfs::types::*;
+
+pub struct FatFS {
+    volume: Arc<Mutex<Volume>>,
+}
+
+fn lookup(volume: &Volume, path: &str) -> VFSResult<DirEntry> {
+    let root = volume.dir_at(0);
+    let mut entry = DirEntry::root();
+
+    for part in path.split('/').filter(|part| !part.is_empty() && *part != ".")
+    {
+        if !entry.is_dir() {
+            return Err(VFSError::NotDirectory);
+        }
+        if part == ".." && entry.is_root() {
+            continue;
+        }
+
+        entry = volume
+            .find(volume.dir_at(entry.cluster), part)?
+            .ok_or(VFSError::NotFound)?;
+        // a `..` that leads back to the root
+        if entry.is_dir() && volume.dir_at(entry.cluster) == root {
+            entry = DirEntry::root();
+        }
+    }
+
+    Ok(entry)
+}
+
+/// makes a new entry at `path`, directories get their first cluster with
+/// `.` and `..` in it
+fn create(volume: &mut Volume, path: &str, attr: u8) -> VFSResult<DirEntry> {
+    volume.check_writable()?;
+    let (parent, name) = split_parent(path)?;
+    let parent = lookup(volume, parent)?;
+    if !parent.is_dir() {
+        return Err(VFSError::NotDirectory);
+    }
+
+    let dir = volume.dir_at(parent.cluster);
+    if volume.find(dir, name)?.is_some() {
+        return Err(VFSError::AlreadyExists);
+    }
+    if attr & ATTR_DIRECTORY == 0 {
+        return volume.add_entry(dir, name, attr, 0);
+    }
+
+    let cluster = volume.alloc_cluster(None)?;
+    let made = volume
+        .init_dir(cluster, parent.cluster)
+        .and_then(|_| volume.add_entry(dir, name, attr, cluster));
+    if made.is_err() {
+        // put the cluster back, the disk is probably full
+        let _ = volume.free_chain(cluster);
+    }
+    made
+}
+
+/// finds the entry at `path` and the directory it's in, for removing it
+fn lookup_in_parent(volume: &Volume, path: &str) -> VFSResult<(Dir, DirEntry)> {
+    let (parent, name) = split_parent(path)?;
+    let parent = lookup(volume, parent)?;
+    if !parent.is_dir() {
+        return Err(VFSError::NotDirectory);
+    }
+
+    let dir = volume.dir_at(parent.cluster);
+    let entry = volume.find(dir, name)?.ok_or(VFSError::NotFound)?;
+    Ok((dir, entry))
+}
+
+impl FatFS {
+    /// mounts whichever of fat12/16/32 is on `disk`, read only if asked to
+    /// or if the disk can't be written
+    pub fn new(disk: Arc<Disk>, read_only: bool) -> Result<Self, &'static str> {
+        let name = disk.name.clone();
+        let mut volume = Volume::new(disk)?;
+        volume.read_only |= read_only;
+        log::info!(
+            "fat: {} is {:?} with {} byte clusters{}",
+            name,
+            volume.kind,
+            volume.cluster_size,
+            if volume.read_only { ", read only" } else { "" }
+        );
+        Ok(Self { volume: Arc::new(Mutex::new(volume)) })
+    }
+}
+
+impl VFSImplementation for FatFS {
+    fn fs_type(&self) -> &'static str { "vfat" }
+
+    fn read_only(&self) -> bool { self.volume.lock().read_only }
+
+    fn open(&self, path: &str, flags: u32) -> VFSResult<Box<dyn VFSFile>> {
+        let mut volume = self.volume.lock();
+        let mut entry = match lookup(&volume, path) {
+            Ok(_) if flags & O_CREAT != 0 && flags & O_EXCL != 0 => {
+                return Err(VFSError::AlreadyExists);
+            },
+            Ok(entry) => entry,
+            Err(VFSError::NotFound) if flags & O_CREAT != 0 => {
+                create(&mut volume, path, ATTR_ARCHIVE)?
+            },
+            Err(e) => return Err(e),
+        };
+
+        if flags & O_TRUNC != 0 && !entry.is_dir() && entry.cluster != 0 {
+            volume.truncate(&mut entry)?;
+        }
+
+        let name =
+            path.rsplit('/').find(|part| !part.is_empty()).unwrap_or("/");
+        Ok(Box::new(FatFile {
+            volume: self.volume.clone(),
+            entry: entry.offset,
+            name: String::from(name),
+            append: flags & O_APPEND != 0,
+            position: AtomicUsize::new(0),
+        }))
+    }
+
+    fn metadata(&self, path: &str) -> VFSResult<VFSMetadata> {
+        let volume = self.volume.lock();
+        let entry = lookup(&volume, path)?;
+        let name =
+            path.rsplit('/').find(|part| !part.is_empty()).unwrap_or("/");
+        Ok(file::metadata(name, &entry))
+    }
+
+    /// anyone who still has the file open gets NotFound from then on, or
+    /// whatever ends up in the slot next
+    fn unlink(&self, path: &str) -> VFSResult<()> {
+        let mut volume = self.volume.lock();
+        volume.check_writable()?;
+        let (dir, entry) = lookup_in_parent(&volume, path)?;
+        if entry.is_dir() {
+            return Err(VFSError::IsDirectory);
+        }
+
+        volume.remove_entry(dir, &entry)?;
+        volume.free_chain(entry.cluster)
+    }
+
+    /// there's nowhere to keep the mode
+    fn mkdir(&self, path: &str, _mode: usize) -> VFSResult<()> {
+        create(&mut self.volume.lock(), path, ATTR_DIRECTORY).map(|_| ())
+    }
+
+    fn rmdir(&self, path: &str) -> VFSResult<()> {
+        let mut volume = self.volume.lock();
+        volume.check_writable()?;
+        let (dir, entry) = lookup_in_parent(&volume, path)?;
+        if !entry.is_dir() {
+            return Err(VFSError::NotDirectory);
+        }
+        if !volume.is_empty_dir(volume.dir_at(entry.cluster))? {
+            return Err(VFSError::NotEmpty);
+        }
+
+        volume.remove_entry(dir, &entry)?;
+        volume.free_chain(entry.cluster)
+    }
+
+    fn sync(&self) -> VFSResult<()> { self.volume.lock().sync() }
+}
@@ -0,0 +1,419 @@
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::drivers::block::Disk;
+use crate::system::bytes::{u16_at, u32_at};
+use crate::system::vfs::fat::consts::*;
+use crate::system::vfs::{VFSError, VFSResult};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FatKind {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+/// where a directory's entries live
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dir {
+    /// the fixed size root of fat12/16, between the fats and the data
+    Root,
+    /// everything else is a cluster chain, same as a file
+    Chain(u32),
+}
+
+/// a mounted fat filesystem, everything here goes thru the block cache
+pub struct Volume {
+    disk: Arc<Disk>,
+    pub kind: FatKind,
+    pub cluster_size: usize,
+    /// byte offset of the first fat and the size of each copy
+    fat_start: u64,
+    fat_size: u64,
+    fat_count: u32,
+    /// the copy reads come from, fat32 can turn mirroring off and pick one
+    active_fat: u32,
+    mirror: bool,
+    root_start: u64,
+    root_entries: u32,
+    root_cluster: u32,
+    data_start: u64,
+    cluster_count: u32,
+    /// fat32 keeps a free count and an allocation hint in here
+    fsinfo: Option<u64>,
+    free_clusters: Option<u32>,
+    next_free: u32,
+    pub read_only: bool,
+}
+
+pub fn io_error(e: &'static str) -> VFSError {
+    log::error!("fat: {}", e);
+    VFSError::IOError
+}
+
+impl Volume {
+    pub fn new(disk: Arc<Disk>) -> Result<Self, &'static str> {
+        let mut bs = vec![0u8; 512];
+        disk.read_at(0, &mut bs)?;
+        if u16_at(&bs, 510) != BOOT_SIGNATURE || !matches!(bs[0], 0xEB | 0xE9) {
+            return Err("not a fat filesystem");
+        }
+
+        let sector_size = u16_at(&bs, 11) as u64;
+        let sectors_per_cluster = bs[13] as u64;
+        let reserved = u16_at(&bs, 14) as u64;
+        let fat_count = bs[16] as u32;
+        let root_entries = u16_at(&bs, 17) as u32;
+        let total = match u16_at(&bs, 19) {
+            0 => u32_at(&bs, 32) as u64,
+            n => n as u64,
+        };
+        let fat16_size = u16_at(&bs, 22) as u64;
+
+        if !matches!(sector_size, 512 | 1024 | 2048 | 4096)
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved == 0
+            || fat_count == 0
+        {
+            return Err("bad bios parameter block");
+        }
+
+        // fat32 is the one without a fat16 sized fat, same test linux uses
+        let fat_sectors =
+            if fat16_size != 0 { fat16_size } else { u32_at(&bs, 36) as u64 };
+        let root_sectors = (root_entries as u64 * 32).div_ceil(sector_size);
+        let data_sector =
+            reserved + fat_count as u64 * fat_sectors + root_sectors;
+        if fat_sectors == 0 || total <= data_sector {
+            return Err("bad bios parameter block");
+        }
+
+        let cluster_count =
+            ((total - data_sector) / sectors_per_cluster) as u32;
+        let kind = if fat16_size == 0 {
+            FatKind::Fat32
+        } else if cluster_count < FAT12_CLUSTERS {
+            FatKind::Fat12
+        } else {
+            FatKind::Fat16
+        };
+
+        let mut fat = Self {
+            disk,
+            kind,
+            cluster_size: (sector_size * sectors_per_cluster) as usize,
+            fat_start: reserved * sector_size,
+            fat_size: fat_sectors * sector_size,
+            fat_count,
+            active_fat: 0,
+            mirror: true,
+            root_start: (reserved + fat_count as u64 * fat_sectors)
+                * sector_size,
+            root_entries,
+            root_cluster: 0,
+            data_start: data_sector * sector_size,
+            cluster_count,
+            fsinfo: None,
+            free_clusters: None,
+            next_free: FIRST_CLUSTER,
+            read_only: false,
+        };
+        fat.read_only = fat.disk.read_only();
+
+        // the fat has to have room for every cluster
+        let fat_bits = match kind {
+            FatKind::Fat12 => 12,
+            FatKind::Fat16 => 16,
+            FatKind::Fat32 => 32,
+        };
+        if (cluster_count as u64 + 2) * fat_bits > fat.fat_size * 8 {
+            return Err("fat is too small for the volume");
+        }
+
+        if kind == FatKind::Fat32 {
+            if root_entries != 0 {
+                return Err("bad bios parameter block");
+            }
+
+            let ext_flags = u16_at(&bs, 40);
+            if ext_flags & NO_MIRROR != 0 {
+                fat.mirror = false;
+                fat.active_fat = (ext_flags & ACTIVE_FAT) as u32;
+                if fat.active_fat >= fat_count {
+                    return Err("bad active fat");
+                }
+            }
+
+            fat.root_cluster = u32_at(&bs, 44);
+            if !fat.valid_cluster(fat.root_cluster) {
+                return Err("bad root cluster");
+            }
+
+            let sector = u16_at(&bs, 48) as u64;
+            if sector != 0 && sector < reserved {
+                fat.read_fsinfo(sector * sector_size)?;
+            }
+        }
+
+        Ok(fat)
+    }
+
+    /// picks up the free count and hint, anything weird in there is ignored
+    fn read_fsinfo(&mut self, offset: u64) -> Result<(), &'static str> {
+        let mut info = vec![0u8; 512];
+        self.disk.read_at(offset, &mut info)?;
+        if u32_at(&info, 0) != FSINFO_LEAD_SIG
+            || u32_at(&info, 484) != FSINFO_STRUCT_SIG
+        {
+            log::warn!("fat: fsinfo sector is bad, ignoring it");
+            return Ok(());
+        }
+
+        self.fsinfo = Some(offset);
+        let free = u32_at(&info, FSINFO_FREE_COUNT as usize);
+        if free != FSINFO_UNKNOWN && free <= self.cluster_count {
+            self.free_clusters = Some(free);
+        }
+        let next = u32_at(&info, FSINFO_NEXT_FREE as usize);
+        if self.valid_cluster(next) {
+            self.next_free = next;
+        }
+        Ok(())
+    }
+
+    fn write_fsinfo(&self) -> VFSResult<()> {
+        let Some(offset) = self.fsinfo else {
+            return Ok(());
+        };
+
+        let free = self.free_clusters.unwrap_or(FSINFO_UNKNOWN);
+        self.disk
+            .write_at(offset + FSINFO_FREE_COUNT, &free.to_le_bytes())
+            .map_err(io_error)?;
+        self.disk
+            .write_at(offset + FSINFO_NEXT_FREE, &self.next_free.to_le_bytes())
+            .map_err(io_error)
+    }
+
+    pub fn sync(&self) -> VFSResult<()> { self.disk.flush().map_err(io_error) }
+
+    pub fn check_writable(&self) -> VFSResult<()> {
+        if self.read_only { Err(VFSError::ReadOnly) } else { Ok(()) }
+    }
+
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> VFSResult<()> {
+        self.disk.read_at(offset, buf).map_err(io_error)
+    }
+
+    pub fn write_at(&self, offset: u64, buf: &[u8]) -> VFSResult<()> {
+        self.check_writable()?;
+        self.disk.write_at(offset, buf).map_err(io_error)
+    }
+
+    pub fn valid_cluster(&self, cluster: u32) -> bool {
+        (FIRST_CLUSTER..self.cluster_count + FIRST_CLUSTER).contains(&cluster)
+    }
+
+    pub fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.data_start
+            + (cluster - FIRST_CLUSTER) as u64 * self.cluster_size as u64
+    }
+
+    /// the directory a cluster number in an entry points at, 0 is the root
+    pub fn dir_at(&self, cluster: u32) -> Dir {
+        match (cluster, self.kind) {
+            (0, FatKind::Fat32) => Dir::Chain(self.root_cluster),
+            (0, _) => Dir::Root,
+            (cluster, _) => Dir::Chain(cluster),
+        }
+    }
+
+    /// the byte ranges on disk a directory is made of, in order
+    pub fn dir_extents(&self, dir: Dir) -> VFSResult<Vec<(u64, usize)>> {
+        match dir {
+            Dir::Root => Ok(vec![(
+                self.root_start,
+                self.root_entries as usize * DIR_ENTRY_SIZE,
+            )]),
+            Dir::Chain(first) => Ok(self
+                .chain(first)?
+                .into_iter()
+                .map(|cluster| {
+                    (self.cluster_offset(cluster), self.cluster_size)
+                })
+                .collect()),
+        }
+    }
+
+    fn end_of_chain(&self) -> u32 {
+        match self.kind {
+            FatKind::Fat12 => 0xFFF,
+            FatKind::Fat16 => 0xFFFF,
+            FatKind::Fat32 => 0x0FFFFFFF,
+        }
+    }
+
+    /// where a cluster's entry is, relative to the start of a fat
+    fn entry_offset(&self, cluster: u32) -> u64 {
+        let cluster = cluster as u64;
+        match self.kind {
+            FatKind::Fat12 => cluster + cluster / 2,
+            FatKind::Fat16 => cluster * 2,
+            FatKind::Fat32 => cluster * 4,
+        }
+    }
+
+    fn fat_entry(&self, cluster: u32) -> VFSResult<u32> {
+        let offset = self.fat_start
+            + self.active_fat as u64 * self.fat_size
+            + self.entry_offset(cluster);
+
+        Ok(match self.kind {
+            FatKind::Fat12 => {
+                let mut raw = [0u8; 2];
+                self.read_at(offset, &mut raw)?;
+                let value = u16::from_le_bytes(raw);
+                // odd clusters get the top 12 bits
+                (if cluster & 1 == 1 { value >> 4 } else { value & 0xFFF })
+                    as u32
+            },
+            FatKind::Fat16 => {
+                let mut raw = [0u8; 2];
+                self.read_at(offset, &mut raw)?;
+                u16::from_le_bytes(raw) as u32
+            },
+            FatKind::Fat32 => {
+                let mut raw = [0u8; 4];
+                self.read_at(offset, &mut raw)?;
+                u32::from_le_bytes(raw) & 0x0FFFFFFF
+            },
+        })
+    }
+
+    /// writes every copy of the fat, or just the active one if fat32 says so
+    fn set_fat_entry(&self, cluster: u32, value: u32) -> VFSResult<()> {
+        let copies = if self.mirror {
+            0..self.fat_count
+        } else {
+            self.active_fat..self.active_fat + 1
+        };
+
+        for copy in copies {
+            let offset = self.fat_start
+                + copy as u64 * self.fat_size
+                + self.entry_offset(cluster);
+            match self.kind {
+                FatKind::Fat12 => {
+                    let mut raw = [0u8; 2];
+                    self.read_at(offset, &mut raw)?;
+                    let old = u16::from_le_bytes(raw);
+                    let value = value as u16 & 0xFFF;
+                    let new = if cluster & 1 == 1 {
+                        (old & 0x000F) | (value << 4)
+                    } else {
+                        (old & 0xF000) | value
+                    };
+                    self.write_at(offset, &new.to_le_bytes())?;
+                },
+                FatKind::Fat16 => {
+                    self.write_at(offset, &(value as u16).to_le_bytes())?
+                },
+                FatKind::Fat32 => {
+                    // the top 4 bits are reserved and have to be kept
+                    let mut raw = [0u8; 4];
+                    self.read_at(offset, &mut raw)?;
+                    let old = u32::from_le_bytes(raw);
+                    let new = (old & 0xF0000000) | (value & 0x0FFFFFFF);
+                    self.write_at(offset, &new.to_le_bytes())?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// every cluster of a file or directory, 0 is an empty file
+    pub fn chain(&self, first: u32) -> VFSResult<Vec<u32>> {
+        let mut chain = Vec::new();
+        let mut cluster = first;
+        while cluster != 0 {
+            if !self.valid_cluster(cluster) {
+                return Err(io_error(
+                    "cluster chain points outside the volume",
+                ));
+            }
+            if chain.len() >= self.cluster_count as usize {
+                return Err(io_error("cluster chain loops"));
+            }
+            chain.push(cluster);
+
+            let next = self.fat_entry(cluster)?;
+            if next >= self.end_of_chain() - 7 {
+                break;
+            }
+            if next == 0 {
+                return Err(io_error("cluster chain runs into a free cluster"));
+            }
+            cluster = next;
+        }
+        Ok(chain)
+    }
+
+    /// grabs a free cluster, zeroes it and hangs it off `prev` if there is
+    /// one
+    pub fn alloc_cluster(&mut self, prev: Option<u32>) -> VFSResult<u32> {
+        self.check_writable()?;
+        if self.free_clusters == Some(0) {
+            return Err(VFSError::NoSpace);
+        }
+
+        let start = if self.valid_cluster(self.next_free) {
+            self.next_free
+        } else {
+            FIRST_CLUSTER
+        };
+        let mut found = None;
+        for i in 0..self.cluster_count {
+            let cluster = FIRST_CLUSTER
+                + (start - FIRST_CLUSTER + i) % self.cluster_count;
+            if self.fat_entry(cluster)? == 0 {
+                found = Some(cluster);
+                break;
+            }
+        }
+        let cluster = found.ok_or(VFSError::NoSpace)?;
+
+        self.write_at(
+            self.cluster_offset(cluster),
+            &vec![0u8; self.cluster_size],
+        )?;
+        self.set_fat_entry(cluster, self.end_of_chain())?;
+        if let Some(prev) = prev {
+            self.set_fat_entry(prev, cluster)?;
+        }
+
+        self.next_free = cluster + 1;
+        self.free_clusters = self.free_clusters.map(|free| free - 1);
+        self.write_fsinfo()?;
+        Ok(cluster)
+    }
+
+    /// frees a whole chain
+    pub fn free_chain(&mut self, first: u32) -> VFSResult<()> {
+        if first == 0 {
+            return Ok(());
+        }
+        self.check_writable()?;
+
+        let chain = self.chain(first)?;
+        for cluster in &chain {
+            self.set_fat_entry(*cluster, 0)?;
+        }
+
+        let freed = chain.len() as u32;
+        self.free_clusters = self
+            .free_clusters
+            .map(|free| (free + freed).min(self.cluster_count));
+        self.write_fsinfo()
+    }
+}
@@ -1,11 +1,17 @@
-use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
 
 use crate::system::vfs::types::{VFSError, VFSFile, VFSResult};
 
 pub const MAX_FDS: usize = 8;
 
 pub enum FdKind {
-    File(Box<dyn VFSFile>),
+    /// shared so syscalls can let go of the process before touching the
+    /// file, a read or write might sleep.
+    File {
+        file: Arc<dyn VFSFile>,
+        path: String,
+    },
     Stdin,
     Stdout,
     Stderr,
@@ -51,6 +57,22 @@ impl FdTable {
             .ok_or(VFSError::NotFound)
     }
 
+    /// the file behind a descriptor, stdio isn't a file
+    pub fn file(&self, fd: usize) -> VFSResult<Arc<dyn VFSFile>> {
+        match self.get(fd)? {
+            FdKind::File { file, .. } => Ok(file.clone()),
+            _ => Err(VFSError::PermissionDenied),
+        }
+    }
+
+    /// iterates over the open descriptors
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &FdKind)> {
+        self.fds
+            .iter()
+            .enumerate()
+            .filter_map(|(fd, slot)| slot.as_ref().map(|kind| (fd, kind)))
+    }
+
     pub fn close(&mut self, fd: usize) -> VFSResult<()> {
         if fd >= MAX_FDS {
             return Err(VFSError::NotFound);
@@ -74,7 +96,7 @@ impl Clone for FdTable {
                 Some(FdKind::Stdin) => table.fds[fd] = Some(FdKind::Stdin),
                 Some(FdKind::Stdout) => table.fds[fd] = Some(FdKind::Stdout),
                 Some(FdKind::Stderr) => table.fds[fd] = Some(FdKind::Stderr),
-                Some(FdKind::File(_)) | None => {},
+                Some(FdKind::File { .. }) | None => {},
             }
         }
         table
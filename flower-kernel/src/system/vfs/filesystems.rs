@@ -0,0 +1,111 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+
+use flower_mono::syscalls::MS_RDONLY;
+
+use crate::drivers::block::{self, Disk};
+use crate::system::vfs::devfs;
+use crate::system::vfs::ext2::Ext2FS;
+use crate::system::vfs::fat::FatFS;
+use crate::system::vfs::procfs::ProcFS;
+use crate::system::vfs::tarfs::TarFS;
+use crate::system::vfs::tmpfs::TmpFS;
+use crate::system::vfs::types::*;
+
+type Create = fn(&str, u32) -> VFSResult<Box<dyn VFSImplementation>>;
+
+pub struct FsType {
+    pub name: &'static str,
+    /// mounts a block device, the others don't look at the source
+    pub block: bool,
+    create: Create,
+}
+
+/// every filesystem mount knows how to make
+pub const FILESYSTEMS: &[FsType] = &[
+    FsType { name: "devfs", block: false, create: devfs },
+    FsType { name: "ext2", block: true, create: ext2 },
+    FsType { name: "procfs", block: false, create: procfs },
+    FsType { name: "tarfs", block: false, create: tarfs },
+    FsType { name: "tmpfs", block: false, create: tmpfs },
+    FsType { name: "vfat", block: true, create: vfat },
+];
+
+impl FsType {
+    pub fn create(
+        &self,
+        source: &str,
+        flags: u32,
+    ) -> VFSResult<Box<dyn VFSImplementation>> {
+        (self.create)(source, flags)
+    }
+}
+
+pub fn find(name: &str) -> Option<&'static FsType> {
+    FILESYSTEMS.iter().find(|fs| fs.name == name)
+}
+
+/// `/dev/vda1` and `vda1` are the same disk
+pub fn device_name(source: &str) -> &str {
+    source.strip_prefix("/dev/").unwrap_or(source)
+}
+
+fn disk(source: &str) -> VFSResult<Arc<Disk>> {
+    block::get(device_name(source)).ok_or(VFSError::NotFound)
+}
+
+fn unmountable(source: &str, e: &'static str) -> VFSError {
+    log::error!("vfs: {} isn't mountable: {}", source, e);
+    VFSError::InvalidArgument
+}
+
+fn devfs(_source: &str, _flags: u32) -> VFSResult<Box<dyn VFSImplementation>> {
+    Ok(Box::new(devfs::create_devfs()))
+}
+
+fn ext2(source: &str, flags: u32) -> VFSResult<Box<dyn VFSImplementation>> {
+    Ext2FS::new(disk(source)?, flags & MS_RDONLY as u32 != 0)
+        .map(|fs| Box::new(fs) as Box<dyn VFSImplementation>)
+        .map_err(|e| unmountable(source, e))
+}
+
+fn procfs(_source: &str, _flags: u32) -> VFSResult<Box<dyn VFSImplementation>> {
+    Ok(Box::new(ProcFS::new()))
+}
+
+fn tarfs(_source: &str, _flags: u32) -> VFSResult<Box<dyn VFSImplementation>> {
+    Ok(Box::new(TarFS::new()))
+}
+
+fn tmpfs(_source: &str, flags: u32) -> VFSResult<Box<dyn VFSImplementation>> {
+    Ok(Box::new(TmpFS::new(flags & MS_RDONLY as u32 != 0)))
+}
+
+fn vfat(source: &str, flags: u32) -> VFSResult<Box<dyn VFSImplementation>> {
+    FatFS::new(disk(source)?, flags & MS_RDONLY as u32 != 0)
+        .map(|fs| Box::new(fs) as Box<dyn VFSImplementation>)
+        .map_err(|e| unmountable(source, e))
+}
+
+/// tries every disk filesystem on `source`, for when no type was given
+pub fn probe(
+    source: &str,
+    flags: u32,
+) -> VFSResult<Box<dyn VFSImplementation>> {
+    let disk = disk(source)?;
+    let read_only = flags & MS_RDONLY as u32 != 0;
+
+    match Ext2FS::new(disk.clone(), read_only) {
+        Ok(fs) => return Ok(Box::new(fs)),
+        // it is ext2, just not one we can mount
+        Err(e) if e != "not an ext2 filesystem" => {
+            return Err(unmountable(source, e));
+        },
+        Err(_) => {},
+    }
+
+    match FatFS::new(disk, read_only) {
+        Ok(fs) => Ok(Box::new(fs)),
+        Err(_) => Err(unmountable(source, "no filesystem we know of")),
+    }
+}
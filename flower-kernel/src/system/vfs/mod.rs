@@ -1,92 +1,210 @@
 use alloc::boxed::Box;
+use alloc::format;
 use alloc::string::{String, ToString};
+use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::ffi::c_int;
 
+use flower_mono::fcntl::{O_CREAT, O_RDWR, O_TRUNC, O_WRONLY};
+use flower_mono::syscalls::{MS_BIND, MS_RDONLY};
 use spin::{Lazy, Mutex};
 
 mod devfs;
+mod ext2;
+mod fat;
 mod fds;
+mod filesystems;
+mod procfs;
 mod tarfs;
+mod tmpfs;
 mod types;
 
 pub use self::fds::*;
+pub use self::filesystems::FILESYSTEMS;
 pub use self::types::*;
-use crate::system::vfs::tarfs::TarFS;
+use crate::boot::cmdline;
 
 pub struct Mount {
+    /// what it was mounted from, a disk, a directory for bind mounts or just
+    /// the filesystem name
+    source: String,
     path: String,
-    fs: Box<dyn VFSImplementation>,
+    /// the directory of `fs` that shows up at `path`, `/` unless it's a bind
+    /// mount
+    root: String,
+    flags: u32,
+    fs: Arc<dyn VFSImplementation>,
+}
+
+/// one line of the mount table, for /proc/mounts
+pub struct MountInfo {
+    pub source: String,
+    pub path: String,
+    pub fs_type: &'static str,
+    pub read_only: bool,
 }
 
 pub struct Vfs {
     mounts: Vec<Mount>,
 }
 
+/// what's left of `path` after `mount`, if `path` is at or under it. only
+/// whole components match, `/devices` isn't under `/dev`
+fn strip_mount<'a>(path: &'a str, mount: &str) -> Option<&'a str> {
+    if mount == "/" {
+        return Some(path);
+    }
+
+    let rest = path.strip_prefix(mount)?;
+    match rest {
+        "" => Some("/"),
+        rest if rest.starts_with('/') => Some(rest),
+        _ => None,
+    }
+}
+
 // internals
 impl Vfs {
     /// creates a new VFS instance
     pub fn new() -> Self { Self { mounts: Vec::new() } }
 
+    fn insert(&mut self, mount: Mount) -> VFSResult<()> {
+        if self.mounts.iter().any(|m| m.path == mount.path) {
+            return Err(VFSError::Busy);
+        }
+        self.mounts.push(mount);
+        Ok(())
+    }
+
+    /// true if a filesystem is already mounted from the disk `source` is on
+    fn device_taken(&self, source: &str) -> bool {
+        let device = filesystems::device_name(source);
+        self.mounts
+            .iter()
+            .filter(|m| m.flags & MS_BIND as u32 == 0)
+            .any(|m| filesystems::device_name(&m.source) == device)
+    }
+
     /// mounts the given filesystem at the given path
     pub fn mount(
         &mut self,
+        source: &str,
         path: &str,
+        flags: u32,
         fs: Box<dyn VFSImplementation>,
     ) -> VFSResult<()> {
-        let path = path.to_string();
-
-        if self.mounts.iter().any(|m| m.path == path) {
-            return Err(VFSError::AlreadyExists);
-        }
-
-        self.mounts.push(Mount { path, fs });
-
-        Ok(())
+        self.insert(Mount {
+            source: source.to_string(),
+            path: path.to_string(),
+            root: "/".to_string(),
+            flags,
+            fs: Arc::from(fs),
+        })
     }
 
-    /// unmounts the filesystem at the given path
-    pub fn unmount(&mut self, path: &str) -> VFSResult<()> {
-        let path = path.to_string();
+    /// makes the directory at `source` show up at `path` as well
+    pub fn bind(
+        &mut self,
+        source: &str,
+        path: &str,
+        flags: u32,
+    ) -> VFSResult<()> {
+        let (mount, root) = self.find(source)?;
+        let fs = mount.fs.clone();
+        // it's the same filesystem, so it's only as writable as that is, but
+        // it can be made read only on its own
+        let flags = flags | mount.flags & MS_RDONLY as u32;
+        self.insert(Mount {
+            source: source.to_string(),
+            path: path.to_string(),
+            root,
+            flags,
+            fs,
+        })
+    }
 
+    /// unmounts the filesystem at the given path, refusing if anything still
+    /// uses it
+    pub fn unmount(
+        &mut self,
+        path: &str,
+    ) -> VFSResult<Arc<dyn VFSImplementation>> {
         let idx = self
             .mounts
             .iter()
             .position(|m| m.path == path)
-            .ok_or(VFSError::NotFound)?;
-        self.mounts.remove(idx);
-        Ok(())
+            .ok_or(VFSError::InvalidArgument)?;
+
+        let nested =
+            |m: &Mount| m.path != path && strip_mount(&m.path, path).is_some();
+        if self.mounts.iter().any(nested) {
+            return Err(VFSError::Busy);
+        }
+        // bind mounts hold the filesystem too, it has to outlive them since
+        // only this mount stands for its device. past those it's open files
+        let mount = &self.mounts[idx];
+        if mount.flags & MS_BIND as u32 == 0 && Arc::strong_count(&mount.fs) > 1
+        {
+            return Err(VFSError::Busy);
+        }
+
+        Ok(self.mounts.remove(idx).fs)
     }
 
-    /// resolves the given path to a filesystem and relative path
+    /// resolves the given path to a filesystem and relative path, the
+    /// deepest mount the path is under wins
     pub fn resolve(
         &self,
         path: &str,
-    ) -> VFSResult<(&dyn VFSImplementation, String)> {
-        let path = path.to_string();
-
-        for mount in &self.mounts {
-            if mount.path == "/" {
-                return Ok((mount.fs.as_ref(), path.clone()));
-            }
-
-            if mount.path == path {
-                return Ok((mount.fs.as_ref(), "/".to_string()));
-            }
-
-            if path.starts_with(&mount.path) {
-                let rest = &path[mount.path.len()..];
-                let relative = if rest.is_empty() || rest == "/" {
-                    "/".to_string()
-                } else if rest.starts_with("/") {
-                    rest.to_string()
-                } else {
-                    continue;
-                };
-                return Ok((mount.fs.as_ref(), relative));
-            }
+    ) -> VFSResult<(Arc<dyn VFSImplementation>, String)> {
+        let (mount, relative) = self.find(path)?;
+        Ok((mount.fs.clone(), relative))
+    }
+
+    /// like `resolve`, but for changing things. a read only mount refuses
+    /// even if its filesystem wouldn't, bind mounts can be read only alone
+    pub fn resolve_writable(
+        &self,
+        path: &str,
+    ) -> VFSResult<(Arc<dyn VFSImplementation>, String)> {
+        let (mount, relative) = self.find(path)?;
+        if mount.flags & MS_RDONLY as u32 != 0 {
+            return Err(VFSError::ReadOnly);
+        }
+        Ok((mount.fs.clone(), relative))
+    }
+
+    /// `resolve` for opening with `flags`, writable if they'd change anything
+    fn resolve_open(
+        &self,
+        path: &str,
+        flags: u32,
+    ) -> VFSResult<(Arc<dyn VFSImplementation>, String)> {
+        if flags & (O_WRONLY | O_RDWR | O_CREAT | O_TRUNC) != 0 {
+            self.resolve_writable(path)
+        } else {
+            self.resolve(path)
         }
+    }
+
+    /// the mount `path` is under and where it is inside that
+    fn find(&self, path: &str) -> VFSResult<(&Mount, String)> {
+        // `..` is dealt with here so it can walk back out of a mount
+        let path = normalize(path);
+
+        let (mount, rest) = self
+            .mounts
+            .iter()
+            .filter_map(|m| Some((m, strip_mount(&path, &m.path)?)))
+            .max_by_key(|(m, _)| m.path.len())
+            .ok_or(VFSError::NotFound)?;
 
-        Err(VFSError::NotFound)
+        let relative = match (mount.root.as_str(), rest) {
+            ("/", rest) => rest.to_string(),
+            (root, "/") => root.to_string(),
+            (root, rest) => format!("{}{}", root, rest),
+        };
+        Ok((mount, relative))
     }
 }
 
@@ -94,37 +212,199 @@ impl Vfs {
 impl Vfs {
     /// opens the file at the given path with the given flags
     pub fn open(&self, path: &str, flags: u32) -> VFSResult<Box<dyn VFSFile>> {
-        let (fs, relative) = self.resolve(path)?;
+        let (fs, relative) = self.resolve_open(path, flags)?;
         fs.open(&relative, flags)
     }
+
+    /// lists the mount table in the order it's searched
+    pub fn mounts(&self) -> Vec<MountInfo> {
+        self.mounts
+            .iter()
+            .map(|mount| MountInfo {
+                source: mount.source.clone(),
+                path: mount.path.clone(),
+                fs_type: mount.fs.fs_type(),
+                read_only: mount.flags & MS_RDONLY as u32 != 0
+                    || mount.fs.read_only(),
+            })
+            .collect()
+    }
+}
+
+/// a file that keeps its filesystem mounted for as long as it's open
+struct MountedFile {
+    file: Box<dyn VFSFile>,
+    _fs: Arc<dyn VFSImplementation>,
+}
+
+impl VFSFile for MountedFile {
+    fn read(&self, buf: &mut [u8]) -> VFSResult<usize> { self.file.read(buf) }
+
+    fn write(&self, buf: &mut [u8]) -> VFSResult<usize> { self.file.write(buf) }
+
+    fn seek(&self, pos: VFSSeek) -> VFSResult<usize> { self.file.seek(pos) }
+
+    fn mmap(
+        &self,
+        len: usize,
+        prot: c_int,
+        flags: c_int,
+    ) -> VFSResult<*mut u8> {
+        self.file.mmap(len, prot, flags)
+    }
+
+    fn mmap_writable(&self) -> bool { self.file.mmap_writable() }
+
+    fn metadata(&self) -> VFSResult<VFSMetadata> { self.file.metadata() }
+
+    fn ioctl(&self, cmd: u64, arg: u64) -> VFSResult<u64> {
+        self.file.ioctl(cmd, arg)
+    }
+
+    fn sync(&self) -> VFSResult<()> { self.file.sync() }
 }
 
 // global instance
 static ROOT_VFS: Lazy<Mutex<Vfs>> = Lazy::new(|| Mutex::new(Vfs::new()));
 
 pub fn install() {
-    let tarfs = TarFS::new();
-    ROOT_VFS
-        .lock()
-        .mount("/init", Box::new(tarfs))
-        .expect("failed to mount tarfs");
+    mount("tarfs", "/init", "tarfs", 0).expect("failed to mount tarfs");
+    mount("devfs", "/dev", "devfs", 0).expect("failed to mount devfs");
+    mount("procfs", "/proc", "procfs", 0).expect("failed to mount procfs");
+
+    if let Some(root) = cmdline::option("root") {
+        // an empty type probes the disk
+        if let Err(e) = mount(root, "/", "", 0) {
+            log::error!("vfs: failed to mount {} at /: {:?}", root, e);
+        }
+    }
+}
+
+/// makes `path` absolute without `.`, `..` or doubled slashes, so it can be
+/// compared against the mount table. there's no working directory, so
+/// everything is relative to /
+fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {},
+            ".." => {
+                parts.pop();
+            },
+            part => parts.push(part),
+        }
+    }
 
-    let devfs = devfs::create_devfs();
-    ROOT_VFS
-        .lock()
-        .mount("/dev", Box::new(devfs))
-        .expect("failed to mount devfs");
+    let mut normalized = String::new();
+    for part in &parts {
+        normalized.push('/');
+        normalized.push_str(part);
+    }
+    if normalized.is_empty() {
+        normalized.push('/');
+    }
+    normalized
+}
 
-    let procfs = devfs::create_procfs();
-    ROOT_VFS
-        .lock()
-        .mount("/proc", Box::new(procfs))
-        .expect("failed to mount procfs");
+/// splits a path into its parent directory and last component
+fn split_parent(path: &str) -> VFSResult<(&str, &str)> {
+    let path = path.trim_end_matches('/');
+    let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(VFSError::InvalidArgument);
+    }
+    Ok((if parent.is_empty() { "/" } else { parent }, name))
 }
 
 // public methods
 pub fn open(path: &str, flags: u32) -> VFSResult<Box<dyn VFSFile>> {
-    ROOT_VFS.lock().open(path, flags)
+    // don't hold the mount table while the filesystem works, procfs needs it
+    let (fs, relative) = ROOT_VFS.lock().resolve_open(path, flags)?;
+    let file = fs.open(&relative, flags)?;
+    Ok(Box::new(MountedFile { file, _fs: fs }))
+}
+
+pub fn mounts() -> Vec<MountInfo> { ROOT_VFS.lock().mounts() }
+
+fn check_directory(path: &str) -> VFSResult<()> {
+    let (fs, relative) = ROOT_VFS.lock().resolve(path)?;
+    if fs.metadata(&relative)?.typ != VFSFileType::Directory {
+        return Err(VFSError::NotDirectory);
+    }
+    Ok(())
+}
+
+/// mounts a `fstype` filesystem from `source` over the directory `target`.
+/// an empty `fstype` tries every disk filesystem on `source`, `MS_BIND`
+/// mounts the directory `source` instead
+pub fn mount(
+    source: &str,
+    target: &str,
+    fstype: &str,
+    flags: u32,
+) -> VFSResult<()> {
+    let target = normalize(target);
+    // / is the only place that doesn't have to exist yet
+    if target != "/" {
+        check_directory(&target)?;
+    }
+
+    if flags & MS_BIND as u32 != 0 {
+        let source = normalize(source);
+        check_directory(&source)?;
+        return ROOT_VFS.lock().bind(&source, &target, flags);
+    }
+
+    let block = match fstype {
+        "" => true,
+        name => filesystems::find(name).ok_or(VFSError::InvalidArgument)?.block,
+    };
+    // two filesystems writing to the same disk would wreck it
+    if block && ROOT_VFS.lock().device_taken(source) {
+        return Err(VFSError::Busy);
+    }
+
+    let fs = match filesystems::find(fstype) {
+        Some(typ) => typ.create(source, flags)?,
+        None => filesystems::probe(source, flags)?,
+    };
+
+    // the disk was read without the lock held, so someone may have mounted
+    // it in the meantime
+    let mut vfs = ROOT_VFS.lock();
+    if block && vfs.device_taken(source) {
+        return Err(VFSError::Busy);
+    }
+    let source = if source.is_empty() { fs.fs_type() } else { source };
+    vfs.mount(source, &target, flags, fs)
+}
+
+/// unmounts whatever is at `target` and writes it out
+pub fn umount(target: &str) -> VFSResult<()> {
+    let target = normalize(target);
+    let fs = ROOT_VFS.lock().unmount(&target)?;
+    fs.sync()
+}
+
+pub fn unlink(path: &str) -> VFSResult<()> {
+    let (fs, relative) = ROOT_VFS.lock().resolve_writable(path)?;
+    fs.unlink(&relative)
+}
+
+pub fn mkdir(path: &str, mode: usize) -> VFSResult<()> {
+    let (fs, relative) = ROOT_VFS.lock().resolve_writable(path)?;
+    fs.mkdir(&relative, mode)
+}
+
+pub fn rmdir(path: &str) -> VFSResult<()> {
+    let (fs, relative) = ROOT_VFS.lock().resolve_writable(path)?;
+    fs.rmdir(&relative)
+}
+
+/// `target` is stored as is, it's only looked at when the link is followed
+pub fn symlink(target: &str, path: &str) -> VFSResult<()> {
+    let (fs, relative) = ROOT_VFS.lock().resolve_writable(path)?;
+    fs.symlink(target, &relative)
 }
 
 /// reads the entire contents of the file then returns it as a vector of bytes.
@@ -0,0 +1,205 @@
+mod process;
+mod system;
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::system::vfs::devfs::kmsg;
+use crate::system::vfs::{
+    VFSError, VFSFile, VFSFileType, VFSImplementation, VFSMetadata,
+    VFSPermissions, VFSResult, VFSSeek,
+};
+use crate::system::{proc, time};
+
+type SystemGenerator = fn() -> String;
+type ProcessGenerator = fn(&proc::Process) -> String;
+
+/// files in the root of /proc
+const SYSTEM_FILES: &[(&str, SystemGenerator)] = &[
+    ("cpuinfo", system::cpuinfo),
+    ("filesystems", system::filesystems),
+    ("interrupts", system::interrupts),
+    ("loadavg", system::loadavg),
+    ("meminfo", system::meminfo),
+    ("mounts", system::mounts),
+    ("partitions", system::partitions),
+    ("pci", system::pci),
+    ("uptime", system::uptime),
+    ("version", system::version),
+];
+
+/// files in every /proc/<pid>
+const PROCESS_FILES: &[(&str, ProcessGenerator)] = &[
+    ("cmdline", process::cmdline),
+    ("fd", process::fd),
+    ("maps", process::maps),
+    ("stat", process::stat),
+    ("status", process::status),
+];
+
+/// a snapshot of a procfs file, the content is generated once on open so
+/// reads never see a half updated view.
+pub struct ProcFile {
+    name: String,
+    typ: VFSFileType,
+    data: Vec<u8>,
+    position: AtomicUsize,
+}
+
+impl ProcFile {
+    fn new(name: &str, typ: VFSFileType, data: String) -> Self {
+        Self {
+            name: name.to_string(),
+            typ,
+            data: data.into_bytes(),
+            position: AtomicUsize::new(0),
+        }
+    }
+
+    /// directories read back as one entry per line
+    fn directory<'a>(
+        name: &str,
+        entries: impl Iterator<Item = &'a str>,
+    ) -> Self {
+        let mut data = String::new();
+        for entry in entries {
+            data.push_str(entry);
+            data.push('\n');
+        }
+        Self::new(name, VFSFileType::Directory, data)
+    }
+}
+
+impl VFSFile for ProcFile {
+    fn read(&self, buf: &mut [u8]) -> VFSResult<usize> {
+        let position = self.position.load(Ordering::Acquire);
+        if position >= self.data.len() {
+            return Ok(0);
+        }
+
+        let len = buf.len().min(self.data.len() - position);
+        buf[..len].copy_from_slice(&self.data[position..position + len]);
+        self.position.fetch_add(len, Ordering::AcqRel);
+        Ok(len)
+    }
+
+    fn write(&self, _buf: &mut [u8]) -> VFSResult<usize> {
+        Err(VFSError::PermissionDenied)
+    }
+
+    fn seek(&self, pos: VFSSeek) -> VFSResult<usize> {
+        let new_pos = match pos {
+            VFSSeek::Start(n) => n,
+            VFSSeek::Current(n) => {
+                self.position.load(Ordering::Acquire).saturating_add(n)
+            },
+            VFSSeek::End(n) => self.data.len().saturating_add(n),
+        }
+        .min(self.data.len());
+
+        self.position.store(new_pos, Ordering::Release);
+        Ok(new_pos)
+    }
+
+    fn mmap(
+        &self,
+        _len: usize,
+        _prot: core::ffi::c_int,
+        _flags: core::ffi::c_int,
+    ) -> VFSResult<*mut u8> {
+        Err(VFSError::Unsupported)
+    }
+
+    fn metadata(&self) -> VFSResult<VFSMetadata> {
+        Ok(VFSMetadata {
+            name: self.name.clone(),
+            typ: self.typ,
+            size: self.data.len(),
+            last_modified: time::now() as usize,
+            owner_id: 0,
+            group_id: 0,
+            permissions: VFSPermissions::from_unix(0o444),
+        })
+    }
+}
+
+pub struct ProcFS;
+
+impl ProcFS {
+    pub fn new() -> Self { Self }
+
+    fn root(&self) -> ProcFile {
+        let pids: Vec<String> =
+            proc::ids().iter().map(|pid| pid.to_string()).collect();
+
+        let entries = SYSTEM_FILES
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(["kmsg", "self"])
+            .chain(pids.iter().map(|pid| pid.as_str()));
+
+        ProcFile::directory("proc", entries)
+    }
+
+    /// `self` or a number, anything else isn't a process
+    fn pid(&self, name: &str) -> Option<u64> {
+        if name == "self" { proc::current_id() } else { name.parse().ok() }
+    }
+
+    fn process(&self, pid: u64, name: Option<&str>) -> VFSResult<ProcFile> {
+        let Some(file) = name else {
+            // just the directory
+            proc::with_process(pid, |_| ()).ok_or(VFSError::NotFound)?;
+            return Ok(ProcFile::directory(
+                &pid.to_string(),
+                PROCESS_FILES.iter().map(|(name, _)| *name),
+            ));
+        };
+
+        let (_, generate) = PROCESS_FILES
+            .iter()
+            .find(|(name, _)| *name == file)
+            .ok_or(VFSError::NotFound)?;
+
+        let data =
+            proc::with_process(pid, generate).ok_or(VFSError::NotFound)?;
+        Ok(ProcFile::new(file, VFSFileType::File, data))
+    }
+}
+
+impl VFSImplementation for ProcFS {
+    fn fs_type(&self) -> &'static str { "procfs" }
+
+    fn open(&self, path: &str, _flags: u32) -> VFSResult<Box<dyn VFSFile>> {
+        let parts: Vec<&str> =
+            path.split('/').filter(|part| !part.is_empty()).collect();
+
+        let file = match parts.as_slice() {
+            [] => self.root(),
+            ["kmsg"] => return Ok(kmsg::open()),
+            [name]
+                if let Some((_, generate)) =
+                    SYSTEM_FILES.iter().find(|(file, _)| file == name) =>
+            {
+                ProcFile::new(name, VFSFileType::File, generate())
+            },
+            [pid] => {
+                let pid = self.pid(pid).ok_or(VFSError::NotFound)?;
+                self.process(pid, None)?
+            },
+            [pid, file] => {
+                let pid = self.pid(pid).ok_or(VFSError::NotFound)?;
+                self.process(pid, Some(file))?
+            },
+            _ => return Err(VFSError::NotFound),
+        };
+
+        Ok(Box::new(file))
+    }
+
+    fn metadata(&self, path: &str) -> VFSResult<VFSMetadata> {
+        self.open(path, 0)?.metadata()
+    }
+}
@@ -0,0 +1,138 @@
+use alloc::format;
+use alloc::string::String;
+use core::fmt::Write;
+
+use x86_64::structures::paging::PageTableFlags;
+
+use crate::arch;
+use crate::arch::layout::{USER_STACK_PAGES, USER_STACK_TOP_PAGE};
+use crate::system::proc::{Process, ProcessLevel, ProcessState};
+use crate::system::vfs::FdKind;
+
+const PAGE_SIZE: u64 = arch::layout::PAGE_SIZE as u64;
+const USER_STACK_BOTTOM: u64 =
+    USER_STACK_TOP_PAGE + PAGE_SIZE - USER_STACK_PAGES * PAGE_SIZE;
+
+fn state_name(state: ProcessState) -> &'static str {
+    match state {
+        ProcessState::Ready => "ready",
+        ProcessState::Running => "running",
+        ProcessState::Sleeping => "sleeping",
+        ProcessState::Zombie => "zombie",
+        ProcessState::Dead => "dead",
+    }
+}
+
+/// total bytes of user memory the process has mapped
+fn mapped_size(proc: &Process) -> u64 {
+    proc.address_space
+        .as_ref()
+        .map(|space| {
+            space.user_regions().iter().map(|(start, end, _)| end - start).sum()
+        })
+        .unwrap_or(0)
+}
+
+pub fn status(proc: &Process) -> String {
+    let heap = proc.user_heap_position.saturating_sub(proc.user_heap);
+    let stack = if proc.level == ProcessLevel::RING3 {
+        USER_STACK_PAGES * PAGE_SIZE
+    } else {
+        0
+    };
+
+    format!(
+        "Name:\t{}
+State:\t{} ({})
+Pid:\t{}
+PPid:\t{}
+Level:\t{}
+VmSize:\t{} kB
+VmHeap:\t{} kB
+VmStack:\t{} kB
+CpuTicks:\t{}
+",
+        proc.name,
+        proc.state_char(),
+        state_name(proc.state),
+        proc.id,
+        proc.parent_id.unwrap_or(0),
+        if proc.level == ProcessLevel::RING3 { "user" } else { "kernel" },
+        mapped_size(proc) / 1024,
+        heap / 1024,
+        stack / 1024,
+        proc.cpu_ticks,
+    )
+}
+
+/// arguments separated by nul, like linux
+pub fn cmdline(proc: &Process) -> String {
+    let mut cmdline = String::new();
+    for arg in &proc.argv {
+        cmdline.push_str(arg);
+        cmdline.push('\0');
+    }
+    cmdline
+}
+
+pub fn maps(proc: &Process) -> String {
+    let mut maps = String::new();
+    let Some(space) = proc.address_space.as_ref() else {
+        return maps;
+    };
+
+    for (start, end, flags) in space.user_regions() {
+        let name = if start >= USER_STACK_BOTTOM
+            && end <= USER_STACK_TOP_PAGE + PAGE_SIZE
+        {
+            "[stack]"
+        } else if start >= proc.user_heap && end <= proc.user_heap_position {
+            "[heap]"
+        } else if end <= proc.user_heap {
+            proc.name.as_str()
+        } else {
+            ""
+        };
+
+        let _ = writeln!(
+            maps,
+            "{:016x}-{:016x} r{}{}p {}",
+            start,
+            end,
+            if flags.contains(PageTableFlags::WRITABLE) { 'w' } else { '-' },
+            if flags.contains(PageTableFlags::NO_EXECUTE) { '-' } else { 'x' },
+            name
+        );
+    }
+
+    maps
+}
+
+/// one descriptor per line, `fd -> target`
+pub fn fd(proc: &Process) -> String {
+    let mut fds = String::new();
+    for (fd, kind) in proc.fds.iter() {
+        let target = match kind {
+            FdKind::File { path, .. } => path.as_str(),
+            FdKind::Stdin => "stdin",
+            FdKind::Stdout => "stdout",
+            FdKind::Stderr => "stderr",
+        };
+        let _ = writeln!(fds, "{} -> {}", fd, target);
+    }
+    fds
+}
+
+/// `pid (name) state ppid cpu_ticks started_at vsize`
+pub fn stat(proc: &Process) -> String {
+    format!(
+        "{} ({}) {} {} {} {} {}\n",
+        proc.id,
+        proc.name,
+        proc.state_char(),
+        proc.parent_id.unwrap_or(0),
+        proc.cpu_ticks,
+        proc.started_at,
+        mapped_size(proc),
+    )
+}
@@ -0,0 +1,323 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use raw_cpuid::CpuId;
+
+use crate::arch;
+use crate::arch::interrupts::InterruptIndex;
+use crate::arch::irq::{self, IrqSource, Trigger};
+use crate::drivers::block::partition::PartitionKind;
+use crate::drivers::{block, pci};
+use crate::system::{self, proc, vfs};
+
+pub fn meminfo() -> String {
+    let mem_total =
+        system::mem::pmm::usable_pages().unwrap_or(0) * arch::layout::PAGE_SIZE;
+    let mem_free =
+        system::mem::pmm::free_pages().unwrap_or(0) * arch::layout::PAGE_SIZE;
+
+    let cache = system::cache::stats();
+    let cached = cache.blocks * system::cache::BLOCK_SIZE;
+    let dirty = cache.dirty * system::cache::BLOCK_SIZE;
+
+    // clean cached blocks can be dropped whenever someone needs the memory
+    let mem_available = mem_free + cached.saturating_sub(dirty);
+    let mem_used = mem_total.saturating_sub(mem_free);
+
+    format!(
+        "MemTotal: {} kB
+MemFree: {} kB
+MemUsed: {} kB
+MemAvailable: {} kB
+Cached: {} kB
+Dirty: {} kB
+CacheHits: {}
+CacheMisses: {}
+CacheEvictions: {}
+CacheWritebacks: {}
+",
+        mem_total / 1024,
+        mem_free / 1024,
+        mem_used / 1024,
+        mem_available / 1024,
+        cached / 1024,
+        dirty / 1024,
+        cache.hits,
+        cache.misses,
+        cache.evictions,
+        cache.writebacks,
+    )
+}
+
+pub fn version() -> String {
+    format!(
+        "{} version {}\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+/// `uptime idle` in seconds, idle is whatever the null process ran for
+pub fn uptime() -> String {
+    let ticks = arch::ticks();
+    let idle = proc::with_process(0, |proc| proc.cpu_ticks).unwrap_or(0);
+
+    format!(
+        "{}.{:02} {}.{:02}\n",
+        ticks / 1000,
+        ticks % 1000 / 10,
+        idle / 1000,
+        idle % 1000 / 10
+    )
+}
+
+pub fn cpuinfo() -> String {
+    let cpuid = CpuId::new();
+    let mut info = String::new();
+
+    let vendor = cpuid.get_vendor_info();
+    let brand = cpuid.get_processor_brand_string();
+    let _ = writeln!(
+        info,
+        "vendor_id\t: {}",
+        vendor.as_ref().map(|vendor| vendor.as_str()).unwrap_or("unknown")
+    );
+    let _ = writeln!(
+        info,
+        "model name\t: {}",
+        brand.as_ref().map(|brand| brand.as_str().trim()).unwrap_or("unknown")
+    );
+
+    if let Some(features) = cpuid.get_feature_info() {
+        let _ = writeln!(info, "cpu family\t: {}", features.family_id());
+        let _ = writeln!(info, "model\t\t: {}", features.model_id());
+        let _ = writeln!(info, "stepping\t: {}", features.stepping_id());
+    }
+
+    if let Some(frequency) = cpuid.get_processor_frequency_info() {
+        let _ = writeln!(
+            info,
+            "cpu MHz\t\t: {}",
+            frequency.processor_base_frequency()
+        );
+    }
+
+    let mut flags = Vec::new();
+    if let Some(features) = cpuid.get_feature_info() {
+        flags.extend(
+            [
+                ("fpu", features.has_fpu()),
+                ("tsc", features.has_tsc()),
+                ("msr", features.has_msr()),
+                ("pae", features.has_pae()),
+                ("apic", features.has_apic()),
+                ("mmx", features.has_mmx()),
+                ("fxsr", features.has_fxsave_fxstor()),
+                ("sse", features.has_sse()),
+                ("sse2", features.has_sse2()),
+                ("sse3", features.has_sse3()),
+                ("ssse3", features.has_ssse3()),
+                ("sse4_1", features.has_sse41()),
+                ("sse4_2", features.has_sse42()),
+                ("x2apic", features.has_x2apic()),
+                ("popcnt", features.has_popcnt()),
+                ("aes", features.has_aesni()),
+                ("avx", features.has_avx()),
+                ("rdrand", features.has_rdrand()),
+                ("hypervisor", features.has_hypervisor()),
+            ]
+            .into_iter()
+            .filter(|(_, has)| *has)
+            .map(|(flag, _)| flag),
+        );
+    }
+    if let Some(features) = cpuid.get_extended_feature_info() {
+        flags.extend(
+            [
+                ("avx2", features.has_avx2()),
+                ("bmi1", features.has_bmi1()),
+                ("bmi2", features.has_bmi2()),
+                ("smep", features.has_smep()),
+                ("smap", features.has_smap()),
+                ("rdseed", features.has_rdseed()),
+            ]
+            .into_iter()
+            .filter(|(_, has)| *has)
+            .map(|(flag, _)| flag),
+        );
+    }
+    let _ = writeln!(info, "flags\t\t: {}", flags.join(" "));
+
+    info
+}
+
+/// `vector: count unhandled source handlers`, unhandled is how often nobody
+/// claimed the interrupt, lapic ones always get claimed
+pub fn interrupts() -> String {
+    let mut interrupts = String::new();
+    for index in InterruptIndex::ALL {
+        let _ = writeln!(
+            interrupts,
+            "{:>3}: {:>10} {:>10}  LAPIC         {}",
+            index.as_u8(),
+            arch::interrupts::count(index.as_u8()),
+            "-",
+            index.name()
+        );
+    }
+
+    irq::lines(|lines| {
+        for line in lines {
+            let names: Vec<&str> = line.names().collect();
+            let trigger =
+                if line.trigger == Trigger::Level { "level" } else { "edge" };
+            let source = match line.source {
+                IrqSource::Gsi(gsi) => {
+                    format!("IO-APIC {:>3}-{}", gsi, trigger)
+                },
+                IrqSource::Msi => format!("PCI-MSI {}", trigger),
+            };
+            let _ = writeln!(
+                interrupts,
+                "{:>3}: {:>10} {:>10}  {:<13}  {}",
+                line.vector,
+                line.count(),
+                line.unhandled,
+                source,
+                names.join(", ")
+            );
+        }
+    });
+    interrupts
+}
+
+/// `address vendor:device class rev` then the upstream bridge, the bus
+/// behind the device and the bound driver, `-` when there isn't one
+pub fn pci() -> String {
+    let mut pci = String::new();
+    for device in pci::devices() {
+        let parent = device
+            .parent
+            .map(|addr| format!("{}", addr))
+            .unwrap_or_else(|| "-".into());
+        let secondary = device
+            .secondary_bus
+            .map(|bus| format!("{:02x}", bus))
+            .unwrap_or_else(|| "-".into());
+        let driver =
+            pci::driver::driver_of(device.addr).map_or("-", |drv| drv.name);
+
+        let _ = writeln!(
+            pci,
+            "{} {:04x}:{:04x} {:02x}.{:02x}.{:02x} rev {:02x} {} {} {}",
+            device.addr,
+            device.vendor_id,
+            device.device_id,
+            device.base_class,
+            device.sub_class,
+            device.interface,
+            device.revision,
+            parent,
+            secondary,
+            driver
+        );
+    }
+    pci
+}
+
+/// `name start sectors type guid label`, whole disks have no type. gpt
+/// types are guids, mbr ones the type byte. whitespace and backslashes in
+/// labels are octal escaped like linux does, `\040` for a space
+pub fn partitions() -> String {
+    let mut partitions = String::from("name start sectors type guid label\n");
+    for disk in block::devices() {
+        let sector_size = disk.sector_size() as u64;
+        let _ = match &disk.partition {
+            None => writeln!(
+                partitions,
+                "{} 0 {} - - -",
+                disk.name,
+                disk.size() / sector_size
+            ),
+            Some(part) => match &part.kind {
+                PartitionKind::Mbr(typ) => writeln!(
+                    partitions,
+                    "{} {} {} {:#04x} - -",
+                    disk.name, part.start, part.sectors, typ
+                ),
+                PartitionKind::Gpt { typ, guid, label } => writeln!(
+                    partitions,
+                    "{} {} {} {} {} {}",
+                    disk.name,
+                    part.start,
+                    part.sectors,
+                    typ,
+                    guid,
+                    if label.is_empty() { "-".into() } else { escape(label) }
+                ),
+            },
+        };
+    }
+    partitions
+}
+
+/// so a field with spaces in it stays one field
+fn escape(field: &str) -> String {
+    let mut escaped = String::new();
+    for c in field.chars() {
+        match c {
+            ' ' | '\t' | '\n' | '\\' => {
+                let _ = write!(escaped, "\\{:03o}", c as u32);
+            },
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `source path type ro|rw 0 0`, like linux
+pub fn mounts() -> String {
+    let mut mounts = String::new();
+    for mount in vfs::mounts() {
+        let _ = writeln!(
+            mounts,
+            "{} {} {} {} 0 0",
+            mount.source,
+            mount.path,
+            mount.fs_type,
+            if mount.read_only { "ro" } else { "rw" }
+        );
+    }
+    mounts
+}
+
+/// the types mount takes, `nodev` ones don't need a disk
+pub fn filesystems() -> String {
+    let mut filesystems = String::new();
+    for fs in vfs::FILESYSTEMS {
+        let nodev = if fs.block { "" } else { "nodev" };
+        let _ = writeln!(filesystems, "{}\t{}", nodev, fs.name);
+    }
+    filesystems
+}
+
+/// `1min 5min 15min runnable/total last_pid`
+pub fn loadavg() -> String {
+    let [one, five, fifteen] = proc::loadavg();
+    let (runnable, total) = proc::runnable();
+
+    format!(
+        "{}.{:02} {}.{:02} {}.{:02} {}/{} {}\n",
+        one / 100,
+        one % 100,
+        five / 100,
+        five % 100,
+        fifteen / 100,
+        fifteen % 100,
+        runnable,
+        total,
+        proc::last_id()
+    )
+}
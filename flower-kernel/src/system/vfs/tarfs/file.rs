@@ -1,10 +1,9 @@
 use alloc::string::String;
-use alloc::sync::Arc;
-use alloc::vec::Vec;
 use core::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::system::vfs::{
-    VFSFile, VFSFileType, VFSMetadata, VFSPermissions, VFSResult, VFSSeek,
+    VFSError, VFSFile, VFSFileType, VFSMetadata, VFSPermissions, VFSResult,
+    VFSSeek,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -37,7 +36,8 @@ impl From<u8> for TarFSFileType {
 pub struct TarFile {
     pub _data_position: usize,
     pub _position: AtomicUsize,
-    pub _data: Arc<Vec<u8>>,
+    /// the whole archive, straight out of the limine module
+    pub _data: &'static [u8],
 
     pub name: String,
     pub path: String,
@@ -60,7 +60,7 @@ impl Clone for TarFile {
         Self {
             _data_position: self._data_position,
             _position: AtomicUsize::new(self._position.load(Ordering::Relaxed)),
-            _data: Arc::clone(&self._data),
+            _data: self._data,
             name: self.name.clone(),
             path: self.path.clone(),
             mode: self.mode,
@@ -121,7 +121,7 @@ impl VFSFile for TarFile {
 
     fn write(&self, _buf: &mut [u8]) -> VFSResult<usize> { unimplemented!() }
 
-    fn seek(&mut self, pos: VFSSeek) -> VFSResult<usize> {
+    fn seek(&self, pos: VFSSeek) -> VFSResult<usize> {
         let mut new_pos = match pos {
             VFSSeek::Start(n) => n,
             VFSSeek::Current(n) => {
@@ -136,15 +136,23 @@ impl VFSFile for TarFile {
         Ok(new_pos)
     }
 
+    /// hands out the module memory itself, the rest of the last page is
+    /// whatever comes after the file in the archive
     fn mmap(
         &self,
-        _len: usize,
+        len: usize,
         _prot: core::ffi::c_int,
         _flags: core::ffi::c_int,
     ) -> VFSResult<*mut u8> {
-        unimplemented!()
+        if len > self.size {
+            return Err(VFSError::InvalidArgument);
+        }
+        Ok(self._data[self._data_position..].as_ptr() as *mut u8)
     }
 
+    /// it's the initramfs everyone shares
+    fn mmap_writable(&self) -> bool { false }
+
     fn metadata(&self) -> VFSResult<VFSMetadata> {
         let typ = match self.file_type {
             TarFSFileType::File => VFSFileType::File,
@@ -3,7 +3,6 @@ mod file;
 
 use alloc::boxed::Box;
 use alloc::string::{String, ToString};
-use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::sync::atomic::AtomicUsize;
 
@@ -13,7 +12,7 @@ use crate::system::vfs::tarfs::file::{TarFSFileType, TarFile};
 use crate::system::vfs::types::*;
 
 pub struct TarFS {
-    data: Arc<Vec<u8>>,
+    data: &'static [u8],
     files: Vec<TarFile>,
 }
 
@@ -41,17 +40,12 @@ impl TarFS {
         }
 
         if let Ok(file) = file {
-            // stream the file into memory
+            // limine maps the module in the hhdm already, so use it where it
+            // is. module memory was never usable, so the pmm doesn't hand it
+            // out, and mmap marks it borrowed so it's never freed either
             let size = file.size() as usize;
-            let mut data = alloc::vec![0u8; size];
-            unsafe {
-                core::ptr::copy_nonoverlapping(
-                    file.addr(),
-                    data.as_mut_ptr(),
-                    size,
-                );
-            }
-            let data = Arc::new(data);
+            let data: &'static [u8] =
+                unsafe { core::slice::from_raw_parts(file.addr(), size) };
 
             // read all the files
             let mut offset = 0;
@@ -112,7 +106,7 @@ impl TarFS {
 
                 let path = "/".to_string() + &file_name;
 
-                // copy the data right away
+                // files point into the archive, nothing gets copied
                 if file_size > 0 {
                     let data_position = offset + 512;
                     if data_position + file_size > data.len() {
@@ -126,7 +120,7 @@ impl TarFS {
                     files.push(TarFile {
                         _data_position: data_position,
                         _position: AtomicUsize::new(0),
-                        _data: Arc::clone(&data),
+                        _data: data,
                         name: file_name
                             .split("/")
                             .last()
@@ -163,7 +157,7 @@ impl TarFS {
             return Self { data, files };
         }
 
-        Self { data: Arc::new(Vec::new()), files: Vec::new() }
+        Self { data: &[], files: Vec::new() }
     }
 
     fn get_file(&self, path: &str) -> VFSResult<&TarFile> {
@@ -172,6 +166,8 @@ impl TarFS {
 }
 
 impl VFSImplementation for TarFS {
+    fn fs_type(&self) -> &'static str { "tarfs" }
+
     fn open(
         &self,
         path: &str,
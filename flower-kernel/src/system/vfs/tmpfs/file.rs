@@ -0,0 +1,161 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use spin::Mutex;
+
+use crate::system::time;
+use crate::system::vfs::{
+    VFSError, VFSFile, VFSFileType, VFSMetadata, VFSPermissions, VFSResult,
+    VFSSeek,
+};
+
+pub enum NodeData {
+    File(Vec<u8>),
+    Dir(BTreeMap<String, Arc<Node>>),
+}
+
+/// a file or directory, it lives as long as something links to it or has
+/// it open
+pub struct Node {
+    pub data: Mutex<NodeData>,
+    pub mode: usize,
+    pub mtime: AtomicU64,
+}
+
+impl Node {
+    pub fn new(data: NodeData, mode: usize) -> Arc<Self> {
+        Arc::new(Self {
+            data: Mutex::new(data),
+            mode: mode & 0o777,
+            mtime: AtomicU64::new(time::now()),
+        })
+    }
+
+    pub fn touch(&self) { self.mtime.store(time::now(), Ordering::Relaxed); }
+
+    pub fn metadata(&self, name: &str) -> VFSMetadata {
+        let (typ, size) = match &*self.data.lock() {
+            NodeData::File(data) => (VFSFileType::File, data.len()),
+            NodeData::Dir(_) => (VFSFileType::Directory, 0),
+        };
+
+        VFSMetadata {
+            name: String::from(name),
+            typ,
+            size,
+            last_modified: self.mtime.load(Ordering::Relaxed) as usize,
+            owner_id: 0,
+            group_id: 0,
+            permissions: VFSPermissions::from_unix(self.mode),
+        }
+    }
+}
+
+/// directories read back as one name per line, like procfs
+fn listing(children: &BTreeMap<String, Arc<Node>>) -> Vec<u8> {
+    let mut listing = Vec::new();
+    for name in children.keys() {
+        listing.extend_from_slice(name.as_bytes());
+        listing.push(b'\n');
+    }
+    listing
+}
+
+pub struct TmpFile {
+    pub node: Arc<Node>,
+    pub name: String,
+    /// every write goes to the end
+    pub append: bool,
+    pub read_only: bool,
+    pub position: AtomicUsize,
+}
+
+impl VFSFile for TmpFile {
+    fn read(&self, buf: &mut [u8]) -> VFSResult<usize> {
+        let position = self.position.load(Ordering::Acquire);
+
+        let read = match &*self.node.data.lock() {
+            NodeData::File(data) if position >= data.len() => 0,
+            NodeData::File(data) => {
+                let len = buf.len().min(data.len() - position);
+                buf[..len].copy_from_slice(&data[position..position + len]);
+                len
+            },
+            NodeData::Dir(children) => {
+                let listing = listing(children);
+                if position >= listing.len() {
+                    0
+                } else {
+                    let len = buf.len().min(listing.len() - position);
+                    buf[..len]
+                        .copy_from_slice(&listing[position..position + len]);
+                    len
+                }
+            },
+        };
+
+        self.position.store(position + read, Ordering::Release);
+        Ok(read)
+    }
+
+    fn write(&self, buf: &mut [u8]) -> VFSResult<usize> {
+        if self.read_only {
+            return Err(VFSError::ReadOnly);
+        }
+
+        let mut node = self.node.data.lock();
+        let NodeData::File(data) = &mut *node else {
+            return Err(VFSError::IsDirectory);
+        };
+
+        let position = if self.append {
+            data.len()
+        } else {
+            self.position.load(Ordering::Acquire)
+        };
+        let end =
+            position.checked_add(buf.len()).ok_or(VFSError::InvalidArgument)?;
+        if end > data.len() {
+            data.resize(end, 0);
+        }
+        data[position..end].copy_from_slice(buf);
+        drop(node);
+
+        self.node.touch();
+        self.position.store(end, Ordering::Release);
+        Ok(buf.len())
+    }
+
+    fn seek(&self, pos: VFSSeek) -> VFSResult<usize> {
+        let current = self.position.load(Ordering::Acquire);
+        let size = match &*self.node.data.lock() {
+            NodeData::File(data) => data.len(),
+            NodeData::Dir(children) => listing(children).len(),
+        };
+        let new_pos = match pos {
+            VFSSeek::Start(n) => n,
+            VFSSeek::Current(n) => current.saturating_add(n),
+            VFSSeek::End(n) => size.saturating_add(n),
+        }
+        .min(size);
+
+        self.position.store(new_pos, Ordering::Release);
+        Ok(new_pos)
+    }
+
+    fn mmap(
+        &self,
+        _len: usize,
+        _prot: core::ffi::c_int,
+        _flags: core::ffi::c_int,
+    ) -> VFSResult<*mut u8> {
+        Err(VFSError::Unsupported)
+    }
+
+    fn metadata(&self) -> VFSResult<VFSMetadata> {
+        Ok(self.node.metadata(&self.name))
+    }
+}
@@ -0,0 +1,164 @@
+mod file;
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::AtomicUsize;
+
+use flower_mono::fcntl::{O_APPEND, O_CREAT, O_EXCL, O_TRUNC};
+
+use crate::system::vfs::split_parent;
+use crate::system::vfs::tmpfs::file::{Node, NodeData, TmpFile};
+use crate::system::vfs::types::*;
+
+/// a filesystem that only lives in memory, gone when it's unmounted
+pub struct TmpFS {
+    root: Arc<Node>,
+    read_only: bool,
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').find(|part| !part.is_empty()).unwrap_or("/")
+}
+
+impl TmpFS {
+    pub fn new(read_only: bool) -> Self {
+        Self {
+            root: Node::new(NodeData::Dir(BTreeMap::new()), 0o755),
+            read_only,
+        }
+    }
+
+    fn check_writable(&self) -> VFSResult<()> {
+        if self.read_only { Err(VFSError::ReadOnly) } else { Ok(()) }
+    }
+
+    fn lookup(&self, path: &str) -> VFSResult<Arc<Node>> {
+        // the way back up for `..`
+        let mut stack = vec![self.root.clone()];
+        for part in
+            path.split('/').filter(|part| !part.is_empty() && *part != ".")
+        {
+            if part == ".." {
+                if stack.len() > 1 {
+                    stack.pop();
+                }
+                continue;
+            }
+
+            let next = match &*stack.last().unwrap().data.lock() {
+                NodeData::Dir(children) => {
+                    children.get(part).cloned().ok_or(VFSError::NotFound)?
+                },
+                NodeData::File(_) => return Err(VFSError::NotDirectory),
+            };
+            stack.push(next);
+        }
+        Ok(stack.pop().unwrap())
+    }
+
+    /// links `node` in at `path`, the parent has to exist already
+    fn insert(&self, path: &str, node: Arc<Node>) -> VFSResult<Arc<Node>> {
+        self.check_writable()?;
+        let (parent, name) = split_parent(path)?;
+        let parent = self.lookup(parent)?;
+
+        let mut data = parent.data.lock();
+        let NodeData::Dir(children) = &mut *data else {
+            return Err(VFSError::NotDirectory);
+        };
+        if children.contains_key(name) {
+            return Err(VFSError::AlreadyExists);
+        }
+        children.insert(String::from(name), node.clone());
+        drop(data);
+
+        parent.touch();
+        Ok(node)
+    }
+
+    /// unlinks whatever is at `path` if `check` is happy with it
+    fn remove(
+        &self,
+        path: &str,
+        check: impl FnOnce(&NodeData) -> VFSResult<()>,
+    ) -> VFSResult<()> {
+        self.check_writable()?;
+        let (parent, name) = split_parent(path)?;
+        let parent = self.lookup(parent)?;
+
+        let mut data = parent.data.lock();
+        let NodeData::Dir(children) = &mut *data else {
+            return Err(VFSError::NotDirectory);
+        };
+        let node = children.get(name).ok_or(VFSError::NotFound)?;
+        check(&node.data.lock())?;
+        children.remove(name);
+        drop(data);
+
+        parent.touch();
+        Ok(())
+    }
+}
+
+impl VFSImplementation for TmpFS {
+    fn fs_type(&self) -> &'static str { "tmpfs" }
+
+    fn read_only(&self) -> bool { self.read_only }
+
+    fn open(&self, path: &str, flags: u32) -> VFSResult<Box<dyn VFSFile>> {
+        let node = match self.lookup(path) {
+            Ok(_) if flags & O_CREAT != 0 && flags & O_EXCL != 0 => {
+                return Err(VFSError::AlreadyExists);
+            },
+            Ok(node) => node,
+            Err(VFSError::NotFound) if flags & O_CREAT != 0 => {
+                self.insert(path, Node::new(NodeData::File(Vec::new()), 0o644))?
+            },
+            Err(e) => return Err(e),
+        };
+
+        if flags & O_TRUNC != 0 {
+            self.check_writable()?;
+            if let NodeData::File(data) = &mut *node.data.lock() {
+                data.clear();
+            }
+        }
+
+        Ok(Box::new(TmpFile {
+            node,
+            name: String::from(file_name(path)),
+            append: flags & O_APPEND != 0,
+            read_only: self.read_only,
+            position: AtomicUsize::new(0),
+        }))
+    }
+
+    fn metadata(&self, path: &str) -> VFSResult<VFSMetadata> {
+        Ok(self.lookup(path)?.metadata(file_name(path)))
+    }
+
+    /// anyone who has it open keeps it until they close it
+    fn unlink(&self, path: &str) -> VFSResult<()> {
+        self.remove(path, |data| match data {
+            NodeData::File(_) => Ok(()),
+            NodeData::Dir(_) => Err(VFSError::IsDirectory),
+        })
+    }
+
+    fn mkdir(&self, path: &str, mode: usize) -> VFSResult<()> {
+        self.insert(path, Node::new(NodeData::Dir(BTreeMap::new()), mode))
+            .map(|_| ())
+    }
+
+    fn rmdir(&self, path: &str) -> VFSResult<()> {
+        self.remove(path, |data| match data {
+            NodeData::Dir(children) if children.is_empty() => Ok(()),
+            NodeData::Dir(_) => Err(VFSError::NotEmpty),
+            NodeData::File(_) => Err(VFSError::NotDirectory),
+        })
+    }
+}
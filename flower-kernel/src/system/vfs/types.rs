@@ -69,7 +69,14 @@ pub enum VFSSeek {
 pub enum VFSError {
     NotFound,
     AlreadyExists,
+    NotDirectory,
+    IsDirectory,
+    NotEmpty,
+    NameTooLong,
+    ReadOnly,
+    Busy,
     InvalidSeek,
+    InvalidArgument,
     PermissionDenied,
     NoSpace,
     IOError,
@@ -81,8 +88,17 @@ impl ToSyscallError for VFSError {
     fn to_syscall_error(&self) -> SyscallError {
         match self {
             Self::NotFound => SyscallError::NoSuchFile,
+            Self::AlreadyExists => SyscallError::FileExists,
+            Self::NotDirectory => SyscallError::NotDirectory,
+            Self::IsDirectory => SyscallError::IsDirectory,
+            Self::NotEmpty => SyscallError::NotEmpty,
+            Self::NameTooLong => SyscallError::TooLong,
+            Self::ReadOnly => SyscallError::ReadOnlyFileSystem,
+            Self::Busy => SyscallError::ResourceBusy,
             Self::InvalidSeek => SyscallError::InvalidArgument,
+            Self::InvalidArgument => SyscallError::InvalidArgument,
             Self::PermissionDenied => SyscallError::NoPermission,
+            Self::NoSpace => SyscallError::NoSpace,
             Self::IOError => SyscallError::IOError,
             _ => SyscallError::Other(format!("Unhandled VFSError: {:?}", self)),
         }
@@ -99,17 +115,34 @@ pub trait VFSFile: Send + Sync {
     fn write(&self, buf: &mut [u8]) -> VFSResult<usize>;
 
     /// seeks to the given position and returns the new position
-    fn seek(&mut self, pos: VFSSeek) -> VFSResult<usize>;
+    fn seek(&self, pos: VFSSeek) -> VFSResult<usize>;
 
     /// maps the file into memory and returns a pointer to the mapped region
     fn mmap(&self, len: usize, prot: c_int, flags: c_int)
     -> VFSResult<*mut u8>;
 
+    /// whether user space gets to write to what mmap hands out
+    fn mmap_writable(&self) -> bool { true }
+
     /// gets the info for the file
     fn metadata(&self) -> VFSResult<VFSMetadata>;
+
+    /// device specific control, `arg` is usually a pointer from userspace
+    fn ioctl(&self, _cmd: u64, _arg: u64) -> VFSResult<u64> {
+        Err(VFSError::Unsupported)
+    }
+
+    /// pushes anything buffered for the file out to its storage
+    fn sync(&self) -> VFSResult<()> { Ok(()) }
 }
 
 pub trait VFSImplementation: Send + Sync {
+    /// short name of the filesystem type, shown in /proc/mounts
+    fn fs_type(&self) -> &'static str;
+
+    /// whether writes get refused, whatever the mount flags say
+    fn read_only(&self) -> bool { false }
+
     /// opens the file
     fn open(&self, path: &str, flags: u32) -> VFSResult<Box<dyn VFSFile>>;
 
@@ -118,4 +151,25 @@ pub trait VFSImplementation: Send + Sync {
 
     /// checks if the file exists
     fn exists(&self, path: &str) -> bool { self.metadata(path).is_ok() }
+
+    /// removes a file, or a link to it anyway
+    fn unlink(&self, _path: &str) -> VFSResult<()> {
+        Err(VFSError::Unsupported)
+    }
+
+    /// creates an empty directory
+    fn mkdir(&self, _path: &str, _mode: usize) -> VFSResult<()> {
+        Err(VFSError::Unsupported)
+    }
+
+    /// removes an empty directory
+    fn rmdir(&self, _path: &str) -> VFSResult<()> { Err(VFSError::Unsupported) }
+
+    /// creates a symlink at `path` pointing to `target`
+    fn symlink(&self, _target: &str, _path: &str) -> VFSResult<()> {
+        Err(VFSError::Unsupported)
+    }
+
+    /// pushes everything the filesystem has buffered out to its storage
+    fn sync(&self) -> VFSResult<()> { Ok(()) }
 }
@@ -5,6 +5,7 @@ use core::fmt::{Display, Formatter};
 use flower_mono::structs::FileStat;
 
 use crate::sys::{fs, kernel};
+use crate::time::SystemTime;
 
 #[derive(Debug)]
 pub enum FileError {
@@ -12,6 +13,7 @@ pub enum FileError {
     FileReadError,
     FileWriteError,
     FileMmapError,
+    FileIoctlError,
     FileInvalid,
 }
 
@@ -24,6 +26,7 @@ impl Display for FileError {
             FileError::FileReadError => write!(f, "Failed to read from file"),
             FileError::FileWriteError => write!(f, "Failed to write to file"),
             FileError::FileMmapError => write!(f, "Failed to mmap file"),
+            FileError::FileIoctlError => write!(f, "Failed to ioctl file"),
             FileError::FileInvalid => write!(f, "Invalid file descriptor"),
         }
     }
@@ -31,10 +34,16 @@ impl Display for FileError {
 
 pub struct FileMetadata {
     pub size: usize,
+    pub modified: SystemTime,
 }
 
 impl From<FileStat> for FileMetadata {
-    fn from(stat: FileStat) -> Self { Self { size: stat.st_size as usize } }
+    fn from(stat: FileStat) -> Self {
+        Self {
+            size: stat.st_size as usize,
+            modified: SystemTime::from_unix(stat.st_mtime),
+        }
+    }
 }
 
 pub struct File {
@@ -96,6 +105,15 @@ impl File {
         let addr = kernel::mmap(self.fd, length);
         if addr.is_null() { Err(FileError::FileMmapError) } else { Ok(addr) }
     }
+
+    pub fn ioctl(&self, cmd: u64, arg: u64) -> Result<u64, FileError> {
+        let result = fs::ioctl(self.fd, cmd, arg);
+        if result < 0 {
+            Err(FileError::FileIoctlError)
+        } else {
+            Ok(result as u64)
+        }
+    }
 }
 
 impl Drop for File {
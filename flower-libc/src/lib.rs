@@ -12,8 +12,10 @@ pub mod env;
 pub mod file;
 pub mod io;
 pub mod process;
+pub mod random;
 pub mod sys;
 pub mod thread;
+pub mod time;
 
 const MAX_PATH_BYTES: usize = 512;
 
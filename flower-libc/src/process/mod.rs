@@ -1,4 +1,6 @@
-use flower_mono::syscalls::{SYS_EXECVE, SYS_EXIT, SYS_FORK, SYS_WAITPID};
+use flower_mono::syscalls::{
+    SYS_EXECVE, SYS_EXIT, SYS_FORK, SYS_POWEROFF, SYS_REBOOT, SYS_WAITPID,
+};
 
 use crate::sys::kernel::{syscall_result, syscall0, syscall1, syscall3};
 use crate::{allocator, with_c_path_raw};
@@ -36,3 +38,15 @@ pub extern "C" fn exit(s: u64) -> ! {
     syscall1(SYS_EXIT, s);
     unreachable!();
 }
+
+#[unsafe(no_mangle)]
+pub extern "C" fn reboot() -> ! {
+    syscall0(SYS_REBOOT);
+    unreachable!();
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn poweroff() -> ! {
+    syscall0(SYS_POWEROFF);
+    unreachable!();
+}
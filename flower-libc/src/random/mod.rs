@@ -0,0 +1,43 @@
+use crate::sys::kernel::getrandom;
+
+/// fills the buffer with random bytes from the kernel, the same pool that
+/// backs /dev/urandom
+pub fn fill(buf: &mut [u8]) -> bool {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let rest = &mut buf[filled..];
+        let result = getrandom(rest.as_mut_ptr(), rest.len(), 0);
+        if result <= 0 {
+            return false;
+        }
+        filled += result as usize;
+    }
+    true
+}
+
+/// panics if the kernel won't hand out randomness, zeros would look random
+/// enough to go unnoticed
+pub fn u64() -> u64 {
+    let mut bytes = [0u8; 8];
+    if !fill(&mut bytes) {
+        panic!("getrandom failed");
+    }
+    u64::from_le_bytes(bytes)
+}
+
+pub fn u32() -> u32 { u64() as u32 }
+
+/// a random number in `0..bound`, without the modulo bias
+pub fn below(bound: u64) -> u64 {
+    if bound == 0 {
+        return 0;
+    }
+
+    let limit = u64::MAX - u64::MAX % bound;
+    loop {
+        let value = u64();
+        if value < limit {
+            return value % bound;
+        }
+    }
+}
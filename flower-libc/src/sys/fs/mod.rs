@@ -2,10 +2,14 @@ use core::ptr;
 
 use flower_mono::structs::FileStat;
 use flower_mono::syscalls::{
-    SYS_CLOSE, SYS_OPEN, SYS_READ, SYS_STAT, SYS_WRITE,
+    SYS_CLOSE, SYS_FSYNC, SYS_IOCTL, SYS_MKDIR, SYS_MOUNT, SYS_OPEN, SYS_READ,
+    SYS_RMDIR, SYS_STAT, SYS_SYMLINK, SYS_SYNC, SYS_UMOUNT, SYS_UNLINK,
+    SYS_WRITE,
 };
 
-use crate::sys::kernel::{syscall_result, syscall1, syscall3};
+use crate::sys::kernel::{
+    syscall_result, syscall0, syscall1, syscall2, syscall3, syscall4,
+};
 use crate::with_c_path_raw;
 
 #[unsafe(no_mangle)]
@@ -52,3 +56,104 @@ pub unsafe extern "C" fn metadata(fd: u64, stat: *mut FileStat) -> i64 {
     let result = syscall3(SYS_STAT, fd, stat as u64, 0);
     if syscall_result(result) < 0 { -1 } else { 0 }
 }
+
+#[unsafe(no_mangle)]
+pub extern "C" fn ioctl(fd: u64, cmd: u64, arg: u64) -> i64 {
+    syscall_result(syscall3(SYS_IOCTL, fd, cmd, arg))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sync() { syscall0(SYS_SYNC); }
+
+#[unsafe(no_mangle)]
+pub extern "C" fn fsync(fd: u64) -> i64 {
+    let result = syscall_result(syscall1(SYS_FSYNC, fd));
+    if result < 0 { -1 } else { 0 }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn unlink(path: *const u8, path_len: usize) -> i64 {
+    match with_c_path_raw(path, path_len, |ptr| {
+        syscall1(SYS_UNLINK, ptr as u64)
+    }) {
+        Some(result) if syscall_result(result) >= 0 => 0,
+        _ => -1,
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn mkdir(path: *const u8, path_len: usize, mode: u64) -> i64 {
+    match with_c_path_raw(path, path_len, |ptr| {
+        syscall2(SYS_MKDIR, ptr as u64, mode)
+    }) {
+        Some(result) if syscall_result(result) >= 0 => 0,
+        _ => -1,
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn rmdir(path: *const u8, path_len: usize) -> i64 {
+    match with_c_path_raw(path, path_len, |ptr| syscall1(SYS_RMDIR, ptr as u64))
+    {
+        Some(result) if syscall_result(result) >= 0 => 0,
+        _ => -1,
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn symlink(
+    target: *const u8,
+    target_len: usize,
+    path: *const u8,
+    path_len: usize,
+) -> i64 {
+    let result = with_c_path_raw(target, target_len, |target| {
+        with_c_path_raw(path, path_len, |path| {
+            syscall2(SYS_SYMLINK, target as u64, path as u64)
+        })
+    });
+    match result.flatten() {
+        Some(result) if syscall_result(result) >= 0 => 0,
+        _ => -1,
+    }
+}
+
+/// an empty `fstype` lets the kernel work out what's on `source`
+#[unsafe(no_mangle)]
+pub extern "C" fn mount(
+    source: *const u8,
+    source_len: usize,
+    target: *const u8,
+    target_len: usize,
+    fstype: *const u8,
+    fstype_len: usize,
+    flags: u64,
+) -> i64 {
+    let result = with_c_path_raw(source, source_len, |source| {
+        with_c_path_raw(target, target_len, |target| {
+            with_c_path_raw(fstype, fstype_len, |fstype| {
+                syscall4(
+                    SYS_MOUNT,
+                    source as u64,
+                    target as u64,
+                    fstype as u64,
+                    flags,
+                )
+            })
+        })
+    });
+    match result.flatten().flatten() {
+        Some(result) if syscall_result(result) >= 0 => 0,
+        _ => -1,
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn umount(target: *const u8, target_len: usize) -> i64 {
+    match with_c_path_raw(target, target_len, |ptr| {
+        syscall1(SYS_UMOUNT, ptr as u64)
+    }) {
+        Some(result) if syscall_result(result) >= 0 => 0,
+        _ => -1,
+    }
+}
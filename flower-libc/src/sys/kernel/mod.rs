@@ -3,3 +3,9 @@ pub use syscalls::*;
 
 pub mod mman;
 pub use mman::*;
+
+pub mod random;
+pub use random::*;
+
+pub mod time;
+pub use time::*;
@@ -0,0 +1,14 @@
+use flower_mono::syscalls::SYS_GETRANDOM;
+
+use crate::sys::kernel::{syscall_result, syscall3};
+
+#[unsafe(no_mangle)]
+pub extern "C" fn getrandom(buf: *mut u8, buf_len: usize, flags: u64) -> i64 {
+    let result = syscall_result(syscall3(
+        SYS_GETRANDOM,
+        buf as u64,
+        buf_len as u64,
+        flags,
+    ));
+    if result < 0 { -1 } else { result }
+}
@@ -0,0 +1,25 @@
+use flower_mono::structs::{Timespec, Timeval};
+use flower_mono::syscalls::{
+    SYS_CLOCK_GETTIME, SYS_GETTIMEOFDAY, SYS_NANOSLEEP,
+};
+
+use crate::sys::kernel::{syscall_result, syscall2};
+
+#[unsafe(no_mangle)]
+pub extern "C" fn clock_gettime(clock: u64, tp: *mut Timespec) -> i64 {
+    let result = syscall_result(syscall2(SYS_CLOCK_GETTIME, clock, tp as u64));
+    if result < 0 { -1 } else { 0 }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn gettimeofday(tv: *mut Timeval, tz: u64) -> i64 {
+    let result = syscall_result(syscall2(SYS_GETTIMEOFDAY, tv as u64, tz));
+    if result < 0 { -1 } else { 0 }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn nanosleep(req: *const Timespec, rem: *mut Timespec) -> i64 {
+    let result =
+        syscall_result(syscall2(SYS_NANOSLEEP, req as u64, rem as u64));
+    if result < 0 { -1 } else { 0 }
+}
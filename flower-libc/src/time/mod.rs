@@ -0,0 +1,144 @@
+use core::fmt::{Display, Formatter};
+use core::ops::{Add, Sub};
+pub use core::time::Duration;
+
+use flower_mono::structs::Timespec;
+use flower_mono::syscalls::{CLOCK_MONOTONIC, CLOCK_REALTIME};
+
+use crate::sys::kernel::{clock_gettime, nanosleep};
+
+fn clock(id: u64) -> Duration {
+    let mut tp = Timespec::default();
+    clock_gettime(id, &mut tp);
+    Duration::new(tp.tv_sec.max(0) as u64, tp.tv_nsec.max(0) as u32)
+}
+
+/// a point on the monotonic clock, only good for measuring time between two
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant(Duration);
+
+impl Instant {
+    pub fn now() -> Self { Self(clock(CLOCK_MONOTONIC)) }
+
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+
+    pub fn elapsed(&self) -> Duration { Instant::now().duration_since(*self) }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Instant { Instant(self.0 + rhs) }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Duration { self.duration_since(rhs) }
+}
+
+/// wall clock time, as a duration since the unix epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SystemTime(Duration);
+
+impl SystemTime {
+    pub const UNIX_EPOCH: SystemTime = SystemTime(Duration::ZERO);
+
+    pub fn now() -> Self { Self(clock(CLOCK_REALTIME)) }
+
+    pub fn from_unix(secs: u64) -> Self { Self(Duration::from_secs(secs)) }
+
+    /// None if `earlier` is actually later
+    pub fn duration_since(&self, earlier: SystemTime) -> Option<Duration> {
+        self.0.checked_sub(earlier.0)
+    }
+
+    pub fn as_unix(&self) -> u64 { self.0.as_secs() }
+
+    pub fn to_datetime(&self) -> DateTime {
+        DateTime::from_unix(self.as_unix())
+    }
+}
+
+/// a broken down utc date, displays as `YYYY-MM-DD HH:MM:SS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// 0 is sunday
+    pub weekday: u8,
+}
+
+impl DateTime {
+    pub const MONTHS: [&'static str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct",
+        "Nov", "Dec",
+    ];
+    pub const WEEKDAYS: [&'static str; 7] =
+        ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+    pub fn from_unix(secs: u64) -> Self {
+        let days = (secs / 86400) as i64;
+        let rem = secs % 86400;
+
+        // civil_from_days, from howard hinnant's date algorithms
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Self {
+            year,
+            month,
+            day,
+            hour: (rem / 3600) as u8,
+            minute: (rem / 60 % 60) as u8,
+            second: (rem % 60) as u8,
+            // the epoch was a thursday
+            weekday: ((days + 4) % 7) as u8,
+        }
+    }
+
+    pub fn weekday_name(&self) -> &'static str {
+        Self::WEEKDAYS[self.weekday as usize]
+    }
+
+    pub fn month_name(&self) -> &'static str {
+        Self::MONTHS[self.month as usize - 1]
+    }
+}
+
+impl Display for DateTime {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+}
+
+/// sleeps for at least the given duration
+pub fn sleep(duration: Duration) {
+    let req = Timespec {
+        tv_sec: duration.as_secs() as i64,
+        tv_nsec: duration.subsec_nanos() as i64,
+    };
+    nanosleep(&req, core::ptr::null_mut());
+}
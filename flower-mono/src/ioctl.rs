@@ -0,0 +1,35 @@
+// framebuffer
+pub const FBIOGET_INFO: u64 = 0x4600;
+pub const FBIOPUT_MODE: u64 = 0x4601;
+pub const FBIO_FLIP: u64 = 0x4602;
+
+// kmsg, levels go 0 (off) to 5 (trace)
+pub const KMSG_GET_CONSOLE_LEVEL: u64 = 0x4B00;
+pub const KMSG_SET_CONSOLE_LEVEL: u64 = 0x4B01;
+
+// audio, rates are in hz and volumes go 0 to 100
+pub const AUDIO_GET_RATE: u64 = 0x4100;
+pub const AUDIO_SET_RATE: u64 = 0x4101;
+pub const AUDIO_GET_CHANNELS: u64 = 0x4102;
+pub const AUDIO_SET_CHANNELS: u64 = 0x4103;
+pub const AUDIO_GET_FORMAT: u64 = 0x4104;
+pub const AUDIO_SET_FORMAT: u64 = 0x4105;
+pub const AUDIO_GET_MASTER_VOLUME: u64 = 0x4106;
+pub const AUDIO_SET_MASTER_VOLUME: u64 = 0x4107;
+pub const AUDIO_GET_PCM_VOLUME: u64 = 0x4108;
+pub const AUDIO_SET_PCM_VOLUME: u64 = 0x4109;
+pub const AUDIO_GET_RECORD_SOURCE: u64 = 0x410A;
+pub const AUDIO_SET_RECORD_SOURCE: u64 = 0x410B;
+pub const AUDIO_GET_RECORD_GAIN: u64 = 0x410C;
+pub const AUDIO_SET_RECORD_GAIN: u64 = 0x410D;
+
+pub const AUDIO_FORMAT_U8: u64 = 0;
+pub const AUDIO_FORMAT_S16_LE: u64 = 1;
+
+pub const AUDIO_SOURCE_MIC: u64 = 0;
+pub const AUDIO_SOURCE_LINE_IN: u64 = 1;
+
+// block devices, sizes are in bytes
+pub const BLK_GET_SIZE: u64 = 0x4200;
+pub const BLK_GET_SECTOR_SIZE: u64 = 0x4201;
+pub const BLK_FLUSH: u64 = 0x4202;
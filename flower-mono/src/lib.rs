@@ -1,3 +1,5 @@
 #![no_std]
+pub mod fcntl;
+pub mod ioctl;
 pub mod structs;
 pub mod syscalls;
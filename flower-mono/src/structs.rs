@@ -4,4 +4,71 @@ pub struct FileStat {
     pub st_mode: u16,
     pub st_dev: u64,
     pub st_size: u64,
+    /// seconds since the unix epoch
+    pub st_mtime: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FramebufferInfo {
+    pub width: u32,
+    pub height: u32,
+    pub pitch: u32,
+    pub bpp: u16,
+
+    /// number of screen sized buffers in video memory, and the visible one
+    pub buffers: u32,
+    pub front: u32,
+
+    pub red_mask_size: u8,
+    pub red_mask_shift: u8,
+    pub green_mask_size: u8,
+    pub green_mask_shift: u8,
+    pub blue_mask_size: u8,
+    pub blue_mask_shift: u8,
+}
+
+impl FramebufferInfo {
+    /// size of a single screen buffer in bytes
+    pub fn size(&self) -> usize { self.pitch as usize * self.height as usize }
+
+    /// size of every buffer combined, this is what can be mapped
+    pub fn total_size(&self) -> usize {
+        self.size() * (self.buffers as usize).max(1)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FramebufferMode {
+    pub width: u32,
+    pub height: u32,
+    pub bpp: u16,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timespec {
+    pub tv_sec: i64,
+    pub tv_nsec: i64,
+}
+
+impl Timespec {
+    pub fn from_nanos(nanos: u128) -> Self {
+        Self {
+            tv_sec: (nanos / 1_000_000_000) as i64,
+            tv_nsec: (nanos % 1_000_000_000) as i64,
+        }
+    }
+
+    pub fn as_nanos(&self) -> u128 {
+        self.tv_sec.max(0) as u128 * 1_000_000_000 + self.tv_nsec.max(0) as u128
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timeval {
+    pub tv_sec: i64,
+    pub tv_usec: i64,
 }
@@ -9,6 +9,39 @@ pub const SYS_WAITPID: u64 = 7;
 pub const SYS_SEEK: u64 = 8;
 pub const SYS_EXECVE: u64 = 9;
 pub const SYS_STAT: u64 = 10;
+pub const SYS_IOCTL: u64 = 11;
+pub const SYS_GETRANDOM: u64 = 12;
+
+// getrandom flags, accepted for compatibility. the pool never blocks.
+pub const GRND_NONBLOCK: u64 = 0x1;
+pub const GRND_RANDOM: u64 = 0x2;
+
+pub const SYS_CLOCK_GETTIME: u64 = 13;
+pub const SYS_GETTIMEOFDAY: u64 = 14;
+pub const SYS_NANOSLEEP: u64 = 15;
+
+// clock ids, same numbers as linux
+pub const CLOCK_REALTIME: u64 = 0;
+pub const CLOCK_MONOTONIC: u64 = 1;
+pub const CLOCK_BOOTTIME: u64 = 7;
+
+pub const SYS_REBOOT: u64 = 16;
+pub const SYS_POWEROFF: u64 = 17;
+
+pub const SYS_SYNC: u64 = 18;
+pub const SYS_FSYNC: u64 = 19;
+
+pub const SYS_UNLINK: u64 = 20;
+pub const SYS_MKDIR: u64 = 21;
+pub const SYS_RMDIR: u64 = 22;
+pub const SYS_SYMLINK: u64 = 23;
+
+pub const SYS_MOUNT: u64 = 24;
+pub const SYS_UMOUNT: u64 = 25;
+
+// mount flags
+pub const MS_RDONLY: u64 = 0x1;
+pub const MS_BIND: u64 = 0x1000;
 
 pub const SYS_WRITE_FS_BASE: u64 = 29;
 